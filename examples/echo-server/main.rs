@@ -4,7 +4,7 @@ extern crate serde_derive;
 
 use roperator::config::{ClientConfig, Credentials, KubeConfig};
 use roperator::prelude::{
-    k8s_types, ChildConfig, Error, K8sType, OperatorConfig, SyncRequest, SyncResponse,
+    k8s_types, ChildConfig, Error, K8sType, OperatorConfig, Scope, SyncRequest, SyncResponse,
 };
 use roperator::serde_json::{json, Value};
 use std::time::Duration;
@@ -17,6 +17,7 @@ static PARENT_TYPE: &K8sType = &K8sType {
     api_version: "example.roperator.com/v1alpha1",
     kind: "EchoServer",
     plural_kind: "echoservers",
+    scope: Scope::Namespaced,
 };
 
 /// Represents an instance of the CRD that is in the kubernetes cluster.
@@ -83,15 +84,17 @@ fn main() {
         client_config_result.expect("failed to resolve cluster data from kubeconfig");
 
     // now we run the operator, passing in our handler functions
-    let err = roperator::runner::run_operator_with_client_config(
+    let result = roperator::runner::run_operator_with_client_config(
         operator_config,
         client_config,
         (handle_sync, handle_error),
     );
 
-    // `run_operator_with_client_config` will never return under normal circumstances, so we only need to handle the sad path here
-    log::error!("Error running operator: {}", err);
-    std::process::exit(1);
+    // `run_operator_with_client_config` only returns `Ok` if the operator was asked to shut down by a signal
+    if let Err(err) = result {
+        log::error!("Error running operator: {}", err);
+        std::process::exit(1);
+    }
 }
 
 /// This function will invoked by the operator any time there's a change to any parent or child resources.
@@ -107,6 +110,7 @@ fn handle_sync(request: &SyncRequest) -> Result<SyncResponse, Error> {
         status,
         children,
         resync: None,
+        child_scales: Vec::new(),
     })
 }
 
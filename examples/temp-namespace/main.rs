@@ -12,7 +12,7 @@ use chrono::offset::Utc;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
 use roperator::config::{ClientConfig, Credentials, KubeConfig};
 use roperator::handler::failable::{DefaultFailableHandler, FailableHandler, HandlerResult};
-use roperator::prelude::{k8s_types, ChildConfig, K8sType, OperatorConfig, SyncRequest};
+use roperator::prelude::{k8s_types, ChildConfig, K8sType, OperatorConfig, Scope, SyncRequest};
 use roperator::serde_json::{json, Value};
 use serde::de::{self, Deserialize, Deserializer};
 use std::time::Duration;
@@ -25,6 +25,7 @@ static PARENT_TYPE: &K8sType = &K8sType {
     api_version: "example.roperator.com/v1alpha1",
     kind: "TempNamespace",
     plural_kind: "tempnamespaces",
+    scope: Scope::Cluster,
 };
 
 /// Represents an instance of the CRD that is in the kubernetes cluster.
@@ -179,12 +180,14 @@ fn main() {
     let handler =
         DefaultFailableHandler::wrap(TempNsHandler).with_regular_resync(Duration::from_secs(30));
     // now we run the operator, passing in our handler functions
-    let err =
+    let result =
         roperator::runner::run_operator_with_client_config(operator_config, client_config, handler);
 
-    // `run_operator_with_client_config` will never return under normal circumstances, so we only need to handle the sad path here
-    log::error!("Error running operator: {}", err);
-    std::process::exit(1);
+    // `run_operator_with_client_config` only returns `Ok` if the operator was asked to shut down by a signal
+    if let Err(err) = result {
+        log::error!("Error running operator: {}", err);
+        std::process::exit(1);
+    }
 }
 
 // returns the desired namespace resource. Nothing fancy here, just the basics
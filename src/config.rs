@@ -1,13 +1,23 @@
 //! Types for creating `OperatorConfig` and `ClientConfig`.
 //! Most users will use `OperatorConfig::new()`.
 //! `ClientConfig` can be created automatically in most cases, but you can also create that manually.
+pub(crate) mod exec;
 mod kubeconfig;
+mod selector;
+mod serialization;
+mod validation;
 
-use crate::k8s_types::K8sType;
+use crate::k8s_types::{K8sType, Scope};
+use crate::resource::{K8sResource, ObjectId};
+use crate::webhook::WebhookConfig;
+
+use self::serialization::default_true;
 
 use std::collections::HashMap;
 use std::io;
-use std::{path::Path, time::Duration};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Default label that's added to all child resources, so that roperator can track the ownership of resources.
 /// The value is the `metadata.uid` of the parent.
@@ -19,18 +29,187 @@ pub const DEFAULT_OWNERSHIP_LABEL_NAME: &str = "app.kubernetes.io/managed-by";
 
 const SERVICE_ACCOUNT_TOKEN_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
 const SERVICE_ACCOUNT_CA_PATH: &str = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt";
+const SERVICE_ACCOUNT_NAMESPACE_PATH: &str =
+    "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+/// Environment variable that's commonly set to the pod's namespace using the downward api
+const POD_NAMESPACE_ENV_VAR: &str = "POD_NAMESPACE";
 const API_SERVER_HOSTNAME: &str = "kubernetes.default.svc";
 
-pub use self::kubeconfig::{KubeConfig, KubeConfigError};
+/// Default maximum sustained rate of requests per second to the api server. This is the same default used by client-go.
+pub const DEFAULT_QPS: f32 = 5.0;
+
+/// Default maximum burst of requests to the api server. This is the same default used by client-go.
+pub const DEFAULT_BURST: u32 = 10;
+
+/// Default maximum amount of time to wait for a single request to the api server to complete
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default amount of time that a watch is kept open before it's re-established
+pub const DEFAULT_WATCH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default maximum number of resources that are returned in each page of a list request
+pub const DEFAULT_LIST_PAGE_SIZE: u32 = 500;
+
+/// Default maximum number of idle connections to the api server that are kept open for re-use
+pub const DEFAULT_MAX_IDLE_CONNECTIONS: usize = 16;
+
+/// Default amount of time that an idle connection to the api server is kept open for re-use
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Default interval of TCP keep-alive probes on connections to the api server. This is the same default used by
+/// client-go.
+pub const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// Default maximum size, in bytes, of the body of a write to the api server. This is the default limit of etcd, which
+/// rejects larger objects.
+pub const DEFAULT_MAX_OBJECT_SIZE: usize = 1_572_864;
+
+/// Default size, in bytes, of a write to the api server that's large enough to log a warning about, which is about
+/// half of the `DEFAULT_MAX_OBJECT_SIZE`
+pub const DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD: usize = 768_000;
+
+/// Maximum number of times that a sync is retried right away after a child update conflicts with a concurrent
+/// change, when `ChildConfig::optimistic_concurrency` is enabled. After that, the sync fails as usual and is retried
+/// after the normal error backoff.
+pub const MAX_CONFLICT_RETRIES: u32 = 3;
+
+/// Default amount of time that a watch may go without hearing from the api server before the operator is reported as
+/// not ready. This is longer than the `DEFAULT_WATCH_TIMEOUT`, since even a watch that never receives any events or
+/// bookmarks is re-established at least that often.
+pub const DEFAULT_WATCH_STALENESS_THRESHOLD: Duration = Duration::from_secs(600);
+
+/// Default amount of time to wait for in-progress syncs to finish when the operator is shut down. This is a bit less
+/// than the default `terminationGracePeriodSeconds` of a pod, so that the operator can exit before it's killed.
+pub const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(25);
+
+/// Default number of watch events that may be waiting to be processed by the operator, across all of the watches
+pub const DEFAULT_EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Controls how requests to the api server are retried when they fail with a transient error, such as a 503 response
+/// or a connection reset. Only idempotent requests (GET and DELETE) are retried after they've been sent. Other
+/// requests are only retried if the connection could not be established, since in that case the request was
+/// never sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetryPolicy {
+    /// The maximum number of times that a request will be attempted, including the initial attempt. A value of
+    /// `1` or less disables retries.
+    pub max_attempts: u32,
+    /// The delay before the first retry. This is doubled for each subsequent retry, and a random jitter is
+    /// applied. If the api server responds with a `Retry-After` header, then that delay is used instead.
+    #[serde(with = "serialization::duration")]
+    pub base_delay: Duration,
+    /// The maximum delay between any two attempts, including delays from a `Retry-After` header
+    #[serde(with = "serialization::duration")]
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns a `RetryPolicy` that never retries any request
+    pub fn no_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Controls how long to wait before syncing a parent again after its sync fails with a retryable error, or after
+/// `finalize` reports that it isn't done yet. The delay grows exponentially with each consecutive failure of the
+/// same parent, and is reset once a sync of that parent succeeds. This is separate from the `RetryPolicy`, which
+/// only applies to individual requests to the api server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReconcileBackoff {
+    /// The delay after the first failure
+    #[serde(with = "serialization::duration")]
+    pub base_delay: Duration,
+    /// The factor that the delay is multiplied by after each subsequent failure. Values less than `1.0` are treated
+    /// as `1.0`, which results in a constant delay.
+    pub multiplier: f64,
+    /// The maximum delay between syncs of a failing parent
+    #[serde(with = "serialization::duration")]
+    pub max_delay: Duration,
+    /// The amount of random jitter to apply to each delay, as a fraction of the delay. A `jitter` of `0.5` means
+    /// that each delay is randomly chosen from between 50% and 150% of the computed value, which keeps parents that
+    /// failed at the same time, such as during an api server outage, from all retrying at the same instant. The
+    /// value is clamped to between `0.0` (no jitter) and `1.0`.
+    pub jitter: f64,
+}
+
+impl Default for ReconcileBackoff {
+    fn default() -> ReconcileBackoff {
+        ReconcileBackoff {
+            base_delay: Duration::from_millis(100),
+            multiplier: 1.5,
+            max_delay: Duration::from_secs(600),
+            jitter: 0.5,
+        }
+    }
+}
+
+/// Whether a circuit breaker counts handler failures for each parent separately, or for all parents together
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CircuitBreakerScope {
+    /// A single circuit is shared by all parents, which is appropriate when they all depend on the same external
+    /// system
+    Global,
+    /// Each parent has its own circuit, so one parent whose dependency is down doesn't affect the others
+    PerParent,
+}
+
+/// Stops invoking the handler after it fails too many times in a row, so that syncs fail right away instead of each
+/// waiting on an external system that's down. Once `failure_threshold` consecutive syncs fail with a retryable
+/// handler error or a `handler_timeout`, the circuit "opens", and syncs fail without invoking the handler for the
+/// `open_duration`. After that, a single sync is allowed through as a probe. If it succeeds, then the circuit closes
+/// again, and otherwise it stays open for another `open_duration`. This is separate from the `reconcile_backoff`,
+/// which only delays retries of each parent after its own failures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failures that opens the circuit. Values less than `1` are treated as `1`.
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a probe is allowed through. Syncs that are rejected while it's open
+    /// are retried once it's time for the probe.
+    #[serde(with = "serialization::duration")]
+    pub open_duration: Duration,
+    /// Whether failures are counted for each parent separately, or for all of them together
+    pub scope: CircuitBreakerScope,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(60),
+            scope: CircuitBreakerScope::Global,
+        }
+    }
+}
+
+pub use self::kubeconfig::{KubeConfig, KubeConfigError, KUBE_CONTEXT_ENV_VAR};
+pub use self::selector::{InvalidFieldSelector, InvalidLabelSelector};
+pub use self::serialization::ConfigFileError;
+pub use self::validation::ConfigError;
 
 /// What to do when there's a difference between the "desired" state of a given resource and the
-/// actual state of that resource in the cluster. The three options are:
+/// actual state of that resource in the cluster. The options are:
 /// - Update the resource in place using an HTTP PUT request
 /// - First delete the resource, then try to re-create it later
 /// - Don't update it automatically, and instead wait for something else to delete the resource and then re-create it with the new state
-///
-/// There's not yet any option for using PATCH requests.
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// - Patch only the fields that are different using an HTTP PATCH request
+/// - Use server-side apply, which tracks ownership of each field that's set by the operator
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum UpdateStrategy {
     /// Means that the resource will be updated in place using an HTTP PUT request
     Replace,
@@ -40,19 +219,155 @@ pub enum UpdateStrategy {
 
     /// The resource will not be modified in any way. Instead, we'll wait until the resource is deleted by some other means and then re-create it
     OnDelete,
+
+    /// The resource will be updated in place using an HTTP PATCH request that includes only the fields that differ
+    /// between the desired and actual state. This leaves alone any fields that were set by other controllers, such as
+    /// `replicas` being set by a HorizontalPodAutoscaler. A strategic merge patch is used, falling back to a
+    /// json merge patch for resource types that don't support strategic merge (such as custom resources).
+    Patch,
+
+    /// The resource will be created and updated using [server-side apply](https://kubernetes.io/docs/reference/using-api/server-side-apply/).
+    /// The api server tracks which fields are owned by the operator, so it can cooperate with other controllers
+    /// that manage different fields of the same resource. The field manager and whether to force conflicts are
+    /// configured on the `ChildConfig`.
+    ServerSideApply,
+}
+
+/// Determines what happens to the dependents of a child when roperator deletes it, by setting the
+/// `propagationPolicy` of the delete request. Dependents are resources that have an owner reference to the child,
+/// such as the ReplicaSets and Pods of a Deployment.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum DeletePolicy {
+    /// The dependents are left behind, and their owner references to the child are removed
+    Orphan,
+    /// The child is deleted immediately, and the garbage collector deletes the dependents afterwards. This is the
+    /// default, and matches `kubectl delete`.
+    #[default]
+    Background,
+    /// The child is only removed once all of its dependents with `blockOwnerDeletion` have been deleted. Until then,
+    /// the child remains with its `deletionTimestamp` set, so a child that's being recreated won't be created again
+    /// until its dependents are gone.
+    Foreground,
 }
 
+impl DeletePolicy {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            DeletePolicy::Orphan => "Orphan",
+            DeletePolicy::Background => "Background",
+            DeletePolicy::Foreground => "Foreground",
+        }
+    }
+}
+
+/// Paths of the fields that are set by the api server on every resource, which are always ignored when comparing
+/// the desired and existing states of a child. Additional paths can be ignored using `ChildConfig::ignore_path`.
+pub const SERVER_MANAGED_PATHS: &[&str] = &[
+    "/metadata/creationTimestamp",
+    "/metadata/generation",
+    "/metadata/managedFields",
+    "/metadata/resourceVersion",
+    "/metadata/selfLink",
+    "/metadata/uid",
+    "/status",
+];
+
+/// Annotation that holds the desired state of a child from the most recent update, for child types that have
+/// `ChildConfig::track_last_applied` enabled. The value is the child as it was returned by the handler, serialized as
+/// json, without the `status` or the `resourceVersion`.
+pub const LAST_APPLIED_ANNOTATION: &str = "roperator.com/last-applied-configuration";
+
 /// Configuration object that's specific to each type of child
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ChildConfig {
     /// The update strategy for this child type, which determines what roperator should do when a
     /// desired from a `SyncResponse` doesn't match the actual state of the cluster.
     pub update_strategy: UpdateStrategy,
+
+    /// The `fieldManager` to use for server-side apply requests. If `None`, then the `operator_name` from the
+    /// `OperatorConfig` will be used. Ignored unless the `update_strategy` is `ServerSideApply`.
+    #[serde(default)]
+    pub field_manager: Option<String>,
+
+    /// If true, then server-side apply requests will use `force=true`, which takes ownership of any fields
+    /// that conflict with other field managers. Otherwise, conflicts will cause the apply to fail with a 409.
+    /// Ignored unless the `update_strategy` is `ServerSideApply`.
+    #[serde(default)]
+    pub force_apply: bool,
+
+    /// JSON pointers to fields that are ignored when deciding whether an existing child needs to be updated, in
+    /// addition to the `SERVER_MANAGED_PATHS`. This is for fields that are defaulted or assigned by the api server,
+    /// like `/spec/clusterIP` of a Service, which would otherwise always be different from the desired state. A `*`
+    /// matches any key or array index, e.g. `/spec/ports/*/nodePort`. Patches never change ignored fields. When an
+    /// array has to be sent as a whole, its elements keep the existing values of any ignored fields.
+    #[serde(default)]
+    pub ignored_paths: Vec<String>,
+
+    /// What happens to the dependents of a child when it's deleted, either because it's no longer desired or
+    /// because it's being recreated. Defaults to `DeletePolicy::Background`.
+    #[serde(default)]
+    pub delete_policy: DeletePolicy,
+
+    /// If true, then every update of an existing child is made conditional on the `resourceVersion` that was
+    /// observed, so that the api server rejects it with a 409 if the child was changed in the meantime. When that
+    /// happens, the parent and the child are read again from the api server and the sync is retried right away, up
+    /// to `MAX_CONFLICT_RETRIES` times. Replace and patch updates always include the `resourceVersion`, so this
+    /// mostly matters for server-side apply, which otherwise overwrites concurrent changes to the fields that it
+    /// owns. Defaults to false.
+    #[serde(default)]
+    pub optimistic_concurrency: bool,
+
+    /// If true, then children of this type are listed and watched as `PartialObjectMetadata`, so that only their
+    /// metadata is sent by the api server and kept in the cache. This greatly reduces memory and bandwidth for large
+    /// types. The children that are passed to the handler will only have `apiVersion`, `kind`, and `metadata`, and
+    /// only the metadata of the desired children is compared to decide whether an update is required, so changes to
+    /// any other fields of existing children won't be detected. Defaults to false.
+    #[serde(default)]
+    pub metadata_only: bool,
+
+    /// If true, then a desired child that can't be created because a resource with the same name already exists is
+    /// adopted, as long as it doesn't belong to another parent. The existing resource is updated to match the desired
+    /// child, which adds the tracking label or owner reference, so that it's managed by the operator from then on.
+    /// This is for bringing existing resources under the management of an operator, and is dangerous otherwise,
+    /// since any resource that happens to have the same name is taken over. Children with the `Recreate` or
+    /// `OnDelete` strategies are replaced when they're adopted, rather than deleted or left alone. Server-side apply
+    /// always takes over existing resources, regardless of this setting. Defaults to false.
+    #[serde(default)]
+    pub adopt_existing: bool,
+
+    /// If true, then the desired state of each child is stored in the `LAST_APPLIED_ANNOTATION` whenever it's
+    /// updated, the same as `kubectl apply` does. Handlers can read it using `SyncRequest::last_applied_child`. With
+    /// the `Patch` strategy, it's used for a three-way merge, so that fields that were in the last applied state but
+    /// are no longer desired are removed from the child, while fields that were set by others are left alone.
+    /// Without it, a field that's removed from the desired state is left on the child. Replace and server-side apply
+    /// already remove such fields. Defaults to false.
+    #[serde(default)]
+    pub track_last_applied: bool,
+
+    /// The api server that children of this type are created in, watched, and updated through, if it's different
+    /// from the one that the parents are in. This allows an operator that runs in one cluster to manage resources
+    /// in others. Children in another cluster never get an owner reference to their parent, so they must be tracked
+    /// using the tracking label, and they're deleted by roperator when the parent is finalized, since the garbage
+    /// collector can't do it. Defaults to `None`, which uses the same client as the parents.
+    #[serde(default)]
+    pub client: Option<ClientConfig>,
 }
 
 impl ChildConfig {
     pub fn new(update_strategy: UpdateStrategy) -> ChildConfig {
-        ChildConfig { update_strategy }
+        ChildConfig {
+            update_strategy,
+            field_manager: None,
+            force_apply: false,
+            ignored_paths: Vec::new(),
+            delete_policy: DeletePolicy::default(),
+            optimistic_concurrency: false,
+            metadata_only: false,
+            adopt_existing: false,
+            track_last_applied: false,
+            client: None,
+        }
     }
 
     /// returns a `ChildConfig` with the `update_strategy` set to `UpdateStrategy::Recreate`
@@ -69,21 +384,341 @@ impl ChildConfig {
     pub fn on_delete() -> ChildConfig {
         ChildConfig::new(UpdateStrategy::OnDelete)
     }
+
+    /// returns a `ChildConfig` with the `update_strategy` set to `UpdateStrategy::Patch`
+    pub fn patch() -> ChildConfig {
+        ChildConfig::new(UpdateStrategy::Patch)
+    }
+
+    /// returns a `ChildConfig` with the `update_strategy` set to `UpdateStrategy::ServerSideApply`, using
+    /// the given `fieldManager`
+    pub fn server_side_apply(field_manager: impl Into<String>) -> ChildConfig {
+        let mut config = ChildConfig::new(UpdateStrategy::ServerSideApply);
+        config.field_manager = Some(field_manager.into());
+        config
+    }
+
+    /// Sets whether server-side apply requests should force ownership of fields that conflict with other
+    /// field managers.
+    pub fn with_force_apply(mut self, force: bool) -> Self {
+        self.force_apply = force;
+        self
+    }
+
+    /// Sets the `DeletePolicy` that's used when deleting children of this type
+    pub fn with_delete_policy(mut self, delete_policy: DeletePolicy) -> Self {
+        self.delete_policy = delete_policy;
+        self
+    }
+
+    /// Adds a JSON pointer to a field that should be ignored when comparing the desired and existing states of
+    /// children of this type, such as `/spec/clusterIP`
+    pub fn ignore_path(mut self, path: impl Into<String>) -> Self {
+        self.ignored_paths.push(path.into());
+        self
+    }
+
+    /// Sets whether updates to existing children of this type are conditional on the `resourceVersion` that was
+    /// observed, and retried after re-reading the child if they conflict with a concurrent change
+    pub fn optimistic_concurrency(mut self, enabled: bool) -> Self {
+        self.optimistic_concurrency = enabled;
+        self
+    }
+
+    /// Sets whether only the metadata of children of this type is watched and cached, rather than the whole object
+    pub fn metadata_only(mut self, enabled: bool) -> Self {
+        self.metadata_only = enabled;
+        self
+    }
+
+    /// Sets whether existing resources that don't belong to any parent are adopted when a desired child of this
+    /// type has the same name
+    pub fn adopt_existing(mut self, enabled: bool) -> Self {
+        self.adopt_existing = enabled;
+        self
+    }
+
+    /// Sets whether the desired state of each child is stored in the `LAST_APPLIED_ANNOTATION`, and used to remove
+    /// fields that are no longer desired
+    pub fn track_last_applied(mut self, enabled: bool) -> Self {
+        self.track_last_applied = enabled;
+        self
+    }
+
+    /// Sets the `ClientConfig` for the api server that children of this type are managed in, for children that are
+    /// in a different cluster than their parents
+    pub fn with_client(mut self, client_config: ClientConfig) -> Self {
+        self.client = Some(client_config);
+        self
+    }
+}
+
+/// Declares a type of child resource that the operator manages, along with the settings for how it's watched and
+/// updated. `OperatorConfig::with_child` creates a declaration with the defaults, and
+/// `OperatorConfig::with_child_declaration` can be used to change the discovery settings as well.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SerializedChildDeclaration", rename_all = "camelCase")]
+pub struct ChildDeclaration {
+    /// The type of the child resource
+    pub k8s_type: &'static K8sType,
+    /// Determines how children of this type are updated
+    pub config: ChildConfig,
+    /// Optional label selector that further restricts which resources of this type are watched, in addition to the
+    /// tracking label. Resources that don't match are never seen by the operator, so they're never passed to the
+    /// handler, updated, or deleted.
+    pub label_selector: Option<String>,
+    /// Determines where children of this type are watched, and defaults to the `scope` of the `k8s_type`. Setting
+    /// it to `Scope::Cluster` for a namespaced type watches children in every namespace, even if the operator is
+    /// restricted to some namespaces, which is useful for children of cluster-scoped parents. A cluster-scoped type
+    /// can't be watched within a namespace, so `Scope::Namespaced` is only valid for namespaced types.
+    pub scope: Scope,
+}
+
+/// The scope of a `ChildDeclaration` defaults to the scope of its type, so it's optional when deserializing
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SerializedChildDeclaration {
+    k8s_type: &'static K8sType,
+    config: ChildConfig,
+    #[serde(default)]
+    label_selector: Option<String>,
+    #[serde(default)]
+    scope: Option<Scope>,
+}
+
+impl From<SerializedChildDeclaration> for ChildDeclaration {
+    fn from(serialized: SerializedChildDeclaration) -> ChildDeclaration {
+        ChildDeclaration {
+            k8s_type: serialized.k8s_type,
+            config: serialized.config,
+            label_selector: serialized.label_selector,
+            scope: serialized.scope.unwrap_or(serialized.k8s_type.scope),
+        }
+    }
+}
+
+impl ChildDeclaration {
+    pub fn new(k8s_type: &'static K8sType, config: ChildConfig) -> ChildDeclaration {
+        ChildDeclaration {
+            k8s_type,
+            config,
+            label_selector: None,
+            scope: k8s_type.scope,
+        }
+    }
+
+    /// Sets a label selector that restricts which resources of this type are watched
+    pub fn with_label_selector(mut self, selector: impl Into<String>) -> Self {
+        self.label_selector = Some(selector.into());
+        self
+    }
+
+    /// Sets the scope that children of this type are watched in
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+}
+
+/// Maps a resource that isn't a child, such as a `Secret` that's referenced by name from the parent's spec, to the
+/// ids of the parents that should be synced when it changes. These are registered for watched types using
+/// `OperatorConfig::with_mapped_watched_type`, or for types that are discovered at runtime using
+/// `OperatorHandle::watch_additional`.
+#[derive(Clone)]
+pub struct ParentMapper(Arc<MapperFn>);
+
+type MapperFn = dyn Fn(&K8sResource) -> Vec<ObjectId> + Send + Sync;
+
+impl ParentMapper {
+    pub fn new(mapper: impl Fn(&K8sResource) -> Vec<ObjectId> + Send + Sync + 'static) -> Self {
+        ParentMapper(Arc::new(mapper))
+    }
+
+    pub(crate) fn parents_of(&self, resource: &K8sResource) -> Vec<ObjectId> {
+        (self.0)(resource)
+    }
+}
+
+impl std::fmt::Debug for ParentMapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("ParentMapper")
+    }
+}
+
+/// Two `ParentMapper`s are only equal if they're clones of the same mapper instance
+impl PartialEq for ParentMapper {
+    fn eq(&self, other: &ParentMapper) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Configuration for electing a single leader among multiple replicas of an operator, using a `Lease` in
+/// the `coordination.k8s.io/v1` api group. Only the replica that holds the lease will reconcile parents. The
+/// defaults for the timing parameters are the same as the ones used by client-go.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderElectionConfig {
+    /// The name of the `Lease` resource
+    pub lease_name: String,
+    /// The namespace of the `Lease` resource. If this is empty, then the lease is in the namespace that the operator
+    /// itself is running in, as returned by `OperatorConfig::operator_namespace`.
+    #[serde(default)]
+    pub lease_namespace: String,
+    /// Uniquely identifies this replica as the holder of the lease. Defaults to the value of the `HOSTNAME`
+    /// environment variable, which is the pod name when running in Kubernetes.
+    #[serde(default = "default_identity")]
+    pub identity: String,
+    /// How long other replicas will wait after the last observed renewal before trying to take over the lease
+    #[serde(
+        with = "serialization::duration",
+        default = "LeaderElectionConfig::default_lease_duration"
+    )]
+    pub lease_duration: Duration,
+    /// How long the leader will keep trying to renew the lease before giving up leadership
+    #[serde(
+        with = "serialization::duration",
+        default = "LeaderElectionConfig::default_renew_deadline"
+    )]
+    pub renew_deadline: Duration,
+    /// How long to wait between attempts to acquire or renew the lease
+    #[serde(
+        with = "serialization::duration",
+        default = "LeaderElectionConfig::default_retry_period"
+    )]
+    pub retry_period: Duration,
+}
+
+/// Returns the first non-empty namespace from the service account's namespace file, the value of the environment
+/// variable, and the explicitly configured namespace, in that order
+fn detect_operator_namespace(
+    namespace_file: &Path,
+    env_value: Option<String>,
+    explicit: Option<&str>,
+) -> Option<String> {
+    std::fs::read_to_string(namespace_file)
+        .ok()
+        .into_iter()
+        .chain(env_value)
+        .chain(explicit.map(str::to_owned))
+        .map(|namespace| namespace.trim().to_owned())
+        .find(|namespace| !namespace.is_empty())
+}
+
+fn default_identity() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| format!("roperator-{}", std::process::id()))
+}
+
+impl LeaderElectionConfig {
+    pub fn new(
+        lease_name: impl Into<String>,
+        lease_namespace: impl Into<String>,
+    ) -> LeaderElectionConfig {
+        LeaderElectionConfig {
+            lease_name: lease_name.into(),
+            lease_namespace: lease_namespace.into(),
+            identity: default_identity(),
+            lease_duration: LeaderElectionConfig::default_lease_duration(),
+            renew_deadline: LeaderElectionConfig::default_renew_deadline(),
+            retry_period: LeaderElectionConfig::default_retry_period(),
+        }
+    }
+
+    /// Returns the config for a lease with the given name, in the namespace that the operator itself is running in
+    pub fn in_operator_namespace(lease_name: impl Into<String>) -> LeaderElectionConfig {
+        LeaderElectionConfig::new(lease_name, "")
+    }
+
+    fn default_lease_duration() -> Duration {
+        Duration::from_secs(15)
+    }
+
+    fn default_renew_deadline() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn default_retry_period() -> Duration {
+        Duration::from_secs(2)
+    }
+
+    /// Sets the identity that will be recorded as the `holderIdentity` of the lease
+    pub fn identity(mut self, identity: impl Into<String>) -> Self {
+        self.identity = identity.into();
+        self
+    }
+
+    /// Sets the `lease_duration`, which defaults to 15 seconds
+    pub fn lease_duration(mut self, lease_duration: Duration) -> Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+
+    /// Sets the `renew_deadline`, which defaults to 10 seconds. This must be less than the `lease_duration`
+    pub fn renew_deadline(mut self, renew_deadline: Duration) -> Self {
+        self.renew_deadline = renew_deadline;
+        self
+    }
+
+    /// Sets the `retry_period`, which defaults to 2 seconds. This must be less than the `renew_deadline`
+    pub fn retry_period(mut self, retry_period: Duration) -> Self {
+        self.retry_period = retry_period;
+        self
+    }
 }
 
 /// This is the main configuration of your operator. It is where you'll specify the type of your
 /// parent and child resources, among other things. `OperatorConfig::new()` returns sensible
 /// defaults for everything except for the child types.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self", rename_all = "camelCase")]
 pub struct OperatorConfig {
     /// The type of the parent resource. This should match the type information from the CRD
     pub parent: &'static K8sType,
-    /// The type of each child resource that the operator will deal with.
-    pub child_types: HashMap<&'static K8sType, ChildConfig>,
-    /// Optional namespace to constrain the operator to. If None, then the operator will monitor
+    /// The declarations of each type of child resource that the operator will deal with. There's at most one
+    /// declaration for each type.
+    #[serde(default)]
+    pub child_types: Vec<ChildDeclaration>,
+    /// Extra types that are watched and cached, but not managed by the operator. Resources of these types are never
+    /// treated as children, and changes to them don't trigger a sync unless there's a mapper for the type in
+    /// `watched_type_mappers`, but they can be looked up from the handler using `SyncRequest::cache()`. They're watched
+    /// within the same `namespaces` as the parent.
+    #[serde(default)]
+    pub watched_types: Vec<&'static K8sType>,
+    /// Mappers for some of the `watched_types`. Whenever a resource of one of these types is created, updated, or
+    /// deleted, each of the parents that the mapper returns is synced. Mappers can't be serialized, so they're
+    /// skipped when the config is loaded from a file.
+    #[serde(skip)]
+    pub watched_type_mappers: HashMap<&'static K8sType, ParentMapper>,
+    /// Optional namespaces to constrain the operator to. If None, then the operator will monitor
     /// and act on any instance of the parent resource in any namespace. If Some, then the operator
-    /// will only ever watch and modify resources in the given namespace.
-    pub namespace: Option<String>,
+    /// will only ever watch and modify resources in the given namespaces, using a separate watch for
+    /// each one.
+    #[serde(default)]
+    pub namespaces: Option<Vec<String>>,
+
+    /// Optional label selector for `Namespace`s, which restricts the operator to the parents in namespaces with
+    /// matching labels. Unlike `namespaces`, the set of namespaces changes as namespaces are created, deleted, or
+    /// relabeled. The parents and children are still watched across the whole cluster, and only the syncs are
+    /// restricted, so this can't be combined with `namespaces`. Cluster scoped parents, including `Namespace`s
+    /// themselves, are always synced.
+    #[serde(default)]
+    pub namespace_selector: Option<String>,
+
+    /// Optional label selector to restrict which parent resources the operator will watch. If `Some`,
+    /// then the selector is sent as the `labelSelector` for both the initial list and all subsequent
+    /// watches of the parent type, so parents that don't match will never be synced. This does **not**
+    /// affect the watches of child resources, which are always selected using the `tracking_label_name`.
+    #[serde(default)]
+    pub label_selector: Option<String>,
+
+    /// Optional field selector to restrict which parent resources the operator will watch, e.g.
+    /// `"spec.nodeName=node-1"`. Like the `label_selector`, this is sent with the initial list and all watches
+    /// of the parent type, and does not affect child resources. Only the fields that the api server supports
+    /// for the parent type may be used.
+    #[serde(default)]
+    pub field_selector: Option<String>,
 
     /// The name of the operator, which must consist of only ascii alphabetic characters and numerals.
     /// This value will be used to add a label to every child resource being managed by this operator,
@@ -91,26 +726,235 @@ pub struct OperatorConfig {
     pub operator_name: String,
     /// The name of the label to use for tracking the relationships between parent and child resources.
     /// Roperator will add the `metadata.uid` of the parent resource to the labels of each child resource it manages.
+    #[serde(default = "OperatorConfig::default_tracking_label_name")]
     pub tracking_label_name: String,
 
+    /// If `true`, then children are tracked using an `ownerReference` to the parent, with `controller: true` and
+    /// `blockOwnerDeletion: true`, instead of the `tracking_label_name` label. Neither the tracking label nor the
+    /// ownership label are added to children in this mode, so it works for children that can't have arbitrary labels.
+    /// Since the api server can't select resources by owner, every resource of each child type is watched, and the
+    /// children of each parent are found using the uid in their owner reference. This can't be used together with a
+    /// custom `tracking_label_name`.
+    #[serde(default)]
+    pub track_via_owner_references: bool,
+
     /// The label to use for marking the `operator_name`. Defaults to `"kubernetes.io/managed-by"`
+    #[serde(default = "OperatorConfig::default_ownership_label_name")]
     pub ownership_label_name: String,
 
-    /// The HTTP port to listen on for exposing health checks and metrics. No server will be started
-    /// if both `expose_metrics` and `expose_health` are `false`
+    /// Extra labels, like a cost center or team, that are added to every child that the operator creates or
+    /// updates. They replace any label of the same name that's returned by the handler. Since they're part of the
+    /// desired state of each child, they're added back if someone else removes them. They can't use the
+    /// `tracking_label_name` or `ownership_label_name`, since those are how children are associated with parents.
+    #[serde(default)]
+    pub managed_labels: HashMap<String, String>,
+
+    /// Extra annotations that are added to every child that the operator creates or updates, the same as the
+    /// `managed_labels`
+    #[serde(default)]
+    pub managed_annotations: HashMap<String, String>,
+
+    /// The names of labels that are copied from the parent to every child that the operator creates or updates, such
+    /// as a team or cost center that's set on each parent. Labels that the parent doesn't have are skipped. They
+    /// replace any label of the same name that's returned by the handler, but the `managed_labels` and the tracking
+    /// labels are added afterwards, so they win over a propagated label with the same name.
+    #[serde(default)]
+    pub propagated_labels: Vec<String>,
+
+    /// The names of annotations that are copied from the parent to every child that the operator creates or updates,
+    /// the same as the `propagated_labels`
+    #[serde(default)]
+    pub propagated_annotations: Vec<String>,
+
+    /// If `Some`, then parents that have an annotation with this name and a value of `"true"` are paused. Neither
+    /// `Handler::sync` nor `Handler::finalize` is invoked for a paused parent, and its children are left as they are.
+    /// Instead, the `PAUSED_CONDITION` is set in its status, and it's set to `False` once the parent is unpaused.
+    /// Defaults to `None`, so parents are never paused.
+    #[serde(default)]
+    pub pause_annotation: Option<String>,
+
+    /// The finalizer that roperator adds to each parent on its first sync, and removes once `Handler::finalize`
+    /// reports that cleanup is complete. Defaults to the `operator_name`, so that parents that were created by
+    /// earlier versions of the operator will still be finalized.
+    #[serde(default)]
+    pub finalizer_name: String,
+
+    /// Whether the parent CRD enables the `/status` subresource. If `true` (the default), then the parent status is
+    /// updated using `PUT .../status`, and `status.observedGeneration` is set to the `metadata.generation` that was
+    /// synced. If `false`, then the status is updated by replacing the whole parent, and `observedGeneration` is not
+    /// set, since every status change would increment the generation.
+    #[serde(default = "default_true")]
+    pub status_subresource: bool,
+
+    /// The HTTP port to listen on for exposing health checks, metrics, and admin endpoints. No server will be started
+    /// if `expose_metrics`, `expose_health`, and `admin_endpoints` are all `false`
+    #[serde(default = "OperatorConfig::default_server_port")]
     pub server_port: u16,
 
+    /// The HTTP port to listen on for exposing metrics, if it should be different from the `server_port`
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+
+    /// The HTTP port to listen on for exposing health checks, if it should be different from the `server_port`
+    #[serde(default)]
+    pub health_port: Option<u16>,
+
     //// If true, then prometheus metrics will be exposed by HTTP at `/metrics`. This is enabled by default
     /// when you use `OperatorConfig::new()`
+    #[serde(default = "default_true")]
     pub expose_metrics: bool,
 
-    //// If true, then a health check will be exposed by HTTP at `/health`. This is enabled by default
-    /// when you use `OperatorConfig::new()`
+    //// If true, then a health check will be exposed by HTTP at `/health`, along with separate liveness and readiness
+    /// checks at `/healthz` and `/readyz`. This is enabled by default when you use `OperatorConfig::new()`
+    #[serde(default = "default_true")]
     pub expose_health: bool,
 
-    //// This is used to space out the time between `Handler::sync()` calls on the same parent resource in a uniform way. If `None`, no exponential backoff is performed.
-    /// maximum period between requested resyncs
-    pub max_error_backoff: Duration,
+    /// If true, then the `server_port` also exposes admin endpoints that queue parents to be synced: `POST /reconcile`
+    /// for all parents, and `POST /reconcile/{namespace}/{name}` (or `/reconcile/{name}` for cluster scoped parents)
+    /// for a single one. These are not authenticated, so this is disabled by default.
+    #[serde(default)]
+    pub admin_endpoints: bool,
+
+    /// How long any watch may go without hearing from the api server before `/readyz` reports that the operator is
+    /// not ready. Each list, re-established watch, event, and bookmark counts. The api server sends bookmarks about
+    /// once a minute, and watches are re-established at least once per `ClientConfig::watch_timeout`, so this should
+    /// be comfortably longer than both. Defaults to `DEFAULT_WATCH_STALENESS_THRESHOLD`.
+    #[serde(
+        with = "serialization::duration",
+        default = "OperatorConfig::default_watch_staleness_threshold"
+    )]
+    pub watch_staleness_threshold: Duration,
+
+    /// Controls how long to wait before re-syncing a parent after a sync fails with a retryable error. The same
+    /// backoff is applied to parents whose `finalize` isn't done yet.
+    #[serde(default)]
+    pub reconcile_backoff: ReconcileBackoff,
+
+    /// The maximum number of parents that may be synced (or finalized) at the same time. If `None` (the default), then
+    /// there's no limit. Regardless of this setting, the same parent is never synced concurrently. Any changes that
+    /// happen while a parent is being synced are coalesced into a single sync that starts once the current one is done.
+    #[serde(default)]
+    pub max_concurrent_reconciles: Option<usize>,
+
+    /// The maximum number of watch events that may be waiting to be processed by the operator, across all of the
+    /// watches. Once it's full, each watch stops reading from the api server until there's room again, which slows
+    /// the api server down through TCP flow control, so a flood of events can't use an unbounded amount of memory.
+    /// Events are never dropped, since that would leave the cache out of date. A value of 0 is treated as 1.
+    /// Defaults to `DEFAULT_EVENT_BUFFER_SIZE`.
+    #[serde(default = "OperatorConfig::default_event_buffer_size")]
+    pub event_buffer_size: usize,
+
+    /// The maximum amount of time to wait for the handler's `sync` to return. If it takes longer, then the sync fails
+    /// and is retried after the normal error backoff, and the request's `CancellationToken` is cancelled. The handler
+    /// can't be interrupted, so it keeps running in the background until it returns, but it no longer counts towards
+    /// the `max_concurrent_reconciles`. Since the parent is no longer considered in progress, it may be synced again,
+    /// and so `sync` may be invoked for the same parent while the abandoned call is still running. Handlers that
+    /// use this should either be safe to run concurrently for the same parent, or stop early once the request's
+    /// `cancellation()` is cancelled. Defaults to `None`, which waits indefinitely.
+    #[serde(with = "serialization::option_duration", default)]
+    pub handler_timeout: Option<Duration>,
+
+    /// If `Some`, then the handler stops being invoked after it fails repeatedly, until a probe succeeds. See
+    /// `CircuitBreakerConfig`. Defaults to `None`.
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+
+    /// If `false` (the default), then updates to children that only change their `status`, `metadata.managedFields`,
+    /// or `metadata.resourceVersion` don't trigger a sync of the parent. This greatly reduces the number of syncs for
+    /// children like Pods, whose status changes constantly. The latest version of each child is still passed to the
+    /// handler the next time the parent is synced for any other reason. Set this to `true` if the parent's status
+    /// is derived from the status of its children.
+    #[serde(default)]
+    pub reconcile_on_child_status_change: bool,
+
+    /// If `Some`, then changes to children won't trigger a sync of a parent until at least this long after its
+    /// previous sync started. Changes that happen in the meantime are coalesced into a single sync once the interval
+    /// has elapsed. Changes to the parent itself, and resyncs, are never delayed. Defaults to `None`.
+    #[serde(with = "serialization::option_duration", default)]
+    pub child_event_rate_limit: Option<Duration>,
+
+    /// How long to wait after an event for the parent or one of its children before syncing the parent, so that
+    /// related events that arrive on separate watches, such as when a parent and several of its children are updated
+    /// together, are coalesced into a single sync. The wait starts with the first event, so a steady stream of events
+    /// can't postpone the sync indefinitely. Resyncs and requested syncs are never delayed. Defaults to zero, which
+    /// syncs as soon as possible.
+    #[serde(with = "serialization::duration", default)]
+    pub reconcile_debounce: Duration,
+
+    /// How long to wait for in-progress syncs to finish once the operator has been asked to shut down, either by a
+    /// `SIGTERM` or `SIGINT` or by an `OperatorHandle`. No new syncs are started during this time. Syncs that are still
+    /// running once it elapses are abandoned. Defaults to `DEFAULT_SHUTDOWN_GRACE_PERIOD`.
+    #[serde(
+        with = "serialization::duration",
+        default = "OperatorConfig::default_shutdown_grace_period"
+    )]
+    pub shutdown_grace_period: Duration,
+
+    /// If `Some`, then every parent will be re-synced at this interval, even if nothing has changed. This is a
+    /// safety net for recovering from missed watch events, or from drift in external systems that can't be watched.
+    /// The resyncs are spread out over the interval, so they don't all happen at once. If `None`, then parents are
+    /// only synced in response to changes (or a `resync` from the `SyncResponse`).
+    #[serde(with = "serialization::option_duration", default)]
+    pub resync_interval: Option<Duration>,
+
+    /// If `Some`, then only one replica of the operator will reconcile parents at a time. Replicas that don't hold the
+    /// lease will wait until they're able to acquire it. If the leader fails to renew the lease, then it stops
+    /// reconciling and the operator shuts down, so that the container can be restarted.
+    #[serde(default)]
+    pub leader_election: Option<LeaderElectionConfig>,
+
+    /// The namespace that the operator itself is running in, which is only used if it can't be detected. See
+    /// `operator_namespace()`. Defaults to `None`.
+    #[serde(default)]
+    pub operator_namespace: Option<String>,
+
+    /// If `true`, then every request that would create, update, or delete a resource is sent with `dryRun=All`, so
+    /// that the api server validates it without persisting any changes. The intended changes are logged at info
+    /// level. This is useful for seeing what an operator would do before letting it loose on a real cluster.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// If `true` (the default), then Kubernetes `Event`s (`events.k8s.io/v1`) are recorded for the parent whenever
+    /// a child is created, updated, or deleted, and whenever a sync or finalize fails. Repeats of the same event are
+    /// aggregated into a single `Event` with an incrementing `series.count`. The operator's service account must be
+    /// allowed to create and patch `events` in the `events.k8s.io` api group.
+    #[serde(default = "default_true")]
+    pub emit_events: bool,
+
+    /// If `true`, then the operator checks that it has every RBAC permission it needs before it starts, using a
+    /// `SelfSubjectAccessReview` for each verb on the parent, child, and watched types (along with `events`, `leases`,
+    /// and `namespaces` when they're used), in each of the `namespaces`. If any are missing, then the operator refuses
+    /// to start, and logs exactly which permissions are missing. Defaults to `false`.
+    #[serde(default)]
+    pub preflight_rbac_check: bool,
+
+    /// If `Some`, then validating and mutating admission webhooks are served over HTTPS alongside the operator. The
+    /// webhook server runs on every replica, even if it isn't the leader, since the api server may call any of them.
+    #[serde(skip)]
+    pub webhook: Option<WebhookConfig>,
+}
+
+/// Configs are serialized with camelCase field names, and durations like `30s`. The `webhook` is never serialized,
+/// since its validators and mutators are code.
+impl serde::Serialize for OperatorConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OperatorConfig::serialize(self, serializer)
+    }
+}
+
+/// Every field except for the `operatorName` and `parent` is optional, and has the same default as in
+/// `OperatorConfig::new`. The `parent` and other types are written the same as the alternate `Display` form of a
+/// `K8sType`, like `apps/v1/Deployment/deployments`.
+impl<'de> serde::Deserialize<'de> for OperatorConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OperatorConfig, D::Error> {
+        let mut config = OperatorConfig::deserialize(deserializer)?;
+        if config.finalizer_name.is_empty() {
+            config.finalizer_name = config.operator_name.clone();
+        }
+        Ok(config)
+    }
 }
 
 impl OperatorConfig {
@@ -118,90 +962,752 @@ impl OperatorConfig {
         let operator_name = operator_name.into();
         OperatorConfig {
             parent,
+            finalizer_name: operator_name.clone(),
+            status_subresource: true,
             operator_name,
-            child_types: HashMap::new(),
-            namespace: None,
-            tracking_label_name: DEFAULT_TRACKING_LABEL_NAME.to_owned(),
-            ownership_label_name: DEFAULT_OWNERSHIP_LABEL_NAME.to_owned(),
-            server_port: 8080,
+            child_types: Vec::new(),
+            watched_types: Vec::new(),
+            watched_type_mappers: HashMap::new(),
+            namespaces: None,
+            namespace_selector: None,
+            label_selector: None,
+            field_selector: None,
+            tracking_label_name: OperatorConfig::default_tracking_label_name(),
+            track_via_owner_references: false,
+            ownership_label_name: OperatorConfig::default_ownership_label_name(),
+            managed_labels: HashMap::new(),
+            managed_annotations: HashMap::new(),
+            propagated_labels: Vec::new(),
+            propagated_annotations: Vec::new(),
+            pause_annotation: None,
+            server_port: OperatorConfig::default_server_port(),
+            metrics_port: None,
+            health_port: None,
             expose_metrics: true,
             expose_health: true,
-            max_error_backoff: Duration::from_secs(600),
+            admin_endpoints: false,
+            watch_staleness_threshold: OperatorConfig::default_watch_staleness_threshold(),
+            reconcile_backoff: ReconcileBackoff::default(),
+            max_concurrent_reconciles: None,
+            event_buffer_size: OperatorConfig::default_event_buffer_size(),
+            handler_timeout: None,
+            circuit_breaker: None,
+            reconcile_on_child_status_change: false,
+            child_event_rate_limit: None,
+            reconcile_debounce: Duration::from_millis(0),
+            shutdown_grace_period: OperatorConfig::default_shutdown_grace_period(),
+            resync_interval: None,
+            leader_election: None,
+            operator_namespace: None,
+            dry_run: false,
+            emit_events: true,
+            preflight_rbac_check: false,
+            webhook: None,
         }
     }
 
+    /// Loads the config from a yaml or json file. Every field except for `operatorName` and `parent` is optional,
+    /// and the defaults are the same as for `OperatorConfig::new`, so a minimal config looks like:
+    ///
+    /// ```yaml
+    /// operatorName: my-operator
+    /// parent: example.com/v1/MyResource/myresources
+    /// childTypes:
+    ///   - k8sType: apps/v1/Deployment/deployments
+    ///     config:
+    ///       updateStrategy: Patch
+    /// resyncInterval: 10m
+    /// ```
+    ///
+    /// Types that aren't built in are leaked the first time that they're loaded. The config isn't validated
+    /// when it's loaded, so mistakes are only reported once the operator starts, or when `validate` is called.
+    pub fn from_yaml_file(path: impl AsRef<Path>) -> Result<OperatorConfig, ConfigFileError> {
+        serialization::from_yaml_file(path.as_ref())
+    }
+
+    fn default_tracking_label_name() -> String {
+        DEFAULT_TRACKING_LABEL_NAME.to_owned()
+    }
+
+    fn default_ownership_label_name() -> String {
+        DEFAULT_OWNERSHIP_LABEL_NAME.to_owned()
+    }
+
+    fn default_server_port() -> u16 {
+        8080
+    }
+
+    fn default_watch_staleness_threshold() -> Duration {
+        DEFAULT_WATCH_STALENESS_THRESHOLD
+    }
+
+    fn default_shutdown_grace_period() -> Duration {
+        DEFAULT_SHUTDOWN_GRACE_PERIOD
+    }
+
+    fn default_event_buffer_size() -> usize {
+        DEFAULT_EVENT_BUFFER_SIZE
+    }
+
     /// Set the namespace for this operator. If set, then the operator will only ever watch or manage
     /// resources within the given namespace
-    pub fn within_namespace(mut self, namespace: impl Into<String>) -> Self {
-        self.namespace = Some(namespace.into());
+    pub fn within_namespace(self, namespace: impl Into<String>) -> Self {
+        self.within_namespaces(std::iter::once(namespace.into()))
+    }
+
+    /// Set multiple namespaces for this operator. If set, then the operator will only ever watch or manage
+    /// resources within the given namespaces. This is useful when the operator's RBAC permissions only
+    /// extend to a specific set of namespaces, since cluster-wide watches would be forbidden.
+    ///
+    /// If the parent type is cluster scoped, then the namespaces only apply to the namespaced child and watched types,
+    /// and parents are always watched across the whole cluster. The namespaces must not be empty, which `validate`
+    /// reports as a `ConfigError::EmptyNamespaces`.
+    pub fn within_namespaces(mut self, namespaces: impl IntoIterator<Item = String>) -> Self {
+        self.namespaces = Some(namespaces.into_iter().collect());
+        self
+    }
+
+    /// Restricts the operator to the parents in namespaces whose labels match the selector, e.g.
+    /// `"example.com/tenant=true"`. Namespaces are watched, so parents are synced as soon as their namespace starts
+    /// to match, and parents in namespaces that stop matching are left alone, along with their children. Parents
+    /// that are being deleted are always finalized, though, so that they aren't stuck with the finalizer. The
+    /// operator needs permission to list and watch `namespaces`.
+    pub fn namespaces_matching(mut self, selector: impl Into<String>) -> Self {
+        self.namespace_selector = Some(selector.into());
+        self
+    }
+
+    /// Sets a label selector to restrict which parent resources will be watched by the operator. The selector
+    /// uses the usual Kubernetes syntax (e.g. `"team=storage,tier!=test"`), and is validated when the
+    /// operator starts.
+    pub fn with_label_selector(mut self, selector: impl Into<String>) -> Self {
+        self.label_selector = Some(selector.into());
+        self
+    }
+
+    /// Sets a field selector to restrict which parent resources will be watched by the operator. The syntax is
+    /// validated when the operator starts, and the selector is also checked against the api server, since each
+    /// resource type only supports selecting on a few fields.
+    pub fn with_field_selector(mut self, selector: impl Into<String>) -> Self {
+        self.field_selector = Some(selector.into());
         self
     }
 
     /// Adds a new child type to this configuration. Every type of resource that the operator may manage
     /// must be included in the `OperatorConfig`.
-    pub fn with_child(mut self, child_type: &'static K8sType, config: ChildConfig) -> Self {
-        self.child_types.insert(child_type, config);
+    pub fn with_child(self, child_type: &'static K8sType, config: ChildConfig) -> Self {
+        self.with_child_declaration(ChildDeclaration::new(child_type, config))
+    }
+
+    /// Adds a new child type to this configuration, after looking up its plural name and scope from the api server's
+    /// discovery endpoint using `K8sType::from_api_version_kind`. This saves having to declare a `K8sType` for types
+    /// that roperator doesn't already define, and an incorrect `plural_kind` would otherwise only show up as 404s once
+    /// the operator is running. It blocks until discovery completes, so it must be called before the operator is
+    /// started, and not from within an async context.
+    ///
+    /// ```no_run
+    /// use roperator::config::{ChildConfig, ClientConfig, OperatorConfig, UpdateStrategy};
+    /// use roperator::k8s_types::{K8sType, Scope};
+    ///
+    /// static PARENT: &K8sType = &K8sType {
+    ///     api_version: "example.com/v1",
+    ///     kind: "Foo",
+    ///     plural_kind: "foos",
+    ///     scope: Scope::Namespaced,
+    /// };
+    ///
+    /// let client_config = ClientConfig::from_kubeconfig("my-operator").unwrap();
+    /// let operator_config = OperatorConfig::new("my-operator", PARENT)
+    ///     .with_child_kind(&client_config, "networking.k8s.io/v1", "Ingress", ChildConfig::new(UpdateStrategy::Replace))
+    ///     .expect("failed to discover Ingress");
+    /// ```
+    pub fn with_child_kind(
+        self,
+        client_config: &ClientConfig,
+        api_version: &str,
+        kind: &str,
+        config: ChildConfig,
+    ) -> Result<Self, anyhow::Error> {
+        let child_type = K8sType::from_api_version_kind(client_config, api_version, kind)?;
+        Ok(self.with_child(child_type, config))
+    }
+
+    /// Adds a new child type to this configuration, with its own discovery settings. This replaces any existing
+    /// declaration of the same type.
+    pub fn with_child_declaration(mut self, declaration: ChildDeclaration) -> Self {
+        match self
+            .child_types
+            .iter_mut()
+            .find(|existing| existing.k8s_type == declaration.k8s_type)
+        {
+            Some(existing) => *existing = declaration,
+            None => self.child_types.push(declaration),
+        }
+        self
+    }
+
+    /// Returns the declaration of the given child type, if it's one of the child types
+    pub fn child_declaration(&self, k8s_type: &K8sType) -> Option<&ChildDeclaration> {
+        self.child_types
+            .iter()
+            .find(|declaration| declaration.k8s_type == k8s_type)
+    }
+
+    /// Adds a type to be watched and cached without being managed by the operator, so that resources of that type
+    /// (e.g. `Secret`s that are referenced by the parent) can be looked up from the handler using
+    /// `SyncRequest::cache()` instead of making a request to the api server. Adding the parent type or one of the
+    /// child types has no effect, since those are always cached.
+    pub fn with_watched_type(mut self, k8s_type: &'static K8sType) -> Self {
+        if !self.watched_types.contains(&k8s_type) {
+            self.watched_types.push(k8s_type);
+        }
+        self
+    }
+
+    /// Adds a watched type, the same as `with_watched_type`, and syncs each of the parents that the `mapper` returns
+    /// whenever a resource of that type is created, updated, or deleted. This is for resources that parents refer to
+    /// without owning them, like a `Secret` that's named in the parent's spec, which aren't associated with their
+    /// parents automatically. The mapper is called with the new state of the resource, or its last known state if
+    /// it was deleted. Registering a mapper for the parent type or one of the child types fails validation.
+    ///
+    /// ```rust
+    /// use roperator::prelude::*;
+    /// use roperator::k8s_types::core::v1::Secret;
+    /// use roperator::resource::ObjectId;
+    /// # static PARENT_TYPE: &K8sType = &K8sType {
+    /// #     api_version: "example.com/v1",
+    /// #     kind: "Database",
+    /// #     plural_kind: "databases",
+    /// #     scope: Scope::Namespaced,
+    /// # };
+    /// // sync the database that's named by the `example.com/database` label of each secret
+    /// let config = OperatorConfig::new("database-operator", PARENT_TYPE).with_mapped_watched_type(
+    ///     Secret,
+    ///     |secret: &K8sResource| {
+    ///         let id = secret.get_object_id();
+    ///         secret
+    ///             .pointer("/metadata/labels/example.com~1database")
+    ///             .and_then(|name| name.as_str())
+    ///             .map(|name| ObjectId::new(id.namespace().unwrap_or("").to_owned(), name.to_owned()))
+    ///             .into_iter()
+    ///             .collect()
+    ///     },
+    /// );
+    /// ```
+    pub fn with_mapped_watched_type<F>(mut self, k8s_type: &'static K8sType, mapper: F) -> Self
+    where
+        F: Fn(&K8sResource) -> Vec<ObjectId> + Send + Sync + 'static,
+    {
+        self = self.with_watched_type(k8s_type);
+        self.watched_type_mappers
+            .insert(k8s_type, ParentMapper::new(mapper));
+        self
+    }
+
+    /// Sets whether to track children using owner references instead of the `tracking_label_name` label
+    pub fn track_via_owner_references(mut self, track_via_owner_references: bool) -> Self {
+        self.track_via_owner_references = track_via_owner_references;
         self
     }
 
-    /// Sets whether to expose a health check HTTP endpoint
+    /// Adds labels to every child that the operator creates or updates, replacing any previous managed labels with
+    /// the same names
+    ///
+    /// ```rust
+    /// use roperator::config::OperatorConfig;
+    /// use roperator::k8s_types::apps::v1::Deployment;
+    ///
+    /// let config = OperatorConfig::new("my-operator", Deployment).with_managed_labels(vec![
+    ///     ("example.com/cost-center".to_owned(), "1234".to_owned()),
+    ///     ("example.com/team".to_owned(), "platform".to_owned()),
+    /// ]);
+    /// assert_eq!(2, config.managed_labels.len());
+    /// ```
+    pub fn with_managed_labels(
+        mut self,
+        labels: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        self.managed_labels.extend(labels);
+        self
+    }
+
+    /// Adds annotations to every child that the operator creates or updates, replacing any previous managed
+    /// annotations with the same names
+    pub fn with_managed_annotations(
+        mut self,
+        annotations: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        self.managed_annotations.extend(annotations);
+        self
+    }
+
+    /// Copies the labels with the given names from the parent to every child that the operator creates or updates.
+    /// Labels that the parent doesn't have are skipped.
+    ///
+    /// ```rust
+    /// use roperator::config::OperatorConfig;
+    /// use roperator::k8s_types::apps::v1::Deployment;
+    ///
+    /// let config = OperatorConfig::new("my-operator", Deployment)
+    ///     .propagate_labels(vec!["example.com/team".to_owned()]);
+    /// assert_eq!(vec!["example.com/team".to_owned()], config.propagated_labels);
+    /// ```
+    pub fn propagate_labels(mut self, names: Vec<String>) -> Self {
+        self.propagated_labels.extend(names);
+        self
+    }
+
+    /// Copies the annotations with the given names from the parent to every child that the operator creates or
+    /// updates. Annotations that the parent doesn't have are skipped.
+    pub fn propagate_annotations(mut self, names: Vec<String>) -> Self {
+        self.propagated_annotations.extend(names);
+        self
+    }
+
+    /// Pauses the reconciliation of any parent that has an annotation with the given name and a value of `"true"`, so
+    /// that it can be changed by hand without the operator reverting it. Removing the annotation, or setting it to
+    /// anything else, resumes reconciliation.
+    ///
+    /// ```rust
+    /// use roperator::config::OperatorConfig;
+    /// use roperator::k8s_types::apps::v1::Deployment;
+    ///
+    /// let config = OperatorConfig::new("my-operator", Deployment).pause_annotation("example.com/paused");
+    /// assert_eq!(Some("example.com/paused"), config.pause_annotation.as_deref());
+    /// ```
+    pub fn pause_annotation(mut self, annotation: impl Into<String>) -> Self {
+        self.pause_annotation = Some(annotation.into());
+        self
+    }
+
+    /// Sets whether to expose the health check HTTP endpoints
     pub fn expose_health(mut self, expose_health: bool) -> Self {
         self.expose_health = expose_health;
         self
     }
 
+    /// Sets how long any watch may go without hearing from the api server before the operator is reported as not ready
+    pub fn watch_staleness_threshold(mut self, watch_staleness_threshold: Duration) -> Self {
+        self.watch_staleness_threshold = watch_staleness_threshold;
+        self
+    }
+
     /// Sets whether to expose prometheus metrics over HTTP
     pub fn expose_metrics(mut self, expose_metrics: bool) -> Self {
         self.expose_metrics = expose_metrics;
         self
     }
 
-    /// Sets the port to listen on for HTTP. This will be ignored if both `expose_metrics` and `expose_health` are `false`
+    /// Sets whether to expose the admin endpoints for triggering syncs over HTTP on the `server_port`. Anyone who can
+    /// reach the port will be able to use them, so make sure that it's not exposed outside of the cluster.
+    pub fn enable_admin_endpoints(mut self, enable: bool) -> Self {
+        self.admin_endpoints = enable;
+        self
+    }
+
+    /// Sets the port to listen on for HTTP. This will be ignored if `expose_metrics`, `expose_health`, and
+    /// `admin_endpoints` are all `false`
     pub fn server_port(mut self, port: u16) -> Self {
         self.server_port = port;
         self
     }
 
+    /// Sets the port to serve metrics on, instead of the `server_port`, for example to keep metrics on a port that's
+    /// only reachable from within the cluster
+    pub fn metrics_port(mut self, port: u16) -> Self {
+        self.metrics_port = Some(port);
+        self
+    }
+
+    /// Sets the port to serve health checks on, instead of the `server_port`
+    pub fn health_port(mut self, port: u16) -> Self {
+        self.health_port = Some(port);
+        self
+    }
+
+    /// Returns the port that metrics are served on, which is the `metrics_port` if it's set, or else the
+    /// `server_port`
+    pub fn metrics_server_port(&self) -> u16 {
+        self.metrics_port.unwrap_or(self.server_port)
+    }
+
+    /// Returns the port that health checks are served on, which is the `health_port` if it's set, or else the
+    /// `server_port`
+    pub fn health_server_port(&self) -> u16 {
+        self.health_port.unwrap_or(self.server_port)
+    }
+
+    /// Sets the name of the finalizer that's added to each parent. Kubernetes recommends that finalizer names
+    /// are qualified with a domain, e.g. `"example.com/my-operator"`. Note that parents that still have the
+    /// previous finalizer will need to have it removed manually.
+    pub fn with_finalizer_name(mut self, finalizer_name: impl Into<String>) -> Self {
+        self.finalizer_name = finalizer_name.into();
+        self
+    }
+
+    /// Sets whether the parent CRD enables the `/status` subresource. This is `true` by default, and should only
+    /// be set to `false` for CRDs that don't have `subresources.status` in their spec.
+    pub fn with_status_subresource(mut self, status_subresource: bool) -> Self {
+        self.status_subresource = status_subresource;
+        self
+    }
+
+    /// Sets the maximum delay between syncs of a parent that keeps failing, leaving the rest of the
+    /// `reconcile_backoff` unchanged
     pub fn max_error_backoff(mut self, max_error_backoff: Duration) -> Self {
-        self.max_error_backoff = max_error_backoff;
+        self.reconcile_backoff.max_delay = max_error_backoff;
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for the handler's `sync` to return before failing the sync. The
+    /// abandoned call keeps running, and may overlap with later syncs of the same parent.
+    pub fn handler_timeout(mut self, handler_timeout: Duration) -> Self {
+        self.handler_timeout = Some(handler_timeout);
+        self
+    }
+
+    /// Stops invoking the handler after it fails repeatedly, until a probe succeeds
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Sets the backoff that's used to delay re-syncing parents after their syncs fail
+    pub fn with_reconcile_backoff(mut self, reconcile_backoff: ReconcileBackoff) -> Self {
+        self.reconcile_backoff = reconcile_backoff;
+        self
+    }
+
+    /// Limits the number of parents that may be synced at the same time, which is useful if the handler makes
+    /// expensive requests to external systems. A limit of 0 is treated as 1.
+    pub fn max_concurrent_reconciles(mut self, max_concurrent_reconciles: usize) -> Self {
+        self.max_concurrent_reconciles = Some(max_concurrent_reconciles.max(1));
+        self
+    }
+
+    /// Sets the maximum number of watch events that may be waiting to be processed, after which the watches stop
+    /// reading from the api server until there's room. A size of 0 is treated as 1.
+    pub fn event_buffer_size(mut self, event_buffer_size: usize) -> Self {
+        self.event_buffer_size = event_buffer_size.max(1);
+        self
+    }
+
+    /// Sets whether updates to children that only change their status trigger a sync of the parent
+    pub fn reconcile_on_child_status_change(
+        mut self,
+        reconcile_on_child_status_change: bool,
+    ) -> Self {
+        self.reconcile_on_child_status_change = reconcile_on_child_status_change;
+        self
+    }
+
+    /// Sets the minimum amount of time between the start of a parent's sync and any sync that's triggered by changes
+    /// to its children
+    pub fn child_event_rate_limit(mut self, child_event_rate_limit: Duration) -> Self {
+        self.child_event_rate_limit = Some(child_event_rate_limit);
+        self
+    }
+
+    /// Sets how long to wait after an event before syncing the parent, in order to coalesce related events into a
+    /// single sync
+    pub fn reconcile_debounce(mut self, reconcile_debounce: Duration) -> Self {
+        self.reconcile_debounce = reconcile_debounce;
+        self
+    }
+
+    /// Sets how long to wait for in-progress syncs to finish when the operator is shut down
+    pub fn shutdown_grace_period(mut self, shutdown_grace_period: Duration) -> Self {
+        self.shutdown_grace_period = shutdown_grace_period;
+        self
+    }
+
+    /// Sets the interval at which every parent will be re-synced, regardless of whether anything has changed
+    pub fn with_resync_interval(mut self, resync_interval: Duration) -> Self {
+        self.resync_interval = Some(resync_interval);
+        self
+    }
+
+    /// Sets whether to run the operator in dry-run mode, where changes are validated by the api server but not persisted
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
         self
     }
+
+    /// Sets whether to record Kubernetes `Event`s for the outcomes of syncs. This is enabled by default
+    pub fn emit_events(mut self, emit_events: bool) -> Self {
+        self.emit_events = emit_events;
+        self
+    }
+
+    /// Sets whether to check that the operator has all of the RBAC permissions it needs before starting, so that
+    /// misconfigured permissions are reported right away instead of as failed watches or syncs
+    pub fn preflight_rbac_check(mut self, preflight_rbac_check: bool) -> Self {
+        self.preflight_rbac_check = preflight_rbac_check;
+        self
+    }
+
+    /// Serves admission webhooks using the given configuration
+    pub fn with_webhook(mut self, webhook: WebhookConfig) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Enables leader election using a `Lease` with the given name and namespace, and the default timings
+    pub fn with_leader_election(
+        self,
+        lease_name: impl Into<String>,
+        lease_namespace: impl Into<String>,
+    ) -> Self {
+        self.with_leader_election_config(LeaderElectionConfig::new(lease_name, lease_namespace))
+    }
+
+    /// Enables leader election using the given configuration
+    pub fn with_leader_election_config(mut self, leader_election: LeaderElectionConfig) -> Self {
+        self.leader_election = Some(leader_election);
+        self
+    }
+
+    /// Sets the namespace that the operator is running in, for when it can't be detected, such as when the operator is
+    /// run outside of the cluster using a kubeconfig
+    pub fn with_operator_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.operator_namespace = Some(namespace.into());
+        self
+    }
+
+    /// Returns the namespace that the operator itself is running in. This is read from the service account's
+    /// `namespace` file when running in a pod, or else from the `POD_NAMESPACE` environment variable, or else it's the
+    /// explicitly configured `operator_namespace`. Returns `None` if none of them are set, which is usually the case
+    /// when running outside of the cluster.
+    pub fn operator_namespace(&self) -> Option<String> {
+        detect_operator_namespace(
+            Path::new(SERVICE_ACCOUNT_NAMESPACE_PATH),
+            std::env::var(POD_NAMESPACE_ENV_VAR).ok(),
+            self.operator_namespace.as_deref(),
+        )
+    }
+
+    /// Returns the `leader_election` config with the `lease_namespace` filled in with the `operator_namespace()` if it
+    /// was left empty
+    pub(crate) fn resolved_leader_election(&self) -> Option<LeaderElectionConfig> {
+        let mut leader_election = self.leader_election.clone()?;
+        if leader_election.lease_namespace.is_empty() {
+            leader_election.lease_namespace = self.operator_namespace().unwrap_or_default();
+        }
+        Some(leader_election)
+    }
+
+    /// Checks the syntax of the `label_selector`, the `namespace_selector`, and the label selectors of the child
+    /// types, so that mistakes are reported on startup instead of as errors from the api server.
+    pub(crate) fn validate_label_selector(&self) -> Result<(), InvalidLabelSelector> {
+        let child_selectors = self
+            .child_types
+            .iter()
+            .filter_map(|child| child.label_selector.as_ref());
+        let selectors = self
+            .label_selector
+            .iter()
+            .chain(self.namespace_selector.iter())
+            .chain(child_selectors);
+        for selector in selectors {
+            self::selector::validate_label_selector(selector)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the child tracking options don't conflict, since children can only be tracked using either
+    /// a label or owner references
+    pub(crate) fn validate_child_tracking(&self) -> Result<(), ConflictingChildTracking> {
+        if self.track_via_owner_references
+            && self.tracking_label_name != DEFAULT_TRACKING_LABEL_NAME
+        {
+            Err(ConflictingChildTracking {
+                tracking_label_name: self.tracking_label_name.clone(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the syntax of the `field_selector`, if one is configured. Whether the field is actually supported
+    /// can only be checked by the api server.
+    pub(crate) fn validate_field_selector(&self) -> Result<(), InvalidFieldSelector> {
+        match self.field_selector.as_ref() {
+            Some(selector) => self::selector::validate_field_selector(selector),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error returned when the `OperatorConfig` enables `track_via_owner_references` and also sets a custom
+/// `tracking_label_name`, which would never be used
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictingChildTracking {
+    /// The `tracking_label_name` that was configured
+    pub tracking_label_name: String,
+}
+
+impl std::fmt::Display for ConflictingChildTracking {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Children cannot be tracked using both owner references and the tracking label: '{}'",
+            self.tracking_label_name
+        )
+    }
+}
+
+impl std::error::Error for ConflictingChildTracking {}
+
+/// Returns the proxy to use for the given api server endpoint from the usual environment variables. Both the
+/// upper and lower case variants are checked, since there's no real consensus on which one should be used.
+pub(crate) fn proxy_from_env(api_server_endpoint: &str) -> Option<String> {
+    let names: &[&str] = if api_server_endpoint.starts_with("http://") {
+        &["HTTP_PROXY", "http_proxy"]
+    } else {
+        &["HTTPS_PROXY", "https_proxy"]
+    };
+    names
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+/// Returns the entries from the `NO_PROXY` environment variable
+pub(crate) fn no_proxy_from_env() -> Vec<String> {
+    ["NO_PROXY", "no_proxy"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 /// Certificate Authority data for verifying Kubernetes TLS certificates. This typically comes from either a
 /// mounted service account Secret at `SERVICE_ACCOUNT_CA_PATH`, or else a "cluster" entry in a kubeconfig file.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum CAData {
     /// The path to a file on disk that contains the CA certificate
     File(String),
     /// The base64 encoded certificate from a kubeconfig file. This value is already base64 encoded in the
     /// kubeconfig file, so you don't need to modify that value at all.
     Contents(String),
+    /// Any number of CAs, which are all trusted. This is useful when connections may be terminated by a gateway
+    /// whose certificate is signed by a different CA than the api server's.
+    Bundle(Vec<CAData>),
 }
 
 /// Represents how to authenticate to the cluster. Roperator supports either using an Authorization header
 /// or a public/private key pair. The `Header` value can support either username/password or token based
 /// authentication.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// When deserializing, the `header`, `certificateBase64`, and `privateKeyBase64` may each be given inline, or
+/// else read from an environment variable using `{fromEnv: NAME}`, or from a file using `{fromFile: path}`.
+///
+/// The `Debug` output replaces the `Header` and `Pem` values and the values of exec `env` variables with `***`,
+/// so that configs can be logged without leaking secrets.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum Credentials {
     /// Represents the value to set for an Authorization header. This value must include the prefix (e.g. `Basic `
     /// or `Bearer `) as well as the properly encoded and formatted value.
-    Header(String),
+    Header(#[serde(with = "serialization::secret")] String),
 
     /// Values for authenticating using a certificate. This is frequently used in kubeconfig files
+    #[serde(rename_all = "camelCase")]
     Pem {
         /// The public certificate, which will be presented to the server during the TLS handshake. This value
         /// is already base64 encoded in the kubeconfig file, so if you take if from there, then no further
         /// modification is necessary
+        #[serde(with = "serialization::secret")]
         certificate_base64: String,
         /// The private key that corresponds to the certificate above
+        #[serde(with = "serialization::secret")]
         private_key_base64: String,
     },
 
+    /// Values for authenticating using a certificate that's read from PEM files, such as ones that are mounted from
+    /// a `Secret`. This is used for the `client-certificate` and `client-key` paths in kubeconfig files. Unlike
+    /// `Pem`, the contents of these files are _not_ base64 encoded. The files are read when the client is created,
+    /// so the operator must be restarted to pick up a rotated certificate.
+    #[serde(rename_all = "camelCase")]
     PemPath {
+        /// Path to the PEM encoded public certificate
         certificate_path: String,
+        /// Path to the PEM encoded private key that corresponds to the certificate
         private_key_path: String,
     },
+
+    /// Runs an external command (a "credential plugin", like `aws eks get-token` or `gke-gcloud-auth-plugin`)
+    /// to obtain a bearer token. The command must print an `ExecCredential` json object to stdout. The token is
+    /// cached until it's within 60 seconds of its `status.expirationTimestamp`, at which point the command will
+    /// be run again. Tokens without an expiration are cached indefinitely. This doesn't need a kubeconfig file, but
+    /// `exec` users from a kubeconfig are loaded as this variant too, with their `apiVersion` passed to the command
+    /// in the `KUBERNETES_EXEC_INFO` variable of `env`.
+    #[serde(rename_all = "camelCase")]
+    Exec {
+        /// The command to execute
+        command: String,
+        /// Arguments that are passed to the command
+        #[serde(default)]
+        args: Vec<String>,
+        /// Environment variables, as name/value pairs, to set for the command in addition to the ones
+        /// inherited from this process
+        #[serde(with = "serialization::env_vars", default)]
+        env: Vec<(String, String)>,
+    },
+
+    /// Reads a bearer token from the given file. The file is re-read periodically, so that tokens which are
+    /// rotated on disk (such as projected service account tokens) are picked up without restarting the
+    /// operator. If re-reading the file fails, the most recently read token will continue to be used.
+    TokenFile(PathBuf),
+}
+
+/// Stands in for a secret value in `Debug` output
+struct Redacted;
+
+impl std::fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("***")
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Credentials::Header(_) => f.debug_tuple("Header").field(&Redacted).finish(),
+            Credentials::Pem { .. } => f
+                .debug_struct("Pem")
+                .field("certificate_base64", &Redacted)
+                .field("private_key_base64", &Redacted)
+                .finish(),
+            Credentials::PemPath {
+                certificate_path,
+                private_key_path,
+            } => f
+                .debug_struct("PemPath")
+                .field("certificate_path", certificate_path)
+                .field("private_key_path", private_key_path)
+                .finish(),
+            Credentials::Exec { command, args, env } => {
+                let env: Vec<(&String, Redacted)> =
+                    env.iter().map(|(name, _)| (name, Redacted)).collect();
+                f.debug_struct("Exec")
+                    .field("command", command)
+                    .field("args", args)
+                    .field("env", &env)
+                    .finish()
+            }
+            Credentials::TokenFile(path) => f.debug_tuple("TokenFile").field(path).finish(),
+        }
+    }
 }
 
 impl Credentials {
@@ -216,6 +1722,32 @@ impl Credentials {
         Credentials::Header(format!("Bearer {}", base64_token.as_ref()))
     }
 
+    /// Creates a `Credentials` that reads the PEM encoded client certificate and private key from the given files
+    pub fn pem_files(
+        certificate_path: impl Into<String>,
+        private_key_path: impl Into<String>,
+    ) -> Credentials {
+        Credentials::PemPath {
+            certificate_path: certificate_path.into(),
+            private_key_path: private_key_path.into(),
+        }
+    }
+
+    /// Creates a `Credentials` that runs the given command to obtain a bearer token, without needing a kubeconfig
+    /// file. The command must print an `ExecCredential` json object to stdout, and the `env` variables are set in
+    /// addition to the ones inherited from this process.
+    pub fn exec(
+        command: impl Into<String>,
+        args: impl IntoIterator<Item = String>,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Credentials {
+        Credentials::Exec {
+            command: command.into(),
+            args: args.into_iter().collect(),
+            env: env.into_iter().collect(),
+        }
+    }
+
     /// Creates a `Credentials` from a raw (_not_ base64 encoded) username and password
     pub fn basic(raw_username: impl AsRef<str>, raw_password: impl AsRef<str>) -> Credentials {
         let formatted = format!("{}:{}", raw_username.as_ref(), raw_password.as_ref());
@@ -226,25 +1758,151 @@ impl Credentials {
 
 /// Configuration for how to connect to the Kubernetes API server and authenticate. This configuration
 /// can typically be created from either a service account or a kubeconfig file using one of the provided
-/// functions, but you may also create configurations manually.
-#[derive(Debug, Clone, PartialEq)]
+/// functions, but you may also create configurations manually, or load them from a file using `from_yaml_file`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ClientConfig {
     /// The http(s) endpoint of the api server, including the scheme and port
     pub api_server_endpoint: String,
     /// The credentials for authenticating with the api server
     pub credentials: Credentials,
     // Certificate for the Certificate Authority that signed the Kubernetes API server certificate
+    #[serde(default)]
     pub ca_data: Option<CAData>,
     /// The user-agent string to include in requests to the api server. This typically doesn't affect
     /// the fuctioning of the operator, but it can be useful when looking through api server logs
     pub user_agent: String,
-    /// Escape hatch for turning off ssl certificate validation **in test environments only**. Don't
-    /// set to `true` in production. Don't be _that_ person.
+    /// Escape hatch for turning off ssl certificate and hostname validation **in test environments only**. Don't
+    /// set to `false` in production. Don't be _that_ person. A warning is logged when the first client is created
+    /// with verification disabled, and the `client_tls_info` metric has a `verify_certs="false"` label, so that
+    /// insecure operators can be found.
+    #[serde(default = "default_true")]
     pub verify_ssl_certs: bool,
-    /// Optional user to impersonate
+    /// If `true` (the default), then the system's root certificates are trusted in addition to the `ca_data`. If
+    /// `false`, then only the `ca_data` is trusted.
+    #[serde(default = "default_true")]
+    pub trust_system_roots: bool,
+    /// Overrides the server name that's sent using SNI and that the api server's certificate is verified against,
+    /// which is otherwise the host of the `api_server_endpoint`. This is needed when the api server is reached
+    /// through an ip address or a load balancer whose name isn't in the certificate. It's loaded from the
+    /// `tls-server-name` of the cluster in a kubeconfig.
+    #[serde(default)]
+    pub tls_server_name: Option<String>,
+    /// Optional user to impersonate, which is sent as the `Impersonate-User` header
+    #[serde(default)]
     pub impersonate: Option<String>,
     /// optional list of groups to add when impersonating a user. Ignored if `impersonate` is empty.
+    #[serde(default)]
     pub impersonate_groups: Vec<String>,
+    /// Optional uid of the user to impersonate, which is sent as the `Impersonate-Uid` header. Ignored if
+    /// `impersonate` is empty.
+    #[serde(default)]
+    pub impersonate_uid: Option<String>,
+    /// Optional extra fields to add when impersonating a user, which are each sent as an `Impersonate-Extra-<key>`
+    /// header. Ignored if `impersonate` is empty.
+    #[serde(default)]
+    pub impersonate_extra: HashMap<String, Vec<String>>,
+    /// The maximum sustained number of requests per second that will be sent to the api server. Requests beyond
+    /// this rate will wait until they're allowed to proceed, rather than failing. A value of `0` or less disables
+    /// rate limiting entirely. Defaults to `DEFAULT_QPS`.
+    #[serde(default = "ClientConfig::default_qps")]
+    pub qps: f32,
+    /// The maximum number of requests that may be sent in a burst, above the sustained `qps`. Defaults to
+    /// `DEFAULT_BURST`.
+    #[serde(default = "ClientConfig::default_burst")]
+    pub burst: u32,
+    /// The maximum amount of time to wait for each attempt of a list, get, create, update, or delete request to
+    /// complete, including reading the response body. Attempts that take longer will fail with `Error::Timeout`.
+    /// Time spent waiting for the client rate limiter or between retries doesn't count towards the timeout.
+    /// Defaults to `DEFAULT_REQUEST_TIMEOUT`.
+    #[serde(
+        with = "serialization::duration",
+        default = "ClientConfig::default_request_timeout"
+    )]
+    pub request_timeout: Duration,
+    /// How long each watch request is kept open. This is sent to the api server as the `timeoutSeconds`
+    /// parameter, and watches are re-established automatically once it elapses. Defaults to
+    /// `DEFAULT_WATCH_TIMEOUT`.
+    #[serde(
+        with = "serialization::duration",
+        default = "ClientConfig::default_watch_timeout"
+    )]
+    pub watch_timeout: Duration,
+    /// The maximum number of resources to request in each page when listing resources, which is sent to the api
+    /// server as the `limit` parameter. Every page is fetched before the list is used, so this only limits the size
+    /// of each response. A value of `0` disables pagination. Defaults to `DEFAULT_LIST_PAGE_SIZE`.
+    #[serde(default = "ClientConfig::default_list_page_size")]
+    pub list_page_size: u32,
+    /// If `true`, then the initial list of each watched type is read from etcd with a quorum read. By default, it's
+    /// served from the api server's watch cache by sending `resourceVersion=0`, which is much cheaper, especially
+    /// when many operators restart at once. The cached list may be slightly stale, but the watch starts from the
+    /// `resourceVersion` of the list, so any newer changes are still observed. Lists that re-seed the cache after a
+    /// watch has expired are always consistent. Defaults to `false`.
+    #[serde(default)]
+    pub consistent_initial_list: bool,
+    /// The maximum number of idle connections to the api server that are kept open, so that they can be re-used by
+    /// later requests instead of establishing a new TLS connection. If the api server supports HTTP/2, which is
+    /// negotiated automatically, then concurrent requests are multiplexed over a single connection anyway. Defaults
+    /// to `DEFAULT_MAX_IDLE_CONNECTIONS`.
+    #[serde(default = "ClientConfig::default_max_idle_connections")]
+    pub max_idle_connections: usize,
+    /// How long an idle connection is kept open before it's closed, or `None` to keep idle connections open
+    /// indefinitely. Defaults to `DEFAULT_IDLE_TIMEOUT`.
+    #[serde(
+        with = "serialization::option_duration",
+        default = "ClientConfig::default_idle_timeout"
+    )]
+    pub idle_timeout: Option<Duration>,
+    /// The interval of TCP keep-alive probes, which detect connections that have been dropped without being
+    /// closed, or `None` to disable them. Defaults to `DEFAULT_TCP_KEEPALIVE`.
+    #[serde(
+        with = "serialization::option_duration",
+        default = "ClientConfig::default_tcp_keepalive"
+    )]
+    pub tcp_keepalive: Option<Duration>,
+    /// Controls how requests are retried when they fail with a transient error. The `request_timeout` applies to
+    /// each attempt separately, and an attempt that times out is not retried.
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    /// The url of an HTTP proxy to connect to the api server through, e.g. `http://proxy.example.com:3128`.
+    /// Connections are tunneled using a `CONNECT` request, so TLS is still established with the api server itself.
+    /// Credentials may be included in the url, and will be sent as a `Proxy-Authorization` header. When the config
+    /// is loaded from a service account or kubeconfig, this defaults to the `HTTPS_PROXY` environment variable
+    /// (or `HTTP_PROXY` for an `http` endpoint).
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Hosts that should be connected to directly instead of through the `proxy`. Each entry may be a domain, which
+    /// also matches all of its subdomains, an ip address, a CIDR block like `10.0.0.0/8`, or `*` to match everything.
+    /// Defaults to the comma separated entries from the `NO_PROXY` environment variable.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+    /// If `true` (the default), then get and list requests are sent with `Accept-Encoding: gzip`, so that the api
+    /// server may compress large responses. Compressed responses are decompressed transparently. Watches are never
+    /// compressed, since their events need to be read as soon as they arrive.
+    #[serde(default = "default_true")]
+    pub accept_compression: bool,
+    /// If `true`, then the body of each create, replace, patch, or apply request that fails is included in the
+    /// error, along with the raw body of the response. This is useful for debugging requests that are rejected by an
+    /// admission webhook, but it's disabled by default since the bodies may contain secrets, and errors are logged.
+    #[serde(default)]
+    pub capture_request_bodies: bool,
+    /// The maximum size, in bytes, of the serialized body of a create, replace, patch, or apply request. Larger
+    /// writes fail right away with `Error::ObjectTooLarge`, which includes the size and the object, instead of being
+    /// rejected by the api server with a less helpful message. This is usually a status that keeps growing. `None`
+    /// disables the check, which may be useful if etcd is configured with a larger limit. Defaults to
+    /// `DEFAULT_MAX_OBJECT_SIZE`.
+    #[serde(default = "ClientConfig::default_max_object_size")]
+    pub max_object_size: Option<usize>,
+    /// Writes with a serialized body that's larger than this number of bytes are logged as a warning, so that
+    /// objects that are growing can be noticed before they reach the `max_object_size`. `None` disables the
+    /// warning. Defaults to `DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD`.
+    #[serde(default = "ClientConfig::default_object_size_warning_threshold")]
+    pub object_size_warning_threshold: Option<usize>,
+    /// Extra headers to add to every request to the api server, keyed by header name. These are added after all
+    /// of the other headers, so a header that's set here, like `Authorization` or `User-Agent`, will replace the
+    /// value that would otherwise be sent. Names and values are checked when the client is created.
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
 }
 
 impl ClientConfig {
@@ -252,43 +1910,353 @@ impl ClientConfig {
     /// (`SERVICE_ACCOUNT_TOKEN_PATH`). Returns an error if either the "token" or "ca.crt" files are
     /// missing.
     ///
+    /// The token is used as `Credentials::TokenFile`, so it will be re-read periodically in order to
+    /// pick up tokens that are rotated by the kubelet.
+    ///
     /// The returned `ClientConfig` will use the default `api_server_endpoint` of `"kubernetes.default.svc"`,
     /// so you'll need to change that if your cluster uses something different.
     ///
-    /// The `user_agent` is typically the same value as the `operator_name` from the `OperatorConfig`.
+    /// The `user_agent` is typically the same value as the `operator_name` from the `OperatorConfig`. Certificate
+    /// verification is always enabled, and is never disabled as a fallback.
     pub fn from_service_account(user_agent: impl Into<String>) -> Result<ClientConfig, io::Error> {
-        use std::fs::File;
-        use std::io::Read;
-
-        let mut token_file = File::open(SERVICE_ACCOUNT_TOKEN_PATH)?;
-        let mut service_account_token = String::new();
-        token_file.read_to_string(&mut service_account_token)?;
+        // make sure that the token is readable up front, so that we can fall back to other configs if it's not
+        std::fs::File::open(SERVICE_ACCOUNT_TOKEN_PATH)?;
 
-        let ca_file_path = Path::new(SERVICE_ACCOUNT_CA_PATH);
-        let ca_data = if ca_file_path.exists() {
-            Some(CAData::File(SERVICE_ACCOUNT_CA_PATH.to_owned()))
-        } else {
-            None
-        };
+        // without the CA, the api server's certificate could only be verified if it was signed by a system root,
+        // which is almost never the case, so it's better to fall back to other configs
+        if !Path::new(SERVICE_ACCOUNT_CA_PATH).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "the service account CA certificate is missing from: {}",
+                    SERVICE_ACCOUNT_CA_PATH
+                ),
+            ));
+        }
+        let ca_data = Some(CAData::File(SERVICE_ACCOUNT_CA_PATH.to_owned()));
 
         let api_server_endpoint = format!("https://{}", API_SERVER_HOSTNAME);
+        let proxy = proxy_from_env(api_server_endpoint.as_str());
         Ok(ClientConfig {
             api_server_endpoint,
             ca_data,
-            credentials: Credentials::Header(format!("Bearer {}", service_account_token)),
+            credentials: Credentials::TokenFile(PathBuf::from(SERVICE_ACCOUNT_TOKEN_PATH)),
             user_agent: user_agent.into(),
             verify_ssl_certs: true,
+            trust_system_roots: true,
+            tls_server_name: None,
             impersonate: None,
             impersonate_groups: Vec::new(),
+            impersonate_uid: None,
+            impersonate_extra: HashMap::new(),
+            qps: DEFAULT_QPS,
+            burst: DEFAULT_BURST,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            watch_timeout: DEFAULT_WATCH_TIMEOUT,
+            list_page_size: DEFAULT_LIST_PAGE_SIZE,
+            consistent_initial_list: false,
+            max_idle_connections: DEFAULT_MAX_IDLE_CONNECTIONS,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            retry_policy: RetryPolicy::default(),
+            proxy,
+            no_proxy: no_proxy_from_env(),
+            accept_compression: true,
+            capture_request_bodies: false,
+            max_object_size: Some(DEFAULT_MAX_OBJECT_SIZE),
+            object_size_warning_threshold: Some(DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD),
+            extra_headers: HashMap::new(),
         })
     }
 
+    /// Loads the config from a yaml or json file. Only the `apiServerEndpoint`, `credentials`, and `userAgent` are
+    /// required, and every other field has the same default as for `from_service_account`, except that the `proxy`
+    /// and `noProxy` aren't taken from the environment. Secrets in the `credentials` may be read from environment
+    /// variables or files, so that they don't need to be written in the config file:
+    ///
+    /// ```yaml
+    /// apiServerEndpoint: https://kubernetes.default.svc
+    /// userAgent: my-operator
+    /// credentials:
+    ///   header:
+    ///     fromEnv: K8S_AUTHORIZATION
+    /// caData:
+    ///   file: /var/run/secrets/kubernetes.io/serviceaccount/ca.crt
+    /// requestTimeout: 30s
+    /// ```
+    pub fn from_yaml_file(path: impl AsRef<Path>) -> Result<ClientConfig, ConfigFileError> {
+        serialization::from_yaml_file(path.as_ref())
+    }
+
+    fn default_qps() -> f32 {
+        DEFAULT_QPS
+    }
+
+    fn default_burst() -> u32 {
+        DEFAULT_BURST
+    }
+
+    fn default_request_timeout() -> Duration {
+        DEFAULT_REQUEST_TIMEOUT
+    }
+
+    fn default_watch_timeout() -> Duration {
+        DEFAULT_WATCH_TIMEOUT
+    }
+
+    fn default_list_page_size() -> u32 {
+        DEFAULT_LIST_PAGE_SIZE
+    }
+
+    fn default_max_idle_connections() -> usize {
+        DEFAULT_MAX_IDLE_CONNECTIONS
+    }
+
+    fn default_idle_timeout() -> Option<Duration> {
+        Some(DEFAULT_IDLE_TIMEOUT)
+    }
+
+    fn default_tcp_keepalive() -> Option<Duration> {
+        Some(DEFAULT_TCP_KEEPALIVE)
+    }
+
+    fn default_max_object_size() -> Option<usize> {
+        Some(DEFAULT_MAX_OBJECT_SIZE)
+    }
+
+    fn default_object_size_warning_threshold() -> Option<usize> {
+        Some(DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD)
+    }
+
     /// Attempts to build a `ClientConfig` from a kubeconfig file. This respects the value of the `KUBECONFIG`
     /// environment variable. Most, but not all, of the authentication methods are supported, including
-    /// certificates, username/password, token, and exec. Roperator does not currently support rotation of
-    /// credentials, so any credentials taken from the kubeconfig will need to be valid for the lifetime of
-    /// the application.
+    /// certificates, username/password, token, and exec. Tokens from exec credential plugins are refreshed
+    /// automatically before they expire, but any other credentials taken from the kubeconfig will need to be
+    /// valid for the lifetime of the application. The context is taken from the `ROPERATOR_KUBE_CONTEXT`
+    /// environment variable if it's set, and otherwise from the `current-context` of the kubeconfig.
     pub fn from_kubeconfig(user_agent: impl Into<String>) -> Result<ClientConfig, KubeConfigError> {
-        self::kubeconfig::load_from_kubeconfig(user_agent.into())
+        self::kubeconfig::load_from_kubeconfig(user_agent.into(), None)
+    }
+
+    /// Builds a `ClientConfig` from a kubeconfig file, the same as `from_kubeconfig`, except that the cluster and
+    /// user are taken from the context with the given name. Returns `KubeConfigError::ContextNotFound` if there's
+    /// no such context in the kubeconfig.
+    pub fn from_kubeconfig_context(
+        user_agent: impl Into<String>,
+        context_name: &str,
+    ) -> Result<ClientConfig, KubeConfigError> {
+        self::kubeconfig::load_from_kubeconfig(user_agent.into(), Some(context_name))
+    }
+
+    /// Sets the user-agent string exactly as given, replacing the one that the config was created with
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Sets a user-agent that follows the Kubernetes convention of `name/version (os/arch) component`, with the
+    /// version of roperator appended to the end. The `component` is typically something like `controller/<commit>`,
+    /// and is left out if it's empty.
+    ///
+    /// ```no_run
+    /// use roperator::config::ClientConfig;
+    ///
+    /// // e.g. "my-operator/1.2.3 (linux/amd64) controller/abc123 roperator/<version>"
+    /// let config = ClientConfig::from_kubeconfig("my-operator")
+    ///     .unwrap()
+    ///     .user_agent_parts("my-operator", "1.2.3", "controller/abc123");
+    /// ```
+    pub fn user_agent_parts(self, name: &str, version: &str, component: &str) -> Self {
+        let mut user_agent = format!(
+            "{}/{} ({}/{})",
+            name,
+            version,
+            std::env::consts::OS,
+            go_arch()
+        );
+        if !component.is_empty() {
+            user_agent.push(' ');
+            user_agent.push_str(component);
+        }
+        user_agent.push_str(concat!(" roperator/", env!("CARGO_PKG_VERSION")));
+        self.user_agent(user_agent)
+    }
+
+    /// Sets the credentials for authenticating with the api server, replacing the ones that were determined
+    /// from the service account or kubeconfig
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Sets the http(s) endpoint of the api server, including the scheme and port, which is useful for pointing an
+    /// in-cluster config at a different api server for testing
+    pub fn with_endpoint(mut self, api_server_endpoint: impl Into<String>) -> Self {
+        self.api_server_endpoint = api_server_endpoint.into();
+        self
+    }
+
+    /// Sets whether to verify the api server's certificate and hostname. See `verify_ssl_certs` for why this should
+    /// never be disabled in production.
+    pub fn with_verify_ssl(mut self, verify_ssl_certs: bool) -> Self {
+        self.verify_ssl_certs = verify_ssl_certs;
+        self
+    }
+
+    /// Impersonates the given user, keeping any existing `impersonate_groups`, `impersonate_uid`, and
+    /// `impersonate_extra`. This can be combined with any of the other constructors, for example:
+    ///
+    /// ```no_run
+    /// use roperator::config::ClientConfig;
+    ///
+    /// let config = ClientConfig::from_service_account("my-operator")
+    ///     .unwrap()
+    ///     .with_impersonate("system:serviceaccount:my-ns:restricted")
+    ///     .with_impersonate_group("my-group");
+    /// ```
+    pub fn with_impersonate(mut self, user: impl Into<String>) -> Self {
+        self.impersonate = Some(user.into());
+        self
+    }
+
+    /// Adds a group to the `impersonate_groups`, which is only sent when a user is also being impersonated
+    pub fn with_impersonate_group(mut self, group: impl Into<String>) -> Self {
+        self.impersonate_groups.push(group.into());
+        self
+    }
+
+    /// Adds a CA to trust in addition to any existing `ca_data`, so that they're combined into a `CAData::Bundle`
+    pub fn add_ca_data(mut self, ca_data: CAData) -> Self {
+        self.ca_data = match self.ca_data.take() {
+            None => Some(ca_data),
+            Some(CAData::Bundle(mut bundle)) => {
+                bundle.push(ca_data);
+                Some(CAData::Bundle(bundle))
+            }
+            Some(existing) => Some(CAData::Bundle(vec![existing, ca_data])),
+        };
+        self
+    }
+
+    /// Sets whether to trust the system's root certificates in addition to the `ca_data`
+    pub fn trust_system_roots(mut self, trust_system_roots: bool) -> Self {
+        self.trust_system_roots = trust_system_roots;
+        self
+    }
+
+    /// Sets the server name to use for SNI and to verify the api server's certificate against, instead of the host
+    /// of the `api_server_endpoint`
+    pub fn tls_server_name(mut self, tls_server_name: impl Into<String>) -> Self {
+        self.tls_server_name = Some(tls_server_name.into());
+        self
+    }
+
+    /// Sets the maximum number of resources to request in each page when listing resources, or `0` to disable
+    /// pagination
+    pub fn list_page_size(mut self, list_page_size: u32) -> Self {
+        self.list_page_size = list_page_size;
+        self
+    }
+
+    /// Sets whether the initial list of each watched type is a consistent read from etcd, instead of being served
+    /// from the api server's watch cache
+    pub fn consistent_initial_list(mut self, consistent_initial_list: bool) -> Self {
+        self.consistent_initial_list = consistent_initial_list;
+        self
+    }
+
+    /// Sets the maximum number of idle connections to the api server that are kept open for re-use
+    pub fn max_idle_connections(mut self, max_idle_connections: usize) -> Self {
+        self.max_idle_connections = max_idle_connections;
+        self
+    }
+
+    /// Sets how long an idle connection is kept open, or `None` to keep idle connections open indefinitely
+    pub fn idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Sets the interval of TCP keep-alive probes, or `None` to disable them
+    pub fn tcp_keepalive(mut self, tcp_keepalive: Option<Duration>) -> Self {
+        self.tcp_keepalive = tcp_keepalive;
+        self
+    }
+
+    /// Sets whether to ask the api server to gzip the responses to get and list requests. This is enabled by default
+    pub fn accept_compression(mut self, accept_compression: bool) -> Self {
+        self.accept_compression = accept_compression;
+        self
+    }
+
+    /// Sets whether to include the request and response bodies in the errors from failed writes. This is disabled by
+    /// default, since the bodies may contain secrets.
+    pub fn capture_request_bodies(mut self, capture_request_bodies: bool) -> Self {
+        self.capture_request_bodies = capture_request_bodies;
+        self
+    }
+
+    /// Sets the maximum size, in bytes, of the body of a write, or `None` to send writes of any size
+    pub fn max_object_size(mut self, max_object_size: Option<usize>) -> Self {
+        self.max_object_size = max_object_size;
+        self
+    }
+
+    /// Sets the size, in bytes, of writes that are logged as a warning, or `None` to never warn
+    pub fn object_size_warning_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.object_size_warning_threshold = threshold;
+        self
+    }
+
+    /// Adds a header that will be sent with every request to the api server, replacing any previous value for
+    /// the same name
+    pub fn with_extra_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.extra_headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Parses the `extra_headers`, returning an error for the first one that isn't a legal header name or value
+    pub(crate) fn validate_extra_headers(&self) -> Result<http::HeaderMap, InvalidHeader> {
+        let mut headers = http::HeaderMap::with_capacity(self.extra_headers.len());
+        for (name, value) in self.extra_headers.iter() {
+            let invalid = |message: String| InvalidHeader {
+                name: name.clone(),
+                message,
+            };
+            let header_name = http::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|err| invalid(err.to_string()))?;
+            let header_value = http::header::HeaderValue::from_str(value.as_str())
+                .map_err(|err| invalid(err.to_string()))?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(headers)
+    }
+}
+
+/// Returns the architecture using the same names as Go, which is what other Kubernetes clients put in their
+/// user-agent
+fn go_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        other => other,
     }
 }
+
+/// Error returned when one of the `extra_headers` in the `ClientConfig` has a name or value that can't be sent in
+/// an http request
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidHeader {
+    /// The name of the header, as it was configured
+    pub name: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Invalid extra header '{}': {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for InvalidHeader {}
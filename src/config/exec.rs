@@ -0,0 +1,148 @@
+//! Support for running exec credential plugins, which print an `ExecCredential` to stdout. This is
+//! what runs the command for `Credentials::Exec`, whether it was configured directly or loaded from an
+//! `exec` user in a kubeconfig file.
+use super::KubeConfigError;
+
+use std::process::Command;
+use std::time::SystemTime;
+
+const EXEC_INFO_ENV_VAR: &str = "KUBERNETES_EXEC_INFO";
+
+/// A bearer token returned from an exec credential plugin
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExecToken {
+    /// The complete value for the Authorization header
+    pub header: String,
+    /// When the token expires, if the plugin told us
+    pub expiration: Option<SystemTime>,
+}
+
+/// Runs the given credential plugin command and parses the token from its output. The command is run
+/// synchronously, so this must not be called directly from async code.
+pub(crate) fn get_exec_token(
+    command: &str,
+    args: &[String],
+    env: &[(String, String)],
+) -> Result<ExecToken, KubeConfigError> {
+    log::debug!(
+        "Getting credentials from command: '{}' with args: {:?}",
+        command,
+        args
+    );
+    let mut cmd = Command::new(command);
+    cmd.args(args);
+    for (name, value) in env.iter() {
+        cmd.env(name, value);
+    }
+
+    let output = cmd.output().map_err(|err| KubeConfigError::ExecFailed {
+        command: command.to_owned(),
+        exit_code: None,
+        stderr: err.to_string(),
+    })?;
+    if !output.status.success() {
+        return Err(KubeConfigError::ExecFailed {
+            command: command.to_owned(),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(output.stderr.as_slice()).into_owned(),
+        });
+    }
+
+    let credential: ExecCredential =
+        serde_yaml::from_slice(output.stdout.as_slice()).map_err(|err| {
+            KubeConfigError::ExecErr(format!(
+                "Invalid stdout from exec command: '{}' : err: {}",
+                command, err
+            ))
+        })?;
+
+    let expiration = match credential.status.expiration_timestamp.as_ref() {
+        Some(timestamp) => {
+            let parsed = chrono::DateTime::parse_from_rfc3339(timestamp).map_err(|err| {
+                KubeConfigError::ExecErr(format!(
+                    "Invalid expirationTimestamp: '{}' from exec command: '{}' : err: {}",
+                    timestamp, command, err
+                ))
+            })?;
+            Some(SystemTime::from(parsed))
+        }
+        None => None,
+    };
+    log::info!(
+        "Successfully got token from command: '{}' with expiration: {:?}",
+        command,
+        credential.status.expiration_timestamp
+    );
+    Ok(ExecToken {
+        header: format!("Bearer {}", credential.status.token),
+        expiration,
+    })
+}
+
+/// Returns the `KUBERNETES_EXEC_INFO` environment variable that tells a kubeconfig credential plugin which
+/// `apiVersion` of `ExecCredential` to print
+pub(crate) fn exec_info_env(api_version: &str) -> (String, String) {
+    let exec_info = serde_json::json!({
+        "apiVersion": api_version,
+        "kind": "ExecCredential",
+        "spec": {
+            "interactive": false,
+        }
+    });
+    (EXEC_INFO_ENV_VAR.to_owned(), exec_info.to_string())
+}
+
+/// used only for deserializing the output of the `exec` command for retrieving credentials
+#[derive(Deserialize, Clone, Debug)]
+struct ExecCredential {
+    status: ExecCredentialStatus,
+}
+
+/// used only for deserializing the output of the `exec` command for retrieving credentials
+#[derive(Deserialize, Clone, Debug)]
+struct ExecCredentialStatus {
+    token: String,
+    #[serde(rename = "expirationTimestamp")]
+    expiration_timestamp: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_and_expiration_are_parsed_from_command_output() {
+        let output = r#"{"kind":"ExecCredential","status":{"token":"abc123","expirationTimestamp":"2030-01-02T03:04:05Z"}}"#;
+        let token =
+            get_exec_token("echo", &[output.to_owned()], &[]).expect("failed to get exec token");
+        assert_eq!("Bearer abc123", token.header.as_str());
+        let expected = chrono::DateTime::parse_from_rfc3339("2030-01-02T03:04:05Z").unwrap();
+        assert_eq!(Some(SystemTime::from(expected)), token.expiration);
+    }
+
+    #[test]
+    fn non_zero_exit_returns_exec_failed_with_stderr() {
+        let args = &[
+            "-c".to_owned(),
+            "echo 'no creds for you' >&2; exit 3".to_owned(),
+        ];
+        match get_exec_token("sh", args, &[]) {
+            Err(KubeConfigError::ExecFailed {
+                exit_code, stderr, ..
+            }) => {
+                assert_eq!(Some(3), exit_code);
+                assert_eq!("no creds for you\n", stderr.as_str());
+            }
+            other => panic!("expected ExecFailed but got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_binary_returns_exec_failed() {
+        let result = get_exec_token("roperator-definitely-not-a-real-command", &[], &[]);
+        match result {
+            Err(KubeConfigError::ExecFailed { exit_code, .. }) => assert!(exit_code.is_none()),
+            other => panic!("expected ExecFailed but got: {:?}", other),
+        }
+    }
+}
@@ -1,7 +1,13 @@
-use super::{CAData, ClientConfig, Credentials};
+use super::{
+    exec::exec_info_env, no_proxy_from_env, proxy_from_env, CAData, ClientConfig, Credentials,
+    RetryPolicy, DEFAULT_BURST, DEFAULT_IDLE_TIMEOUT, DEFAULT_LIST_PAGE_SIZE,
+    DEFAULT_MAX_IDLE_CONNECTIONS, DEFAULT_MAX_OBJECT_SIZE, DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD,
+    DEFAULT_QPS, DEFAULT_REQUEST_TIMEOUT, DEFAULT_TCP_KEEPALIVE, DEFAULT_WATCH_TIMEOUT,
+};
 
 use dirs::home_dir;
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display};
 use std::fs::File;
 use std::io;
@@ -10,6 +16,10 @@ use std::path::{Path, PathBuf};
 const MISSING_CREDENTIAL_MESSAGE: &str = "No supported credentials found in kubeconfig file for the selected context. Only token, username/password, client certificate, and exec are currently supported. Please file an issue if there's another mechanism that you need";
 const NO_HOME_DIR_MESSAGE: &str = "Unable to determine HOME directory to load ~/.kube/config";
 
+/// The name of an environment variable that can be used to select a context from the kubeconfig file, instead of
+/// using its `current-context`
+pub const KUBE_CONTEXT_ENV_VAR: &str = "ROPERATOR_KUBE_CONTEXT";
+
 /// Error representing a problem with loading a kubeconfig file, or creating a `ClientConfig`
 /// from it.
 #[derive(Debug)]
@@ -19,7 +29,23 @@ pub enum KubeConfigError {
     MissingCredentials,
     NoHomeDir,
     InvalidKubeconfig(String),
+    /// The selected context does not exist in the kubeconfig file
+    ContextNotFound {
+        name: String,
+        available: Vec<String>,
+    },
     ExecErr(String),
+    /// One of the files listed in `KUBECONFIG` could not be read or parsed
+    File {
+        path: PathBuf,
+        error: Box<KubeConfigError>,
+    },
+    /// The exec credential command could not be run, or it exited unsuccessfully
+    ExecFailed {
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
 }
 
 impl From<serde_yaml::Error> for KubeConfigError {
@@ -44,28 +70,110 @@ impl Display for KubeConfigError {
             KubeConfigError::InvalidKubeconfig(ref msg) => {
                 write!(f, "Invalid kubeconfig file: {}", msg)
             }
+            KubeConfigError::ContextNotFound {
+                ref name,
+                ref available,
+            } => write!(
+                f,
+                "No context found with name: '{}', available contexts are: [{}]",
+                name,
+                available.join(", ")
+            ),
             KubeConfigError::ExecErr(ref msg) => write!(f, "exec error: {}", msg),
+            KubeConfigError::File {
+                ref path,
+                ref error,
+            } => write!(
+                f,
+                "Error in kubeconfig file '{}': {}",
+                path.display(),
+                error
+            ),
+            KubeConfigError::ExecFailed {
+                ref command,
+                exit_code,
+                ref stderr,
+            } => write!(
+                f,
+                "exec command: '{}' failed with exit code: {:?}, stderr: {}",
+                command, exit_code, stderr
+            ),
         }
     }
 }
 impl std::error::Error for KubeConfigError {}
 
-fn get_kubeconfig_path() -> Result<PathBuf, KubeConfigError> {
-    std::env::var("KUBECONFIG")
-        .ok()
-        .map(PathBuf::from)
-        .or_else(|| {
-            home_dir().map(|mut home| {
-                home.push(".kube/config");
-                home
-            })
+/// Returns the paths from the `KUBECONFIG` environment variable, which may contain several paths separated by
+/// the platform's path separator (`:`, or `;` on Windows). Falls back to `~/.kube/config` if it isn't set.
+fn get_kubeconfig_paths() -> Result<Vec<PathBuf>, KubeConfigError> {
+    let from_env = std::env::var_os("KUBECONFIG")
+        .map(|value| {
+            std::env::split_paths(&value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if !from_env.is_empty() {
+        return Ok(from_env);
+    }
+    home_dir()
+        .map(|mut home| {
+            home.push(".kube/config");
+            vec![home]
         })
         .ok_or(KubeConfigError::NoHomeDir)
 }
 
+/// Loads and merges all of the given kubeconfig files the same way that kubectl does. Clusters, users, and
+/// contexts are combined, and the first file to define an entry with a given name wins. The `current-context` is
+/// taken from the first file that sets one. Files that don't exist are skipped, as long as at least one does.
+/// Relative certificate-authority paths are resolved against the directory of the file that they appear in.
+fn load_merged(paths: &[PathBuf]) -> Result<KubeConfig, KubeConfigError> {
+    let mut merged: Option<KubeConfig> = None;
+    for path in paths {
+        if paths.len() > 1 && !path.exists() {
+            log::debug!(
+                "Skipping kubeconfig file '{}' that does not exist",
+                path.display()
+            );
+            continue;
+        }
+        let conf = KubeConfig::load_file(path)
+            .and_then(|conf| conf.with_absolute_paths(path))
+            .map_err(|error| KubeConfigError::File {
+                path: path.clone(),
+                error: Box::new(error),
+            })?;
+        merged = Some(match merged {
+            Some(prev) => prev.merge(conf),
+            None => conf,
+        });
+    }
+    merged.ok_or_else(|| {
+        KubeConfigError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("None of the kubeconfig files exist: {:?}", paths),
+        ))
+    })
+}
+
+/// Appends the entries from `additional` that don't have the same name as an entry in `entries`
+fn merge_named<T>(entries: &mut Vec<T>, additional: Vec<T>, name: impl Fn(&T) -> &str) {
+    let mut names = entries
+        .iter()
+        .map(|e| name(e).to_owned())
+        .collect::<HashSet<_>>();
+    for entry in additional {
+        if names.insert(name(&entry).to_owned()) {
+            entries.push(entry);
+        }
+    }
+}
+
 pub fn load_kubeconfig(
     user_agent: String,
     file_path: impl AsRef<Path>,
+    context: Option<&str>,
 ) -> Result<ClientConfig, KubeConfigError> {
     let reader = File::open(file_path.as_ref())?;
     let kubeconfig: KubeConfig = serde_yaml::from_reader(reader)?;
@@ -78,15 +186,26 @@ pub fn load_kubeconfig(
             ),
         ))
     })?;
-    kubeconfig.create_client_config(user_agent, dir)
+    kubeconfig.resolve(user_agent, dir, context, None)
 }
 
-pub fn load_from_kubeconfig(user_agent: String) -> Result<ClientConfig, KubeConfigError> {
-    let path = get_kubeconfig_path()?;
-    load_kubeconfig(user_agent, path)
+pub fn load_from_kubeconfig(
+    user_agent: String,
+    context: Option<&str>,
+) -> Result<ClientConfig, KubeConfigError> {
+    let paths = get_kubeconfig_paths()?;
+    if let [path] = paths.as_slice() {
+        return load_kubeconfig(user_agent, path, context);
+    }
+    let kubeconfig = load_merged(&paths)?;
+    // all of the relative paths have already been resolved, so the directory doesn't matter here
+    kubeconfig.resolve(user_agent, Path::new(""), context, None)
 }
 
-fn get_credentials(user: &UserInfo) -> Result<Credentials, KubeConfigError> {
+fn get_credentials(
+    user: &UserInfo,
+    kube_config_dir: &Path,
+) -> Result<Credentials, KubeConfigError> {
     if let Some(token) = user.token.as_ref() {
         log::debug!("Using auth token from kubeconfig");
         return Ok(Credentials::base64_bearer_token(token));
@@ -99,7 +218,20 @@ fn get_credentials(user: &UserInfo) -> Result<Credentials, KubeConfigError> {
         return Ok(Credentials::basic(username, pass));
     }
     if let Some(exec) = user.exec.as_ref() {
-        return get_exec_token(exec).map(Credentials::Header);
+        log::debug!("Using exec credentials from kubeconfig: {:?}", exec);
+        let mut env: Vec<(String, String)> = exec
+            .env
+            .iter()
+            .map(|var| (var.name.clone(), var.value.clone()))
+            .collect();
+        if let Some(version) = exec.api_version.as_ref() {
+            env.push(exec_info_env(version));
+        }
+        return Ok(Credentials::Exec {
+            command: exec.command.clone(),
+            args: exec.args.clone(),
+            env,
+        });
     }
 
     if let Some(certificate_path) = user.client_certificate.as_ref() {
@@ -109,9 +241,11 @@ fn get_credentials(user: &UserInfo) -> Result<Credentials, KubeConfigError> {
             )
         })?;
 
+        // relative paths are relative to the kubeconfig file, the same as for the certificate-authority
+        let resolve = |path: &str| kube_config_dir.join(path).to_string_lossy().to_string();
         return Ok(Credentials::PemPath {
-            certificate_path: certificate_path.clone(),
-            private_key_path: private_key_path.clone(),
+            certificate_path: resolve(certificate_path),
+            private_key_path: resolve(private_key_path),
         });
     }
 
@@ -131,50 +265,6 @@ fn get_credentials(user: &UserInfo) -> Result<Credentials, KubeConfigError> {
     Err(KubeConfigError::MissingCredentials)
 }
 
-fn get_exec_token(exec: &Exec) -> Result<String, KubeConfigError> {
-    use std::process::Command;
-
-    log::debug!("Getting credentials from: {:?}", exec);
-    let mut cmd = Command::new(exec.command.as_str());
-    for arg in exec.args.iter() {
-        cmd.arg(arg);
-    }
-
-    for var in exec.env.iter() {
-        cmd.env(var.name.as_str(), var.value.as_str());
-    }
-
-    let output = cmd.output()?;
-    let credential: ExecCredential =
-        serde_yaml::from_slice(output.stdout.as_slice()).map_err(|err| {
-            KubeConfigError::ExecErr(format!(
-                "Invalid stdout from exec command: '{}' : err: {}",
-                exec.command, err
-            ))
-        })?;
-
-    log::info!(
-        "Successfully got token from command: '{}' with expiration: {:?}",
-        exec.command,
-        credential.status.expiration_timestamp
-    );
-    Ok(format!("Bearer {}", credential.status.token))
-}
-
-/// used only for deserializing the output of the `exec` command for retrieving credentials
-#[derive(Deserialize, Clone, Debug)]
-struct ExecCredential {
-    status: ExecCredentialStatus,
-}
-
-/// used only for deserializing the output of the `exec` command for retrieving credentials
-#[derive(Deserialize, Clone, Debug)]
-struct ExecCredentialStatus {
-    token: String,
-    #[serde(rename = "expirationTimestamp")]
-    expiration_timestamp: Option<String>,
-}
-
 // below are struct definitions that are used only for deserializing the kubeconfig. These are NOT
 // complete definitions, so should not be exposed outside of this module.
 
@@ -184,6 +274,10 @@ struct ClusterInfo {
     server: String,
     certificate_authority_data: Option<String>,
     certificate_authority: Option<PathBuf>,
+    #[serde(default)]
+    insecure_skip_tls_verify: bool,
+    tls_server_name: Option<String>,
+    proxy_url: Option<String>,
 }
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
@@ -212,6 +306,10 @@ struct UserInfo {
     pub as_user: Option<String>,
     #[serde(rename = "as-groups", default)]
     pub as_groups: Vec<String>,
+    #[serde(rename = "as-uid")]
+    pub as_uid: Option<String>,
+    #[serde(rename = "as-user-extra", default)]
+    pub as_user_extra: HashMap<String, Vec<String>>,
 
     pub exec: Option<Exec>,
 }
@@ -224,6 +322,8 @@ struct ExecEnv {
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 struct Exec {
+    #[serde(rename = "apiVersion")]
+    api_version: Option<String>,
     command: String,
     #[serde(default)]
     args: Vec<String>,
@@ -255,24 +355,28 @@ struct Context {
 /// loading the kubeconfig file and creating a `ClientConfig` from it.
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 pub struct KubeConfig {
-    #[serde(rename = "current-context")]
-    current_context: String,
+    #[serde(rename = "current-context", default)]
+    current_context: Option<String>,
+    #[serde(default)]
     clusters: Vec<Cluster>,
+    #[serde(default)]
     users: Vec<User>,
+    #[serde(default)]
     contexts: Vec<Context>,
 }
 
 impl KubeConfig {
     /// Attempts to load the kube config file from the usual locations. If the `KUBECONFIG`
-    /// environement variable is set, then the path specified by it is used. Otherwise,
-    /// it will look in `~/.kube/config`. If the file is not found, or if it is syntactically
-    /// or structurally invalid, then an error will be returned.
-    /// If the file is found, then the path that it was loaded from is also returned in
-    /// the tuple.
+    /// environement variable is set, then the paths specified by it are used, and the files are merged
+    /// the same way as kubectl does. Otherwise, it will look in `~/.kube/config`. If no file is found,
+    /// or if any of them are syntactically or structurally invalid, then an error will be returned.
+    /// If the file is found, then the path of the first file is also returned in the tuple. Relative
+    /// paths in the files are resolved when they're loaded, so it doesn't matter which directory is
+    /// later passed to `create_client_config`.
     pub fn load() -> Result<(KubeConfig, PathBuf), KubeConfigError> {
-        let path = get_kubeconfig_path()?;
-        let conf = KubeConfig::load_file(&path)?;
-        Ok((conf, path))
+        let paths = get_kubeconfig_paths()?;
+        let conf = load_merged(&paths)?;
+        Ok((conf, paths.into_iter().next().unwrap()))
     }
 
     /// Attempts to load the kube config file from the specified path. Returns an error if
@@ -283,15 +387,68 @@ impl KubeConfig {
         Ok(conf)
     }
 
+    /// Rewrites relative paths in this kubeconfig to be absolute, using the directory of the file at `path`
+    fn with_absolute_paths(mut self, path: &Path) -> Result<KubeConfig, KubeConfigError> {
+        let mut dir = path.parent().map(PathBuf::from).unwrap_or_default();
+        if dir.is_relative() {
+            dir = std::env::current_dir()?.join(dir);
+        }
+        for cluster in self.clusters.iter_mut() {
+            if let Some(ca_path) = cluster.cluster.certificate_authority.as_mut() {
+                *ca_path = dir.join(&ca_path);
+            }
+        }
+        for user in self.users.iter_mut() {
+            let user = &mut user.user;
+            for path in user
+                .client_certificate
+                .iter_mut()
+                .chain(user.client_key.iter_mut())
+            {
+                *path = dir.join(&path).to_string_lossy().to_string();
+            }
+        }
+        Ok(self)
+    }
+
+    /// Merges `other` into this kubeconfig, giving precedence to the entries in `self`
+    fn merge(mut self, other: KubeConfig) -> KubeConfig {
+        if self
+            .current_context
+            .as_ref()
+            .filter(|c| !c.is_empty())
+            .is_none()
+        {
+            self.current_context = other.current_context;
+        }
+        merge_named(&mut self.clusters, other.clusters, |c| c.name.as_str());
+        merge_named(&mut self.users, other.users, |u| u.name.as_str());
+        merge_named(&mut self.contexts, other.contexts, |c| c.name.as_str());
+        self
+    }
+
     /// Creates a `ClientConfig` from the data in this kube config. Returns an error if the kube config
     /// file is missing required data. The `kubeconfig_parent_dir` is used in order to resolve relative
     /// file paths that appear in the file, for example as paths to certificate files.
+    /// The context named by the `ROPERATOR_KUBE_CONTEXT` environment variable is used if it's set, and otherwise
+    /// the `current-context` from the file.
     pub fn create_client_config(
         &self,
         user_agent: String,
         kubeconfig_parent_dir: &Path,
     ) -> Result<ClientConfig, KubeConfigError> {
-        self.resolve(user_agent, kubeconfig_parent_dir, None)
+        self.resolve(user_agent, kubeconfig_parent_dir, None, None)
+    }
+
+    /// Creates a `ClientConfig`, the same as `create_client_config`, except that the cluster and user are
+    /// taken from the context with the given name, regardless of the `current-context` in the file.
+    pub fn create_client_config_for_context(
+        &self,
+        user_agent: String,
+        kubeconfig_parent_dir: &Path,
+        context_name: &str,
+    ) -> Result<ClientConfig, KubeConfigError> {
+        self.resolve(user_agent, kubeconfig_parent_dir, Some(context_name), None)
     }
 
     /// Creates a `ClientConfig`, the same as `create_client_config`, except that the given `credentials`
@@ -304,26 +461,42 @@ impl KubeConfig {
         kubeconfig_parent_dir: &Path,
         credentials: Credentials,
     ) -> Result<ClientConfig, KubeConfigError> {
-        self.resolve(user_agent, kubeconfig_parent_dir, Some(credentials))
+        self.resolve(user_agent, kubeconfig_parent_dir, None, Some(credentials))
+    }
+
+    /// Returns the name of the context to use. An explicit context takes precedence over the environment
+    /// variable, which takes precedence over the `current-context` from the file.
+    fn selected_context_name(&self, context: Option<&str>) -> Result<String, KubeConfigError> {
+        context
+            .map(String::from)
+            .or_else(|| std::env::var(KUBE_CONTEXT_ENV_VAR).ok())
+            .filter(|name| !name.is_empty())
+            .or_else(|| self.current_context.clone())
+            .ok_or_else(|| {
+                KubeConfigError::InvalidKubeconfig(
+                    "No context was specified, and the kubeconfig has no 'current-context'"
+                        .to_owned(),
+                )
+            })
     }
 
     fn resolve(
         &self,
         user_agent: String,
         kube_config_dir: &Path,
+        context: Option<&str>,
         override_credentials: Option<Credentials>,
     ) -> Result<ClientConfig, KubeConfigError> {
-        let current_context = self.current_context.as_str();
+        let context_name = self.selected_context_name(context)?;
         let found_context = self
             .contexts
             .iter()
-            .find(|ctx| ctx.name.as_str() == current_context)
-            .ok_or_else(|| {
-                KubeConfigError::InvalidKubeconfig(format!(
-                    "No countext found for current context: '{}'",
-                    current_context
-                ))
+            .find(|ctx| ctx.name == context_name)
+            .ok_or_else(|| KubeConfigError::ContextNotFound {
+                name: context_name.clone(),
+                available: self.contexts.iter().map(|ctx| ctx.name.clone()).collect(),
             })?;
+        log::debug!("Using kubeconfig context: '{}'", context_name);
         let found_cluster = self
             .clusters
             .iter()
@@ -348,44 +521,92 @@ impl KubeConfig {
         let credentials = if let Some(creds) = override_credentials {
             creds
         } else {
-            get_credentials(&found_user.user)?
+            get_credentials(&found_user.user, kube_config_dir)?
         };
 
         let impersonate = found_user.user.as_user.clone();
         let impersonate_groups = found_user.user.as_groups.clone();
+        let impersonate_uid = found_user.user.as_uid.clone();
+        let impersonate_extra = found_user.user.as_user_extra.clone();
+
+        let cluster = &found_cluster.cluster;
+        let ca_data = match (
+            cluster.certificate_authority_data.as_ref(),
+            cluster.certificate_authority.as_ref(),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(KubeConfigError::InvalidKubeconfig(format!(
+                    "cluster '{}' specifies both 'certificate-authority' and 'certificate-authority-data'",
+                    found_cluster.name
+                )));
+            }
+            (Some(data), None) => {
+                // CAData::Contents is kept base64 encoded, but we check it here so that a bad value is reported
+                // as a problem with the kubeconfig rather than when the client is created
+                base64::decode(data).map_err(|err| {
+                    KubeConfigError::InvalidKubeconfig(format!(
+                        "Invalid base64 content of certificate-authority-data: {}",
+                        err
+                    ))
+                })?;
+                Some(CAData::Contents(data.clone()))
+            }
+            (None, Some(ca_path)) => {
+                // TODO: we'll need to make a breaking change to the CAData enum so that we can
+                // always use Paths instead of strings for these
+                let resolved_path = kube_config_dir.join(ca_path).to_string_lossy().to_string();
+                log::debug!(
+                    "Resolved cluster certificate-authority path '{}' to '{}'",
+                    ca_path.display(),
+                    resolved_path
+                );
+                Some(CAData::File(resolved_path))
+            }
+            (None, None) => None,
+        };
+        if cluster.insecure_skip_tls_verify {
+            log::warn!(
+                "TLS certificate verification is disabled for cluster '{}' by 'insecure-skip-tls-verify'",
+                found_cluster.name
+            );
+        }
 
-        let ca_data = found_cluster
+        // a proxy-url in the kubeconfig takes precedence over the environment, which is the same as kubectl
+        let api_server_endpoint = found_cluster.cluster.server.clone();
+        let proxy = found_cluster
             .cluster
-            .certificate_authority_data
+            .proxy_url
             .clone()
-            .map(CAData::Contents)
-            .or_else(|| {
-                found_cluster
-                    .cluster
-                    .certificate_authority
-                    .clone()
-                    .map(|ca_path| {
-                        // TODO: we'll need to make a breaking change to the CAData enum so that we can
-                        // always use Paths instead of strings for these
-                        let resolved_path =
-                            kube_config_dir.join(&ca_path).to_string_lossy().to_string();
-                        log::debug!(
-                            "Resolved cluster certificate-authority path '{}' to '{}'",
-                            ca_path.display(),
-                            resolved_path
-                        );
-                        CAData::File(resolved_path)
-                    })
-            });
-
+            .or_else(|| proxy_from_env(api_server_endpoint.as_str()));
         let conf = ClientConfig {
             user_agent,
             credentials,
             impersonate,
             impersonate_groups,
-            api_server_endpoint: found_cluster.cluster.server.clone(),
+            impersonate_uid,
+            impersonate_extra,
+            api_server_endpoint,
             ca_data,
-            verify_ssl_certs: true,
+            verify_ssl_certs: !cluster.insecure_skip_tls_verify,
+            trust_system_roots: true,
+            tls_server_name: cluster.tls_server_name.clone(),
+            qps: DEFAULT_QPS,
+            burst: DEFAULT_BURST,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            watch_timeout: DEFAULT_WATCH_TIMEOUT,
+            list_page_size: DEFAULT_LIST_PAGE_SIZE,
+            consistent_initial_list: false,
+            max_idle_connections: DEFAULT_MAX_IDLE_CONNECTIONS,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            retry_policy: RetryPolicy::default(),
+            proxy,
+            no_proxy: no_proxy_from_env(),
+            accept_compression: true,
+            capture_request_bodies: false,
+            max_object_size: Some(DEFAULT_MAX_OBJECT_SIZE),
+            object_size_warning_threshold: Some(DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD),
+            extra_headers: HashMap::new(),
         };
         Ok(conf)
     }
@@ -400,8 +621,236 @@ mod test {
         let file = "src/config/test-data/kubeconfig-with-ca-file.yaml";
         let user_agent = "my-user-agent";
         let loaded =
-            load_kubeconfig(user_agent.to_string(), file).expect("failed to load kubeconfig");
+            load_kubeconfig(user_agent.to_string(), file, None).expect("failed to load kubeconfig");
         let expected = CAData::File("src/config/test-data/./dummy-ca.crt".to_string());
         assert_eq!(Some(expected), loaded.ca_data);
+        assert!(loaded.verify_ssl_certs);
+    }
+
+    #[test]
+    fn user_agent_parts_follow_the_kubernetes_convention() {
+        let file = "src/config/test-data/kubeconfig-with-ca-file.yaml";
+        let loaded = load_kubeconfig("my-user-agent".to_owned(), file, None)
+            .expect("failed to load kubeconfig")
+            .user_agent_parts("my-operator", "1.2.3", "controller/abc123");
+        let expected = format!(
+            "my-operator/1.2.3 ({}/{}) controller/abc123 roperator/{}",
+            std::env::consts::OS,
+            crate::config::go_arch(),
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(expected, loaded.user_agent);
+
+        let loaded = loaded.user_agent_parts("my-operator", "1.2.3", "");
+        assert!(loaded
+            .user_agent
+            .ends_with(concat!(") roperator/", env!("CARGO_PKG_VERSION"))));
+    }
+
+    #[test]
+    fn loads_kubeconfig_with_explicit_context() {
+        let file = "src/config/test-data/kubeconfig-with-ca-file.yaml";
+        let loaded = load_kubeconfig("my-user-agent".to_owned(), file, Some("ca-data-context"))
+            .expect("failed to load kubeconfig");
+        assert_eq!("https://with-ca-path.test", loaded.api_server_endpoint);
+
+        let err = load_kubeconfig("my-user-agent".to_owned(), file, Some("nope"))
+            .expect_err("expected an error");
+        match err {
+            KubeConfigError::ContextNotFound { name, available } => {
+                assert_eq!("nope", name);
+                assert_eq!(vec!["ca-path-context", "ca-data-context"], available);
+            }
+            other => panic!("expected ContextNotFound, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn merges_multiple_kubeconfig_files() {
+        let paths = vec![
+            PathBuf::from("src/config/test-data/kubeconfig-fragment.yaml"),
+            PathBuf::from("src/config/test-data/does-not-exist.yaml"),
+            PathBuf::from("src/config/test-data/kubeconfig-with-ca-file.yaml"),
+        ];
+        let merged = load_merged(&paths).expect("failed to load kubeconfig");
+        // the first file doesn't set a current-context, so it comes from the second one
+        assert_eq!(Some("ca-path-context"), merged.current_context.as_deref());
+        let context_names = merged
+            .contexts
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["fragment-context", "ca-path-context", "ca-data-context"],
+            context_names
+        );
+
+        // the first file defines the "certificates" user, so that one wins
+        let conf = merged
+            .resolve("my-user-agent".to_owned(), Path::new(""), None, None)
+            .expect("failed to resolve client config");
+        assert_eq!(
+            Credentials::base64_bearer_token("fragment-token"),
+            conf.credentials
+        );
+        let expected_ca = std::env::current_dir()
+            .unwrap()
+            .join("src/config/test-data/./dummy-ca.crt");
+        assert_eq!(
+            Some(CAData::File(expected_ca.to_string_lossy().to_string())),
+            conf.ca_data
+        );
+    }
+
+    #[test]
+    fn malformed_kubeconfig_file_is_reported_with_its_path() {
+        let paths = vec![
+            PathBuf::from("src/config/test-data/kubeconfig-with-ca-file.yaml"),
+            PathBuf::from("src/config/test-data/dummy-ca.crt"),
+        ];
+        match load_merged(&paths) {
+            Err(KubeConfigError::File { path, error }) => {
+                assert_eq!(paths[1], path);
+                assert!(matches!(*error, KubeConfigError::Format(_)));
+            }
+            other => panic!("expected a File error, got: {:?}", other),
+        }
+    }
+
+    fn client_config_for_cluster(cluster: &str) -> Result<ClientConfig, KubeConfigError> {
+        let yaml = format!(
+            r#"
+current-context: ctx
+clusters:
+- name: test-cluster
+  cluster:
+    server: https://test-cluster.test
+{}
+contexts:
+- name: ctx
+  context:
+    cluster: test-cluster
+    user: test-user
+users:
+- name: test-user
+  user:
+    token: abc
+"#,
+            cluster
+        );
+        let kubeconfig: KubeConfig = serde_yaml::from_str(&yaml).expect("invalid test kubeconfig");
+        kubeconfig.create_client_config("my-user-agent".to_owned(), Path::new("/kube"))
+    }
+
+    #[test]
+    fn loads_kubeconfig_with_cluster_ca_data() {
+        let loaded = client_config_for_cluster("    certificate-authority-data: Zm9vYmFy")
+            .expect("failed to load kubeconfig");
+        assert_eq!(
+            Some(CAData::Contents("Zm9vYmFy".to_owned())),
+            loaded.ca_data
+        );
+        assert!(loaded.verify_ssl_certs);
+    }
+
+    #[test]
+    fn invalid_cluster_ca_data_is_an_error() {
+        let result = client_config_for_cluster("    certificate-authority-data: '!not base64!'");
+        assert!(matches!(result, Err(KubeConfigError::InvalidKubeconfig(_))));
+    }
+
+    #[test]
+    fn insecure_skip_tls_verify_disables_cert_verification() {
+        let loaded = client_config_for_cluster("    insecure-skip-tls-verify: true")
+            .expect("failed to load kubeconfig");
+        assert!(!loaded.verify_ssl_certs);
+        assert_eq!(None, loaded.ca_data);
+    }
+
+    #[test]
+    fn tls_server_name_is_loaded_from_the_cluster() {
+        let loaded = client_config_for_cluster("    tls-server-name: kubernetes.default.svc")
+            .expect("failed to load kubeconfig");
+        assert_eq!(
+            Some("kubernetes.default.svc"),
+            loaded.tls_server_name.as_deref()
+        );
+        let loaded = client_config_for_cluster("").expect("failed to load kubeconfig");
+        assert_eq!(None, loaded.tls_server_name);
+    }
+
+    #[test]
+    fn client_certificate_paths_are_relative_to_the_kubeconfig() {
+        let yaml = r#"
+current-context: ctx
+clusters:
+- name: test-cluster
+  cluster:
+    server: https://test-cluster.test
+contexts:
+- name: ctx
+  context:
+    cluster: test-cluster
+    user: test-user
+users:
+- name: test-user
+  user:
+    client-certificate: certs/tls.crt
+    client-key: /etc/secrets/tls.key
+"#;
+        let kubeconfig: KubeConfig = serde_yaml::from_str(yaml).expect("invalid test kubeconfig");
+        let loaded = kubeconfig
+            .create_client_config("my-user-agent".to_owned(), Path::new("/kube"))
+            .expect("failed to load kubeconfig");
+        assert_eq!(
+            Credentials::pem_files("/kube/certs/tls.crt", "/etc/secrets/tls.key"),
+            loaded.credentials
+        );
+    }
+
+    #[test]
+    fn impersonation_fields_are_loaded_from_the_user() {
+        let yaml = r#"
+current-context: ctx
+clusters:
+- name: test-cluster
+  cluster:
+    server: https://test-cluster.test
+contexts:
+- name: ctx
+  context:
+    cluster: test-cluster
+    user: test-user
+users:
+- name: test-user
+  user:
+    token: abc
+    as: jane
+    as-groups: [admins, devs]
+    as-uid: user-uid
+    as-user-extra:
+      reason: [testing]
+"#;
+        let kubeconfig: KubeConfig = serde_yaml::from_str(yaml).expect("invalid test kubeconfig");
+        let loaded = kubeconfig
+            .create_client_config("my-user-agent".to_owned(), Path::new("/kube"))
+            .expect("failed to load kubeconfig");
+        assert_eq!(Some("jane".to_owned()), loaded.impersonate);
+        assert_eq!(
+            vec!["admins".to_owned(), "devs".to_owned()],
+            loaded.impersonate_groups
+        );
+        assert_eq!(Some("user-uid".to_owned()), loaded.impersonate_uid);
+        assert_eq!(
+            Some(&vec!["testing".to_owned()]),
+            loaded.impersonate_extra.get("reason")
+        );
+    }
+
+    #[test]
+    fn specifying_both_ca_file_and_ca_data_is_an_error() {
+        let cluster = "    certificate-authority: ca.crt\n    certificate-authority-data: Zm9vYmFy";
+        let result = client_config_for_cluster(cluster);
+        assert!(matches!(result, Err(KubeConfigError::InvalidKubeconfig(_))));
     }
 }
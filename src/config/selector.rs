@@ -0,0 +1,300 @@
+//! Validation of Kubernetes label and field selector strings. This is only meant to catch mistakes in the
+//! configuration early, before they turn into 400 responses from the api server. The api server
+//! is still the authority on what's valid, so this intentionally stays close to the syntax that's
+//! documented at https://kubernetes.io/docs/concepts/overview/working-with-objects/labels/
+use std::fmt::{self, Display};
+
+const MAX_NAME_LEN: usize = 63;
+const MAX_PREFIX_LEN: usize = 253;
+
+/// Error returned when a label selector in the `OperatorConfig` is not syntactically valid
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidLabelSelector {
+    /// The complete selector that was configured
+    pub selector: String,
+    /// Describes which part of the selector is invalid
+    pub message: String,
+}
+
+impl Display for InvalidLabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid label selector: '{}': {}",
+            self.selector, self.message
+        )
+    }
+}
+
+impl std::error::Error for InvalidLabelSelector {}
+
+/// Error returned when a field selector in the `OperatorConfig` is not valid. This is returned either if the
+/// syntax is invalid, or if the api server rejected the selector, which happens when the resource doesn't
+/// support selecting on the given field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidFieldSelector {
+    /// The complete selector that was configured
+    pub selector: String,
+    /// Describes which part of the selector is invalid
+    pub message: String,
+}
+
+impl Display for InvalidFieldSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid field selector: '{}': {}",
+            self.selector, self.message
+        )
+    }
+}
+
+impl std::error::Error for InvalidFieldSelector {}
+
+/// Validates the syntax of the given field selector. Only the syntax is checked here, since the fields that
+/// may be selected on are different for each resource, and only the api server knows which ones are supported.
+pub fn validate_field_selector(selector: &str) -> Result<(), InvalidFieldSelector> {
+    let err = |message: String| InvalidFieldSelector {
+        selector: selector.to_owned(),
+        message,
+    };
+    if selector.trim().is_empty() {
+        return Err(err("selector must not be empty".to_owned()));
+    }
+    for requirement in split_field_requirements(selector) {
+        validate_field_requirement(requirement.trim()).map_err(err)?;
+    }
+    Ok(())
+}
+
+/// splits the selector on commas, except for those that are escaped with a backslash
+fn split_field_requirements(selector: &str) -> Vec<&str> {
+    let mut requirements = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in selector.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => {
+                requirements.push(&selector[start..i]);
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    requirements.push(&selector[start..]);
+    requirements
+}
+
+fn validate_field_requirement(requirement: &str) -> Result<(), String> {
+    if requirement.is_empty() {
+        return Err("selector contains an empty requirement".to_owned());
+    }
+    for op in &["==", "!=", "="] {
+        if let Some(idx) = requirement.find(op) {
+            let field = requirement[..idx].trim();
+            let is_valid_field = !field.is_empty()
+                && field.split('.').all(|part| {
+                    !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric())
+                });
+            if !is_valid_field {
+                return Err(format!("invalid field: '{}'", field));
+            }
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "expected '<field>=<value>' or '<field>!=<value>' but got '{}'",
+        requirement
+    ))
+}
+
+/// Validates the given label selector, returning an error that describes the first problem that was found
+pub fn validate_label_selector(selector: &str) -> Result<(), InvalidLabelSelector> {
+    let err = |message: String| InvalidLabelSelector {
+        selector: selector.to_owned(),
+        message,
+    };
+    if selector.trim().is_empty() {
+        return Err(err("selector must not be empty".to_owned()));
+    }
+    for requirement in split_requirements(selector).map_err(err)? {
+        validate_requirement(requirement.trim()).map_err(err)?;
+    }
+    Ok(())
+}
+
+/// splits the selector on commas, except for those that appear inside of a parenthesized set
+fn split_requirements(selector: &str) -> Result<Vec<&str>, String> {
+    let mut requirements = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err("unbalanced ')'".to_owned()),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                requirements.push(&selector[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err("unbalanced '('".to_owned());
+    }
+    requirements.push(&selector[start..]);
+    Ok(requirements)
+}
+
+fn validate_requirement(requirement: &str) -> Result<(), String> {
+    if requirement.is_empty() {
+        return Err("selector contains an empty requirement".to_owned());
+    }
+    if let Some(key) = requirement.strip_prefix('!') {
+        return validate_key(key.trim());
+    }
+    for op in &["==", "!=", "="] {
+        if let Some(idx) = requirement.find(op) {
+            validate_key(requirement[..idx].trim())?;
+            return validate_value(requirement[(idx + op.len())..].trim());
+        }
+    }
+    if let Some(paren_idx) = requirement.find('(') {
+        if !requirement.ends_with(')') {
+            return Err(format!("expected ')' at the end of '{}'", requirement));
+        }
+        let mut parts = requirement[..paren_idx].split_whitespace();
+        let key = parts.next().unwrap_or("");
+        let op = parts.next().unwrap_or("");
+        if parts.next().is_some() || (op != "in" && op != "notin") {
+            return Err(format!(
+                "expected '<key> in (...)' or '<key> notin (...)' but got '{}'",
+                requirement
+            ));
+        }
+        validate_key(key)?;
+        let values = &requirement[(paren_idx + 1)..(requirement.len() - 1)];
+        for value in values.split(',') {
+            validate_value(value.trim())?;
+        }
+        return Ok(());
+    }
+    validate_key(requirement)
+}
+
+fn validate_key(key: &str) -> Result<(), String> {
+    let name = match key.find('/') {
+        Some(idx) => {
+            let prefix = &key[..idx];
+            if prefix.is_empty() || prefix.len() > MAX_PREFIX_LEN || !is_dns_subdomain(prefix) {
+                return Err(format!("invalid prefix in label key: '{}'", key));
+            }
+            &key[(idx + 1)..]
+        }
+        None => key,
+    };
+    if name.is_empty() || !is_label_value(name) {
+        return Err(format!("invalid label key: '{}'", key));
+    }
+    Ok(())
+}
+
+fn validate_value(value: &str) -> Result<(), String> {
+    if value.is_empty() || is_label_value(value) {
+        Ok(())
+    } else {
+        Err(format!("invalid label value: '{}'", value))
+    }
+}
+
+fn is_label_value(value: &str) -> bool {
+    let is_alphanumeric = |c: char| c.is_ascii_alphanumeric();
+    value.len() <= MAX_NAME_LEN
+        && value.starts_with(is_alphanumeric)
+        && value.ends_with(is_alphanumeric)
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn is_dns_subdomain(value: &str) -> bool {
+    value.split('.').all(|part| {
+        !part.is_empty()
+            && !part.starts_with('-')
+            && !part.ends_with('-')
+            && part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn valid_selectors_are_accepted() {
+        let selectors = &[
+            "app",
+            "!app",
+            "app=foo",
+            "app==foo",
+            "app!=foo",
+            "app=",
+            "example.com/team=storage,tier!=test",
+            "env in (prod, staging)",
+            "env notin (dev),app",
+        ];
+        for selector in selectors.iter() {
+            if let Err(err) = validate_label_selector(selector) {
+                panic!("expected selector to be valid: {}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn field_selectors_are_validated() {
+        let valid = &[
+            "spec.nodeName=node-1",
+            "status.phase!=Running,metadata.namespace==default",
+            "spec.nodeName=",
+            "metadata.name=a\\,b",
+        ];
+        for selector in valid.iter() {
+            if let Err(err) = validate_field_selector(selector) {
+                panic!("expected selector to be valid: {}", err);
+            }
+        }
+        let invalid = &["", "spec.nodeName", "=foo", "spec..nodeName=foo", "a=b,"];
+        for selector in invalid.iter() {
+            assert!(
+                validate_field_selector(selector).is_err(),
+                "expected selector to be invalid: '{}'",
+                selector
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_selectors_are_rejected() {
+        let selectors = &[
+            "",
+            "app,",
+            "app=foo bar",
+            "-app=foo",
+            "Example.com/team=storage",
+            "env in (prod",
+            "env within (prod)",
+            "env)",
+        ];
+        for selector in selectors.iter() {
+            assert!(
+                validate_label_selector(selector).is_err(),
+                "expected selector to be invalid: '{}'",
+                selector
+            );
+        }
+    }
+}
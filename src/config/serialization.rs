@@ -0,0 +1,384 @@
+//! Helpers for (de)serializing `OperatorConfig` and `ClientConfig`, so that they can be loaded from files. Durations
+//! are written as a number with a unit, like `30s` or `500ms`, and secrets may be read from environment variables or
+//! files instead of being inlined.
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Error returned when a configuration file can't be loaded
+#[derive(Debug)]
+pub enum ConfigFileError {
+    /// The file couldn't be read
+    Io(PathBuf, io::Error),
+    /// The file isn't valid yaml (or json), or doesn't match the structure of the config
+    Parse(PathBuf, serde_yaml::Error),
+}
+
+impl Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigFileError::Io(path, err) => {
+                write!(f, "Failed to read config file: {}: {}", path.display(), err)
+            }
+            ConfigFileError::Parse(path, err) => {
+                write!(f, "Invalid config file: {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigFileError::Io(_, err) => Some(err),
+            ConfigFileError::Parse(_, err) => Some(err),
+        }
+    }
+}
+
+/// Reads the yaml (or json, which is also valid yaml) file at the given path
+pub(crate) fn from_yaml_file<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigFileError> {
+    let file =
+        std::fs::File::open(path).map_err(|err| ConfigFileError::Io(path.to_owned(), err))?;
+    serde_yaml::from_reader(io::BufReader::new(file))
+        .map_err(|err| ConfigFileError::Parse(path.to_owned(), err))
+}
+
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+const DURATION_UNITS: &[(&str, u128)] = &[
+    ("h", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+fn format_duration(duration: &Duration) -> String {
+    let nanos = duration.as_nanos();
+    if nanos == 0 {
+        return "0s".to_owned();
+    }
+    let (unit, nanos_per_unit) = DURATION_UNITS
+        .iter()
+        .find(|(_, nanos_per_unit)| nanos.is_multiple_of(*nanos_per_unit))
+        .expect("every duration is a whole number of nanoseconds");
+    format!("{}{}", nanos / nanos_per_unit, unit)
+}
+
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let digits_end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(digits_end);
+    let number = number.parse::<u64>().map_err(|_| {
+        format!(
+            "invalid duration: '{}', expected a number and unit, like '30s'",
+            input
+        )
+    })?;
+    let nanos_per_unit = DURATION_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, nanos_per_unit)| *nanos_per_unit)
+        .ok_or_else(|| {
+            format!(
+                "invalid duration: '{}', the unit must be one of: h, m, s, ms, us, ns",
+                input
+            )
+        })?;
+    let nanos = u128::from(number) * nanos_per_unit;
+    let secs = u64::try_from(nanos / 1_000_000_000)
+        .map_err(|_| format!("invalid duration: '{}', it's too large", input))?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// (De)serializes a `Duration` as a string with a single unit, like `90s`
+pub(crate) mod duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_duration(duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let as_string = String::deserialize(deserializer)?;
+        parse_duration(&as_string).map_err(D::Error::custom)
+    }
+}
+
+/// (De)serializes an `Option<Duration>` the same as `duration`, with `null` for `None`
+pub(crate) mod option_duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        duration.as_ref().map(format_duration).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(as_string) => parse_duration(&as_string)
+                .map(Some)
+                .map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Where to get the value of a secret, like a token or private key. It may be given inline as a plain string, or
+/// else read from an environment variable or a file when the config is loaded.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SecretSource {
+    Inline(String),
+    #[serde(rename_all = "camelCase")]
+    FromEnv {
+        from_env: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    FromFile {
+        from_file: PathBuf,
+    },
+}
+
+/// Deserializes a secret string from a `SecretSource`. Secrets are always serialized inline.
+pub(crate) mod secret {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        match SecretSource::deserialize(deserializer)? {
+            SecretSource::Inline(value) => Ok(value),
+            SecretSource::FromEnv { from_env } => std::env::var(&from_env).map_err(|err| {
+                D::Error::custom(format!(
+                    "failed to read secret from environment variable: '{}': {}",
+                    from_env, err
+                ))
+            }),
+            SecretSource::FromFile { from_file } => std::fs::read_to_string(&from_file)
+                .map(|value| value.trim_end().to_owned())
+                .map_err(|err| {
+                    D::Error::custom(format!(
+                        "failed to read secret from file: '{}': {}",
+                        from_file.display(),
+                        err
+                    ))
+                }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EnvVar {
+    name: String,
+    value: String,
+}
+
+/// (De)serializes environment variables as a list of `name` and `value` pairs, the same as in a kubeconfig
+pub(crate) mod env_vars {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        env: &[(String, String)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(env.iter().map(|(name, value)| EnvVar {
+            name: name.clone(),
+            value: value.clone(),
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, String)>, D::Error> {
+        let env = Vec::<EnvVar>::deserialize(deserializer)?;
+        Ok(env.into_iter().map(|var| (var.name, var.value)).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{ChildConfig, ClientConfig, Credentials, OperatorConfig};
+    use crate::k8s_types::apps::v1::Deployment;
+    use crate::k8s_types::core::v1::{ConfigMap, Secret};
+    use crate::k8s_types::K8sType;
+
+    static PARENT_TYPE: &K8sType = &K8sType {
+        api_version: "example.com/v1",
+        kind: "Parent",
+        plural_kind: "parents",
+        scope: crate::k8s_types::Scope::Namespaced,
+    };
+
+    #[test]
+    fn durations_are_formatted_with_the_largest_whole_unit() {
+        let cases = &[
+            (Duration::from_secs(7200), "2h"),
+            (Duration::from_secs(90), "90s"),
+            (Duration::from_secs(600), "10m"),
+            (Duration::from_millis(1500), "1500ms"),
+            (Duration::from_micros(3), "3us"),
+            (Duration::from_nanos(1), "1ns"),
+            (Duration::from_secs(0), "0s"),
+        ];
+        for (duration, expected) in cases {
+            assert_eq!(*expected, format_duration(duration));
+            assert_eq!(*duration, parse_duration(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn invalid_durations_are_rejected() {
+        for input in &[
+            "",
+            "30",
+            "s",
+            "1.5s",
+            "-1s",
+            "10 parsecs",
+            "5d",
+            "18446744073709551615h",
+        ] {
+            assert!(
+                parse_duration(input).is_err(),
+                "{} should be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn secrets_are_read_from_the_environment_or_files() {
+        #[derive(Deserialize)]
+        struct Config {
+            #[serde(with = "secret")]
+            token: String,
+        }
+
+        std::env::set_var("ROPERATOR_TEST_SECRET", "from-env");
+        let config: Config =
+            serde_json::from_str(r#"{"token": {"fromEnv": "ROPERATOR_TEST_SECRET"}}"#).unwrap();
+        assert_eq!("from-env", config.token);
+
+        let path = std::env::temp_dir().join(format!("roperator-secret-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let json = serde_json::json!({ "token": { "fromFile": path } });
+        let config: Config = serde_json::from_value(json).unwrap();
+        assert_eq!("from-file", config.token);
+        std::fs::remove_file(&path).unwrap();
+
+        let config: Config = serde_json::from_str(r#"{"token": "inline"}"#).unwrap();
+        assert_eq!("inline", config.token);
+
+        let result =
+            serde_json::from_str::<Config>(r#"{"token": {"fromEnv": "ROPERATOR_TEST_MISSING"}}"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn operator_config_defaults_are_the_same_as_the_builder() {
+        let yaml = r#"
+operatorName: test-operator
+parent: example.com/v1/Parent/parents
+childTypes:
+  - k8sType: apps/v1/Deployment/deployments
+    config:
+      updateStrategy: Patch
+watchedTypes:
+  - v1/Secret/secrets
+resyncInterval: 10m
+"#;
+        let actual: OperatorConfig = serde_yaml::from_str(yaml).unwrap();
+        let expected = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .with_child(Deployment, ChildConfig::patch())
+            .with_watched_type(Secret)
+            .with_resync_interval(Duration::from_secs(600));
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn operator_config_round_trips_through_yaml() {
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .within_namespaces(vec!["ns-a".to_owned()])
+            .with_child(Deployment, ChildConfig::patch())
+            .with_child(ConfigMap, ChildConfig::replace())
+            .with_leader_election("test-lease", "operators")
+            .emit_events(false);
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let actual: OperatorConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config, actual);
+    }
+
+    #[test]
+    fn client_config_credentials_are_read_from_the_environment() {
+        std::env::set_var("ROPERATOR_TEST_AUTH_HEADER", "Bearer abc");
+        let yaml = r#"
+apiServerEndpoint: https://kubernetes.default.svc
+userAgent: test-operator
+credentials:
+  header:
+    fromEnv: ROPERATOR_TEST_AUTH_HEADER
+caData:
+  file: /ca.crt
+requestTimeout: 5s
+idleTimeout: ~
+"#;
+        let actual: ClientConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            Credentials::Header("Bearer abc".to_owned()),
+            actual.credentials
+        );
+        assert_eq!(Duration::from_secs(5), actual.request_timeout);
+        assert_eq!(None, actual.idle_timeout);
+        assert_eq!(
+            Some(crate::config::DEFAULT_TCP_KEEPALIVE),
+            actual.tcp_keepalive
+        );
+        assert!(actual.verify_ssl_certs);
+        assert!(actual.accept_compression);
+    }
+
+    #[test]
+    fn secrets_are_not_included_in_debug_output() {
+        let credentials = vec![
+            Credentials::Header("Bearer super-secret".to_owned()),
+            Credentials::Pem {
+                certificate_base64: "super-secret-cert".to_owned(),
+                private_key_base64: "super-secret-key".to_owned(),
+            },
+            Credentials::exec(
+                "get-token",
+                Vec::new(),
+                vec![("TOKEN".to_owned(), "super-secret".to_owned())],
+            ),
+        ];
+        for credentials in credentials {
+            let yaml = "{apiServerEndpoint: 'https://kubernetes.default.svc', userAgent: test-operator, credentials: {tokenFile: /token}}";
+            let mut client_config: ClientConfig = serde_yaml::from_str(yaml).unwrap();
+            client_config.credentials = credentials;
+            let config = OperatorConfig::new("test-operator", PARENT_TYPE)
+                .with_child(Deployment, ChildConfig::patch().with_client(client_config));
+            let debug = format!("{:?}", config);
+            assert!(debug.contains("***"), "secret was not redacted: {}", debug);
+            assert!(!debug.contains("super-secret"), "secret leaked: {}", debug);
+        }
+    }
+}
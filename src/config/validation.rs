@@ -0,0 +1,455 @@
+//! Validation of the `OperatorConfig` as a whole, which is done before the operator makes any requests to the api
+//! server, so that mistakes are reported with a clear message instead of as confusing failures later on.
+use super::{ConflictingChildTracking, InvalidFieldSelector, InvalidLabelSelector, OperatorConfig};
+use crate::k8s_types::{K8sType, Scope};
+
+use std::fmt::{self, Display};
+
+/// Error returned from `OperatorConfig::validate`, with a separate variant for each way that the config can be
+/// invalid
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The `operator_name` is empty. It's used as the default finalizer name, field manager, and user agent, so it's
+    /// required.
+    EmptyOperatorName,
+    /// The `finalizer_name` is empty
+    EmptyFinalizerName,
+    /// A child type is the same as the parent type, which would make every child a parent as well
+    ChildTypeIsParentType(&'static K8sType),
+    /// A cluster-scoped child type was declared with `Scope::Namespaced`, but it can't be watched within a namespace
+    NamespacedClusterScopedChild(&'static K8sType),
+    /// The `tracking_label_name` and `ownership_label_name` are the same, so the label on each child couldn't hold
+    /// both the parent's uid and the operator's name
+    DuplicateLabelNames(String),
+    /// One of the `managed_labels` has the same name as the `tracking_label_name` or `ownership_label_name`, which
+    /// would interfere with how children are associated with parents
+    ReservedManagedLabel(String),
+    /// The port for metrics, health checks, or admin endpoints is 0 while they're enabled, which would have the server listen on a
+    /// random port that nothing knows to connect to
+    InvalidServerPort,
+    /// The `metrics_port` or `health_port` is set, but the server that it's for is disabled. The value is the name of
+    /// the field.
+    PortOfDisabledServer(&'static str),
+    /// The webhook server is configured to listen on the same port as metrics, health checks, or admin endpoints, but it only serves
+    /// HTTPS
+    ConflictingPorts(u16),
+    /// A `ParentMapper` was registered for the parent type or one of the child types, which are already associated
+    /// with their parents, so the mapper would never be called
+    MappedTypeIsManaged(&'static K8sType),
+    /// A child type has its own `ChildConfig::client`, but children are tracked using owner references, which can't
+    /// refer to a parent in another cluster
+    OwnerReferenceToOtherCluster(&'static K8sType),
+    /// A child type is cluster scoped and the parent type is namespaced, but children are tracked using owner
+    /// references, which can't refer to a namespaced parent from a cluster scoped child
+    OwnerReferenceToNamespacedParent(&'static K8sType),
+    /// The `namespaces` are `Some`, but empty, which would have the operator watch nothing at all. Use `None` to
+    /// watch every namespace.
+    EmptyNamespaces,
+    /// Both the `namespaces` and the `namespace_selector` are set, but the operator can only be restricted using one
+    /// of them
+    ConflictingNamespaces,
+    /// The `lease_namespace` of the `leader_election` is empty, which means that the lease is in the operator's own
+    /// namespace, but `OperatorConfig::operator_namespace` couldn't find it
+    UnknownOperatorNamespace,
+    InvalidLabelSelector(InvalidLabelSelector),
+    InvalidFieldSelector(InvalidFieldSelector),
+    ConflictingChildTracking(ConflictingChildTracking),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::EmptyOperatorName => f.write_str("Invalid OperatorConfig: the operator_name must not be empty"),
+            ConfigError::EmptyFinalizerName => f.write_str("Invalid OperatorConfig: the finalizer_name must not be empty"),
+            ConfigError::ChildTypeIsParentType(k8s_type) => write!(
+                f,
+                "Invalid OperatorConfig: the parent type {} cannot also be a child type",
+                k8s_type
+            ),
+            ConfigError::NamespacedClusterScopedChild(k8s_type) => write!(
+                f,
+                "Invalid OperatorConfig: the child type {} is cluster scoped, so it cannot be declared with a namespaced scope",
+                k8s_type
+            ),
+            ConfigError::DuplicateLabelNames(label_name) => write!(
+                f,
+                "Invalid OperatorConfig: the tracking_label_name and ownership_label_name must be different, but both are '{}'",
+                label_name
+            ),
+            ConfigError::ReservedManagedLabel(label_name) => write!(
+                f,
+                "Invalid OperatorConfig: the managed label '{}' is reserved for the tracking_label_name or ownership_label_name",
+                label_name
+            ),
+            ConfigError::InvalidServerPort => f.write_str(
+                "Invalid OperatorConfig: the server_port must not be 0 when metrics, health checks, or admin endpoints are exposed on it",
+            ),
+            ConfigError::PortOfDisabledServer(field) => write!(
+                f,
+                "Invalid OperatorConfig: the {} is set, but nothing is exposed on it",
+                field
+            ),
+            ConfigError::ConflictingPorts(port) => write!(
+                f,
+                "Invalid OperatorConfig: the webhook server cannot use port {}, since it's also used for metrics or health checks",
+                port
+            ),
+            ConfigError::MappedTypeIsManaged(k8s_type) => write!(
+                f,
+                "Invalid OperatorConfig: {} is the parent type or a child type, so it cannot have a watched type mapper",
+                k8s_type
+            ),
+            ConfigError::OwnerReferenceToOtherCluster(k8s_type) => write!(
+                f,
+                "Invalid OperatorConfig: children of type {} are in another cluster, so they must be tracked using the tracking label instead of owner references",
+                k8s_type
+            ),
+            ConfigError::OwnerReferenceToNamespacedParent(k8s_type) => write!(
+                f,
+                "Invalid OperatorConfig: children of type {} are cluster scoped and the parent is namespaced, so they must be tracked using the tracking label instead of owner references",
+                k8s_type
+            ),
+            ConfigError::EmptyNamespaces => f.write_str(
+                "Invalid OperatorConfig: the namespaces must not be empty, leave them unset to watch every namespace",
+            ),
+            ConfigError::ConflictingNamespaces => f.write_str(
+                "Invalid OperatorConfig: the namespaces and namespace_selector cannot both be set",
+            ),
+            ConfigError::UnknownOperatorNamespace => f.write_str(
+                "Invalid OperatorConfig: the lease_namespace is empty, but the operator's own namespace couldn't be detected, so the operator_namespace must be set",
+            ),
+            ConfigError::InvalidLabelSelector(err) => Display::fmt(err, f),
+            ConfigError::InvalidFieldSelector(err) => Display::fmt(err, f),
+            ConfigError::ConflictingChildTracking(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::InvalidLabelSelector(err) => Some(err),
+            ConfigError::InvalidFieldSelector(err) => Some(err),
+            ConfigError::ConflictingChildTracking(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvalidLabelSelector> for ConfigError {
+    fn from(err: InvalidLabelSelector) -> ConfigError {
+        ConfigError::InvalidLabelSelector(err)
+    }
+}
+
+impl From<InvalidFieldSelector> for ConfigError {
+    fn from(err: InvalidFieldSelector) -> ConfigError {
+        ConfigError::InvalidFieldSelector(err)
+    }
+}
+
+impl From<ConflictingChildTracking> for ConfigError {
+    fn from(err: ConflictingChildTracking) -> ConfigError {
+        ConfigError::ConflictingChildTracking(err)
+    }
+}
+
+impl OperatorConfig {
+    /// Checks the config for mistakes that would otherwise cause the operator to fail in confusing ways after it's
+    /// started. This is called automatically when the operator is started, before any requests are made to the api
+    /// server. Returns the first problem that was found.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.operator_name.is_empty() {
+            return Err(ConfigError::EmptyOperatorName);
+        }
+        if self.finalizer_name.is_empty() {
+            return Err(ConfigError::EmptyFinalizerName);
+        }
+        if self.child_declaration(self.parent).is_some() {
+            return Err(ConfigError::ChildTypeIsParentType(self.parent));
+        }
+        let namespaced_cluster_child = self
+            .child_types
+            .iter()
+            .find(|child| child.scope == Scope::Namespaced && !child.k8s_type.is_namespaced());
+        if let Some(child) = namespaced_cluster_child {
+            return Err(ConfigError::NamespacedClusterScopedChild(child.k8s_type));
+        }
+        if self.tracking_label_name == self.ownership_label_name {
+            return Err(ConfigError::DuplicateLabelNames(
+                self.tracking_label_name.clone(),
+            ));
+        }
+        for name in &[&self.tracking_label_name, &self.ownership_label_name] {
+            if self.managed_labels.contains_key(name.as_str()) {
+                return Err(ConfigError::ReservedManagedLabel((*name).clone()));
+            }
+        }
+        let managed_mapped_type = self.watched_type_mappers.keys().find(|k8s_type| {
+            **k8s_type == self.parent || self.child_declaration(k8s_type).is_some()
+        });
+        if let Some(k8s_type) = managed_mapped_type {
+            return Err(ConfigError::MappedTypeIsManaged(k8s_type));
+        }
+        if self.track_via_owner_references {
+            let other_cluster_child = self
+                .child_types
+                .iter()
+                .find(|child| child.config.client.is_some());
+            if let Some(child) = other_cluster_child {
+                return Err(ConfigError::OwnerReferenceToOtherCluster(child.k8s_type));
+            }
+            if self.parent.is_namespaced() {
+                let cluster_scoped_child = self
+                    .child_types
+                    .iter()
+                    .find(|child| !child.k8s_type.is_namespaced());
+                if let Some(child) = cluster_scoped_child {
+                    return Err(ConfigError::OwnerReferenceToNamespacedParent(
+                        child.k8s_type,
+                    ));
+                }
+            }
+        }
+        if self.namespaces.as_ref().map(Vec::is_empty).unwrap_or(false) {
+            return Err(ConfigError::EmptyNamespaces);
+        }
+        if self.namespaces.is_some() && self.namespace_selector.is_some() {
+            return Err(ConfigError::ConflictingNamespaces);
+        }
+        if let Some(leader_election) = self.resolved_leader_election() {
+            if leader_election.lease_namespace.is_empty() {
+                return Err(ConfigError::UnknownOperatorNamespace);
+            }
+        }
+        self.validate_ports()?;
+        self.validate_label_selector()?;
+        self.validate_field_selector()?;
+        self.validate_child_tracking()?;
+        Ok(())
+    }
+
+    fn validate_ports(&self) -> Result<(), ConfigError> {
+        if self.metrics_port.is_some() && !self.expose_metrics {
+            return Err(ConfigError::PortOfDisabledServer("metrics_port"));
+        }
+        if self.health_port.is_some() && !self.expose_health {
+            return Err(ConfigError::PortOfDisabledServer("health_port"));
+        }
+        let exposed_ports = [
+            Some(self.metrics_server_port()).filter(|_| self.expose_metrics),
+            Some(self.health_server_port()).filter(|_| self.expose_health),
+            Some(self.server_port).filter(|_| self.admin_endpoints),
+        ];
+        if exposed_ports.contains(&Some(0)) {
+            return Err(ConfigError::InvalidServerPort);
+        }
+        if let Some(webhook) = self.webhook.as_ref() {
+            if exposed_ports.contains(&Some(webhook.port)) {
+                return Err(ConfigError::ConflictingPorts(webhook.port));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{
+        detect_operator_namespace, ChildConfig, ChildDeclaration, ClientConfig,
+        LeaderElectionConfig,
+    };
+    use crate::k8s_types::core::v1::{ConfigMap, Namespace, Pod};
+    use crate::webhook::WebhookConfig;
+
+    fn config() -> OperatorConfig {
+        OperatorConfig::new("test-operator", Pod).with_child(ConfigMap, ChildConfig::replace())
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(Ok(()), config().validate());
+    }
+
+    #[test]
+    fn each_invalid_config_is_reported() {
+        let mut same_labels = config();
+        same_labels.tracking_label_name = "example.com/label".to_owned();
+        same_labels.ownership_label_name = "example.com/label".to_owned();
+
+        let other_cluster: ClientConfig = serde_yaml::from_str(
+            "{apiServerEndpoint: 'https://other:6443', userAgent: test, credentials: {header: 'Bearer abc'}}",
+        )
+        .unwrap();
+
+        let cases = vec![
+            (
+                OperatorConfig::new("", Pod).with_finalizer_name("example.com/test"),
+                ConfigError::EmptyOperatorName,
+            ),
+            (
+                config().with_finalizer_name(""),
+                ConfigError::EmptyFinalizerName,
+            ),
+            (
+                config().with_child(Pod, ChildConfig::replace()),
+                ConfigError::ChildTypeIsParentType(Pod),
+            ),
+            (
+                same_labels,
+                ConfigError::DuplicateLabelNames("example.com/label".to_owned()),
+            ),
+            (
+                config().with_managed_labels(vec![(
+                    crate::config::DEFAULT_OWNERSHIP_LABEL_NAME.to_owned(),
+                    "other".to_owned(),
+                )]),
+                ConfigError::ReservedManagedLabel(
+                    crate::config::DEFAULT_OWNERSHIP_LABEL_NAME.to_owned(),
+                ),
+            ),
+            (
+                config().with_child_declaration(
+                    ChildDeclaration::new(Namespace, ChildConfig::replace())
+                        .with_scope(Scope::Namespaced),
+                ),
+                ConfigError::NamespacedClusterScopedChild(Namespace),
+            ),
+            (config().server_port(0), ConfigError::InvalidServerPort),
+            (
+                config().server_port(0).metrics_port(9090),
+                ConfigError::InvalidServerPort,
+            ),
+            (
+                config()
+                    .server_port(0)
+                    .metrics_port(9090)
+                    .health_port(8081)
+                    .enable_admin_endpoints(true),
+                ConfigError::InvalidServerPort,
+            ),
+            (
+                config().expose_metrics(false).metrics_port(9090),
+                ConfigError::PortOfDisabledServer("metrics_port"),
+            ),
+            (
+                config().expose_health(false).health_port(8081),
+                ConfigError::PortOfDisabledServer("health_port"),
+            ),
+            (
+                config()
+                    .metrics_port(8443)
+                    .with_webhook(WebhookConfig::new("cert.pem", "key.pem")),
+                ConfigError::ConflictingPorts(8443),
+            ),
+            (
+                config().with_mapped_watched_type(ConfigMap, |_| Vec::new()),
+                ConfigError::MappedTypeIsManaged(ConfigMap),
+            ),
+            (
+                OperatorConfig::new("test-operator", Pod)
+                    .track_via_owner_references(true)
+                    .with_child(ConfigMap, ChildConfig::replace().with_client(other_cluster)),
+                ConfigError::OwnerReferenceToOtherCluster(ConfigMap),
+            ),
+            (
+                OperatorConfig::new("test-operator", Pod)
+                    .track_via_owner_references(true)
+                    .with_child(Namespace, ChildConfig::replace()),
+                ConfigError::OwnerReferenceToNamespacedParent(Namespace),
+            ),
+            (
+                config()
+                    .within_namespace("default")
+                    .namespaces_matching("example.com/tenant"),
+                ConfigError::ConflictingNamespaces,
+            ),
+            (
+                config().within_namespaces(Vec::new()),
+                ConfigError::EmptyNamespaces,
+            ),
+        ];
+        for (config, expected) in cases {
+            assert_eq!(Err(expected), config.validate());
+        }
+    }
+
+    #[test]
+    fn server_port_is_ignored_when_nothing_is_exposed() {
+        let config = config()
+            .server_port(0)
+            .expose_metrics(false)
+            .expose_health(false);
+        assert_eq!(Ok(()), config.validate());
+    }
+
+    #[test]
+    fn metrics_and_health_may_use_separate_ports() {
+        let config = config().server_port(0).metrics_port(9090).health_port(8081);
+        assert_eq!(Ok(()), config.validate());
+        assert_eq!(9090, config.metrics_server_port());
+        assert_eq!(8081, config.health_server_port());
+    }
+
+    #[test]
+    fn existing_validations_are_included() {
+        let result = config().with_label_selector("foo in (bar").validate();
+        assert!(matches!(result, Err(ConfigError::InvalidLabelSelector(_))));
+        let result = config()
+            .with_child_declaration(
+                ChildDeclaration::new(ConfigMap, ChildConfig::replace())
+                    .with_label_selector("foo in (bar"),
+            )
+            .validate();
+        assert!(matches!(result, Err(ConfigError::InvalidLabelSelector(_))));
+        let result = config().namespaces_matching("foo in (bar").validate();
+        assert!(matches!(result, Err(ConfigError::InvalidLabelSelector(_))));
+
+        let mut config = config().track_via_owner_references(true);
+        config.tracking_label_name = "example.com/parent".to_owned();
+        let result = config.validate();
+        assert!(matches!(
+            result,
+            Err(ConfigError::ConflictingChildTracking(_))
+        ));
+    }
+
+    #[test]
+    fn operator_namespace_is_detected_from_the_service_account_then_the_environment() {
+        let path = std::env::temp_dir().join(format!("roperator-namespace-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let actual =
+            detect_operator_namespace(&path, Some("from-env".to_owned()), Some("explicit"));
+        assert_eq!(Some("from-file".to_owned()), actual);
+        std::fs::remove_file(&path).unwrap();
+
+        let actual =
+            detect_operator_namespace(&path, Some("from-env".to_owned()), Some("explicit"));
+        assert_eq!(Some("from-env".to_owned()), actual);
+        let actual = detect_operator_namespace(&path, Some(String::new()), Some("explicit"));
+        assert_eq!(Some("explicit".to_owned()), actual);
+        assert_eq!(None, detect_operator_namespace(&path, None, None));
+    }
+
+    #[test]
+    fn lease_namespace_defaults_to_the_operator_namespace() {
+        let detected = config()
+            .with_leader_election_config(LeaderElectionConfig::in_operator_namespace("lease"))
+            .with_operator_namespace("operators");
+        assert_eq!(Ok(()), detected.validate());
+        // the explicit value is only used if the namespace can't be detected, so it's not always the one that's used
+        assert_eq!(
+            detected.operator_namespace(),
+            detected
+                .resolved_leader_election()
+                .map(|leader_election| leader_election.lease_namespace)
+        );
+
+        let explicit = config().with_leader_election("lease", "elsewhere");
+        assert_eq!(
+            "elsewhere",
+            explicit.resolved_leader_election().unwrap().lease_namespace
+        );
+    }
+}
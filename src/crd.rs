@@ -0,0 +1,259 @@
+//! Generates `CustomResourceDefinition` manifests from Rust types, so that the CRD schema doesn't drift away from
+//! the structs that your operator uses. The OpenAPI v3 schema is derived using [schemars](https://docs.rs/schemars),
+//! which is re-exported from this module so that you can use `#[derive(JsonSchema)]` with a compatible version.
+//!
+//! ```
+//! use roperator::crd::{generate_crd, schemars::JsonSchema};
+//! use roperator::prelude::K8sType;
+//! use roperator::k8s_types::Scope;
+//!
+//! #[derive(JsonSchema)]
+//! struct FooSpec {
+//!     replicas: u32,
+//!     image: String,
+//! }
+//!
+//! static FOO: &K8sType = &K8sType {
+//!     api_version: "example.com/v1",
+//!     kind: "Foo",
+//!     plural_kind: "foos",
+//!     scope: Scope::Namespaced,
+//! };
+//!
+//! let crd = generate_crd::<FooSpec>(FOO);
+//! let yaml = roperator::serde_yaml::to_string(&crd).unwrap();
+//! assert!(yaml.contains("foos.example.com"));
+//! ```
+use crate::k8s_types::K8sType;
+
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+pub use schemars;
+
+/// Returns a `CustomResourceDefinition` for the given type, with the same scope as the type, using the schema of `Spec` for the
+/// `spec` field. This is the same as `CrdBuilder::new::<Spec>(k8s_type).build()`.
+pub fn generate_crd<Spec: JsonSchema>(k8s_type: &'static K8sType) -> serde_yaml::Value {
+    CrdBuilder::new::<Spec>(k8s_type).build()
+}
+
+/// Builds a `CustomResourceDefinition`, for when the defaults used by `generate_crd` aren't what you need
+#[derive(Debug, Clone)]
+pub struct CrdBuilder {
+    k8s_type: &'static K8sType,
+    spec_schema: Value,
+    status_schema: Option<Value>,
+    status_subresource: bool,
+    cluster_scoped: bool,
+}
+
+impl CrdBuilder {
+    /// Creates a new builder for a CRD with the same scope as `k8s_type`, using the schema of `Spec` for the `spec`
+    /// field
+    pub fn new<Spec: JsonSchema>(k8s_type: &'static K8sType) -> CrdBuilder {
+        CrdBuilder {
+            k8s_type,
+            spec_schema: schema_for::<Spec>(),
+            status_schema: None,
+            status_subresource: false,
+            cluster_scoped: !k8s_type.is_namespaced(),
+        }
+    }
+
+    /// Uses the schema of `Status` for the `status` field, and enables the status subresource
+    pub fn with_status<Status: JsonSchema>(mut self) -> Self {
+        self.status_schema = Some(schema_for::<Status>());
+        self.status_subresource = true;
+        self
+    }
+
+    /// Sets whether to enable the status subresource. If no status schema was given using `with_status`, then any
+    /// status will be accepted.
+    pub fn with_status_subresource(mut self, status_subresource: bool) -> Self {
+        self.status_subresource = status_subresource;
+        self
+    }
+
+    /// Sets whether the resource is cluster scoped, rather than namespaced. This defaults to the `scope` of the
+    /// `K8sType`, so it's only needed if that's not what the CRD should use.
+    pub fn cluster_scoped(mut self, cluster_scoped: bool) -> Self {
+        self.cluster_scoped = cluster_scoped;
+        self
+    }
+
+    /// Returns the `CustomResourceDefinition`, which can be serialized as yaml using `serde_yaml::to_string`
+    pub fn build(self) -> serde_yaml::Value {
+        let (group, version) = split_api_version(self.k8s_type.api_version);
+        let kind = self.k8s_type.kind;
+        let plural = self.k8s_type.plural_kind;
+
+        let mut properties = serde_json::Map::new();
+        properties.insert("spec".to_owned(), self.spec_schema);
+        if let Some(status) = self.status_schema {
+            properties.insert("status".to_owned(), status);
+        } else if self.status_subresource {
+            properties.insert(
+                "status".to_owned(),
+                json!({
+                    "type": "object",
+                    "x-kubernetes-preserve-unknown-fields": true,
+                }),
+            );
+        }
+
+        let mut crd_version = json!({
+            "name": version,
+            "served": true,
+            "storage": true,
+            "schema": {
+                "openAPIV3Schema": {
+                    "type": "object",
+                    "properties": properties,
+                },
+            },
+        });
+        if self.status_subresource {
+            crd_version
+                .as_object_mut()
+                .unwrap()
+                .insert("subresources".to_owned(), json!({ "status": {} }));
+        }
+
+        let crd = json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": {
+                "name": format!("{}.{}", plural, group),
+            },
+            "spec": {
+                "group": group,
+                "scope": if self.cluster_scoped { "Cluster" } else { "Namespaced" },
+                "names": {
+                    "kind": kind,
+                    "plural": plural,
+                    "singular": kind.to_lowercase(),
+                },
+                "versions": [crd_version],
+            },
+        });
+        serde_yaml::to_value(&crd).expect("CRD json is always valid yaml")
+    }
+}
+
+/// Returns the OpenAPI v3 schema for the type. Subschemas are inlined, since Kubernetes doesn't allow `$ref`s
+fn schema_for<T: JsonSchema>() -> Value {
+    let mut settings = SchemaSettings::openapi3();
+    settings.inline_subschemas = true;
+    settings.meta_schema = None;
+    let root = settings.into_generator().into_root_schema_for::<T>();
+    let mut schema = serde_json::to_value(&root.schema).expect("failed to serialize schema");
+    if let Some(obj) = schema.as_object_mut() {
+        // the title is just the name of the rust type, which isn't useful in a CRD
+        obj.remove("title");
+    }
+    schema
+}
+
+fn split_api_version(api_version: &str) -> (&str, &str) {
+    let mut parts = api_version.splitn(2, '/');
+    let first = parts.next().unwrap_or("");
+    match parts.next() {
+        Some(version) => (first, version),
+        // core types don't have a group, but they also can't be custom resources
+        None => ("", first),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::k8s_types::Scope;
+
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct TestSpec {
+        replicas: u32,
+        image: Option<String>,
+        nested: Nested,
+    }
+
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct Nested {
+        enabled: bool,
+    }
+
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct TestStatus {
+        ready: bool,
+    }
+
+    static TEST_TYPE: &K8sType = &K8sType {
+        api_version: "example.com/v1alpha1",
+        kind: "TestThing",
+        plural_kind: "testthings",
+        scope: Scope::Namespaced,
+    };
+
+    fn as_json(crd: serde_yaml::Value) -> Value {
+        serde_yaml::from_value(crd).unwrap()
+    }
+
+    #[test]
+    fn crd_is_generated_with_names_from_k8s_type() {
+        let crd = as_json(generate_crd::<TestSpec>(TEST_TYPE));
+
+        assert_eq!(
+            Some(&json!("testthings.example.com")),
+            crd.pointer("/metadata/name")
+        );
+        assert_eq!(Some(&json!("example.com")), crd.pointer("/spec/group"));
+        assert_eq!(Some(&json!("Namespaced")), crd.pointer("/spec/scope"));
+        assert_eq!(
+            Some(&json!({"kind": "TestThing", "plural": "testthings", "singular": "testthing"})),
+            crd.pointer("/spec/names")
+        );
+        assert_eq!(
+            Some(&json!("v1alpha1")),
+            crd.pointer("/spec/versions/0/name")
+        );
+        assert!(crd.pointer("/spec/versions/0/subresources").is_none());
+
+        let spec = crd
+            .pointer("/spec/versions/0/schema/openAPIV3Schema/properties/spec")
+            .unwrap();
+        assert_eq!(Some(&json!("object")), spec.get("type"));
+        assert_eq!(
+            Some(&json!("boolean")),
+            spec.pointer("/properties/nested/properties/enabled/type")
+        );
+        assert_eq!(
+            Some(&json!(true)),
+            spec.pointer("/properties/image/nullable")
+        );
+        assert!(!serde_json::to_string(spec).unwrap().contains("$ref"));
+    }
+
+    #[test]
+    fn status_subresource_is_added_with_status_schema() {
+        let crd = as_json(
+            CrdBuilder::new::<TestSpec>(TEST_TYPE)
+                .with_status::<TestStatus>()
+                .cluster_scoped(true)
+                .build(),
+        );
+        assert_eq!(Some(&json!("Cluster")), crd.pointer("/spec/scope"));
+        assert_eq!(
+            Some(&json!({ "status": {} })),
+            crd.pointer("/spec/versions/0/subresources")
+        );
+        assert_eq!(
+            Some(&json!("boolean")),
+            crd.pointer(
+                "/spec/versions/0/schema/openAPIV3Schema/properties/status/properties/ready/type"
+            )
+        );
+    }
+}
@@ -9,20 +9,31 @@
 #[cfg(any(feature = "failable", docs))]
 pub mod failable;
 
+pub(crate) mod cache;
+pub(crate) mod cancellation;
+pub(crate) mod condition;
+pub(crate) mod scale;
+
 // only expose the reqeust mod during tests.
 #[cfg(feature = "test")]
 pub mod request;
 
 #[cfg(not(feature = "test"))]
-mod request;
+pub(crate) mod request;
 
+use crate::k8s_types::{K8sType, Scope};
+use crate::resource::K8sResource;
 use anyhow::Error;
 use serde::Serialize;
 use serde_json::Value;
 use std::fmt::{self, Debug};
 use std::time::Duration;
 
+pub use self::cache::ResourceCache;
+pub use self::cancellation::{CancellationToken, Cancelled};
+pub use self::condition::Condition;
 pub use self::request::{RawView, RequestChildren, SyncRequest, TypedIter, TypedView};
+pub use self::scale::{ChildScale, Scale, ScaleMetadata, ScaleSpec};
 /// The return value from your handler function, which has the status to set for the parent, as well as any
 /// desired child resources. Any existing child resources that are **not** included in this response **will be deleted**.
 #[derive(Deserialize, Serialize, Clone, PartialEq)]
@@ -46,6 +57,12 @@ pub struct SyncResponse {
     /// if you want a time-based resync in addition. The typical use cases are for error handling
     /// and managing external (non-k8s) resources.
     pub resync: Option<Duration>,
+
+    /// The desired number of replicas of children, which are set using each child's `scale` subresource, instead of
+    /// by updating the whole child. This is usually used together with leaving `spec.replicas` out of the desired
+    /// child, so that the replicas aren't changed by the child update as well.
+    #[serde(default)]
+    pub child_scales: Vec<ChildScale>,
 }
 
 impl Debug for SyncResponse {
@@ -67,6 +84,7 @@ impl SyncResponse {
             status,
             children: Vec::new(),
             resync: None,
+            child_scales: Vec::new(),
         }
     }
 
@@ -82,6 +100,99 @@ impl SyncResponse {
         })
     }
 
+    /// Adds a child of the given type to the response by serializing the given object, which may be one of the
+    /// structs from the `k8s_openapi` crate, or any other serializable type. The `apiVersion` and `kind` are set
+    /// from the `child_type`, and if the child type is namespaced and the object doesn't have a namespace, then it's
+    /// put in the parent's namespace. The tracking labels and owner reference are always added by roperator once
+    /// the response is returned, so they don't need to be set here. Returns an error if the child doesn't serialize
+    /// to a JSON object.
+    ///
+    /// ```
+    /// extern crate roperator;
+    /// extern crate k8s_openapi;
+    ///
+    /// use k8s_openapi::api::core::v1::ConfigMap;
+    /// use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    /// use roperator::handler::SyncResponse;
+    /// use roperator::serde_json::{json, Value};
+    /// # let request = roperator::handler::request::test_request();
+    ///
+    /// let config_map = ConfigMap {
+    ///     metadata: Some(ObjectMeta {
+    ///         name: Some(format!("{}-config", request.parent.name())),
+    ///         ..Default::default()
+    ///     }),
+    ///     ..Default::default()
+    /// };
+    /// let mut response = SyncResponse::new(Value::Null);
+    /// response
+    ///     .add_child_of_type(&request.parent, roperator::k8s_types::core::v1::ConfigMap, config_map)
+    ///     .expect("failed to serialize ConfigMap");
+    /// assert_eq!(json!("ConfigMap"), response.children[0]["kind"]);
+    /// assert_eq!(json!(request.parent.namespace()), response.children[0]["metadata"]["namespace"]);
+    /// ```
+    pub fn add_child_of_type<C: Serialize>(
+        &mut self,
+        parent: &K8sResource,
+        child_type: &K8sType,
+        child: C,
+    ) -> Result<(), serde_json::Error> {
+        let mut child = serde_json::to_value(child)?;
+        let obj = child.as_object_mut().ok_or_else(|| {
+            serde::ser::Error::custom(format!("{} child is not a JSON object", child_type))
+        })?;
+        obj.insert("apiVersion".to_owned(), child_type.api_version.into());
+        obj.insert("kind".to_owned(), child_type.kind.into());
+        if let (Scope::Namespaced, Some(namespace)) = (child_type.scope, parent.namespace()) {
+            let metadata = obj
+                .entry("metadata")
+                .or_insert_with(|| Value::Object(Default::default()));
+            if let Some(metadata) = metadata.as_object_mut() {
+                metadata
+                    .entry("namespace")
+                    .or_insert_with(|| namespace.into());
+            }
+        }
+        self.children.push(child);
+        Ok(())
+    }
+
+    /// Adds the condition to `status.conditions`, replacing any existing condition of the same type. If the status
+    /// is `null`, then it's replaced with an object. The `lastTransitionTime` is only changed when the condition's
+    /// status is different from the one that the parent already has.
+    pub fn set_condition(&mut self, condition: Condition) {
+        condition::upsert_condition(&mut self.status, condition);
+    }
+
+    /// Sets the desired number of replicas of the child with the given type and name, which must support the `scale`
+    /// subresource, such as a `Deployment` or `StatefulSet`. Roperator sends a minimal `Scale` to `PUT .../scale`
+    /// once the children have been updated, and only if the child's current `spec.replicas` is different, so no other
+    /// fields of the child are changed. Namespaced children are looked up in the parent's namespace. Calling this
+    /// again for the same child replaces the previous value.
+    ///
+    /// ```
+    /// use roperator::handler::SyncResponse;
+    /// use roperator::k8s_types::apps::v1::Deployment;
+    /// use roperator::serde_json::Value;
+    ///
+    /// let mut response = SyncResponse::new(Value::Null);
+    /// response.set_child_scale(Deployment, "web", 3);
+    /// response.set_child_scale(Deployment, "web", 5);
+    /// assert_eq!(1, response.child_scales.len());
+    /// assert_eq!(5, response.child_scales[0].replicas);
+    /// ```
+    pub fn set_child_scale(
+        &mut self,
+        child_type: &K8sType,
+        name: impl Into<String>,
+        replicas: i32,
+    ) {
+        let scale = ChildScale::new(child_type, name, replicas);
+        self.child_scales
+            .retain(|existing| !existing.is_same_child(&scale));
+        self.child_scales.push(scale);
+    }
+
     /// sets the `resync` field of the response to `Some(duration)`, which instructs roperator
     /// to invoke your sync handler after the given time period, regardless of whether any
     /// changes are observed.
@@ -106,6 +217,70 @@ pub struct FinalizeResponse {
     pub retry: Option<Duration>,
 }
 
+/// The type of the condition that's set on the parent while it's being finalized, by
+/// `FinalizeResponse::in_progress`, or by roperator when the finalize handler returns an error
+pub const TERMINATING_CONDITION: &str = "Terminating";
+
+/// The type of the condition that roperator sets on a parent that's paused by the `OperatorConfig::pause_annotation`,
+/// instead of invoking the handler
+pub const PAUSED_CONDITION: &str = "Paused";
+
+impl FinalizeResponse {
+    /// Returns a response that allows the deletion of the parent to proceed
+    pub fn finalized(status: Value) -> FinalizeResponse {
+        FinalizeResponse {
+            status,
+            retry: None,
+        }
+    }
+
+    /// Returns a response indicating that the parent isn't finalized yet, for example because the deletion of some
+    /// external resource has been requested, but isn't confirmed yet. The `TERMINATING_CONDITION` is set in the
+    /// status with the given message, so that the progress is visible on the parent, and the finalize function is
+    /// invoked again after `retry`. Each consecutive retry is delayed by at least the next step of the exponential
+    /// `reconcile_backoff`, so polling slows down while the cleanup is taking a long time.
+    ///
+    /// ```rust
+    /// use roperator::handler::{FinalizeResponse, TERMINATING_CONDITION};
+    /// use roperator::serde_json::Value;
+    /// use std::time::Duration;
+    ///
+    /// let response = FinalizeResponse::in_progress(
+    ///     Value::Null,
+    ///     Duration::from_secs(5),
+    ///     "Waiting for the database to be deleted",
+    /// );
+    /// assert_eq!(Some(Duration::from_secs(5)), response.retry);
+    /// assert_eq!(
+    ///     Some(TERMINATING_CONDITION),
+    ///     response.status.pointer("/conditions/0/type").and_then(Value::as_str)
+    /// );
+    /// ```
+    pub fn in_progress(
+        status: Value,
+        retry: Duration,
+        message: impl Into<String>,
+    ) -> FinalizeResponse {
+        let mut response = FinalizeResponse {
+            status,
+            retry: Some(retry),
+        };
+        response.set_condition(Condition::new(
+            TERMINATING_CONDITION,
+            true,
+            "CleanupInProgress",
+            message,
+        ));
+        response
+    }
+
+    /// Adds the condition to `status.conditions`, the same as `SyncResponse::set_condition`. This only has an effect
+    /// if the parent isn't finalized yet, since the status isn't updated otherwise.
+    pub fn set_condition(&mut self, condition: Condition) {
+        condition::upsert_condition(&mut self.status, condition);
+    }
+}
+
 impl Debug for FinalizeResponse {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let as_string = if f.alternate() {
@@ -118,6 +293,98 @@ impl Debug for FinalizeResponse {
     }
 }
 
+/// An error that can be returned from a `Handler` in order to control how roperator responds to it. Since `ReconcileError`
+/// implements `std::error::Error`, it can be returned from `sync` or `finalize` using either `?` or `.into()`. Any other
+/// error that's returned from a handler is treated as `Retryable` with the default backoff.
+///
+/// ```rust
+/// use roperator::prelude::*;
+/// use roperator::handler::ReconcileError;
+///
+/// fn sync(request: &SyncRequest) -> Result<SyncResponse, Error> {
+///     let replicas = request.parent.pointer("/spec/replicas").and_then(|r| r.as_u64());
+///     if replicas.is_none() {
+///         // there's no point in retrying until the parent is changed
+///         return Err(ReconcileError::invalid_resource("spec.replicas must be a number").into());
+///     }
+///     Ok(SyncResponse::new(serde_json::Value::Null))
+/// }
+/// ```
+#[derive(Debug)]
+pub enum ReconcileError {
+    /// A transient error, such as a failure to reach an external service. The sync will be retried after the
+    /// `backoff` if it's `Some`, or else after the normal error backoff.
+    Retryable {
+        source: Error,
+        backoff: Option<Duration>,
+    },
+    /// An error that won't be fixed by retrying. The parent won't be synced again until either it or one of its
+    /// children is changed.
+    Fatal(Error),
+    /// The parent resource is malformed, so it will be skipped until it's changed. This is not counted as a sync error.
+    InvalidResource(String),
+}
+
+impl ReconcileError {
+    /// Returns a `Retryable` error that will use the normal error backoff
+    pub fn retryable<E: Into<Error>>(source: E) -> ReconcileError {
+        ReconcileError::Retryable {
+            source: source.into(),
+            backoff: None,
+        }
+    }
+
+    /// Returns a `Retryable` error that will be retried after the given duration instead of the normal error backoff
+    pub fn retry_after<E: Into<Error>>(source: E, backoff: Duration) -> ReconcileError {
+        ReconcileError::Retryable {
+            source: source.into(),
+            backoff: Some(backoff),
+        }
+    }
+
+    /// Returns a `Fatal` error, which will not be retried
+    pub fn fatal<E: Into<Error>>(source: E) -> ReconcileError {
+        ReconcileError::Fatal(source.into())
+    }
+
+    /// Returns an `InvalidResource` error with the given description of what's wrong with the parent
+    pub fn invalid_resource<S: Into<String>>(message: S) -> ReconcileError {
+        ReconcileError::InvalidResource(message.into())
+    }
+}
+
+impl fmt::Display for ReconcileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReconcileError::Retryable { source, .. } => write!(f, "Retryable error: {}", source),
+            ReconcileError::Fatal(source) => write!(f, "Fatal error: {}", source),
+            ReconcileError::InvalidResource(message) => write!(f, "Invalid resource: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ReconcileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReconcileError::Retryable { source, .. } | ReconcileError::Fatal(source) => {
+                Some(source.as_ref())
+            }
+            ReconcileError::InvalidResource(_) => None,
+        }
+    }
+}
+
+/// Converts any error returned from a handler. If the error is a `ReconcileError`, then it's returned as is, and
+/// otherwise it's wrapped as `Retryable`.
+impl From<Error> for ReconcileError {
+    fn from(err: Error) -> ReconcileError {
+        match err.downcast::<ReconcileError>() {
+            Ok(reconcile_error) => reconcile_error,
+            Err(other) => ReconcileError::retryable(other),
+        }
+    }
+}
+
 /// The main trait that's used to implement your operator. Most operators will only need to implement
 /// the `sync` function.
 pub trait Handler: Send + Sync + 'static {
@@ -132,9 +399,18 @@ pub trait Handler: Send + Sync + 'static {
     /// based on some immutable metadata from the parent resource (name, namespace, uid).
     ///
     /// If this function returns an `Err`, then roperator will retry calling this function
-    /// after applying a backoff delay.
+    /// after applying a backoff delay. Return a `ReconcileError` to control whether and when it gets retried.
     fn sync(&self, request: &SyncRequest) -> Result<SyncResponse, Error>;
 
+    /// Invoked immediately before every call to `sync`, on the same thread. This is an extension point for logic that
+    /// applies to every sync, such as audit logging or timing, so that it doesn't need to be repeated in the handler.
+    /// It's not invoked before `finalize`. The default implementation does nothing.
+    fn before_sync(&self, _request: &SyncRequest) {}
+
+    /// Invoked immediately after every call to `sync`, with the result that it returned, before any changes are made
+    /// to the parent or its children. The default implementation does nothing.
+    fn after_sync(&self, _request: &SyncRequest, _result: &Result<SyncResponse, Error>) {}
+
     /// Finalize is invoked whenever the parent resource starts being deleted. Roperator makes every reasonable attempt to
     /// ensure that this function gets invoked _at least once_ for each parent as it's being deleted. We cannot make any
     /// guarantees, though, since it's possible for Kuberentes resources to be force deleted without waiting for finalizers.
@@ -148,7 +424,8 @@ pub trait Handler: Send + Sync + 'static {
     /// not modify the status.
     ///
     /// If this function returns an `Err`, then roperator will retry calling this function
-    /// after applying a backoff delay.
+    /// after applying a backoff delay. If it panics, then it's treated as a fatal error and isn't retried, so the
+    /// parent keeps its finalizer until it's changed again or the operator is restarted.
     fn finalize(&self, request: &SyncRequest) -> Result<FinalizeResponse, Error> {
         Ok(FinalizeResponse {
             status: request.parent.status().cloned().unwrap_or(Value::Null),
@@ -179,6 +456,7 @@ where
                 status,
                 resync,
                 children: Vec::new(),
+                child_scales: Vec::new(),
             })
         })
     }
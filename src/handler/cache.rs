@@ -0,0 +1,191 @@
+//! Contains the `ResourceCache`, which gives handlers read-only access to the resources that the operator is
+//! already watching, so that they can be looked up without making a request to the api server.
+use crate::k8s_types::K8sType;
+use crate::resource::{K8sResource, ObjectIdRef};
+
+use serde::de::DeserializeOwned;
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// Read access to the local cache of a single resource type
+pub(crate) trait CachedType: Send + Sync {
+    fn get(&self, id: &ObjectIdRef<'_>) -> Option<K8sResource>;
+    fn list(&self, namespace: Option<&str>) -> Vec<K8sResource>;
+}
+
+/// A read-only view of the resources that the operator is watching, which is available to the handler from
+/// `SyncRequest::cache()`. This includes the parent type, all of the child types, and any extra types that were
+/// added with `OperatorConfig::with_watched_type`. Lookups are served from memory, so they're much cheaper than
+/// making a request to the api server, but the results may be slightly out of date.
+///
+/// Unlike the rest of the `SyncRequest`, the cache is not a snapshot. It always reflects the latest state that the
+/// operator has observed, so two lookups of the same resource may return different results. Looking up a type that
+/// isn't watched by the operator always returns `None`, and child types only include resources that are managed by
+/// this operator.
+///
+/// ```rust
+/// use roperator::k8s_types::core::v1::Secret;
+///
+/// # let request = roperator::handler::request::test_request();
+/// if let Some(secret) = request.cache().get(Secret, ("foo", "credentials")) {
+///     println!("found secret: {}", secret.get_object_id());
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct ResourceCache {
+    types: Arc<HashMap<&'static K8sType, Arc<dyn CachedType>>>,
+}
+
+impl ResourceCache {
+    pub(crate) fn new(types: HashMap<&'static K8sType, Arc<dyn CachedType>>) -> ResourceCache {
+        ResourceCache {
+            types: Arc::new(types),
+        }
+    }
+
+    /// Returns a new cache with all of the same types, plus the given one
+    pub(crate) fn with_type(
+        &self,
+        k8s_type: &'static K8sType,
+        cached: Arc<dyn CachedType>,
+    ) -> ResourceCache {
+        let mut types = (*self.types).clone();
+        types.insert(k8s_type, cached);
+        ResourceCache::new(types)
+    }
+
+    /// Returns true if resources of the given type are being watched, and so may be returned from this cache
+    pub fn is_watched(&self, k8s_type: &K8sType) -> bool {
+        self.types.contains_key(k8s_type)
+    }
+
+    /// Returns a copy of the resource with the given type and id, if it exists in the cache. Namespace can be an
+    /// empty str for resources that are not namespaced.
+    pub fn get<'a>(
+        &self,
+        k8s_type: &K8sType,
+        id: impl Into<ObjectIdRef<'a>>,
+    ) -> Option<K8sResource> {
+        let id = id.into();
+        self.types.get(k8s_type).and_then(|cached| cached.get(&id))
+    }
+
+    /// Finds the resource with the given type and id, and deserializes it as the given type. Returns `Ok(None)` if
+    /// there's no such resource in the cache.
+    pub fn get_typed<'a, T: DeserializeOwned>(
+        &self,
+        k8s_type: &K8sType,
+        id: impl Into<ObjectIdRef<'a>>,
+    ) -> Result<Option<T>, serde_json::Error> {
+        self.get(k8s_type, id)
+            .map(K8sResource::into_type)
+            .transpose()
+    }
+
+    /// Returns copies of all the cached resources of the given type in the given namespace, or in every namespace
+    /// if `namespace` is `None`
+    pub fn list(&self, k8s_type: &K8sType, namespace: Option<&str>) -> Vec<K8sResource> {
+        self.types
+            .get(k8s_type)
+            .map(|cached| cached.list(namespace))
+            .unwrap_or_default()
+    }
+}
+
+impl Debug for ResourceCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.types.keys()).finish()
+    }
+}
+
+// The cache isn't part of the state of a request, so it's ignored when comparing them
+impl PartialEq for ResourceCache {
+    fn eq(&self, _: &ResourceCache) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::k8s_types::core::v1::{Pod, Secret};
+    use crate::runner::resource_map::ResourceMap;
+    use serde_json::json;
+
+    struct MapCache(ResourceMap);
+
+    impl CachedType for MapCache {
+        fn get(&self, id: &ObjectIdRef<'_>) -> Option<K8sResource> {
+            self.0.get_copy(id.clone())
+        }
+
+        fn list(&self, namespace: Option<&str>) -> Vec<K8sResource> {
+            self.0.iter_namespace(namespace).cloned().collect()
+        }
+    }
+
+    fn secret(namespace: &str, name: &str) -> K8sResource {
+        K8sResource::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": {
+                "namespace": namespace,
+                "name": name,
+                "uid": format!("{}-{}", namespace, name),
+                "resourceVersion": "1",
+            },
+            "data": {
+                "password": "aHVudGVyMg==",
+            }
+        }))
+        .unwrap()
+    }
+
+    fn cache_of_secrets() -> ResourceCache {
+        let mut map = ResourceMap::new();
+        map.insert(secret("foo", "a"));
+        map.insert(secret("foo", "b"));
+        map.insert(secret("bar", "a"));
+        let mut types: HashMap<&'static K8sType, Arc<dyn CachedType>> = HashMap::new();
+        types.insert(Secret, Arc::new(MapCache(map)));
+        ResourceCache::new(types)
+    }
+
+    #[test]
+    fn resources_are_looked_up_by_type_and_id() {
+        let cache = cache_of_secrets();
+        assert!(cache.is_watched(Secret));
+        assert!(!cache.is_watched(Pod));
+
+        let result = cache.get(Secret, ("bar", "a")).unwrap();
+        assert_eq!("bar-a", result.uid());
+        assert!(cache.get(Secret, ("bar", "b")).is_none());
+        assert!(cache.get(Pod, ("foo", "a")).is_none());
+
+        let typed = cache
+            .get_typed::<serde_json::Value>(Secret, ("foo", "b"))
+            .unwrap()
+            .unwrap();
+        assert_eq!("aHVudGVyMg==", typed.pointer("/data/password").unwrap());
+    }
+
+    #[test]
+    fn resources_are_listed_by_namespace() {
+        let cache = cache_of_secrets();
+        assert_eq!(3, cache.list(Secret, None).len());
+
+        let mut names = cache
+            .list(Secret, Some("foo"))
+            .iter()
+            .map(|res| res.get_object_id().name().to_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(vec!["a".to_owned(), "b".to_owned()], names);
+
+        assert!(cache.list(Secret, Some("baz")).is_empty());
+        assert!(cache.list(Pod, None).is_empty());
+        assert!(ResourceCache::default().list(Secret, None).is_empty());
+    }
+}
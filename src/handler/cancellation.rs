@@ -0,0 +1,79 @@
+//! Lets the operator tell a handler that's still running that its work is no longer needed
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Tells whether the sync or finalize that a `SyncRequest` is for has been cancelled. The operator cancels it when it
+/// starts shutting down, and when a newer change to the parent or one of its children is observed, since the parent
+/// will be synced again with the newer state as soon as the current sync completes. Handlers that make slow calls to
+/// external systems can check it between steps, in order to give up early. Handlers that never check it still work
+/// the same as before.
+///
+/// ```rust
+/// use roperator::handler::{SyncRequest, SyncResponse};
+/// use roperator::serde_json::json;
+/// # fn provision_database(_: &SyncRequest) {}
+/// # fn provision_user(_: &SyncRequest) {}
+///
+/// fn sync(request: &SyncRequest) -> Result<SyncResponse, roperator::prelude::Error> {
+///     provision_database(request);
+///     // returns a `Cancelled` error if the operator no longer needs the result
+///     request.cancellation().check()?;
+///     provision_user(request);
+///     Ok(SyncResponse::new(json!({"provisioned": true})))
+/// }
+/// ```
+///
+/// If `sync` returns the `Cancelled` error after it was cancelled, then the error isn't reported and the sync isn't
+/// retried with a backoff, since the parent is already going to be synced again (or the operator is shutting down).
+/// Any other error is reported and backs off as usual, even if the sync was cancelled in the meantime. Errors from
+/// `finalize` are handled the same as always.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that hasn't been cancelled
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Returns true once the sync or finalize has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns a `Cancelled` error if the sync or finalize has been cancelled, which makes it easy to bail out
+    /// using `?`
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Cancels every clone of this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// The token isn't part of the state of a request, so it's ignored when comparing them
+impl PartialEq for CancellationToken {
+    fn eq(&self, _: &CancellationToken) -> bool {
+        true
+    }
+}
+
+/// The error that's returned by `CancellationToken::check` once the token has been cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("The sync was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
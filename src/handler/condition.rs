@@ -0,0 +1,229 @@
+//! Helpers for reporting standard `status.conditions` on the parent, following the Kubernetes api conventions
+use chrono::{SecondsFormat, Utc};
+use serde_json::Value;
+
+/// A single entry in `status.conditions`, with the same fields as the `metav1.Condition` type. Add it to the
+/// parent's status using `SyncResponse::set_condition`.
+///
+/// Roperator takes care of the `lastTransitionTime` when the status is updated. If the parent already has a
+/// condition of the same type with the same status, then its existing `lastTransitionTime` is kept, so that it only
+/// changes when the status actually flips. If the parent's type uses the status subresource, then the
+/// `observedGeneration` is also set to the generation that was synced, unless it's already set.
+///
+/// ```rust
+/// use roperator::handler::{Condition, SyncResponse};
+/// use roperator::serde_json::Value;
+///
+/// let mut response = SyncResponse::new(Value::Null);
+/// response.set_condition(Condition::new("Ready", true, "Reconciled", "All children are ready"));
+/// assert_eq!(Some("True"), response.status.pointer("/conditions/0/status").and_then(Value::as_str));
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    /// The type of the condition, in CamelCase, e.g. `Ready`
+    #[serde(rename = "type")]
+    pub condition_type: String,
+    /// One of `True`, `False`, or `Unknown`
+    pub status: String,
+    /// A CamelCase reason for the last transition, e.g. `Reconciled`
+    pub reason: String,
+    /// A human readable message with details about the last transition, which may be empty
+    pub message: String,
+    /// An RFC 3339 timestamp of when the status last changed. If this is `None`, then the current time is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_transition_time: Option<String>,
+    /// The generation of the parent that the condition was determined from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+}
+
+impl Condition {
+    /// Creates a condition with a status of `True` or `False`
+    pub fn new(
+        condition_type: impl Into<String>,
+        status: bool,
+        reason: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Condition {
+        let status = if status { "True" } else { "False" };
+        Condition::with_status(condition_type, status, reason, message)
+    }
+
+    /// Creates a condition with a status of `Unknown`
+    pub fn unknown(
+        condition_type: impl Into<String>,
+        reason: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Condition {
+        Condition::with_status(condition_type, "Unknown", reason, message)
+    }
+
+    fn with_status(
+        condition_type: impl Into<String>,
+        status: &str,
+        reason: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Condition {
+        Condition {
+            condition_type: condition_type.into(),
+            status: status.to_owned(),
+            reason: reason.into(),
+            message: message.into(),
+            last_transition_time: None,
+            observed_generation: None,
+        }
+    }
+}
+
+/// Adds the condition to `status.conditions`, replacing any existing condition of the same type. A `null` status is
+/// replaced with an object, but any other status that isn't an object is left alone.
+pub(crate) fn upsert_condition(status: &mut Value, mut condition: Condition) {
+    if status.is_null() {
+        *status = Value::Object(Default::default());
+    }
+    let obj = match status.as_object_mut() {
+        Some(obj) => obj,
+        None => {
+            log::warn!(
+                "Cannot set condition: {} because the status is not an object",
+                condition.condition_type
+            );
+            return;
+        }
+    };
+    if condition.last_transition_time.is_none() {
+        condition.last_transition_time =
+            Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+    let condition_type = condition.condition_type.clone();
+    let condition = serde_json::to_value(condition).expect("condition is always serializable");
+
+    let conditions = obj
+        .entry("conditions")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if !conditions.is_array() {
+        *conditions = Value::Array(Vec::new());
+    }
+    let conditions = conditions.as_array_mut().unwrap();
+    match conditions
+        .iter_mut()
+        .find(|existing| type_of(existing) == Some(condition_type.as_str()))
+    {
+        Some(existing) => *existing = condition,
+        None => conditions.push(condition),
+    }
+}
+
+/// Returns true if the status has a condition of the given type
+pub(crate) fn has_condition(status: Option<&Value>, condition_type: &str) -> bool {
+    status
+        .and_then(|status| status.pointer("/conditions"))
+        .and_then(Value::as_array)
+        .map(|conditions| {
+            conditions
+                .iter()
+                .any(|condition| type_of(condition) == Some(condition_type))
+        })
+        .unwrap_or(false)
+}
+
+/// Updates the conditions in the new status so that each one keeps the `lastTransitionTime` of the existing
+/// condition of the same type, if its status hasn't changed. If `generation` is `Some`, then it's also set as the
+/// `observedGeneration` of conditions that don't already have one.
+pub(crate) fn preserve_transition_times(
+    old_status: Option<&Value>,
+    new_status: &mut Value,
+    generation: Option<i64>,
+) {
+    let new_conditions = match new_status
+        .pointer_mut("/conditions")
+        .and_then(Value::as_array_mut)
+    {
+        Some(conditions) => conditions,
+        None => return,
+    };
+    let old_conditions = old_status
+        .and_then(|status| status.pointer("/conditions"))
+        .and_then(Value::as_array);
+
+    for condition in new_conditions.iter_mut() {
+        let old = old_conditions.and_then(|old| {
+            old.iter()
+                .find(|old| type_of(old).is_some() && type_of(old) == type_of(condition))
+        });
+        let obj = match condition.as_object_mut() {
+            Some(obj) => obj,
+            None => continue,
+        };
+        let unchanged_since = old
+            .filter(|old| old.pointer("/status") == obj.get("status"))
+            .and_then(|old| old.pointer("/lastTransitionTime"));
+        if let Some(time) = unchanged_since {
+            obj.insert("lastTransitionTime".to_owned(), time.clone());
+        }
+        if let Some(generation) = generation {
+            obj.entry("observedGeneration")
+                .or_insert_with(|| generation.into());
+        }
+    }
+}
+
+fn type_of(condition: &Value) -> Option<&str> {
+    condition.pointer("/type").and_then(Value::as_str)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn conditions_are_upserted_by_type() {
+        let mut status = Value::Null;
+        upsert_condition(&mut status, Condition::new("Ready", false, "Pending", ""));
+        upsert_condition(&mut status, Condition::unknown("Degraded", "Unknown", ""));
+        upsert_condition(
+            &mut status,
+            Condition::new("Ready", true, "Reconciled", "all good"),
+        );
+
+        let conditions = status.pointer("/conditions").unwrap().as_array().unwrap();
+        assert_eq!(2, conditions.len());
+        assert_eq!(Some("Ready"), type_of(&conditions[0]));
+        assert_eq!(json!("True"), conditions[0]["status"]);
+        assert_eq!(json!("all good"), conditions[0]["message"]);
+        assert!(conditions[0]["lastTransitionTime"].is_string());
+        assert_eq!(json!("Unknown"), conditions[1]["status"]);
+    }
+
+    #[test]
+    fn transition_time_only_changes_when_the_status_flips() {
+        let old = json!({
+            "conditions": [
+                {"type": "Ready", "status": "True", "reason": "A", "message": "", "lastTransitionTime": "2020-01-01T00:00:00Z"},
+                {"type": "Degraded", "status": "False", "reason": "A", "message": "", "lastTransitionTime": "2020-01-01T00:00:00Z"},
+            ]
+        });
+        let mut new = json!({
+            "conditions": [
+                {"type": "Ready", "status": "True", "reason": "B", "message": "", "lastTransitionTime": "2021-01-01T00:00:00Z"},
+                {"type": "Degraded", "status": "True", "reason": "B", "message": "", "lastTransitionTime": "2021-01-01T00:00:00Z"},
+                {"type": "Other", "status": "True", "reason": "B", "message": "", "lastTransitionTime": "2021-01-01T00:00:00Z", "observedGeneration": 2},
+            ]
+        });
+        preserve_transition_times(Some(&old), &mut new, Some(3));
+
+        assert_eq!(
+            json!("2020-01-01T00:00:00Z"),
+            new["conditions"][0]["lastTransitionTime"]
+        );
+        assert_eq!(json!("B"), new["conditions"][0]["reason"]);
+        assert_eq!(
+            json!("2021-01-01T00:00:00Z"),
+            new["conditions"][1]["lastTransitionTime"]
+        );
+        assert_eq!(json!(3), new["conditions"][0]["observedGeneration"]);
+        assert_eq!(json!(2), new["conditions"][2]["observedGeneration"]);
+    }
+}
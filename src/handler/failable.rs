@@ -198,6 +198,7 @@ impl BackoffConfig {
 ///                     status,
 ///                     resync: backoff,
 ///                     children: Vec::new(),
+///                     child_scales: Vec::new(),
 ///                 })
 ///             }
 ///         }
@@ -595,6 +596,7 @@ impl<H: FailableHandler> Handler for DefaultFailableHandler<H> {
             resync,
             children,
             status: status_json,
+            child_scales: Vec::new(),
         })
     }
 
@@ -1,6 +1,8 @@
 //! Contains the `SyncRequest`, which is passed to the `Handler` function, as well as some
 //! helpers for accessing and deserializing resources from the request.
 //!
+use crate::handler::cache::ResourceCache;
+use crate::handler::cancellation::CancellationToken;
 use crate::k8s_types::K8sType;
 use crate::resource::{K8sResource, K8sTypeRef, ObjectIdRef, ResourceJson};
 
@@ -18,6 +20,12 @@ pub struct SyncRequest {
     /// The entire set of children related to this parent instance, as they exist in the cluster at the time.
     /// In the happy path, this will include all of the children that have been returned in a previous `SyncResponse`
     pub children: Vec<K8sResource>,
+    /// The operator's cache of watched resources, which isn't part of the serialized request
+    #[serde(skip)]
+    pub(crate) cache: ResourceCache,
+    /// Cancelled by the operator once the result of the handler is no longer needed
+    #[serde(skip)]
+    pub(crate) cancellation: CancellationToken,
 }
 
 impl Debug for SyncRequest {
@@ -41,13 +49,138 @@ impl SyncRequest {
         self.parent.clone().into_type()
     }
 
+    /// Deserialize the parent resource as the given type. This is the same as `deserialize_parent`, but reads a
+    /// bit nicer alongside `typed_child`, e.g. `request.typed_parent::<MyResource>()?`.
+    pub fn typed_parent<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        self.deserialize_parent()
+    }
+
+    /// Finds the child with the given type and name, and deserializes it as the given type. The namespace
+    /// doesn't need to be specified, since children are always in the same namespace as the parent (or else
+    /// cluster-scoped). Returns `Ok(None)` if there's no such child in the request.
+    ///
+    /// ```
+    /// extern crate roperator;
+    /// extern crate k8s_openapi;
+    ///
+    /// use k8s_openapi::api::core::v1::Service;
+    /// # let request = roperator::handler::request::test_request();
+    /// let service: Option<Service> = request
+    ///     .typed_child(roperator::k8s_types::core::v1::Service, "bar")
+    ///     .expect("failed to deserialize Service");
+    /// assert!(service.is_some());
+    /// ```
+    pub fn typed_child<T: DeserializeOwned>(
+        &self,
+        k8s_type: &K8sType,
+        name: &str,
+    ) -> Result<Option<T>, serde_json::Error> {
+        self.children()
+            .of_type(k8s_type)
+            .iter()
+            .find(|child| child.get_object_id().name() == name)
+            .map(|child| child.clone().into_type())
+            .transpose()
+    }
+
+    /// Returns the desired state of the child with the given type and name from the last time that it was updated, as
+    /// it was returned by the handler. This is only stored for child types that have
+    /// `ChildConfig::track_last_applied` enabled, so it returns `None` for other types, and for children that don't
+    /// exist or haven't been updated since it was enabled. Comparing it with the current state of the child tells
+    /// which fields were set by the operator and which were set by others.
+    pub fn last_applied_child(&self, k8s_type: &K8sType, name: &str) -> Option<Value> {
+        self.children()
+            .of_type(k8s_type)
+            .iter()
+            .find(|child| child.get_object_id().name() == name)
+            .and_then(K8sResource::last_applied)
+    }
+
+    /// Returns true if the parent's spec may have changed since it was last synced, by comparing
+    /// `metadata.generation` with the `status.observedGeneration` that roperator sets when it updates the status.
+    /// Handlers can use this to skip expensive work when the sync was only triggered by a change to the status,
+    /// metadata, or a child. Note that the response still needs to include all of the desired children, since any
+    /// that are left out will be deleted.
+    ///
+    /// This returns true whenever the `observedGeneration` is missing, which is always the case if the parent CRD
+    /// doesn't enable the status subresource (see `OperatorConfig::with_status_subresource`). Without the status
+    /// subresource, every update to the parent increments the generation, so it can't be used to detect spec changes.
+    pub fn spec_changed(&self) -> bool {
+        !self.parent.is_generation_observed()
+    }
+
+    /// Returns the token that tells whether this sync or finalize has been cancelled. See `CancellationToken`.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
     /// Returns a view of just the children of this request, which is useful for passing to a function that determines the current
     /// status. The returned view has a variety of functions for accessing individual children and groups of children.
     pub fn children(&self) -> RequestChildren {
         RequestChildren(self)
     }
+
+    /// Returns an iterator over the children of the given type. This is a shortcut for
+    /// `request.children().of_type(type_ref).iter()`. The `children` of a request only ever include the resources
+    /// that roperator itself considers to belong to this parent, using the same tracking label or controller
+    /// `ownerReference` that it uses to decide which children to update and delete, so there's no need to check the
+    /// ownership of each one.
+    ///
+    /// ```rust
+    /// use roperator::k8s_types::core::v1::Pod;
+    ///
+    /// # let request = roperator::handler::request::test_request();
+    /// let pod_names = request
+    ///     .children_of_type(Pod)
+    ///     .map(|pod| pod.get_object_id().name().to_owned())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(vec!["bar".to_owned(), "baz".to_owned()], pod_names);
+    /// ```
+    pub fn children_of_type<'b>(&self, type_ref: impl Into<K8sTypeRef<'b>>) -> RawIter<'_, 'b> {
+        self.children().of_type(type_ref).iter()
+    }
+
+    /// Returns a read-only view of all the resources that the operator is watching, which can be used to look up
+    /// resources that aren't children of this parent, such as a `Secret` that's referenced by the parent's spec.
+    /// Extra types can be watched using `OperatorConfig::with_watched_type`.
+    pub fn cache(&self) -> &ResourceCache {
+        &self.cache
+    }
+
+    /// Returns a name for a child that's unique to this parent, in the form `<parent name>-<suffix>-<uid prefix>`.
+    /// Including part of the parent's uid avoids collisions with the children of other parents that happen to have
+    /// the same name, such as parents in different namespaces that both have a cluster-scoped child. The name is
+    /// stable for as long as the parent exists. The parent's name is shortened if necessary, so that the result is
+    /// never longer than 63 characters, as long as the `suffix` is reasonably short.
+    ///
+    /// ```
+    /// # let request = roperator::handler::request::test_request();
+    /// assert_eq!("bar-config-abc123", request.unique_child_name("config"));
+    /// ```
+    pub fn unique_child_name(&self, suffix: &str) -> String {
+        let uid_prefix = self
+            .parent
+            .uid()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .take(8)
+            .collect::<String>();
+        let name = self.parent.get_object_id().name().to_owned();
+        let max_name_len = MAX_CHILD_NAME_LEN.saturating_sub(suffix.len() + uid_prefix.len() + 2);
+        let name = name
+            .chars()
+            .take(max_name_len)
+            .collect::<String>()
+            .trim_end_matches(&['-', '.'][..])
+            .to_owned();
+        format!("{}-{}-{}", name, suffix, uid_prefix)
+    }
 }
 
+/// The maximum length of names that are generated by `SyncRequest::unique_child_name`, which is the length limit for
+/// resources like Services whose names must be DNS labels
+const MAX_CHILD_NAME_LEN: usize = 63;
+
 /// A view of a subset of child resouces that share a given apiVersion and kind. This view has accessors
 /// for retrieving deserialized child resources. These accessors all accept `impl Into<ObjectIdRef<'_>>`
 /// as their input, which allows passing a variety of types, including `&ObjectId` and `(&str, &str)`.
@@ -395,6 +528,8 @@ pub fn test_request() -> SyncRequest {
                 }
             }),
         ],
+        cache: ResourceCache::default(),
+        cancellation: Default::default(),
     }
 }
 
@@ -446,6 +581,55 @@ pub mod test {
         assert_eq!("def456", result.metadata.uid);
     }
 
+    #[test]
+    fn typed_child_is_found_by_name() {
+        let request = test_request();
+
+        let pod = request
+            .typed_child::<AnyResource>(crate::k8s_types::core::v1::Pod, "baz")
+            .expect("failed to deserialize pod")
+            .expect("no pod found");
+        assert_eq!("def456", pod.metadata.uid);
+
+        let missing = request
+            .typed_child::<AnyResource>(crate::k8s_types::core::v1::Pod, "nope")
+            .expect("failed to deserialize pod");
+        assert!(missing.is_none());
+
+        let parent = request
+            .typed_parent::<AnyResource>()
+            .expect("failed to deserialize parent");
+        assert_eq!("abc123", parent.metadata.uid);
+    }
+
+    #[test]
+    fn spec_changed_compares_generation_to_observed_generation() {
+        let with_parent = |parent: Value| SyncRequest {
+            parent: K8sResource::from_value(parent).unwrap(),
+            children: Vec::new(),
+            cache: ResourceCache::default(),
+            cancellation: Default::default(),
+        };
+        let parent = |status: Value| {
+            serde_json::json!({
+                "apiVersion": "foo.com/v1",
+                "kind": "MyThing",
+                "metadata": {
+                    "namespace": "foo",
+                    "name": "bar",
+                    "uid": "abc123",
+                    "resourceVersion": "1",
+                    "generation": 3,
+                },
+                "status": status,
+            })
+        };
+        assert!(!with_parent(parent(serde_json::json!({"observedGeneration": 3}))).spec_changed());
+        assert!(with_parent(parent(serde_json::json!({"observedGeneration": 2}))).spec_changed());
+        assert!(with_parent(parent(serde_json::json!({}))).spec_changed());
+        assert!(with_parent(parent(Value::Null)).spec_changed());
+    }
+
     #[test]
     fn request_children_allows_retrieving_first_raw_resource_matching_type() {
         let request = test_request();
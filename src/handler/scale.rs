@@ -0,0 +1,98 @@
+//! Types for setting the number of replicas of a child through its `scale` subresource
+use crate::k8s_types::K8sType;
+
+/// A request to set the desired number of replicas of a child, which is added to a `SyncResponse` using
+/// `SyncResponse::set_child_scale`. Once the children have been updated, roperator sends a `Scale` to the child's
+/// `scale` subresource if its current `spec.replicas` is different. Only children of the parent are ever scaled, and
+/// children that don't exist yet are scaled on a later sync, once they've been created.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChildScale {
+    pub api_version: String,
+    pub kind: String,
+    /// The namespace of the child, or `None` to use the namespace of the parent
+    #[serde(default)]
+    pub namespace: Option<String>,
+    pub name: String,
+    pub replicas: i32,
+}
+
+impl ChildScale {
+    pub fn new(child_type: &K8sType, name: impl Into<String>, replicas: i32) -> ChildScale {
+        ChildScale {
+            api_version: child_type.api_version.to_owned(),
+            kind: child_type.kind.to_owned(),
+            namespace: None,
+            name: name.into(),
+            replicas,
+        }
+    }
+
+    /// Returns true if this is for the same child as the `other`
+    pub(crate) fn is_same_child(&self, other: &ChildScale) -> bool {
+        self.api_version == other.api_version
+            && self.kind == other.kind
+            && self.namespace == other.namespace
+            && self.name == other.name
+    }
+}
+
+/// An `autoscaling/v1` `Scale`, which is the body of requests to the `scale` subresource. It only has the fields that
+/// are needed to change the desired number of replicas, so no other fields of the workload are touched.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scale {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ScaleMetadata,
+    pub spec: ScaleSpec,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScaleMetadata {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScaleSpec {
+    pub replicas: i32,
+}
+
+impl Scale {
+    pub fn new(namespace: Option<&str>, name: impl Into<String>, replicas: i32) -> Scale {
+        Scale {
+            api_version: "autoscaling/v1".to_owned(),
+            kind: "Scale".to_owned(),
+            metadata: ScaleMetadata {
+                name: name.into(),
+                namespace: namespace.map(str::to_owned),
+            },
+            spec: ScaleSpec { replicas },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn scale_only_includes_the_name_and_replicas() {
+        let scale = Scale::new(Some("ns"), "web", 3);
+        let expected = json!({
+            "apiVersion": "autoscaling/v1",
+            "kind": "Scale",
+            "metadata": {
+                "name": "web",
+                "namespace": "ns",
+            },
+            "spec": {
+                "replicas": 3,
+            },
+        });
+        assert_eq!(expected, serde_json::to_value(&scale).unwrap());
+    }
+}
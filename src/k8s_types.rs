@@ -6,21 +6,34 @@
 //!
 //! ```rust
 //! use roperator::prelude::K8sType;
+//! use roperator::k8s_types::Scope;
 //!
 //! static MY_TYPE: &K8sType = &K8sType {
 //!     api_version: "my.group/v1",
 //!     kind: "MyType",
-//!     plural_kind: "mytypes"
+//!     plural_kind: "mytypes",
+//!     scope: Scope::Namespaced,
 //! };
 //! ```
 //!
 //! If you need to load the type information at runtime, though, you could use `define_type` function, which will
 //! take its arguments as `String`s and return a `&'static K8sType` by leaking the memory. This is fine, as long as
-//! you only do it once, on startup.
+//! you only do it once, on startup. If you don't know the plural name of a type, then
+//! `K8sType::from_api_version_kind` can look it up from the api server. Types can also be parsed from strings like
+//! `apps/v1/Deployment/deployments`, which is the alternate (`{:#}`) `Display` form of a `K8sType`.
 //!
+pub(crate) mod discovery;
+
 use crate::resource::K8sTypeRef;
 
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
 use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+pub use self::discovery::KindNotFound;
 
 /// A basic description of a Kubernetes resource, with just enough information to allow Roperator
 /// to communicate with the api server. We use `&'static str` for all of these so that it's easy
@@ -28,13 +41,14 @@ use std::fmt::{self, Display};
 /// a static, like:
 ///
 /// ```no_run
-/// use roperator::k8s_types::K8sType;
+/// use roperator::k8s_types::{K8sType, Scope};
 ///
 /// #[allow(non_upper_case_globals)]
 /// pub static MyCrd: &K8sType = &K8sType {
 ///     api_version: "example.com/v1",
 ///     kind: "MyCrd",
-///     plural_kind: "mycrds"
+///     plural_kind: "mycrds",
+///     scope: Scope::Namespaced,
 /// };
 /// ```
 ///
@@ -45,24 +59,40 @@ pub struct K8sType {
     pub api_version: &'static str,
     pub kind: &'static str,
     pub plural_kind: &'static str,
+    /// Whether resources of this type live in a namespace. This determines the urls that are used to access them,
+    /// since cluster-scoped resources don't have a namespace segment.
+    pub scope: Scope,
+}
+
+/// The scope of a `K8sType`, which corresponds to the `scope` of a CustomResourceDefinition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    Namespaced,
+    Cluster,
 }
 
 /// Creates a `&'static K8sType` at runtime **by leaking memory**. This is totally fine, as long as it's only
 /// done once on application startup, but you definitely want to avoid repeated calls to define the same type.
-pub fn define_type(api_version: String, kind: String, plural_kind: String) -> &'static K8sType {
-    fn leak_str(s: String) -> &'static str {
-        Box::leak(s.into_boxed_str())
-    }
-
+pub fn define_type(
+    api_version: String,
+    kind: String,
+    plural_kind: String,
+    scope: Scope,
+) -> &'static K8sType {
     let k8s_type = K8sType {
         api_version: leak_str(api_version),
         kind: leak_str(kind),
         plural_kind: leak_str(plural_kind),
+        scope,
     };
     log::info!("Dynamically defining {:?}", k8s_type);
     Box::leak(Box::new(k8s_type))
 }
 
+fn leak_str(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
 impl K8sType {
     pub fn as_group_and_version(&self) -> (&str, &str) {
         // TODO: validate the apiVersion string and panic with a helpful message if it's wrong
@@ -83,43 +113,259 @@ impl K8sType {
         self.as_group_and_version().1
     }
 
+    /// Returns true if resources of this type live in a namespace
+    pub fn is_namespaced(&self) -> bool {
+        self.scope == Scope::Namespaced
+    }
+
     pub fn to_type_ref(&self) -> K8sTypeRef<'static> {
         K8sTypeRef(self.api_version, self.kind)
     }
+
+    /// Returns a reference to the subresource with the given name, such as `"scale"` or `"status"`
+    ///
+    /// ```rust
+    /// use roperator::k8s_types::apps::v1::Deployment;
+    ///
+    /// let scale = Deployment.subresource("scale");
+    /// assert_eq!("apps/v1/deployments/scale", scale.to_string());
+    /// ```
+    pub fn subresource<'a>(&'a self, name: &'a str) -> K8sSubresource<'a> {
+        K8sSubresource {
+            k8s_type: self,
+            name,
+        }
+    }
 }
 
+/// Formats the type as `{api_version}/{plural_kind}`, which is the path of the type in api server urls, such as
+/// `apps/v1/deployments`. The alternate form (`{:#}`) includes everything that's needed to parse the type again using
+/// `FromStr`, as `{api_version}/{kind}/{plural_kind}`, followed by `@Cluster` for cluster scoped types, such as
+/// `apps/v1/Deployment/deployments` or `rbac.authorization.k8s.io/v1/ClusterRole/clusterroles@Cluster`.
 impl Display for K8sType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}/{}", self.api_version, self.plural_kind)
+        if f.alternate() {
+            write!(f, "{}/{}/{}", self.api_version, self.kind, self.plural_kind)?;
+            if self.scope == Scope::Cluster {
+                f.write_str(CLUSTER_SCOPE_SUFFIX)?;
+            }
+            Ok(())
+        } else {
+            write!(f, "{}/{}", self.api_version, self.plural_kind)
+        }
+    }
+}
+
+const CLUSTER_SCOPE_SUFFIX: &str = "@Cluster";
+
+/// Parses the alternate (`{:#}`) `Display` form of a type, such as `apps/v1/Deployment/deployments`, or
+/// `v1/Namespace/namespaces@Cluster`. The plain `Display` form can't be parsed, since the `kind` can't be
+/// recovered from the plural. The built-in types are returned without allocating. Any other type is **leaked** the
+/// first time that it's parsed, like with `define_type`, and parsing it again returns the same strings, so only
+/// the distinct types that are parsed use any memory.
+///
+/// ```rust
+/// use roperator::k8s_types::{apps::v1::Deployment, K8sType};
+///
+/// let parsed: K8sType = "apps/v1/Deployment/deployments".parse().unwrap();
+/// assert_eq!(Deployment, &parsed);
+/// assert_eq!("apps/v1/Deployment/deployments", format!("{:#}", parsed));
+/// assert!("apps/v1/deployments".parse::<K8sType>().is_err());
+/// ```
+impl FromStr for K8sType {
+    type Err = ParseK8sTypeError;
+
+    fn from_str(input: &str) -> Result<K8sType, ParseK8sTypeError> {
+        parse_static(input).cloned()
+    }
+}
+
+lazy_static! {
+    /// The built-in types, along with every other type that's been parsed or deserialized, so that each type is
+    /// only leaked once
+    static ref KNOWN_TYPES: Mutex<Vec<&'static K8sType>> = Mutex::new(built_in_types());
+}
+
+fn built_in_types() -> Vec<&'static K8sType> {
+    [
+        core::v1::types(),
+        admissionregistration_k8s_io::v1beta1::types(),
+        apiextensions_k8s_io::v1beta1::types(),
+        apiregistration_k8s_io::v1::types(),
+        apps::v1::types(),
+        autoscaling::v1::types(),
+        authentication_k8s_io::v1::types(),
+        authorization_k8s_io::v1::types(),
+        batch::v1::types(),
+        batch::v1beta1::types(),
+        certificates_k8s_io::v1beta1::types(),
+        coordination_k8s_io::v1::types(),
+        events_k8s_io::v1::types(),
+        events_k8s_io::v1beta1::types(),
+        extensions::v1beta1::types(),
+        networking_k8s_io::v1beta1::types(),
+        networking_k8s_io::v1::types(),
+        node_k8s_io::v1beta1::types(),
+        policy::v1beta1::types(),
+        rbac_authorization_k8s_io::v1::types(),
+        scheduling_k8s_io::v1::types(),
+        storage_k8s_io::v1::types(),
+    ]
+    .concat()
+}
+
+/// Parses a type, and returns the known type that matches it, or else leaks a new one
+fn parse_static(input: &str) -> Result<&'static K8sType, ParseK8sTypeError> {
+    let error = |message: &'static str| ParseK8sTypeError {
+        input: input.to_owned(),
+        message,
+    };
+    let (path, scope) = match input.strip_suffix(CLUSTER_SCOPE_SUFFIX) {
+        Some(path) => (path, Scope::Cluster),
+        None => (input, Scope::Namespaced),
+    };
+    let segments = path.split('/').collect::<Vec<_>>();
+    if segments.iter().any(|segment| {
+        segment.is_empty()
+            || !segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    }) {
+        return Err(error(
+            "each segment must be non-empty and contain only ascii alphanumerics, '.', or '-'",
+        ));
+    }
+    let (api_version, kind, plural_kind) = match segments.as_slice() {
+        [version, kind, plural] => (version.to_string(), *kind, *plural),
+        [group, version, kind, plural] => (format!("{}/{}", group, version), *kind, *plural),
+        _ => {
+            return Err(error(
+                "expected '{group}/{version}/{kind}/{plural}' or '{version}/{kind}/{plural}' for core types",
+            ))
+        }
+    };
+    if !kind.starts_with(|c: char| c.is_ascii_uppercase()) {
+        return Err(error(
+            "the kind must start with an uppercase letter, and must be included before the plural",
+        ));
+    }
+    let mut known_types = KNOWN_TYPES.lock().unwrap();
+    let known = known_types.iter().find(|known| {
+        known.api_version == api_version
+            && known.kind == kind
+            && known.plural_kind == plural_kind
+            && known.scope == scope
+    });
+    if let Some(known) = known {
+        return Ok(*known);
+    }
+    let k8s_type: &'static K8sType = Box::leak(Box::new(K8sType {
+        api_version: leak_str(api_version),
+        kind: leak_str(kind.to_owned()),
+        plural_kind: leak_str(plural_kind.to_owned()),
+        scope,
+    }));
+    known_types.push(k8s_type);
+    Ok(k8s_type)
+}
+
+/// Error returned when parsing a `K8sType` from a string that's not in the alternate `Display` form
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseK8sTypeError {
+    /// The complete string that was being parsed
+    pub input: String,
+    /// Describes what's wrong with the input
+    pub message: &'static str,
+}
+
+impl Display for ParseK8sTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid K8sType: '{}': {}", self.input, self.message)
+    }
+}
+
+impl std::error::Error for ParseK8sTypeError {}
+
+/// Types are serialized as strings, using the same format as `FromStr`
+impl Serialize for K8sType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:#}", self))
+    }
+}
+
+/// Deserializing a type that isn't built in **leaks** its strings the first time, the same as parsing it with `FromStr`
+impl<'de> Deserialize<'de> for K8sType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<K8sType, D::Error> {
+        let as_string = String::deserialize(deserializer)?;
+        as_string.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Allows configs that refer to `&'static K8sType`s to be deserialized. The built-in types are returned as is, and
+/// any other type is leaked the first time that it's deserialized, the same as with `FromStr`.
+impl<'de> Deserialize<'de> for &'static K8sType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<&'static K8sType, D::Error> {
+        let as_string = String::deserialize(deserializer)?;
+        parse_static(&as_string).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A subresource of a `K8sType`, such as `scale` or `status`. Requests for a subresource are sent to
+/// `.../{plural_kind}/{name}/{subresource}`. The subresource only affects the urls of requests, so the `apiVersion`
+/// and `kind` are still those of the base `k8s_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct K8sSubresource<'a> {
+    pub k8s_type: &'a K8sType,
+    pub name: &'a str,
+}
+
+impl<'a> Display for K8sSubresource<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.k8s_type, self.name)
     }
 }
 
 macro_rules! k8s_type {
-    ($ref_name:ident, $api_version:expr, $kind:expr, $plural_kind:expr) => {
+    ($ref_name:ident, $api_version:expr, $kind:expr, $plural_kind:expr, $scope:expr) => {
         #[allow(non_upper_case_globals)]
         pub static $ref_name: &crate::k8s_types::K8sType = &crate::k8s_types::K8sType {
             api_version: $api_version,
             kind: $kind,
             plural_kind: $plural_kind,
+            scope: $scope,
         };
     };
-    ($ref_name:ident, core, v1, $kind:expr, $plural_kind:expr) => {
-        k8s_type!($ref_name, "v1", $kind, $plural_kind)
+    ($ref_name:ident, core, v1, $kind:expr, $plural_kind:expr, $scope:expr) => {
+        k8s_type!($ref_name, "v1", $kind, $plural_kind, $scope)
+    };
+}
+
+/// Types are namespaced unless they're marked with `@ Cluster`
+macro_rules! scope {
+    () => {
+        crate::k8s_types::Scope::Namespaced
+    };
+    ($scope:ident) => {
+        crate::k8s_types::Scope::$scope
     };
 }
 
 macro_rules! def_types {
     (@nogroupmod, $group:expr, [
         $( $version:ident => [
-            $( $kind:ident ~ $plural_kind:ident ),*
+            $( $kind:ident ~ $plural_kind:ident $(@ $scope:ident)? ),*
         ]),*
     ]) => {
         $(
             pub mod $version {
 
                 $(
-                    k8s_type!($kind, concat!($group, "/", stringify!($version)), stringify!($kind), stringify!($plural_kind));
+                    k8s_type!($kind, concat!($group, "/", stringify!($version)), stringify!($kind), stringify!($plural_kind), scope!($($scope)?));
                 )*
+
+                pub(crate) fn types() -> Vec<&'static crate::k8s_types::K8sType> {
+                    vec![$($kind),*]
+                }
             }
 
         )*
@@ -134,14 +380,18 @@ macro_rules! def_types {
     };
     (@core => [
         $( $version:ident => [
-            $( $kind:ident ~ $plural_kind:ident ),*
+            $( $kind:ident ~ $plural_kind:ident $(@ $scope:ident)? ),*
         ]),*
     ]) => {
         pub mod core {
             $(pub mod $version {
                 $(
-                    k8s_type!($kind, stringify!($version), stringify!($kind), stringify!($plural_kind));
+                    k8s_type!($kind, stringify!($version), stringify!($kind), stringify!($plural_kind), scope!($($scope)?));
                 )*
+
+                pub(crate) fn types() -> Vec<&'static crate::k8s_types::K8sType> {
+                    vec![$($kind),*]
+                }
             })*
         }
     }
@@ -150,20 +400,20 @@ macro_rules! def_types {
 def_types! {
     @core => [
         v1 => [
-            Namespace ~ namespaces,
-            Node ~ nodes,
+            Namespace ~ namespaces @ Cluster,
+            Node ~ nodes @ Cluster,
             Pod ~ pods,
             PodTemplate ~ podtemplates,
             ReplicationController ~ replicationcontrollers,
             Event ~ events,
             Service ~ services,
             Endpoints ~ endpoints,
-            ComponentStatus ~ componentstatuses,
+            ComponentStatus ~ componentstatuses @ Cluster,
             Secret ~ secrets,
             ConfigMap ~ configmaps,
             LimitRange ~ limitranges,
             PersistentVolumeClaim ~ persistentvolumeclaims,
-            PersistentVolume ~ persistentvolumes,
+            PersistentVolume ~ persistentvolumes @ Cluster,
             ResourceQuota ~ resourcequotas,
             Binding ~ bindings,
             ServiceAccount ~ serviceaccounts
@@ -175,8 +425,8 @@ pub mod admissionregistration_k8s_io {
     def_types! {
         @nogroupmod, "admissionregistration.k8s.io", [
             v1beta1 => [
-                MutatingWebhookConfiguration ~ mutatingwebhookconfigurations,
-                ValidatingWebhookConfiguration ~ validatingwebhookconfigurations
+                MutatingWebhookConfiguration ~ mutatingwebhookconfigurations @ Cluster,
+                ValidatingWebhookConfiguration ~ validatingwebhookconfigurations @ Cluster
             ]
         ]
     }
@@ -186,7 +436,7 @@ pub mod apiextensions_k8s_io {
     def_types! {
         @nogroupmod, "apiextensions.k8s.io", [
             v1beta1 => [
-                CustomResourceDefinition ~ customresourcedefinitions
+                CustomResourceDefinition ~ customresourcedefinitions @ Cluster
             ]
         ]
     }
@@ -196,7 +446,7 @@ pub mod apiregistration_k8s_io {
     def_types! {
         @nogroupmod, "apiregistration.k8s.io", [
             v1 => [
-                APIService ~ apiservices
+                APIService ~ apiservices @ Cluster
             ]
         ]
     }
@@ -226,7 +476,7 @@ pub mod authentication_k8s_io {
     def_types! {
         @nogroupmod, "authentication.k8s.io", [
             v1 => [
-                TokenReview ~ tokenreviews
+                TokenReview ~ tokenreviews @ Cluster
             ]
         ]
     }
@@ -237,9 +487,9 @@ pub mod authorization_k8s_io {
         @nogroupmod, "authorization.k8s.io", [
             v1 => [
                 LocalSubjectAccessReview ~ localsubjectaccessreviews,
-                SelfSubjectAccessReview ~ selfsubjectaccessreviews,
-                SelfSubjectRulesReview ~ selfsubjectrulesreviews,
-                SubjectAccessReview ~ subjectaccessreviews
+                SelfSubjectAccessReview ~ selfsubjectaccessreviews @ Cluster,
+                SelfSubjectRulesReview ~ selfsubjectrulesreviews @ Cluster,
+                SubjectAccessReview ~ subjectaccessreviews @ Cluster
             ]
         ]
     }
@@ -248,6 +498,7 @@ pub mod authorization_k8s_io {
 def_types! {
     batch => [
         v1 => [
+            CronJob ~ cronjobs,
             Job ~ jobs
         ],
         v1beta1 => [
@@ -260,7 +511,7 @@ pub mod certificates_k8s_io {
     def_types! {
         @nogroupmod, "certificates.k8s.io", [
             v1beta1 => [
-                CertificateSigningRequest ~ certificatesigningrequests
+                CertificateSigningRequest ~ certificatesigningrequests @ Cluster
             ]
         ]
     }
@@ -279,6 +530,9 @@ pub mod coordination_k8s_io {
 pub mod events_k8s_io {
     def_types! {
         @nogroupmod, "events.k8s.io", [
+            v1 => [
+                Event ~ events
+            ],
             v1beta1 => [
                 Event ~ events
             ]
@@ -294,7 +548,7 @@ pub mod extensions {
                 Deployment ~ deployments,
                 Ingress ~ ingresses,
                 NetworkPolicy ~ networkpolicies,
-                PodSecurityPolicy ~ podsecuritypolicies,
+                PodSecurityPolicy ~ podsecuritypolicies @ Cluster,
                 ReplicaSet ~ replicasets
             ]
         ]
@@ -320,7 +574,7 @@ pub mod node_k8s_io {
     def_types! {
         @nogroupmod, "node.k8s.io", [
             v1beta1 => [
-                RuntimeClass ~ runtimeclasses
+                RuntimeClass ~ runtimeclasses @ Cluster
             ]
         ]
     }
@@ -331,7 +585,7 @@ pub mod policy {
         @nogroupmod, "policy", [
             v1beta1 => [
                 PodDisruptionBudget ~ poddisruptionbudgets,
-                PodSecurityPolicy ~ podsecuritypolicies
+                PodSecurityPolicy ~ podsecuritypolicies @ Cluster
             ]
         ]
     }
@@ -341,8 +595,8 @@ pub mod rbac_authorization_k8s_io {
     def_types! {
         @nogroupmod, "rbac.authorization.k8s.io", [
             v1 => [
-                ClusterRoleBinding ~ clusterrolebindings,
-                ClusterRole ~ clusterroles,
+                ClusterRoleBinding ~ clusterrolebindings @ Cluster,
+                ClusterRole ~ clusterroles @ Cluster,
                 RoleBinding ~ rolebindings,
                 Role ~ roles
             ]
@@ -354,7 +608,7 @@ pub mod scheduling_k8s_io {
     def_types! {
         @nogroupmod, "scheduling.k8s.io", [
             v1 => [
-                PriorityClass ~ priorityclasses
+                PriorityClass ~ priorityclasses @ Cluster
             ]
         ]
     }
@@ -364,10 +618,10 @@ pub mod storage_k8s_io {
     def_types! {
         @nogroupmod, "storage.k8s.io", [
             v1 => [
-                CSIDriver ~ csidrivers,
-                CSINode ~ csinodes,
-                StorageClass ~ storageclasses,
-                VolumeAttachment ~ volumeattachments
+                CSIDriver ~ csidrivers @ Cluster,
+                CSINode ~ csinodes @ Cluster,
+                StorageClass ~ storageclasses @ Cluster,
+                VolumeAttachment ~ volumeattachments @ Cluster
             ]
         ]
     }
@@ -390,4 +644,235 @@ mod test {
         assert_eq!("", subject.group());
         assert_eq!("v1", subject.version());
     }
+
+    #[test]
+    fn subresource_does_not_change_the_base_type() {
+        let subject = apps::v1::Deployment.subresource("scale");
+        assert_eq!(apps::v1::Deployment, subject.k8s_type);
+        assert_eq!("apps/v1", subject.k8s_type.api_version);
+        assert_eq!("apps/v1/deployments", apps::v1::Deployment.to_string());
+        assert_eq!("apps/v1/deployments/scale", subject.to_string());
+    }
+
+    #[test]
+    fn built_in_types_have_the_correct_scope() {
+        assert!(core::v1::Pod.is_namespaced());
+        assert!(!core::v1::Namespace.is_namespaced());
+        assert_eq!(Scope::Cluster, core::v1::PersistentVolume.scope);
+        assert_eq!(
+            Scope::Cluster,
+            rbac_authorization_k8s_io::v1::ClusterRole.scope
+        );
+        assert_eq!(Scope::Namespaced, rbac_authorization_k8s_io::v1::Role.scope);
+        assert_eq!(Scope::Cluster, storage_k8s_io::v1::StorageClass.scope);
+    }
+
+    #[test]
+    fn common_built_in_types_have_the_correct_api_version_and_plural() {
+        let cases: &[(&K8sType, &str, &str)] = &[
+            (apps::v1::Deployment, "apps/v1", "apps/v1/deployments"),
+            (apps::v1::StatefulSet, "apps/v1", "apps/v1/statefulsets"),
+            (apps::v1::DaemonSet, "apps/v1", "apps/v1/daemonsets"),
+            (apps::v1::ReplicaSet, "apps/v1", "apps/v1/replicasets"),
+            (batch::v1::Job, "batch/v1", "batch/v1/jobs"),
+            (batch::v1::CronJob, "batch/v1", "batch/v1/cronjobs"),
+            (core::v1::ConfigMap, "v1", "v1/configmaps"),
+            (core::v1::Secret, "v1", "v1/secrets"),
+            (
+                networking_k8s_io::v1::Ingress,
+                "networking.k8s.io/v1",
+                "networking.k8s.io/v1/ingresses",
+            ),
+            (
+                core::v1::PersistentVolumeClaim,
+                "v1",
+                "v1/persistentvolumeclaims",
+            ),
+            (core::v1::ServiceAccount, "v1", "v1/serviceaccounts"),
+            (
+                rbac_authorization_k8s_io::v1::Role,
+                "rbac.authorization.k8s.io/v1",
+                "rbac.authorization.k8s.io/v1/roles",
+            ),
+            (
+                rbac_authorization_k8s_io::v1::RoleBinding,
+                "rbac.authorization.k8s.io/v1",
+                "rbac.authorization.k8s.io/v1/rolebindings",
+            ),
+        ];
+        for (k8s_type, api_version, display) in cases {
+            assert_eq!(*api_version, k8s_type.api_version);
+            assert_eq!(*display, k8s_type.to_string());
+            assert!(k8s_type.is_namespaced(), "{} is namespaced", k8s_type);
+        }
+    }
+
+    #[test]
+    fn alternate_display_round_trips_through_from_str() {
+        let mut cases: Vec<K8sType> = vec![
+            apps::v1::Deployment.clone(),
+            core::v1::Pod.clone(),
+            core::v1::Namespace.clone(),
+            rbac_authorization_k8s_io::v1::ClusterRole.clone(),
+            networking_k8s_io::v1::Ingress.clone(),
+            authorization_k8s_io::v1::SelfSubjectAccessReview.clone(),
+        ];
+        let groups = ["", "apps", "example.com", "my-group.example.io"];
+        let versions = ["v1", "v1beta1", "v2alpha3"];
+        let kinds = [("Foo", "foos"), ("FooBar", "foobars"), ("X", "x-es")];
+        for group in groups.iter() {
+            for version in versions.iter() {
+                for (kind, plural_kind) in kinds.iter() {
+                    for scope in [Scope::Namespaced, Scope::Cluster].iter() {
+                        let api_version = if group.is_empty() {
+                            version.to_string()
+                        } else {
+                            format!("{}/{}", group, version)
+                        };
+                        cases.push(K8sType {
+                            api_version: Box::leak(api_version.into_boxed_str()),
+                            kind,
+                            plural_kind,
+                            scope: *scope,
+                        });
+                    }
+                }
+            }
+        }
+
+        for k8s_type in cases {
+            let formatted = format!("{:#}", k8s_type);
+            let parsed = formatted
+                .parse::<K8sType>()
+                .unwrap_or_else(|err| panic!("failed to parse '{}': {}", formatted, err));
+            assert_eq!(k8s_type, parsed);
+            assert_eq!(formatted, format!("{:#}", parsed));
+        }
+    }
+
+    /// A tiny xorshift generator, so that the property tests below are repeatable without any extra dependencies
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn segment(&mut self, first: &[u8], rest: &[u8]) -> String {
+            let len = 1 + (self.next() % 12) as usize;
+            (0..len)
+                .map(|i| {
+                    let chars = if i == 0 { first } else { rest };
+                    chars[(self.next() % chars.len() as u64) as usize] as char
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn random_types_round_trip_through_from_str_and_are_only_leaked_once() {
+        const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789.-";
+        const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        const ANY: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        let mut random = Xorshift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let version = random.segment(LOWER, LOWER);
+            let api_version = if random.next() & 3 == 0 {
+                version
+            } else {
+                format!("{}/{}", random.segment(LOWER, LOWER), version)
+            };
+            let scope = if random.next() & 1 == 0 {
+                Scope::Namespaced
+            } else {
+                Scope::Cluster
+            };
+            let k8s_type = K8sType {
+                api_version: Box::leak(api_version.into_boxed_str()),
+                kind: Box::leak(random.segment(UPPER, ANY).into_boxed_str()),
+                plural_kind: Box::leak(random.segment(LOWER, LOWER).into_boxed_str()),
+                scope,
+            };
+
+            let formatted = format!("{:#}", k8s_type);
+            let parsed = formatted
+                .parse::<K8sType>()
+                .unwrap_or_else(|err| panic!("failed to parse '{}': {}", formatted, err));
+            assert_eq!(k8s_type, parsed);
+            assert_eq!(formatted, format!("{:#}", parsed));
+
+            let parsed_again = formatted.parse::<K8sType>().unwrap();
+            assert!(std::ptr::eq(parsed.kind, parsed_again.kind));
+            assert!(std::ptr::eq(parsed.api_version, parsed_again.api_version));
+        }
+    }
+
+    #[test]
+    fn built_in_types_are_not_leaked_when_parsed_or_deserialized() {
+        for k8s_type in built_in_types() {
+            let formatted = format!("{:#}", k8s_type);
+            let parsed = formatted.parse::<K8sType>().unwrap();
+            assert!(
+                std::ptr::eq(k8s_type.kind, parsed.kind),
+                "{} was leaked",
+                formatted
+            );
+            let deserialized: &'static K8sType =
+                serde_json::from_value(serde_json::json!(formatted)).unwrap();
+            assert!(
+                std::ptr::eq(k8s_type, deserialized),
+                "{} was leaked",
+                formatted
+            );
+        }
+    }
+
+    #[test]
+    fn alternate_display_includes_the_kind_and_scope() {
+        assert_eq!(
+            "apps/v1/Deployment/deployments",
+            format!("{:#}", apps::v1::Deployment)
+        );
+        assert_eq!(
+            "v1/Namespace/namespaces@Cluster",
+            format!("{:#}", core::v1::Namespace)
+        );
+    }
+
+    #[test]
+    fn malformed_types_are_rejected() {
+        let cases = [
+            "",
+            "pods",
+            "v1/pods",
+            "apps/v1/deployments",
+            "apps/v1/Deployment/",
+            "/v1/Pod/pods",
+            "apps/v1/Deployment/deployments/scale",
+            "apps/v1/deployment/deployments",
+            "v1/Pod/pods@Namespaced",
+            "v1/Pod/po ds",
+        ];
+        for input in cases.iter() {
+            let err = input
+                .parse::<K8sType>()
+                .expect_err(&format!("'{}' should be rejected", input));
+            assert_eq!(*input, err.input);
+        }
+    }
+
+    #[test]
+    fn types_are_serialized_as_strings() {
+        let json = serde_json::to_value(rbac_authorization_k8s_io::v1::ClusterRole).unwrap();
+        assert_eq!(
+            serde_json::json!("rbac.authorization.k8s.io/v1/ClusterRole/clusterroles@Cluster"),
+            json
+        );
+        let deserialized: &'static K8sType = serde_json::from_value(json).unwrap();
+        assert_eq!(rbac_authorization_k8s_io::v1::ClusterRole, deserialized);
+        assert!(serde_json::from_str::<K8sType>("\"v1/pods\"").is_err());
+    }
 }
@@ -0,0 +1,368 @@
+//! Looks up the plural name and scope of a type using the api server's discovery endpoints, so that `K8sType`s can
+//! be created from just an `apiVersion` and `kind`.
+use crate::config::ClientConfig;
+use crate::k8s_types::{define_type, K8sType, Scope};
+use crate::runner::client::Client;
+use crate::runner::metrics::Metrics;
+
+use anyhow::Error;
+use lazy_static::lazy_static;
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+
+/// The parts of the `APIResourceList` returned from `/apis/{group}/{version}` that we care about
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ApiResourceList {
+    #[serde(default)]
+    resources: Vec<ApiResource>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct ApiResource {
+    /// The plural name, or `{plural}/{subresource}` for subresources
+    name: String,
+    kind: String,
+    namespaced: bool,
+}
+
+/// Error returned from `K8sType::from_api_version_kind` when the api server doesn't serve a resource with the given
+/// `kind` in the given `apiVersion`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KindNotFound {
+    pub api_version: String,
+    pub kind: String,
+    /// The kinds that are available in the `apiVersion`, which is empty if the `apiVersion` isn't served at all
+    pub available: Vec<String>,
+}
+
+impl Display for KindNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.available.is_empty() {
+            write!(
+                f,
+                "No kind '{}' found because apiVersion '{}' is not served by the api server",
+                self.kind, self.api_version
+            )
+        } else {
+            write!(
+                f,
+                "No kind '{}' found in apiVersion '{}', available kinds are: {}",
+                self.kind,
+                self.api_version,
+                self.available.join(", ")
+            )
+        }
+    }
+}
+
+impl std::error::Error for KindNotFound {}
+
+#[derive(Debug, Default)]
+struct DiscoveryCache {
+    /// The resources of each apiVersion, keyed by the api server endpoint and the apiVersion
+    resources: HashMap<(String, String), Vec<ApiResource>>,
+    /// Types that have already been defined, keyed by apiVersion, kind, and plural kind, so that repeated lookups
+    /// don't leak more memory
+    types: HashMap<(String, String, String), &'static K8sType>,
+}
+
+lazy_static! {
+    static ref DISCOVERY_CACHE: Mutex<DiscoveryCache> = Mutex::new(DiscoveryCache::default());
+}
+
+impl K8sType {
+    /// Looks up the plural name and scope of the given `apiVersion` and `kind` using the api server's discovery
+    /// endpoint (`/apis/{group}/{version}`, or `/api/v1` for the core types), and returns a `&'static K8sType` for
+    /// it. The results are cached, so only the first successful lookup of each `apiVersion` makes a request. Returns a
+    /// `KindNotFound` error, which lists the kinds that are available, if there's no such kind in the `apiVersion`.
+    ///
+    /// This blocks the current thread until the request completes, so it's meant to be called on startup, before
+    /// the operator is started. Like `define_type`, the returned type is created by leaking memory, but only once
+    /// for each type.
+    ///
+    /// ```no_run
+    /// use roperator::config::ClientConfig;
+    /// use roperator::k8s_types::{K8sType, Scope};
+    ///
+    /// let client_config = ClientConfig::from_kubeconfig("my-operator").unwrap();
+    /// let ingress = K8sType::from_api_version_kind(&client_config, "networking.k8s.io/v1", "Ingress")
+    ///     .expect("failed to discover Ingress");
+    /// assert_eq!("ingresses", ingress.plural_kind);
+    /// assert_eq!(Scope::Namespaced, ingress.scope);
+    /// ```
+    pub fn from_api_version_kind(
+        client_config: &ClientConfig,
+        api_version: &str,
+        kind: &str,
+    ) -> Result<&'static K8sType, Error> {
+        let key = (
+            client_config.api_server_endpoint.clone(),
+            api_version.to_owned(),
+        );
+        let cached = DISCOVERY_CACHE.lock().unwrap().resources.get(&key).cloned();
+        let resources = match cached {
+            Some(resources) => resources,
+            None => {
+                let resources = fetch_resources(client_config, api_version)?;
+                // an apiVersion that isn't served yet may be added later, e.g. by installing a CRD
+                if !resources.is_empty() {
+                    let mut cache = DISCOVERY_CACHE.lock().unwrap();
+                    cache.resources.insert(key, resources.clone());
+                }
+                resources
+            }
+        };
+
+        let resource = find_resource(&resources, api_version, kind)?;
+        let mut cache = DISCOVERY_CACHE.lock().unwrap();
+        let k8s_type = *cache
+            .types
+            .entry((
+                api_version.to_owned(),
+                resource.kind.clone(),
+                resource.name.clone(),
+            ))
+            .or_insert_with(|| {
+                define_type(
+                    api_version.to_owned(),
+                    resource.kind.clone(),
+                    resource.name.clone(),
+                    resource.scope(),
+                )
+            });
+        Ok(k8s_type)
+    }
+}
+
+impl ApiResource {
+    fn scope(&self) -> Scope {
+        if self.namespaced {
+            Scope::Namespaced
+        } else {
+            Scope::Cluster
+        }
+    }
+}
+
+fn fetch_resources(
+    client_config: &ClientConfig,
+    api_version: &str,
+) -> Result<Vec<ApiResource>, Error> {
+    let client = Client::new(client_config.clone(), Metrics::new().client_metrics())?;
+    let mut runtime = tokio::runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()?;
+    let list = runtime.block_on(client.discover_resources(api_version))?;
+    Ok(list.map(|list| list.resources).unwrap_or_default())
+}
+
+/// Finds the resource with the given kind, ignoring subresources, which share the kind of their parent resource
+fn find_resource<'a>(
+    resources: &'a [ApiResource],
+    api_version: &str,
+    kind: &str,
+) -> Result<&'a ApiResource, KindNotFound> {
+    let top_level = resources.iter().filter(|res| !res.name.contains('/'));
+    top_level
+        .clone()
+        .find(|res| res.kind == kind)
+        .ok_or_else(|| {
+            let mut available = top_level.map(|res| res.kind.clone()).collect::<Vec<_>>();
+            available.sort();
+            available.dedup();
+            KindNotFound {
+                api_version: api_version.to_owned(),
+                kind: kind.to_owned(),
+                available,
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Credentials, RetryPolicy};
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response};
+    use serde_json::json;
+
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn networking_resources() -> ApiResourceList {
+        serde_json::from_value(json!({
+            "kind": "APIResourceList",
+            "apiVersion": "v1",
+            "groupVersion": "networking.k8s.io/v1",
+            "resources": [
+                {"name": "ingresses", "singularName": "", "namespaced": true, "kind": "Ingress", "verbs": ["get"]},
+                {"name": "ingresses/status", "singularName": "", "namespaced": true, "kind": "Ingress", "verbs": ["get"]},
+                {"name": "ingressclasses", "singularName": "", "namespaced": false, "kind": "IngressClass", "verbs": ["get"]},
+                {"name": "networkpolicies", "singularName": "", "namespaced": true, "kind": "NetworkPolicy", "verbs": ["get"]},
+            ]
+        }))
+        .unwrap()
+    }
+
+    /// Serves the networking.k8s.io/v1 discovery document from a separate thread, and counts the requests
+    fn start_discovery_server() -> (SocketAddr, Arc<AtomicUsize>) {
+        let request_count = Arc::new(AtomicUsize::new(0));
+        let count = request_count.clone();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let count = count.clone();
+                    async move {
+                        Ok::<_, hyper::Error>(service_fn(move |req| {
+                            count.fetch_add(1, Ordering::SeqCst);
+                            let status = if req.uri().path() == "/apis/networking.k8s.io/v1" {
+                                200
+                            } else {
+                                404
+                            };
+                            let body = json!({
+                                "resources": [
+                                    {"name": "ingresses", "namespaced": true, "kind": "Ingress"},
+                                    {"name": "ingressclasses", "namespaced": false, "kind": "IngressClass"},
+                                ]
+                            });
+                            let response = Response::builder()
+                                .status(status)
+                                .body(Body::from(body.to_string()))
+                                .unwrap();
+                            async move { Ok::<_, hyper::Error>(response) }
+                        }))
+                    }
+                });
+                let server =
+                    hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+                tx.send(server.local_addr()).unwrap();
+                let _ = server.await;
+            });
+        });
+        (rx.recv().unwrap(), request_count)
+    }
+
+    fn client_config(addr: SocketAddr) -> ClientConfig {
+        ClientConfig {
+            api_server_endpoint: format!("http://{}", addr),
+            credentials: Credentials::Header("Bearer foo".to_owned()),
+            ca_data: None,
+            user_agent: "test".to_owned(),
+            verify_ssl_certs: true,
+            trust_system_roots: true,
+            tls_server_name: None,
+            impersonate: None,
+            impersonate_groups: Vec::new(),
+            impersonate_uid: None,
+            impersonate_extra: HashMap::new(),
+            qps: crate::config::DEFAULT_QPS,
+            burst: crate::config::DEFAULT_BURST,
+            request_timeout: crate::config::DEFAULT_REQUEST_TIMEOUT,
+            watch_timeout: crate::config::DEFAULT_WATCH_TIMEOUT,
+            list_page_size: crate::config::DEFAULT_LIST_PAGE_SIZE,
+            consistent_initial_list: false,
+            max_idle_connections: crate::config::DEFAULT_MAX_IDLE_CONNECTIONS,
+            idle_timeout: Some(crate::config::DEFAULT_IDLE_TIMEOUT),
+            tcp_keepalive: Some(crate::config::DEFAULT_TCP_KEEPALIVE),
+            retry_policy: RetryPolicy::no_retries(),
+            proxy: None,
+            no_proxy: Vec::new(),
+            accept_compression: true,
+            capture_request_bodies: false,
+            max_object_size: Some(crate::config::DEFAULT_MAX_OBJECT_SIZE),
+            object_size_warning_threshold: Some(
+                crate::config::DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD,
+            ),
+            extra_headers: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn resources_are_found_by_kind_ignoring_subresources() {
+        let list = networking_resources();
+        let found = find_resource(&list.resources, "networking.k8s.io/v1", "Ingress").unwrap();
+        assert_eq!("ingresses", found.name);
+        assert!(found.namespaced);
+
+        let found = find_resource(&list.resources, "networking.k8s.io/v1", "IngressClass").unwrap();
+        assert_eq!("ingressclasses", found.name);
+        assert!(!found.namespaced);
+    }
+
+    #[test]
+    fn missing_kind_error_lists_available_kinds() {
+        let list = networking_resources();
+        let err = find_resource(&list.resources, "networking.k8s.io/v1", "ingress").unwrap_err();
+        assert_eq!(
+            vec!["Ingress", "IngressClass", "NetworkPolicy"],
+            err.available
+        );
+        assert_eq!(
+            "No kind 'ingress' found in apiVersion 'networking.k8s.io/v1', available kinds are: Ingress, IngressClass, NetworkPolicy",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn discovery_results_are_cached() {
+        let (addr, request_count) = start_discovery_server();
+        let config = client_config(addr);
+
+        let ingress =
+            K8sType::from_api_version_kind(&config, "networking.k8s.io/v1", "Ingress").unwrap();
+        assert_eq!("ingresses", ingress.plural_kind);
+        assert_eq!("Ingress", ingress.kind);
+        assert_eq!("networking.k8s.io/v1", ingress.api_version);
+        assert_eq!(Scope::Namespaced, ingress.scope);
+
+        let class = K8sType::from_api_version_kind(&config, "networking.k8s.io/v1", "IngressClass")
+            .unwrap();
+        assert_eq!(Scope::Cluster, class.scope);
+        let again =
+            K8sType::from_api_version_kind(&config, "networking.k8s.io/v1", "Ingress").unwrap();
+        assert!(std::ptr::eq(ingress, again));
+        assert_eq!(1, request_count.load(Ordering::SeqCst));
+
+        let err = K8sType::from_api_version_kind(&config, "example.com/v1", "Foo").unwrap_err();
+        let err = err.downcast::<KindNotFound>().unwrap();
+        assert!(err.available.is_empty());
+        assert_eq!(2, request_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn children_can_be_declared_by_kind() {
+        use crate::config::{ChildConfig, OperatorConfig, UpdateStrategy};
+
+        let (addr, _) = start_discovery_server();
+        let config = client_config(addr);
+        let operator_config = OperatorConfig::new("test", crate::k8s_types::core::v1::Pod)
+            .with_child_kind(
+                &config,
+                "networking.k8s.io/v1",
+                "IngressClass",
+                ChildConfig::new(UpdateStrategy::Replace),
+            )
+            .unwrap();
+        let child_type = operator_config.child_types[0].k8s_type;
+        assert_eq!("ingressclasses", child_type.plural_kind);
+        assert_eq!(Scope::Cluster, child_type.scope);
+
+        let err = OperatorConfig::new("test", crate::k8s_types::core::v1::Pod)
+            .with_child_kind(
+                &config,
+                "networking.k8s.io/v1",
+                "Ingresses",
+                ChildConfig::new(UpdateStrategy::Replace),
+            )
+            .unwrap_err();
+        assert!(err.downcast_ref::<KindNotFound>().is_some());
+    }
+}
@@ -16,6 +16,7 @@
 //!    api_version: "example.roperator.com/v1alpha1",
 //!    kind: "BusyBox",
 //!    plural_kind: "busyboxes",
+//!    scope: Scope::Namespaced,
 //! };
 //!
 //! // In your main function create the OperatorConfig, which tells Roperator about
@@ -24,8 +25,9 @@
 //! let operator_config = OperatorConfig::new(OPERATOR_NAME, PARENT_TYPE)
 //!     .with_child(k8s_types::core::v1::Pod, ChildConfig::recreate());
 //!
-//! // this function will block the current thread indifinitely while the operator runs
-//! run_operator(operator_config, handle_sync);
+//! // this function will block the current thread while the operator runs, and only returns `Ok` once the
+//! // operator has been shut down by a SIGTERM or SIGINT
+//! run_operator(operator_config, handle_sync).expect("failed to run operator");
 //!
 //! fn handle_sync(request: &SyncRequest) -> Result<SyncResponse, Error> {
 //!     // for this tiny example, we'll only create a single Pod. You can also use any of the types
@@ -57,6 +59,7 @@
 //!         status,
 //!         children: vec![pod],
 //!         resync: None,
+//!         child_scales: Vec::new(),
 //!     })
 //! }
 //! ```
@@ -69,19 +72,25 @@
 extern crate serde_derive;
 
 pub mod config;
+#[cfg(feature = "crd")]
+pub mod crd;
 pub mod handler;
 pub mod k8s_types;
+pub mod metrics;
 pub mod resource;
 pub mod runner;
+pub mod webhook;
 
 pub use serde;
 pub use serde_json;
 pub use serde_yaml;
 
 pub mod prelude {
-    pub use crate::config::{ChildConfig, ClientConfig, OperatorConfig, UpdateStrategy};
-    pub use crate::handler::{FinalizeResponse, Handler, SyncRequest, SyncResponse};
-    pub use crate::k8s_types::{self, K8sType};
+    pub use crate::config::{
+        ChildConfig, ChildDeclaration, ClientConfig, DeletePolicy, OperatorConfig, UpdateStrategy,
+    };
+    pub use crate::handler::{Condition, FinalizeResponse, Handler, SyncRequest, SyncResponse};
+    pub use crate::k8s_types::{self, K8sType, Scope};
     pub use crate::resource::K8sResource;
     pub use crate::runner::run_operator;
     pub use anyhow::Error;
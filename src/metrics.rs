@@ -0,0 +1,43 @@
+//! Prometheus metrics that are served over HTTP at `/metrics` when `OperatorConfig::expose_metrics` is `true`.
+//!
+//! The metrics that roperator exports are:
+//!
+//! - `reconcile_duration_seconds`: histogram of the time taken to sync or finalize a parent, labeled by the
+//!   `apiVersion` and `kind` of the parent
+//! - `reconcile_outcomes`: counter of syncs and finalizes, labeled by `apiVersion`, `kind`, and `outcome`, which is
+//!   one of `success`, `retryable_error`, or `fatal_error`
+//! - `handler_panics`: counter of syncs and finalizes where the handler panicked, labeled by the `apiVersion` and
+//!   `kind` of the parent
+//! - `work_queue_depth`: gauge of the number of parents that are waiting to be synced
+//! - `sync_counts` and `sync_errors`: counters of syncs and sync errors, labeled by the `namespace` and `name` of
+//!   each parent
+//! - `events_received`: counter of all events processed by the operator
+//! - `cached_resources`, `watcher_requests`, `watcher_errors`, and `watch_events`: metrics for the watches of each
+//!   resource type, labeled by `apiVersion` and `kind`
+//! - `watch_connects`, `watch_relists`, and `watch_last_event_timestamp_seconds`: the number of times each watch was
+//!   (re)established, the number of times its cache was re-listed because the watch's `resourceVersion` expired, and
+//!   the unix time of the last event that it received, labeled by `apiVersion` and `kind`
+//! - `watch_events_by_event_type`: counter of watch events, labeled by `apiVersion`, `kind`, and `type`, which is one
+//!   of `ADDED`, `MODIFIED`, `DELETED`, `BOOKMARK`, or `ERROR`
+//! - `client_api_server_request_time` and `client_rate_limiter_wait_time`: histograms of the time spent on requests
+//!   to the api server, and waiting on the client-side rate limiter
+//! - `client_tls_info`: always 1, with a `verify_certs` label that's `"false"` if the client doesn't verify the api
+//!   server's certificate
+//!
+//! To export your own operator-specific metrics alongside these, you can start the operator using
+//! `roperator::runner::run_operator_with_metrics`, which gives you access to the same `Registry` that roperator uses.
+//! Any metrics that you register with the prometheus default registry are also served from the same endpoint:
+//!
+//! ```rust
+//! use roperator::metrics::prometheus::{IntCounter, Opts};
+//!
+//! let provisioned = IntCounter::with_opts(Opts::new(
+//!     "databases_provisioned",
+//!     "number of databases that have been provisioned",
+//! ))
+//! .unwrap();
+//! roperator::metrics::register(Box::new(provisioned.clone())).unwrap();
+//! provisioned.inc();
+//! ```
+pub use prometheus;
+pub use prometheus::{default_registry, register, Registry};
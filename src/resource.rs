@@ -67,6 +67,15 @@ impl std::fmt::Display for K8sResource {
     }
 }
 
+/// parses the `LAST_APPLIED_ANNOTATION` of the given resource json
+pub(crate) fn parse_last_applied(resource: &Value) -> Option<Value> {
+    let serialized = resource
+        .pointer("/metadata/annotations")?
+        .get(crate::config::LAST_APPLIED_ANNOTATION)?
+        .as_str()?;
+    serde_json::from_str(serialized).ok()
+}
+
 impl K8sResource {
     /// Attemnpts to create a `K8sResource` from a raw json value. Returns an error if the json
     /// is missing any required fields
@@ -165,6 +174,27 @@ impl K8sResource {
             .unwrap_or(-1)
     }
 
+    /// returns the value of `status.observedGeneration`, if present. Roperator sets this whenever it updates
+    /// the status of a parent that has the `/status` subresource enabled.
+    pub fn observed_generation(&self) -> Option<i64> {
+        self.0
+            .pointer("/status/observedGeneration")
+            .and_then(Value::as_i64)
+    }
+
+    /// returns true if `status.observedGeneration` is equal to `metadata.generation`, which means that the
+    /// latest spec of the resource has already been synced
+    pub fn is_generation_observed(&self) -> bool {
+        self.observed_generation() == Some(self.generation())
+    }
+
+    /// returns the desired state of this child from the last time that roperator updated it, which is only stored for
+    /// child types that have `ChildConfig::track_last_applied` enabled. Returns `None` if the annotation is missing
+    /// or isn't valid json.
+    pub fn last_applied(&self) -> Option<Value> {
+        parse_last_applied(&self.0)
+    }
+
     /// returns true if `metadata.deletionTimestamp` is set, which would indicate
     /// that the resource is in the process of being deleted
     pub fn is_deletion_timestamp_set(&self) -> bool {
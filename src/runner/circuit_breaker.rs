@@ -0,0 +1,182 @@
+//! Stops invoking the handler while it keeps failing, so that an external system that's down doesn't tie up every
+//! sync until it times out. See `CircuitBreakerConfig`.
+use crate::config::{CircuitBreakerConfig, CircuitBreakerScope};
+use crate::runner::duration_to_millis;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    /// The handler is invoked as usual. Circuits without any failures aren't stored at all.
+    Closed { failures: u32 },
+    /// The handler isn't invoked until the given time
+    Open { until: Instant },
+    /// A single probe was let through at the given time. If it doesn't report its result within the `open_duration`,
+    /// for example because the sync was cancelled, then another probe is allowed.
+    HalfOpen { probe_started: Instant },
+}
+
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    /// keyed by parent uid, or by the empty string if the scope is global
+    circuits: Mutex<HashMap<String, CircuitState>>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: CircuitBreakerConfig) -> CircuitBreaker {
+        CircuitBreaker {
+            config,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key<'a>(&self, parent_uid: &'a str) -> &'a str {
+        match self.config.scope {
+            CircuitBreakerScope::Global => "",
+            CircuitBreakerScope::PerParent => parent_uid,
+        }
+    }
+
+    /// Returns `Ok` if the handler may be invoked for the given parent, or else how long it is until the next probe
+    pub(crate) fn try_acquire(&self, parent_uid: &str, now: Instant) -> Result<(), Duration> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let state = match circuits.get_mut(self.key(parent_uid)) {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        let next_probe = match *state {
+            CircuitState::Closed { .. } => return Ok(()),
+            CircuitState::Open { until } => until,
+            CircuitState::HalfOpen { probe_started } => probe_started + self.config.open_duration,
+        };
+        if now >= next_probe {
+            log::info!(
+                "Circuit breaker is half-open, invoking the handler for parent with uid: {} as a probe",
+                parent_uid
+            );
+            *state = CircuitState::HalfOpen { probe_started: now };
+            Ok(())
+        } else {
+            Err(next_probe - now)
+        }
+    }
+
+    /// Records the outcome of invoking the handler for the given parent
+    pub(crate) fn record(&self, parent_uid: &str, failed: bool, now: Instant) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let key = self.key(parent_uid);
+        if !failed {
+            if let Some(CircuitState::Open { .. }) | Some(CircuitState::HalfOpen { .. }) =
+                circuits.remove(key)
+            {
+                log::info!(
+                    "Circuit breaker closed after the handler succeeded for parent with uid: {}",
+                    parent_uid
+                );
+            }
+            return;
+        }
+        let failures = match circuits.get(key) {
+            Some(CircuitState::Closed { failures }) => failures + 1,
+            // a failed probe, or a failure of a sync that was started before the circuit opened
+            Some(_) => self.config.failure_threshold.max(1),
+            None => 1,
+        };
+        let state = if failures >= self.config.failure_threshold.max(1) {
+            log::warn!(
+                "Circuit breaker opened after {} consecutive handler failures, the handler will not be invoked for {}ms",
+                failures,
+                duration_to_millis(self.config.open_duration)
+            );
+            CircuitState::Open {
+                until: now + self.config.open_duration,
+            }
+        } else {
+            CircuitState::Closed { failures }
+        };
+        circuits.insert(key.to_owned(), state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn breaker(scope: CircuitBreakerScope) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 3,
+            open_duration: Duration::from_secs(10),
+            scope,
+        })
+    }
+
+    #[test]
+    fn circuit_opens_after_consecutive_failures_and_closes_after_a_successful_probe() {
+        let breaker = breaker(CircuitBreakerScope::Global);
+        let start = Instant::now();
+        for _ in 0..2 {
+            assert_eq!(Ok(()), breaker.try_acquire("a", start));
+            breaker.record("a", true, start);
+        }
+        // a success resets the count
+        breaker.record("b", false, start);
+        for _ in 0..3 {
+            assert_eq!(Ok(()), breaker.try_acquire("a", start));
+            breaker.record("a", true, start);
+        }
+        let later = start + Duration::from_secs(4);
+        assert_eq!(Err(Duration::from_secs(6)), breaker.try_acquire("b", later));
+
+        let probe_time = start + Duration::from_secs(10);
+        assert_eq!(Ok(()), breaker.try_acquire("b", probe_time));
+        // only one probe is allowed at a time
+        assert_eq!(
+            Err(Duration::from_secs(10)),
+            breaker.try_acquire("a", probe_time)
+        );
+        breaker.record("b", true, probe_time);
+        assert!(breaker.try_acquire("a", probe_time).is_err());
+
+        let probe_time = probe_time + Duration::from_secs(10);
+        assert_eq!(Ok(()), breaker.try_acquire("a", probe_time));
+        breaker.record("a", false, probe_time);
+        assert_eq!(Ok(()), breaker.try_acquire("b", probe_time));
+    }
+
+    #[test]
+    fn lost_probes_are_replaced_after_the_open_duration() {
+        let breaker = breaker(CircuitBreakerScope::Global);
+        let start = Instant::now();
+        for _ in 0..3 {
+            breaker.record("a", true, start);
+        }
+        let probe_time = start + Duration::from_secs(10);
+        assert_eq!(Ok(()), breaker.try_acquire("a", probe_time));
+        assert!(breaker
+            .try_acquire("a", probe_time + Duration::from_secs(9))
+            .is_err());
+        assert_eq!(
+            Ok(()),
+            breaker.try_acquire("a", probe_time + Duration::from_secs(10))
+        );
+        breaker.record("a", true, probe_time + Duration::from_secs(11));
+        assert_eq!(
+            Err(Duration::from_secs(10)),
+            breaker.try_acquire("a", probe_time + Duration::from_secs(11))
+        );
+    }
+
+    #[test]
+    fn per_parent_circuits_are_independent() {
+        let breaker = breaker(CircuitBreakerScope::PerParent);
+        let start = Instant::now();
+        for _ in 0..3 {
+            breaker.record("a", true, start);
+        }
+        assert!(breaker.try_acquire("a", start).is_err());
+        assert_eq!(Ok(()), breaker.try_acquire("b", start));
+    }
+}
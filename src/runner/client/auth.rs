@@ -0,0 +1,248 @@
+use crate::config::exec::{get_exec_token, ExecToken};
+use crate::config::{Credentials, KubeConfigError};
+use crate::runner::client::Error;
+
+use http::{header, HeaderValue, Request};
+use hyper::Body;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Tokens from exec plugins will be refreshed once they're within this long of expiring
+const EXEC_TOKEN_REFRESH_WINDOW: Duration = Duration::from_secs(60);
+
+/// How often to re-read tokens from a `Credentials::TokenFile`. The kubelet rotates projected service account
+/// tokens well before they expire, so this only needs to be frequent enough to pick up the new token in time.
+const TOKEN_FILE_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Adds the Authorization header to requests for any credentials that can change over the lifetime
+/// of the client. Static `Credentials::Header` values are added when the request is built, so the
+/// provider doesn't need to do anything for those.
+#[derive(Debug)]
+pub(crate) enum AuthProvider {
+    Static,
+    Exec(ExecTokenCache),
+    TokenFile(TokenFileCache),
+}
+
+impl AuthProvider {
+    pub fn new(credentials: &Credentials) -> AuthProvider {
+        match credentials {
+            Credentials::Exec { command, args, env } => AuthProvider::Exec(ExecTokenCache {
+                command: command.clone(),
+                args: args.clone(),
+                env: env.clone(),
+                cached: Mutex::new(None),
+            }),
+            Credentials::TokenFile(path) => AuthProvider::TokenFile(TokenFileCache {
+                path: path.clone(),
+                cached: Mutex::new(None),
+            }),
+            _ => AuthProvider::Static,
+        }
+    }
+
+    pub async fn authorize(&self, request: &mut Request<Body>) -> Result<(), Error> {
+        let header_value = match self {
+            AuthProvider::Static => return Ok(()),
+            AuthProvider::Exec(cache) => cache.get_header().await?,
+            AuthProvider::TokenFile(cache) => cache.get_header().await?,
+        };
+        let value = HeaderValue::from_str(header_value.as_str()).map_err(|_| {
+            Error::Credentials(KubeConfigError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "token contains characters that are not allowed in an Authorization header",
+            )))
+        })?;
+        request.headers_mut().insert(header::AUTHORIZATION, value);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ExecTokenCache {
+    command: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    cached: Mutex<Option<ExecToken>>,
+}
+
+impl ExecTokenCache {
+    async fn get_header(&self) -> Result<String, Error> {
+        if let Some(header) = self.get_cached_header() {
+            return Ok(header);
+        }
+        let command = self.command.clone();
+        let args = self.args.clone();
+        let env = self.env.clone();
+        let token =
+            tokio::task::spawn_blocking(move || get_exec_token(command.as_str(), &args, &env))
+                .await
+                .map_err(|err| {
+                    Error::Credentials(KubeConfigError::ExecErr(format!(
+                        "exec credential task failed: {}",
+                        err
+                    )))
+                })?
+                .map_err(Error::Credentials)?;
+
+        let header = token.header.clone();
+        *self.cached.lock().unwrap() = Some(token);
+        Ok(header)
+    }
+
+    fn get_cached_header(&self) -> Option<String> {
+        let lock = self.cached.lock().unwrap();
+        lock.as_ref()
+            .filter(|token| !needs_refresh(token, SystemTime::now()))
+            .map(|token| token.header.clone())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct TokenFileCache {
+    path: PathBuf,
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl TokenFileCache {
+    async fn get_header(&self) -> Result<String, Error> {
+        let previous = self.cached.lock().unwrap().clone();
+        if let Some((header, read_at)) = previous.as_ref() {
+            if read_at.elapsed() < TOKEN_FILE_RELOAD_INTERVAL {
+                return Ok(header.clone());
+            }
+        }
+
+        let path = self.path.clone();
+        let result = tokio::task::spawn_blocking(move || read_token_file(&path))
+            .await
+            .unwrap_or_else(|err| {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("reading the token file failed: {}", err),
+                ))
+            });
+        match result {
+            Ok(header) => {
+                *self.cached.lock().unwrap() = Some((header.clone(), Instant::now()));
+                Ok(header)
+            }
+            Err(err) => match previous {
+                Some((header, _)) => {
+                    log::warn!(
+                        "Failed to re-read token file: {}, continuing to use the previous token, err: {}",
+                        self.path.display(),
+                        err
+                    );
+                    Ok(header)
+                }
+                None => Err(Error::Credentials(KubeConfigError::Io(err))),
+            },
+        }
+    }
+}
+
+fn read_token_file(path: &Path) -> Result<String, io::Error> {
+    let token = std::fs::read_to_string(path)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("token file '{}' is empty", path.display()),
+        ));
+    }
+    Ok(format!("Bearer {}", token))
+}
+
+fn needs_refresh(token: &ExecToken, now: SystemTime) -> bool {
+    match token.expiration {
+        Some(expiration) => now + EXEC_TOKEN_REFRESH_WINDOW >= expiration,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exec_token_is_refreshed_when_close_to_expiration() {
+        let now = SystemTime::now();
+        let token = |expiration| ExecToken {
+            header: "Bearer foo".to_owned(),
+            expiration,
+        };
+        assert!(!needs_refresh(&token(None), now));
+        assert!(!needs_refresh(
+            &token(Some(now + Duration::from_secs(600))),
+            now
+        ));
+        assert!(needs_refresh(
+            &token(Some(now + Duration::from_secs(30))),
+            now
+        ));
+        assert!(needs_refresh(
+            &token(Some(now - Duration::from_secs(30))),
+            now
+        ));
+    }
+
+    #[test]
+    fn exec_credentials_are_run_once_and_cached() {
+        let count_path =
+            std::env::temp_dir().join(format!("roperator-exec-count-{}", std::process::id()));
+        let _ = std::fs::remove_file(&count_path);
+        let script = format!(
+            r#"echo run >> {}; echo '{{"kind":"ExecCredential","status":{{"token":"'$MY_TOKEN'"}}}}'"#,
+            count_path.display()
+        );
+        let credentials = Credentials::exec(
+            "sh",
+            vec!["-c".to_owned(), script],
+            vec![("MY_TOKEN".to_owned(), "vended-token".to_owned())],
+        );
+        let provider = AuthProvider::new(&credentials);
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+        for _ in 0..3 {
+            let mut request = Request::new(Body::empty());
+            runtime.block_on(provider.authorize(&mut request)).unwrap();
+            assert_eq!(
+                "Bearer vended-token",
+                request.headers().get(header::AUTHORIZATION).unwrap()
+            );
+        }
+        let runs = std::fs::read_to_string(&count_path).unwrap();
+        std::fs::remove_file(&count_path).unwrap();
+        assert_eq!(1, runs.lines().count());
+    }
+
+    #[test]
+    fn token_file_falls_back_to_previous_token_when_file_cannot_be_read() {
+        let path = std::env::temp_dir().join(format!("roperator-token-{}", std::process::id()));
+        std::fs::write(&path, "first-token\n").unwrap();
+        let cache = TokenFileCache {
+            path: path.clone(),
+            cached: Mutex::new(None),
+        };
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let header = runtime.block_on(cache.get_header()).unwrap();
+        assert_eq!("Bearer first-token", header.as_str());
+
+        // pretend that the token was read long enough ago to need to be reloaded
+        let stale = Instant::now() - TOKEN_FILE_RELOAD_INTERVAL;
+        *cache.cached.lock().unwrap() = Some((header, stale));
+        std::fs::write(&path, "second-token").unwrap();
+        let header = runtime.block_on(cache.get_header()).unwrap();
+        assert_eq!("Bearer second-token", header.as_str());
+
+        *cache.cached.lock().unwrap() = Some((header, stale));
+        std::fs::remove_file(&path).unwrap();
+        let header = runtime.block_on(cache.get_header()).unwrap();
+        assert_eq!("Bearer second-token", header.as_str());
+    }
+}
@@ -1,12 +1,25 @@
+mod auth;
+mod proxy;
+mod rate_limit;
 mod request;
+mod retry;
 
-use crate::config::{CAData, ClientConfig, Credentials};
-use crate::k8s_types::K8sType;
+use crate::config::{CAData, ClientConfig, Credentials, DeletePolicy, KubeConfigError};
+use crate::k8s_types::authorization_k8s_io::v1::SelfSubjectAccessReview;
+use crate::k8s_types::discovery::ApiResourceList;
+use crate::k8s_types::{K8sSubresource, K8sType};
 use crate::resource::ObjectIdRef;
 use crate::runner::metrics::ClientMetrics;
 
+use self::auth::AuthProvider;
+use self::proxy::ProxyConnector;
+use self::rate_limit::RateLimiter;
+use self::retry::RetryState;
+
 use bytes::buf::ext::BufExt;
-use http::{Request, Response};
+use bytes::Bytes;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use http::{HeaderMap, Request, Response};
 use hyper::client::Client as HyperClient;
 use hyper::client::HttpConnector;
 use hyper::Body;
@@ -14,20 +27,31 @@ use hyper_openssl::HttpsConnector;
 use lazy_static::lazy_static;
 use openssl::pkey::PKey;
 use openssl::ssl::{SslConnector, SslMethod};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::X509CheckFlags;
 use openssl::x509::X509;
 use regex::bytes::Regex;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use tokio::stream::StreamExt;
+use tracing::Instrument;
 
+use std::borrow::Cow;
 use std::fs::File;
+use std::future::Future;
 use std::io;
 use std::io::Read;
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
 
 pub use self::request::{MergeStrategy, Patch};
 
+/// How many times a paginated list will be started over after its continue token expires, before giving up
+const MAX_LIST_RESTARTS: u32 = 3;
+
+/// The maximum number of bytes of each captured request and response body that's included in an error's message
+const MAX_CAPTURED_BODY_LEN: usize = 4096;
+
 lazy_static! {
     static ref NEWLINE_REGEX: Regex = Regex::new("([\\r\\n]+)").unwrap();
 }
@@ -37,6 +61,33 @@ pub enum Error {
     Io(hyper::error::Error),
     Serde(serde_json::Error),
     Http(http::StatusCode),
+    /// The api server rejected the request and responded with a `Status` describing why
+    Api(Box<ApiError>),
+    Credentials(KubeConfigError),
+    Timeout(Duration),
+    Decompression(io::Error),
+    /// A failed write, along with the bodies of the request and response. These are only captured when
+    /// `ClientConfig::capture_request_bodies` is enabled.
+    Captured(Box<CapturedError>),
+    /// The body of a write was larger than `ClientConfig::max_object_size`, so it wasn't sent to the api server
+    ObjectTooLarge(Box<ObjectTooLargeError>),
+}
+
+#[derive(Debug)]
+pub struct CapturedError {
+    pub error: Error,
+    pub request_body: String,
+    pub response_body: String,
+}
+
+#[derive(Debug)]
+pub struct ObjectTooLargeError {
+    /// The type and id of the object that was being written
+    pub object: String,
+    /// The size of the serialized request body, in bytes
+    pub size: usize,
+    /// The `max_object_size` that it exceeded
+    pub limit: usize,
 }
 
 impl std::error::Error for Error {
@@ -45,6 +96,12 @@ impl std::error::Error for Error {
             Error::Io(e) => Some(e as &(dyn std::error::Error + 'static)),
             Error::Serde(e) => Some(e as &(dyn std::error::Error + 'static)),
             Error::Http(_) => None,
+            Error::Api(e) => Some(e.as_ref() as &(dyn std::error::Error + 'static)),
+            Error::Credentials(e) => Some(e as &(dyn std::error::Error + 'static)),
+            Error::Timeout(_) => None,
+            Error::Decompression(e) => Some(e as &(dyn std::error::Error + 'static)),
+            Error::Captured(e) => Some(&e.error as &(dyn std::error::Error + 'static)),
+            Error::ObjectTooLarge(_) => None,
         }
     }
 }
@@ -61,9 +118,20 @@ impl Error {
     pub fn is_http_status(&self, code: u16) -> bool {
         match self {
             Error::Http(ref status) => status.as_u16() == code,
+            Error::Api(ref api_error) => api_error.code == code,
+            Error::Captured(ref captured) => captured.error.is_http_status(code),
             _ => false,
         }
     }
+
+    /// Returns the `Status` from the api server, if this error is because the request was rejected
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api(ref api_error) => Some(api_error.as_ref()),
+            Error::Captured(ref captured) => captured.error.api_error(),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
@@ -72,10 +140,41 @@ impl std::fmt::Display for Error {
             Error::Io(ref e) => write!(f, "Io Error: {}", e),
             Error::Serde(ref e) => write!(f, "(De)Serialization error: {}", e),
             Error::Http(ref e) => write!(f, "Http Error: {}", e),
+            Error::Api(ref e) => write!(f, "{}", e),
+            Error::Credentials(ref e) => write!(f, "Credentials Error: {}", e),
+            Error::Timeout(ref t) => write!(f, "Request timed out after {}ms", t.as_millis()),
+            Error::Decompression(ref e) => write!(f, "Failed to decompress response body: {}", e),
+            Error::Captured(ref e) => write!(
+                f,
+                "{}, request body: {}, response body: {}",
+                e.error,
+                truncate_body(e.request_body.as_str()),
+                truncate_body(e.response_body.as_str())
+            ),
+            Error::ObjectTooLarge(ref e) => write!(
+                f,
+                "{} is too large to write: {} bytes exceeds the limit of {} bytes",
+                e.object, e.size, e.limit
+            ),
         }
     }
 }
 
+fn truncate_body(body: &str) -> Cow<'_, str> {
+    if body.len() <= MAX_CAPTURED_BODY_LEN {
+        return Cow::Borrowed(body);
+    }
+    let mut end = MAX_CAPTURED_BODY_LEN;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!(
+        "{}... ({} more bytes)",
+        &body[..end],
+        body.len() - end
+    ))
+}
+
 impl From<hyper::error::Error> for Error {
     fn from(e: hyper::error::Error) -> Error {
         Error::Io(e)
@@ -89,45 +188,41 @@ impl From<serde_json::Error> for Error {
 
 #[derive(Debug)]
 struct ClientInner {
-    http_client: HyperClient<HttpsConnector<HttpConnector>>,
+    http_client: HyperClient<HttpsConnector<ProxyConnector>>,
     config: ClientConfig,
+    auth: AuthProvider,
+    rate_limiter: Option<RateLimiter>,
+    extra_headers: HeaderMap,
     metrics: ClientMetrics,
 }
 
+/// The `bool` is whether this is a dry run client, which sends `dryRun=All` with every write request
 #[derive(Debug, Clone)]
-pub struct Client(Arc<ClientInner>);
+pub struct Client(Arc<ClientInner>, bool);
 
 impl Client {
     pub fn new(mut config: ClientConfig, metrics: ClientMetrics) -> Result<Client, io::Error> {
+        let extra_headers = config
+            .validate_extra_headers()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
         let mut http = HttpConnector::new();
         http.enforce_http(false);
+        http.set_keepalive(config.tcp_keepalive);
 
         let mut ssl = SslConnector::builder(SslMethod::tls())?;
         // enable http2 using alpn
         ssl.set_alpn_protos(b"\x02h2\x08http/1.1")?;
-        match config.ca_data.take() {
-            Some(CAData::Contents(certs)) => {
-                // if the CA cert contents are provided inline, as they are from a kubeconfig file, then we need to manually
-                // parse them and add them to the openssl cert store
-                let decoded = base64::decode(&certs).map_err(|err| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        format!(
-                            "Invalid base64 content of certificate-authority-data: {}",
-                            err
-                        ),
-                    )
-                })?;
-                let certs = X509::stack_from_pem(decoded.as_slice())?;
-                let cert_store = ssl.cert_store_mut();
-                for cert in certs {
-                    cert_store.add_cert(cert)?;
-                }
-            }
-            Some(CAData::File(path)) => {
-                ssl.set_ca_file(path.as_str())?;
+        // the connector starts out trusting the system roots, so they're replaced with an empty store if they're
+        // not wanted
+        if !config.trust_system_roots {
+            ssl.set_cert_store(X509StoreBuilder::new()?.build());
+        }
+        if let Some(ca_data) = config.ca_data.take() {
+            let cert_store = ssl.cert_store_mut();
+            for cert in ca_certificates(&ca_data)? {
+                cert_store.add_cert(cert)?;
             }
-            None => {}
         }
 
         if let Credentials::PemPath {
@@ -135,16 +230,13 @@ impl Client {
             ref private_key_path,
         } = config.credentials
         {
-            let mut file = File::open(certificate_path)?;
-            let mut file_content_cert = vec![];
-            file.read_to_end(&mut file_content_cert)?;
+            let file_content_cert = read_pem_file(certificate_path, "client certificate")?;
+            let file_content_key = read_pem_file(private_key_path, "client private key")?;
 
-            let mut file = File::open(private_key_path)?;
-            let mut file_content_key = vec![];
-            file.read_to_end(&mut file_content_key)?;
-
-            let cert = X509::from_pem(file_content_cert.as_slice())?;
-            let pkey = PKey::private_key_from_pem(file_content_key.as_slice())?;
+            let cert = X509::from_pem(file_content_cert.as_slice())
+                .map_err(|err| invalid_pem_file(certificate_path, "client certificate", err))?;
+            let pkey = PKey::private_key_from_pem(file_content_key.as_slice())
+                .map_err(|err| invalid_pem_file(private_key_path, "client private key", err))?;
             ssl.set_certificate(&*cert)?; // &* is to convert from X509 to &X509Ref where X509 impls Deref to X509Ref
             ssl.set_private_key(&*pkey)?; // same as above
             ssl.check_private_key()?; // ensures that the provided private key and certificate actually go together
@@ -174,33 +266,181 @@ impl Client {
             ssl.check_private_key()?; // ensures that the provided private key and certificate actually go together
         }
 
-        if config.verify_ssl_certs {
+        let verify_ssl_certs = config.verify_ssl_certs;
+        metrics.tls_configured(verify_ssl_certs);
+        if verify_ssl_certs {
             ssl.set_verify(openssl::ssl::SslVerifyMode::PEER);
         } else {
-            log::warn!("TLS Certificate verifification has been disabled! All connections to the Kubernetes api server will be insecure!");
+            warn_insecure_once(config.api_server_endpoint.as_str());
             ssl.set_verify(openssl::ssl::SslVerifyMode::NONE);
         }
 
-        let https = HttpsConnector::with_connector(http, ssl)?;
+        let connector = ProxyConnector::new(http, config.proxy.as_deref(), &config.no_proxy)?;
+        let mut https = HttpsConnector::with_connector(connector, ssl)?;
+        let tls_server_name = config.tls_server_name.clone();
+        if tls_server_name.is_some() || !verify_ssl_certs {
+            https.set_callback(move |conf, _| {
+                if let Some(server_name) = tls_server_name.as_ref() {
+                    // SNI and hostname verification would otherwise use the host of each request's uri
+                    conf.set_use_server_name_indication(false);
+                    conf.set_verify_hostname(false);
+                    conf.set_hostname(server_name.as_str())?;
+                    let param = conf.param_mut();
+                    param.set_hostflags(X509CheckFlags::NO_PARTIAL_WILDCARDS);
+                    param.set_host(server_name.as_str())?;
+                }
+                if !verify_ssl_certs {
+                    // a hostname mismatch is already ignored along with every other verification error, but this
+                    // makes sure that nothing is checked regardless of how the connector's defaults change
+                    conf.set_verify_hostname(false);
+                    conf.set_verify(openssl::ssl::SslVerifyMode::NONE);
+                }
+                Ok(())
+            });
+        }
 
-        let client = HyperClient::builder().build(https);
+        // connections are pooled by hyper, and http2 connections are shared by any number of concurrent requests
+        let client = HyperClient::builder()
+            .pool_max_idle_per_host(config.max_idle_connections)
+            .pool_idle_timeout(config.idle_timeout)
+            .build(https);
 
+        let auth = AuthProvider::new(&config.credentials);
+        let rate_limiter = RateLimiter::new(config.qps, config.burst);
         let inner = ClientInner {
             http_client: client,
             config,
+            auth,
+            rate_limiter,
+            extra_headers,
             metrics,
         };
-        Ok(Client(Arc::new(inner)))
+        Ok(Client(Arc::new(inner), false))
     }
 
+    /// Returns a client that shares the same connections and configuration, but which will add `dryRun=All` to
+    /// every request that would modify a resource, so that the api server validates them without persisting them
+    pub fn with_dry_run(&self, dry_run: bool) -> Client {
+        Client(self.0.clone(), dry_run)
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.1
+    }
+
+    /// Lists every resource of the given type, following continue tokens until the last page. If `metadata_only` is
+    /// true, then the items are `PartialObjectMetadata`, with only their metadata. If `initial_list` is true, and
+    /// `consistent_initial_list` isn't enabled, then the list is served from the api server's watch cache by sending
+    /// `resourceVersion=0`, which may be slightly stale. That's fine for seeding a cache before a watch, since the
+    /// watch starts from the `resourceVersion` of the list and delivers anything newer.
     pub async fn list_all(
         &self,
         k8s_type: &K8sType,
         namespace: Option<&str>,
         label_selector: Option<&str>,
+        field_selector: Option<&str>,
+        metadata_only: bool,
+        initial_list: bool,
     ) -> Result<ObjectList<Value>, Error> {
-        let req = request::list_request(&self.0.config, k8s_type, label_selector, namespace)?;
-        self.get_response_body(req).await
+        let page_size = Some(self.0.config.list_page_size).filter(|size| *size > 0);
+        let from_cache = initial_list && !self.0.config.consistent_initial_list;
+        let mut items = Vec::new();
+        let mut continue_token: Option<String> = None;
+        let mut restarts = 0;
+        loop {
+            let mut req = request::list_request(
+                &self.0.config,
+                k8s_type,
+                label_selector,
+                field_selector,
+                page_size,
+                continue_token.as_deref(),
+                // the continue token already determines the resourceVersion of the rest of the pages
+                Some("0").filter(|_| from_cache && continue_token.is_none()),
+                namespace,
+            )?;
+            if metadata_only {
+                request::accept_partial_metadata(&mut req, request::PARTIAL_METADATA_LIST);
+            }
+            let page: ObjectList<Value> = match self.get_response_body(req).await {
+                // the continue token expires after a few minutes, and then the list has to start over
+                Err(ref err)
+                    if err.is_http_status(410)
+                        && continue_token.is_some()
+                        && restarts < MAX_LIST_RESTARTS =>
+                {
+                    log::warn!(
+                        "Continue token expired while listing {}, restarting the list",
+                        k8s_type
+                    );
+                    restarts += 1;
+                    items.clear();
+                    continue_token = None;
+                    continue;
+                }
+                result => result?,
+            };
+            let ObjectList {
+                metadata,
+                items: page_items,
+            } = page;
+            items.extend(page_items);
+            match metadata.continue_token.as_ref().filter(|t| !t.is_empty()) {
+                Some(token) => continue_token = Some(token.clone()),
+                // the resourceVersion of the last page is the one that the watch needs to start from
+                None => return Ok(ObjectList { metadata, items }),
+            }
+        }
+    }
+
+    /// Lists at most one resource using the given selectors, in order to check whether the api server accepts
+    /// them. Returns the error from the api server if the request was rejected with a 400 status.
+    pub async fn check_list_selectors(
+        &self,
+        k8s_type: &K8sType,
+        namespace: Option<&str>,
+        label_selector: Option<&str>,
+        field_selector: Option<&str>,
+    ) -> Result<Option<ApiError>, Error> {
+        let req = request::list_request(
+            &self.0.config,
+            k8s_type,
+            label_selector,
+            field_selector,
+            Some(1),
+            None,
+            None,
+            namespace,
+        )?;
+        let response = self.get_response(req).await?;
+        match response.status().as_u16() {
+            200..=299 => Ok(None),
+            400 => {
+                let body = Client::read_body_bytes(response).await?;
+                let api_error = serde_json::from_slice::<ApiError>(body.as_ref())?;
+                Ok(Some(api_error))
+            }
+            _ => Err(Client::error_from_response(response).await),
+        }
+    }
+
+    /// Asks the api server whether the current user is allowed to perform the action described by the
+    /// `resourceAttributes`, by creating a `SelfSubjectAccessReview`
+    pub async fn check_access(&self, resource_attributes: Value) -> Result<bool, Error> {
+        let review = serde_json::json!({
+            "apiVersion": SelfSubjectAccessReview.api_version,
+            "kind": SelfSubjectAccessReview.kind,
+            "spec": {
+                "resourceAttributes": resource_attributes,
+            },
+        });
+        let req = request::create_request(&self.0.config, SelfSubjectAccessReview, &review)?;
+        let response = self.get_response_body::<Value>(req).await?;
+        let allowed = response
+            .pointer("/status/allowed")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        Ok(allowed)
     }
 
     pub async fn watch(
@@ -209,35 +449,76 @@ impl Client {
         namespace: Option<&str>,
         resource_version: Option<&str>,
         label_selector: Option<&str>,
+        field_selector: Option<&str>,
+        metadata_only: bool,
     ) -> Result<LineDeserializer<WatchEvent>, Error> {
-        let req = request::watch_request(
+        let mut req = request::watch_request(
             &self.0.config,
             k8s_type,
             resource_version,
             label_selector,
-            None,
+            field_selector,
+            Some(self.0.config.watch_timeout.as_secs().max(1) as u32),
             namespace,
         )?;
+        if metadata_only {
+            request::accept_partial_metadata(&mut req, request::PARTIAL_METADATA);
+        }
         self.get_response_lines_deserialized(req).await
     }
 
+    /// Returns the maximum amount of time that a watch may remain open. The `watch_timeout` is passed to the
+    /// api server, which should normally end the watch on its own, but this allows the caller to re-establish
+    /// a watch that's gone quiet without being closed. The `request_timeout` is added as a grace period.
+    pub fn max_watch_duration(&self) -> Duration {
+        self.0.config.watch_timeout + self.0.config.request_timeout
+    }
+
     pub async fn update_status(
         &self,
         k8s_type: &K8sType,
         id: &ObjectIdRef<'_>,
         new_status: &Value,
     ) -> Result<(), Error> {
-        let req = request::update_status_request(&self.0.config, k8s_type, id, new_status)?;
+        self.replace_subresource(k8s_type.subresource("status"), id, new_status)
+            .await
+    }
+
+    /// gets the given subresource (e.g. `scale`) of a resource, and converts a 404 response into a None value
+    #[allow(dead_code)]
+    pub async fn get_subresource(
+        &self,
+        subresource: K8sSubresource<'_>,
+        id: &ObjectIdRef<'_>,
+    ) -> Result<Option<Value>, Error> {
+        let req = request::get_subresource_request(&self.0.config, subresource, id)?;
+        match self.get_response_body::<Value>(req).await {
+            Ok(body) => Ok(Some(body)),
+            Err(ref e) if e.is_http_status(404) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn replace_subresource(
+        &self,
+        subresource: K8sSubresource<'_>,
+        id: &ObjectIdRef<'_>,
+        body: &Value,
+    ) -> Result<(), Error> {
+        let req = request::replace_subresource_request(&self.0.config, subresource, id, body)?;
         self.execute_ensure_success(req).await
     }
 
+    /// Deletes the resource using the given `propagationPolicy`, or the api server's default for the type if the
+    /// policy is `None`
     pub async fn delete_resource(
         &self,
         k8s_type: &K8sType,
         id: &ObjectIdRef<'_>,
+        delete_policy: Option<DeletePolicy>,
     ) -> Result<(), Error> {
         log::info!("Deleting resouce '{}' with type: {}", id, k8s_type);
-        let req = request::delete_request(&self.0.config, k8s_type, id)?;
+        let req = request::delete_request(&self.0.config, k8s_type, id, delete_policy)?;
         let response = self.get_response(req).await?;
 
         match response.status().as_u16() {
@@ -253,13 +534,12 @@ impl Client {
                     id,
                     other
                 );
-                Err(Error::http(response.status()))
+                Err(Client::error_from_response(response).await)
             }
         }
     }
 
     /// gets the requested resource by name and converts a 404 response into a None value
-    #[cfg(feature = "testkit")]
     pub async fn get_resource(
         &self,
         k8s_type: &K8sType,
@@ -273,6 +553,20 @@ impl Client {
         }
     }
 
+    /// gets the list of resources for the given apiVersion from the discovery endpoint, and converts a 404 response
+    /// into a None value, since that means the apiVersion isn't served by the api server
+    pub async fn discover_resources(
+        &self,
+        api_version: &str,
+    ) -> Result<Option<ApiResourceList>, Error> {
+        let req = request::discovery_request(&self.0.config, api_version)?;
+        match self.get_response_body::<ApiResourceList>(req).await {
+            Ok(body) => Ok(Some(body)),
+            Err(ref e) if e.is_http_status(404) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn create_resource(&self, k8s_type: &K8sType, resource: &Value) -> Result<(), Error> {
         let req = request::create_request(&self.0.config, k8s_type, resource)?;
         self.execute_ensure_success(req).await
@@ -298,23 +592,95 @@ impl Client {
         self.execute_ensure_success(req).await
     }
 
+    pub async fn apply_resource(
+        &self,
+        k8s_type: &K8sType,
+        id: &ObjectIdRef<'_>,
+        resource: &Value,
+        field_manager: &str,
+        force: bool,
+    ) -> Result<(), Error> {
+        let req =
+            request::apply_request(&self.0.config, k8s_type, id, resource, field_manager, force)?;
+        self.execute_ensure_success(req).await
+    }
+
     pub async fn execute_ensure_success(&self, req: Request<Body>) -> Result<(), Error> {
-        let response = self.get_response(req).await?;
-        if response.status().is_success() {
-            Ok(())
+        self.ensure_success(req).await
+    }
+
+    async fn ensure_success(&self, req: Request<Body>) -> Result<(), Error> {
+        let (req, request_body) = if self.0.config.capture_request_bodies {
+            let (parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            (
+                Request::from_parts(parts, Body::from(body.clone())),
+                Some(body),
+            )
         } else {
-            let status = response.status();
-            let body = hyper::body::to_bytes(response.into_body()).await?;
-            if let Ok(as_str) = std::str::from_utf8(body.as_ref()) {
-                log::error!("Response status: {}, body: {}", status, as_str);
-            } else {
-                log::error!(
-                    "Response status: {}, binary body with {} bytes",
-                    status,
-                    body.len()
-                );
+            (req, None)
+        };
+        let response = self.send(req).await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let err = match request_body {
+            Some(request_body) => {
+                Client::captured_error_from_response(request_body, response).await
+            }
+            None => Client::error_from_response(response).await,
+        };
+        log::error!("Request failed: {}", err);
+        Err(err)
+    }
+
+    /// Like `error_from_response`, but the error also includes the request body and the raw response body
+    async fn captured_error_from_response(request_body: Bytes, response: Response<Body>) -> Error {
+        let status = response.status();
+        let response_body = match Client::read_body_bytes(response).await {
+            Ok(body) => body,
+            Err(err) => {
+                log::debug!("Response status: {}, failed to read body: {}", status, err);
+                return Error::http(status);
+            }
+        };
+        Error::Captured(Box::new(CapturedError {
+            error: Client::error_from_body(status, response_body.as_ref()),
+            request_body: String::from_utf8_lossy(request_body.as_ref()).into_owned(),
+            response_body: String::from_utf8_lossy(response_body.as_ref()).into_owned(),
+        }))
+    }
+
+    /// Reads the body of an unsuccessful response and returns it as an `Error::Api` if it's a `Status`, which is
+    /// what the api server responds with whenever it rejects a request. Any other body results in an `Error::Http`
+    /// with just the response status.
+    async fn error_from_response(response: Response<Body>) -> Error {
+        let status = response.status();
+        let body = match Client::read_body_bytes(response).await {
+            Ok(body) => body,
+            Err(err) => {
+                log::debug!("Response status: {}, failed to read body: {}", status, err);
+                return Error::http(status);
+            }
+        };
+        Client::error_from_body(status, body.as_ref())
+    }
+
+    fn error_from_body(status: http::StatusCode, body: &[u8]) -> Error {
+        match serde_json::from_slice::<ApiError>(body) {
+            Ok(api_error) => Error::Api(Box::new(api_error)),
+            Err(_) => {
+                if let Ok(as_str) = std::str::from_utf8(body) {
+                    log::debug!("Response status: {}, body: {}", status, as_str);
+                } else {
+                    log::debug!(
+                        "Response status: {}, binary body with {} bytes",
+                        status,
+                        body.len()
+                    );
+                }
+                Error::http(status)
             }
-            Err(Error::http(status))
         }
     }
 
@@ -329,32 +695,46 @@ impl Client {
     async fn get_response_lines(&self, req: Request<Body>) -> Result<Lines, Error> {
         let resp = self.get_response(req).await?;
         if !resp.status().is_success() {
-            Err(Error::http(resp.status()))
+            Err(Client::error_from_response(resp).await)
         } else {
             Ok(Lines::from_body(resp.into_body()))
         }
     }
 
+    /// Sends the request and waits for the response headers. Each attempt fails with `Error::Timeout` if that
+    /// takes longer than the `request_timeout`. Reading the body of the response is not included in the timeout,
+    /// since this is used for watches.
     async fn get_response(&self, req: Request<Body>) -> Result<Response<Body>, Error> {
+        self.send(req).await
+    }
+
+    async fn send(&self, req: Request<Body>) -> Result<Response<Body>, Error> {
         let method = req.method().to_string();
         let uri = req.uri().to_string();
         let start_time = Instant::now();
 
-        self.private_execute_request(start_time, method.as_str(), uri.as_str(), req)
+        self.execute_with_retries(start_time, method.as_str(), uri.as_str(), req)
             .await
     }
 
     async fn get_response_body<T: DeserializeOwned>(&self, req: Request<Body>) -> Result<T, Error> {
+        self.read_response_body(req).await
+    }
+
+    async fn read_response_body<T: DeserializeOwned>(
+        &self,
+        req: Request<Body>,
+    ) -> Result<T, Error> {
         let method = req.method().to_string();
         let uri = req.uri().to_string();
         let start_time = Instant::now();
 
         let response = self
-            .private_execute_request(start_time, method.as_str(), uri.as_str(), req)
+            .execute_with_retries(start_time, method.as_str(), uri.as_str(), req)
             .await?;
 
         let status_code = response.status().as_u16();
-        let result = Client::read_body(response).await;
+        let result = self.with_timeout(Client::read_body(response)).await;
         let success = result.is_ok();
         let duration = start_time.elapsed().as_millis();
         log::debug!(
@@ -368,17 +748,114 @@ impl Client {
         result
     }
 
-    async fn private_execute_request(
+    /// Executes the request, retrying transient failures according to the `retry_policy`. Requests that aren't
+    /// idempotent are only retried if the connection to the api server could not be established.
+    async fn execute_with_retries(
+        &self,
+        start_time: Instant,
+        method: &str,
+        uri: &str,
+        req: Request<Body>,
+    ) -> Result<Response<Body>, Error> {
+        // nested under the span of the reconcile that made the request, if there is one
+        let span = tracing::debug_span!("request", verb = method, url = uri);
+        self.execute_attempts(start_time, method, uri, req)
+            .instrument(span)
+            .await
+    }
+
+    async fn execute_attempts(
         &self,
         start_time: Instant,
         method: &str,
         uri: &str,
         req: Request<Body>,
+    ) -> Result<Response<Body>, Error> {
+        let (mut parts, body) = req.into_parts();
+        // the body needs to be buffered so that it can be sent again on each attempt
+        let body = hyper::body::to_bytes(body).await?;
+        if self.1 && parts.method != http::Method::GET {
+            parts.uri = add_dry_run_param(&parts.uri);
+            log::info!(
+                "Dry run of {} request to: {}, body: {}",
+                method,
+                uri,
+                String::from_utf8_lossy(body.as_ref())
+            );
+        }
+        let idempotent = retry::is_idempotent(&parts.method);
+        let mut retries = RetryState::new(&self.0.config.retry_policy);
+        loop {
+            let mut attempt = Request::new(Body::from(body.clone()));
+            *attempt.method_mut() = parts.method.clone();
+            *attempt.uri_mut() = parts.uri.clone();
+            *attempt.version_mut() = parts.version;
+            *attempt.headers_mut() = parts.headers.clone();
+
+            let result = self
+                .private_execute_request(start_time, method, uri, attempt)
+                .await;
+            let retry_after = match result {
+                Ok(ref response) if idempotent && retry::is_retryable_status(response.status()) => {
+                    retry::get_retry_after(response)
+                }
+                Err(Error::Io(ref err)) if idempotent || err.is_connect() => None,
+                _ => return result,
+            };
+            match retries.next_delay(retry_after) {
+                Some(delay) => {
+                    log::warn!(
+                        "Retrying {} request to: {} in {}ms, attempt {} of {}",
+                        method,
+                        uri,
+                        delay.as_millis(),
+                        retries.attempts(),
+                        self.0.config.retry_policy.max_attempts
+                    );
+                    tokio::time::delay_for(delay).await;
+                }
+                None => return result,
+            }
+        }
+    }
+
+    async fn private_execute_request(
+        &self,
+        start_time: Instant,
+        method: &str,
+        uri: &str,
+        mut req: Request<Body>,
     ) -> Result<Response<Body>, Error> {
         log::debug!("Starting {} request to: {}", method, uri);
+        if let Some(limiter) = self.0.rate_limiter.as_ref() {
+            let wait = limiter.acquire().await;
+            self.0.metrics.rate_limiter_waited(wait);
+            if wait > Duration::from_millis(0) {
+                log::debug!(
+                    "{} request to: {} was delayed {}ms by the client rate limiter",
+                    method,
+                    uri,
+                    wait.as_millis()
+                );
+            }
+        }
+        if let Err(err) = self.0.auth.authorize(&mut req).await {
+            log::error!(
+                "Failed to get credentials for {} request to: {}, err: {}",
+                method,
+                uri,
+                err
+            );
+            return Err(err);
+        }
+        add_extra_headers(&mut req, &self.0.extra_headers);
         // we measure duration separately for the logs and for the prometheus metrics... should figure out an alternative
         let timer = self.0.metrics.request_started();
-        let result = self.0.http_client.request(req).await;
+        // the timeout only starts once the rate limiter has let the request through, so that throttled or
+        // retried requests don't time out unless a single attempt is slow
+        let result = self
+            .with_timeout(async { Ok(self.0.http_client.request(req).await?) })
+            .await;
         let duration = start_time.elapsed().as_millis();
         timer.observe_duration();
         match result {
@@ -400,20 +877,32 @@ impl Client {
                     uri,
                     err
                 );
-                Err(err.into())
+                Err(err)
             }
         }
     }
 
+    async fn with_timeout<T>(
+        &self,
+        future: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let timeout = self.0.config.request_timeout;
+        match tokio::time::timeout(timeout, future).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout(timeout)),
+        }
+    }
+
     async fn read_body<T: DeserializeOwned>(response: Response<Body>) -> Result<T, Error> {
         if !response.status().is_success() {
-            return Err(Error::http(response.status()));
+            return Err(Client::error_from_response(response).await);
         }
 
-        let deserialized = if log::log_enabled!(log::Level::Trace) {
+        let deserialized = if log::log_enabled!(log::Level::Trace) || is_compressed(&response) {
             // if we're logging the response body, then we'll need to read it all into a
-            // single buffer, since we can't read a response body twice without copying
-            let body = hyper::body::to_bytes(response.into_body()).await?;
+            // single buffer, since we can't read a response body twice without copying.
+            // Compressed bodies also need to be buffered so that they can be decompressed.
+            let body = Client::read_body_bytes(response).await?;
             let as_str = String::from_utf8_lossy(body.as_ref());
             log::trace!("Got response body: {}", as_str);
             serde_json::from_slice(body.as_ref())?
@@ -424,6 +913,61 @@ impl Client {
         };
         Ok(deserialized)
     }
+
+    /// Reads the whole response body, and decompresses it according to the `Content-Encoding` header
+    async fn read_body_bytes(response: Response<Body>) -> Result<Bytes, Error> {
+        let encoding = response
+            .headers()
+            .get(http::header::CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        decompress(encoding.as_deref(), body)
+    }
+}
+
+fn is_compressed(response: &Response<Body>) -> bool {
+    response
+        .headers()
+        .get(http::header::CONTENT_ENCODING)
+        .map(|value| value != "identity")
+        .unwrap_or(false)
+}
+
+/// Decompresses a response body that was encoded with `gzip` or `deflate`. Bodies with any other encoding are
+/// returned unchanged.
+fn decompress(encoding: Option<&str>, body: Bytes) -> Result<Bytes, Error> {
+    let mut decompressed = Vec::with_capacity(body.len() * 4);
+    let result = match encoding {
+        Some("gzip") => GzDecoder::new(body.as_ref()).read_to_end(&mut decompressed),
+        Some("deflate") => ZlibDecoder::new(body.as_ref()).read_to_end(&mut decompressed),
+        _ => return Ok(body),
+    };
+    result.map_err(Error::Decompression)?;
+    log::debug!(
+        "Decompressed {} response body from {} to {} bytes, a {:.1}% reduction",
+        encoding.unwrap_or_default(),
+        body.len(),
+        decompressed.len(),
+        compression_ratio(body.len(), decompressed.len()) * 100.0
+    );
+    Ok(Bytes::from(decompressed))
+}
+
+fn compression_ratio(compressed_len: usize, decompressed_len: usize) -> f64 {
+    if decompressed_len == 0 {
+        0.0
+    } else {
+        1.0 - (compressed_len as f64 / decompressed_len as f64)
+    }
+}
+
+fn add_dry_run_param(uri: &http::Uri) -> http::Uri {
+    let as_string = uri.to_string();
+    let separator = if uri.query().is_some() { '&' } else { '?' };
+    format!("{}{}dryRun=All", as_string, separator)
+        .parse()
+        .expect("adding a query parameter must result in a valid uri")
 }
 
 pub struct Lines {
@@ -568,9 +1112,28 @@ pub enum WatchEvent {
     Added(Value),
     Modified(Value),
     Deleted(Value),
+    /// Sent periodically when `allowWatchBookmarks` is set, so that the watch can be resumed from a recent
+    /// `resourceVersion`. The object only includes the `resourceVersion` in its metadata.
+    Bookmark(Value),
     Error(ApiError),
 }
 
+impl WatchEvent {
+    /// The `type` of the event, as it's sent by the api server, e.g. `ADDED`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            WatchEvent::Added(_) => "ADDED",
+            WatchEvent::Modified(_) => "MODIFIED",
+            WatchEvent::Deleted(_) => "DELETED",
+            WatchEvent::Bookmark(_) => "BOOKMARK",
+            WatchEvent::Error(_) => "ERROR",
+        }
+    }
+}
+
+/// A `Status` returned by the api server when it rejects a request, or as the object of an `ERROR` watch event. The
+/// `reason` is a machine readable description of why the request failed, such as `Conflict`, `AlreadyExists`,
+/// `Invalid`, or `Forbidden`, and `details` may hold field-level `causes` for validation failures.
 #[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
 pub struct ApiError {
     pub status: String,
@@ -579,6 +1142,49 @@ pub struct ApiError {
     #[serde(default)]
     pub reason: String,
     pub code: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<StatusDetails>,
+}
+
+impl ApiError {
+    /// The reason for the failure, e.g. `Conflict`, or an empty string if the api server didn't provide one
+    pub fn reason(&self) -> &str {
+        self.reason.as_str()
+    }
+
+    /// The http status code of the response
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// A human readable description of the failure
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    /// The individual causes of the failure, which for `Invalid` requests includes each field that failed validation
+    pub fn causes(&self) -> &[StatusCause] {
+        self.details
+            .as_ref()
+            .map(|details| details.causes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Returns true if the reason for the failure matches the given reason, e.g. `Conflict`
+    pub fn is_reason(&self, reason: &str) -> bool {
+        self.reason == reason
+    }
+
+    /// Finds the `ApiError` that caused the given error, if any, such as an error returned by `run_operator` when the
+    /// api server rejected one of its requests
+    pub fn find_in(error: &anyhow::Error) -> Option<&ApiError> {
+        error.chain().find_map(|cause| {
+            cause
+                .downcast_ref::<Error>()
+                .and_then(Error::api_error)
+                .or_else(|| cause.downcast_ref::<ApiError>())
+        })
+    }
 }
 
 impl std::fmt::Display for ApiError {
@@ -587,15 +1193,57 @@ impl std::fmt::Display for ApiError {
             f,
             "Api Error: status: '{}', code: {}, reason: '{}', message: '{}'",
             self.status, self.code, self.reason, self.message
-        )
+        )?;
+        for cause in self.causes() {
+            write!(f, ", cause: {}", cause)?;
+        }
+        Ok(())
     }
 }
 impl std::error::Error for ApiError {}
 
+/// Extra information about an `ApiError`, which identifies the resource that the request was for
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, Default)]
+pub struct StatusDetails {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub group: String,
+    #[serde(default)]
+    pub kind: String,
+    #[serde(default)]
+    pub causes: Vec<StatusCause>,
+}
+
+/// A single cause of an `ApiError`. For validation failures, the `field` is the path to the invalid field, e.g.
+/// `spec.replicas`.
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq, Default)]
+pub struct StatusCause {
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub field: String,
+}
+
+impl std::fmt::Display for StatusCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}: {}", self.reason, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.field, self.reason, self.message)
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct ListMeta {
     #[serde(rename = "resourceVersion")]
     pub resource_version: Option<String>,
+    /// Token for fetching the next page of the list, which is missing or empty on the last page
+    #[serde(rename = "continue")]
+    pub continue_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Clone)]
@@ -604,15 +1252,867 @@ pub struct ObjectList<T> {
     pub items: Vec<T>,
 }
 
+/// Returns all of the certificates from the `CAData`, which may contain any number of PEM encoded certificates
+fn ca_certificates(ca_data: &CAData) -> Result<Vec<X509>, io::Error> {
+    match ca_data {
+        CAData::Contents(certs) => {
+            // if the CA cert contents are provided inline, as they are from a kubeconfig file, then we need to manually
+            // parse them
+            let decoded = base64::decode(certs).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!(
+                        "Invalid base64 content of certificate-authority-data: {}",
+                        err
+                    ),
+                )
+            })?;
+            Ok(X509::stack_from_pem(decoded.as_slice())?)
+        }
+        CAData::File(path) => {
+            let contents = read_pem_file(path, "certificate authority")?;
+            X509::stack_from_pem(contents.as_slice())
+                .map_err(|err| invalid_pem_file(path, "certificate authority", err))
+        }
+        CAData::Bundle(bundle) => {
+            let mut certs = Vec::new();
+            for ca_data in bundle {
+                certs.extend(ca_certificates(ca_data)?);
+            }
+            Ok(certs)
+        }
+    }
+}
+
+/// Reads the whole PEM file, returning an error that says which file couldn't be read, since the error from opening a
+/// file doesn't include its path
+fn read_pem_file(path: &str, description: &str) -> Result<Vec<u8>, io::Error> {
+    let mut contents = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Failed to read {} from '{}': {}", description, path, err),
+            )
+        })?;
+    Ok(contents)
+}
+
+/// Logs a warning the first time that a client is created without certificate verification. Operators create
+/// several clients, and repeating the warning for each of them would only make it easier to ignore.
+fn warn_insecure_once(api_server_endpoint: &str) {
+    static WARNING: Once = Once::new();
+    WARNING.call_once(|| {
+        log::warn!(
+            "TLS certificate and hostname verification has been disabled by `verify_ssl_certs: false`! All connections to the Kubernetes api server at {} are insecure, and vulnerable to interception!",
+            api_server_endpoint
+        );
+    });
+}
+
+fn invalid_pem_file(path: &str, description: &str, err: openssl::error::ErrorStack) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Invalid PEM content of {} in '{}': {}",
+            description, path, err
+        ),
+    )
+}
+
+/// Adds the configured `extra_headers` to the request, replacing any headers with the same names that have already
+/// been set, including those set by the `AuthProvider`
+fn add_extra_headers(req: &mut Request<Body>, extra_headers: &HeaderMap) {
+    for (name, value) in extra_headers.iter() {
+        req.headers_mut().insert(name.clone(), value.clone());
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::runner::metrics::Metrics;
     use bytes::Bytes;
     use futures_util::StreamExt;
     use hyper::Body;
     use std::io::Read;
     use tokio::runtime;
 
+    #[test]
+    fn requests_fail_with_timeout_error_when_the_api_server_does_not_respond() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            // accepts connections but never responds to them
+            let mut listener =
+                tokio::net::TcpListener::bind(std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                    .await
+                    .unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut connections = Vec::new();
+                while let Ok((conn, _)) = listener.accept().await {
+                    connections.push(conn);
+                }
+            });
+
+            let mut config = test_client_config(format!("http://{}", addr));
+            config.request_timeout = Duration::from_millis(100);
+            let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+
+            let start = Instant::now();
+            let result = client
+                .list_all(
+                    crate::k8s_types::core::v1::Pod,
+                    None,
+                    None,
+                    None,
+                    false,
+                    false,
+                )
+                .await;
+            match result {
+                Err(Error::Timeout(timeout)) => assert_eq!(Duration::from_millis(100), timeout),
+                other => panic!("expected timeout error, got: {:?}", other.map(|_| ())),
+            }
+            assert!(start.elapsed() < Duration::from_secs(5));
+        });
+    }
+
+    /// Starts a server that responds to the nth request (starting at 0) with the status returned by `status_for`
+    async fn start_mock_server(
+        status_for: fn(usize) -> u16,
+    ) -> (std::net::SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let request_count = Arc::new(AtomicUsize::new(0));
+        let count = request_count.clone();
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let count = count.clone();
+            async move {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(move |_req| {
+                    let n = count.fetch_add(1, Ordering::SeqCst);
+                    let response = Response::builder()
+                        .status(status_for(n))
+                        .header(http::header::RETRY_AFTER, "0")
+                        .body(Body::from(
+                            r#"{"apiVersion":"v1","kind":"Pod","metadata":{"name":"foo"}}"#,
+                        ))
+                        .unwrap();
+                    async move { Ok::<_, hyper::Error>(response) }
+                }))
+            }
+        });
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, request_count)
+    }
+
+    #[test]
+    fn lists_are_paginated_and_restarted_when_the_continue_token_expires() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let queries = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let expired_count = Arc::new(AtomicUsize::new(0));
+            let (recorded, expired) = (queries.clone(), expired_count.clone());
+            let make_service = hyper::service::make_service_fn(move |_| {
+                let (recorded, expired) = (recorded.clone(), expired.clone());
+                async move {
+                    Ok::<_, hyper::Error>(hyper::service::service_fn(move |req: Request<Body>| {
+                        let query = req.uri().query().unwrap_or_default().to_owned();
+                        recorded.lock().unwrap().push(query.clone());
+                        let pod = |name: &str| serde_json::json!({"metadata": {"name": name}});
+                        let (status, body) = if !query.contains("continue=") {
+                            let body = serde_json::json!({
+                                "metadata": {"resourceVersion": "1", "continue": "page-2"},
+                                "items": [pod("a"), pod("b")],
+                            });
+                            (200, body)
+                        } else if expired.fetch_add(1, Ordering::SeqCst) == 0 {
+                            (410, serde_json::json!({"kind": "Status", "code": 410}))
+                        } else {
+                            let body = serde_json::json!({
+                                "metadata": {"resourceVersion": "2", "continue": ""},
+                                "items": [pod("c")],
+                            });
+                            (200, body)
+                        };
+                        let response = Response::builder()
+                            .status(status)
+                            .body(Body::from(body.to_string()))
+                            .unwrap();
+                        async move { Ok::<_, hyper::Error>(response) }
+                    }))
+                }
+            });
+            let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            let config = test_client_config(format!("http://{}", addr)).list_page_size(2);
+            let client = Client::new(config.clone(), Metrics::new().client_metrics()).unwrap();
+            let list = client
+                .list_all(
+                    crate::k8s_types::core::v1::Pod,
+                    None,
+                    None,
+                    None,
+                    false,
+                    true,
+                )
+                .await
+                .unwrap();
+
+            let names = list
+                .items
+                .iter()
+                .map(|item| item.pointer("/metadata/name").unwrap().as_str().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(vec!["a", "b", "c"], names);
+            assert_eq!(Some("2".to_owned()), list.metadata.resource_version);
+            // only the first page of the initial list is served from the cache, and the list still returns the
+            // resourceVersion of the last page for the watch to start from
+            assert_eq!(
+                vec![
+                    "limit=2&resourceVersion=0",
+                    "limit=2&continue=page-2",
+                    "limit=2&resourceVersion=0",
+                    "limit=2&continue=page-2"
+                ],
+                *queries.lock().unwrap()
+            );
+
+            queries.lock().unwrap().clear();
+            let config = config.consistent_initial_list(true);
+            let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+            let list = client
+                .list_all(
+                    crate::k8s_types::core::v1::Pod,
+                    None,
+                    None,
+                    None,
+                    false,
+                    true,
+                )
+                .await
+                .unwrap();
+            assert_eq!(Some("2".to_owned()), list.metadata.resource_version);
+            assert_eq!(
+                vec!["limit=2", "limit=2&continue=page-2"],
+                *queries.lock().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn gzipped_responses_are_decompressed() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let make_service = hyper::service::make_service_fn(|_| async {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(|req: Request<Body>| {
+                    let accepts_gzip = req
+                        .headers()
+                        .get(http::header::ACCEPT_ENCODING)
+                        .map(|value| value == "gzip")
+                        .unwrap_or(false);
+                    let items = (0..500)
+                        .map(|i| serde_json::json!({"metadata": {"name": format!("pod-{}", i)}}))
+                        .collect::<Vec<_>>();
+                    let body = serde_json::json!({"metadata": {}, "items": items}).to_string();
+                    let response = if accepts_gzip {
+                        let mut encoder =
+                            GzEncoder::new(Vec::new(), flate2::Compression::default());
+                        encoder.write_all(body.as_bytes()).unwrap();
+                        Response::builder()
+                            .header(http::header::CONTENT_ENCODING, "gzip")
+                            .body(Body::from(encoder.finish().unwrap()))
+                    } else {
+                        Response::builder().body(Body::from(body))
+                    };
+                    async move { Ok::<_, hyper::Error>(response.unwrap()) }
+                }))
+            });
+            let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            let config = test_client_config(format!("http://{}", addr));
+            for accept_compression in &[true, false] {
+                let config = config.clone().accept_compression(*accept_compression);
+                let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+                let list = client
+                    .list_all(
+                        crate::k8s_types::core::v1::Pod,
+                        None,
+                        None,
+                        None,
+                        false,
+                        false,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(500, list.items.len());
+            }
+        });
+    }
+
+    #[test]
+    fn unknown_content_encodings_are_left_alone() {
+        let body = Bytes::from_static(b"{}");
+        assert_eq!(body, decompress(None, body.clone()).unwrap());
+        assert_eq!(body, decompress(Some("identity"), body.clone()).unwrap());
+        match decompress(Some("gzip"), body) {
+            Err(Error::Decompression(_)) => {}
+            other => panic!("expected decompression error, got: {:?}", other),
+        }
+        assert!((compression_ratio(25, 100) - 0.75).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn extra_headers_are_added_to_requests() {
+        let config = test_client_config("http://localhost".to_owned())
+            .with_extra_header("X-Tenant", "foo")
+            .with_extra_header("user-agent", "custom-agent");
+        let extra_headers = config.validate_extra_headers().unwrap();
+
+        let mut req = Request::get("http://localhost/api/v1/pods")
+            .header(http::header::USER_AGENT, "test")
+            .header(http::header::AUTHORIZATION, "Bearer foo")
+            .body(Body::empty())
+            .unwrap();
+        add_extra_headers(&mut req, &extra_headers);
+        let headers = req.headers();
+        assert_eq!("foo", headers.get("x-tenant").unwrap());
+        assert_eq!(
+            "custom-agent",
+            headers.get(http::header::USER_AGENT).unwrap()
+        );
+        assert_eq!(1, headers.get_all(http::header::USER_AGENT).iter().count());
+        assert_eq!(
+            "Bearer foo",
+            headers.get(http::header::AUTHORIZATION).unwrap()
+        );
+    }
+
+    #[test]
+    fn client_creation_fails_with_the_path_of_a_missing_pem_file() {
+        let mut config = test_client_config("https://localhost".to_owned());
+        config.credentials =
+            Credentials::pem_files("/does/not/exist/tls.crt", "/does/not/exist/tls.key");
+        let err = Client::new(config, Metrics::new().client_metrics()).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().contains("client certificate"));
+        assert!(err.to_string().contains("/does/not/exist/tls.crt"));
+
+        let mut config = test_client_config("https://localhost".to_owned());
+        // the dummy certificate exists, but isn't valid PEM
+        config.credentials =
+            Credentials::pem_files("src/config/test-data/dummy-ca.crt", "Cargo.toml");
+        let err = Client::new(config, Metrics::new().client_metrics()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().contains("dummy-ca.crt"));
+    }
+
+    #[test]
+    fn client_creation_fails_when_extra_headers_are_invalid() {
+        let metrics = Metrics::new();
+        let config =
+            test_client_config("http://localhost".to_owned()).with_extra_header("X Tenant", "foo");
+        let err = Client::new(config, metrics.client_metrics()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        let config = test_client_config("http://localhost".to_owned())
+            .with_extra_header("X-Tenant", "foo\nbar");
+        let err = config.validate_extra_headers().unwrap_err();
+        assert_eq!("X-Tenant", err.name);
+    }
+
+    fn retry_test_client(addr: std::net::SocketAddr) -> Client {
+        let mut config = test_client_config(format!("http://{}", addr));
+        config.retry_policy.base_delay = Duration::from_millis(10);
+        Client::new(config, Metrics::new().client_metrics()).unwrap()
+    }
+
+    #[test]
+    fn time_spent_waiting_for_the_rate_limiter_does_not_count_towards_the_request_timeout() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (addr, request_count) = start_mock_server(|_| 200).await;
+            let mut config = test_client_config(format!("http://{}", addr));
+            config.qps = 2.0;
+            config.burst = 1;
+            config.request_timeout = Duration::from_millis(200);
+            let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+
+            let id = ObjectIdRef::new("default", "foo");
+            let start = Instant::now();
+            for _ in 0..3 {
+                client
+                    .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                    .await
+                    .expect("throttled request should not have timed out");
+            }
+            assert!(start.elapsed() >= Duration::from_millis(900));
+            assert_eq!(3, request_count.load(std::sync::atomic::Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn idempotent_requests_are_retried_after_transient_errors() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (addr, request_count) = start_mock_server(|n| if n < 2 { 503 } else { 200 }).await;
+            let client = retry_test_client(addr);
+
+            let id = ObjectIdRef::new("default", "foo");
+            let result = client
+                .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                .await
+                .expect("get_resource should have succeeded after retrying");
+            assert!(result.is_some());
+            assert_eq!(3, request_count.load(std::sync::atomic::Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn non_idempotent_requests_are_not_retried_after_a_response() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (addr, request_count) = start_mock_server(|_| 503).await;
+            let client = retry_test_client(addr);
+
+            let pod = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "namespace": "default", "name": "foo" },
+            });
+            let result = client
+                .create_resource(crate::k8s_types::core::v1::Pod, &pod)
+                .await;
+            assert!(result.unwrap_err().is_http_status(503));
+            assert_eq!(1, request_count.load(std::sync::atomic::Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn rejected_requests_return_the_status_from_the_api_server() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let make_service = hyper::service::make_service_fn(|_| async {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(|_req| async {
+                    let body = serde_json::json!({
+                        "kind": "Status",
+                        "apiVersion": "v1",
+                        "metadata": {},
+                        "status": "Failure",
+                        "message": "Pod \"foo\" is invalid: spec.containers: Required value",
+                        "reason": "Invalid",
+                        "details": {
+                            "name": "foo",
+                            "kind": "Pod",
+                            "causes": [{
+                                "reason": "FieldValueRequired",
+                                "message": "Required value",
+                                "field": "spec.containers"
+                            }]
+                        },
+                        "code": 422
+                    });
+                    Ok::<_, hyper::Error>(
+                        Response::builder()
+                            .status(422)
+                            .body(Body::from(body.to_string()))
+                            .unwrap(),
+                    )
+                }))
+            });
+            let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+            let client = retry_test_client(addr);
+
+            let pod = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "namespace": "default", "name": "foo" },
+            });
+            let err = client
+                .create_resource(crate::k8s_types::core::v1::Pod, &pod)
+                .await
+                .unwrap_err();
+            assert!(err.is_http_status(422));
+            let api_error = err.api_error().expect("expected an api error");
+            assert!(api_error.is_reason("Invalid"));
+            assert_eq!(422, api_error.code());
+            assert_eq!(
+                vec![StatusCause {
+                    reason: "FieldValueRequired".to_owned(),
+                    message: "Required value".to_owned(),
+                    field: "spec.containers".to_owned(),
+                }],
+                api_error.causes()
+            );
+            assert!(err.to_string().contains("spec.containers"));
+
+            let api_error = api_error.clone();
+            let wrapped = anyhow::Error::new(err).context("creating the pod");
+            assert_eq!(Some(&api_error), ApiError::find_in(&wrapped));
+            assert_eq!(
+                None,
+                ApiError::find_in(&anyhow::anyhow!("not an api error"))
+            );
+        });
+    }
+
+    #[test]
+    fn request_and_response_bodies_are_included_in_errors_when_captured() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (addr, _) = start_mock_server(|_| 400).await;
+            let pod = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "namespace": "default", "name": "captured-pod" },
+            });
+
+            let client = retry_test_client(addr);
+            let err = client
+                .create_resource(crate::k8s_types::core::v1::Pod, &pod)
+                .await
+                .unwrap_err();
+            assert!(!err.to_string().contains("captured-pod"));
+
+            let config =
+                test_client_config(format!("http://{}", addr)).capture_request_bodies(true);
+            let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+            let err = client
+                .create_resource(crate::k8s_types::core::v1::Pod, &pod)
+                .await
+                .unwrap_err();
+            assert!(err.is_http_status(400));
+            let message = err.to_string();
+            assert!(message.contains(r#""name":"captured-pod""#), "{}", message);
+            assert!(message.contains(r#"response body: {"apiVersion":"v1","kind":"Pod""#));
+        });
+    }
+
+    #[test]
+    fn captured_bodies_are_truncated() {
+        let body = "é".repeat(MAX_CAPTURED_BODY_LEN);
+        let truncated = truncate_body(body.as_str());
+        assert!(truncated.starts_with(&body[..MAX_CAPTURED_BODY_LEN]));
+        assert!(truncated.ends_with(&format!("... ({} more bytes)", MAX_CAPTURED_BODY_LEN)));
+        assert_eq!("short", truncate_body("short"));
+    }
+
+    #[test]
+    fn sequential_requests_reuse_a_single_connection() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let connection_count = Arc::new(AtomicUsize::new(0));
+            let connections = connection_count.clone();
+            // the outer closure is invoked once for each new connection
+            let make_service = hyper::service::make_service_fn(move |_| {
+                connections.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    Ok::<_, hyper::Error>(hyper::service::service_fn(move |_req| {
+                        let response = Response::builder()
+                            .status(200)
+                            .body(Body::from(
+                                r#"{"apiVersion":"v1","kind":"Pod","metadata":{"name":"foo"}}"#,
+                            ))
+                            .unwrap();
+                        async move { Ok::<_, hyper::Error>(response) }
+                    }))
+                }
+            });
+            let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            let config = test_client_config(format!("http://{}", addr)).max_idle_connections(1);
+            let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+            let id = ObjectIdRef::new("default", "foo");
+            for _ in 0..10 {
+                let result = client
+                    .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                    .await
+                    .expect("get_resource failed");
+                assert!(result.is_some());
+            }
+            assert_eq!(1, connection_count.load(Ordering::SeqCst));
+        });
+    }
+
+    fn self_signed_ca_pem(common_name: &str) -> Vec<u8> {
+        self_signed_cert(common_name).0.to_pem().unwrap()
+    }
+
+    fn self_signed_cert(common_name: &str) -> (X509, PKey<openssl::pkey::Private>) {
+        use openssl::asn1::Asn1Time;
+        use openssl::hash::MessageDigest;
+        use openssl::x509::X509NameBuilder;
+
+        let key = PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    /// Serves HTTPS requests using the given certificate from a separate thread, and returns the address to connect to
+    fn start_tls_server(cert: X509, key: PKey<openssl::pkey::Private>) -> std::net::SocketAddr {
+        use openssl::ssl::SslAcceptor;
+        use std::io::Write;
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        let acceptor = acceptor.build();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match acceptor.accept(stream.unwrap()) {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer);
+                let body = r#"{"apiVersion":"v1","kind":"Pod","metadata":{"name":"foo"}}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes());
+                let _ = stream.shutdown();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn certificates_are_verified_against_the_tls_server_name() {
+        let (cert, key) = self_signed_cert("kubernetes.example.test");
+        let ca = base64::encode(cert.to_pem().unwrap());
+        let addr = start_tls_server(cert, key);
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let id = ObjectIdRef::new("default", "foo");
+            let config = test_client_config(format!("https://{}", addr))
+                .add_ca_data(CAData::Contents(ca))
+                .trust_system_roots(false);
+
+            let client = Client::new(config.clone(), Metrics::new().client_metrics()).unwrap();
+            let result = client
+                .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                .await;
+            assert!(result.is_err(), "expected the ip address not to match");
+
+            let config = config.tls_server_name("kubernetes.example.test");
+            let client = Client::new(config, Metrics::new().client_metrics()).unwrap();
+            let pod = client
+                .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                .await
+                .expect("request should succeed with the tls_server_name");
+            assert!(pod.is_some());
+        });
+    }
+
+    #[test]
+    fn disabling_verification_skips_certificate_and_hostname_checks() {
+        let (cert, key) = self_signed_cert("kubernetes.example.test");
+        let addr = start_tls_server(cert, key);
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let id = ObjectIdRef::new("default", "foo");
+            let mut config = test_client_config(format!("https://{}", addr));
+            let client = Client::new(config.clone(), Metrics::new().client_metrics()).unwrap();
+            let result = client
+                .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                .await;
+            assert!(result.is_err(), "expected the certificate to be untrusted");
+
+            config.verify_ssl_certs = false;
+            let metrics = Metrics::new();
+            let client = Client::new(config, metrics.client_metrics()).unwrap();
+            let pod = client
+                .get_resource(crate::k8s_types::core::v1::Pod, &id)
+                .await
+                .expect("request should succeed without verification");
+            assert!(pod.is_some());
+
+            let text = String::from_utf8(metrics.encode_as_text().unwrap()).unwrap();
+            assert!(text.contains(r#"client_tls_info{verify_certs="false"} 1"#));
+        });
+    }
+
+    #[test]
+    fn all_certificates_in_a_ca_bundle_are_loaded() {
+        let cluster_ca = self_signed_ca_pem("cluster-ca");
+        let gateway_ca = self_signed_ca_pem("gateway-ca");
+        let path =
+            std::env::temp_dir().join(format!("roperator-test-ca-{}.crt", std::process::id()));
+        std::fs::write(&path, gateway_ca.as_slice()).unwrap();
+
+        let config = test_client_config("https://localhost".to_owned())
+            .add_ca_data(CAData::Contents(base64::encode(&cluster_ca)))
+            .add_ca_data(CAData::File(path.to_string_lossy().into_owned()));
+        let ca_data = config.ca_data.clone().unwrap();
+        let certs = ca_certificates(&ca_data).unwrap();
+
+        let names = certs
+            .iter()
+            .map(|cert| {
+                let entry = cert.subject_name().entries().next().unwrap();
+                entry.data().to_string().unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["cluster-ca", "gateway-ca"], names);
+        assert!(Client::new(
+            config.trust_system_roots(false),
+            Metrics::new().client_metrics()
+        )
+        .is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = CAData::Bundle(vec![CAData::File("/does/not/exist.crt".to_owned())]);
+        let err = ca_certificates(&missing).unwrap_err();
+        assert!(err.to_string().contains("/does/not/exist.crt"));
+    }
+
+    fn test_client_config(api_server_endpoint: String) -> ClientConfig {
+        ClientConfig {
+            api_server_endpoint,
+            credentials: Credentials::Header("Bearer foo".to_owned()),
+            ca_data: None,
+            user_agent: "test".to_owned(),
+            verify_ssl_certs: true,
+            trust_system_roots: true,
+            tls_server_name: None,
+            impersonate: None,
+            impersonate_groups: Vec::new(),
+            impersonate_uid: None,
+            impersonate_extra: std::collections::HashMap::new(),
+            qps: crate::config::DEFAULT_QPS,
+            burst: crate::config::DEFAULT_BURST,
+            request_timeout: crate::config::DEFAULT_REQUEST_TIMEOUT,
+            watch_timeout: crate::config::DEFAULT_WATCH_TIMEOUT,
+            list_page_size: crate::config::DEFAULT_LIST_PAGE_SIZE,
+            consistent_initial_list: false,
+            max_idle_connections: crate::config::DEFAULT_MAX_IDLE_CONNECTIONS,
+            idle_timeout: Some(crate::config::DEFAULT_IDLE_TIMEOUT),
+            tcp_keepalive: Some(crate::config::DEFAULT_TCP_KEEPALIVE),
+            retry_policy: crate::config::RetryPolicy::default(),
+            proxy: None,
+            no_proxy: Vec::new(),
+            accept_compression: true,
+            capture_request_bodies: false,
+            max_object_size: Some(crate::config::DEFAULT_MAX_OBJECT_SIZE),
+            object_size_warning_threshold: Some(
+                crate::config::DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD,
+            ),
+            extra_headers: std::collections::HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn dry_run_param_is_appended_to_query() {
+        let uri: http::Uri = "https://foo.com/api/v1/namespaces/ns/pods".parse().unwrap();
+        assert_eq!(
+            "https://foo.com/api/v1/namespaces/ns/pods?dryRun=All",
+            add_dry_run_param(&uri).to_string()
+        );
+        let uri: http::Uri = "https://foo.com/api/v1/pods/foo?fieldManager=bar"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            "https://foo.com/api/v1/pods/foo?fieldManager=bar&dryRun=All",
+            add_dry_run_param(&uri).to_string()
+        );
+    }
+
     #[test]
     fn lines_iterates_lines() {
         let input1 = &b"line1\nline2\r\nline3\r\n\r\n\r\n\rlong"[..];
@@ -0,0 +1,128 @@
+//! A token bucket rate limiter for requests to the api server, similar to the one used by client-go.
+//! Requests that exceed the limit wait for their turn rather than failing.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    qps: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl RateLimiter {
+    /// Returns a new `RateLimiter`, or `None` if `qps` is not positive, which means that requests should
+    /// not be limited
+    pub fn new(qps: f32, burst: u32) -> Option<RateLimiter> {
+        if qps <= 0.0 {
+            return None;
+        }
+        // a burst of 0 would mean that no requests could ever be sent
+        let burst = f64::from(burst.max(1));
+        Some(RateLimiter {
+            qps: f64::from(qps),
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                last_update: Instant::now(),
+            }),
+        })
+    }
+
+    /// Waits until a request is allowed to be sent, and returns how long it had to wait
+    pub async fn acquire(&self) -> Duration {
+        let wait = self.reserve(Instant::now());
+        if wait > Duration::from_millis(0) {
+            tokio::time::delay_for(wait).await;
+        }
+        wait
+    }
+
+    /// Takes a token from the bucket, and returns how long the caller must wait before the token is actually
+    /// available. The token may be borrowed from the future, which is what allows concurrent callers to be queued
+    /// up in order.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now
+            .checked_duration_since(state.last_update)
+            .unwrap_or_else(|| Duration::from_millis(0));
+        let refilled = state.tokens + duration_as_secs_f64(elapsed) * self.qps;
+        state.tokens = refilled.min(self.burst) - 1.0;
+        state.last_update = now;
+
+        if state.tokens >= 0.0 {
+            Duration::from_millis(0)
+        } else {
+            let wait_micros = (-state.tokens / self.qps * 1_000_000.0).ceil() as u64;
+            Duration::from_micros(wait_micros)
+        }
+    }
+}
+
+fn duration_as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn non_positive_qps_disables_rate_limiting() {
+        assert!(RateLimiter::new(0.0, 10).is_none());
+        assert!(RateLimiter::new(-1.0, 10).is_none());
+    }
+
+    #[test]
+    fn requests_are_paced_to_the_configured_qps() {
+        let qps = 100.0;
+        let burst = 10;
+        let limiter = RateLimiter::new(qps, burst).unwrap();
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+        let total_wait = runtime.block_on(async {
+            let mut total_wait = Duration::from_millis(0);
+            for _ in 0..50 {
+                total_wait += limiter.acquire().await;
+            }
+            total_wait
+        });
+        let elapsed = start.elapsed();
+
+        // the first 10 requests are allowed immediately by the burst, and the other 40 are paced at 100 per second
+        let expected = Duration::from_millis(400);
+        assert!(
+            elapsed >= expected - Duration::from_millis(50),
+            "requests were not rate limited, elapsed: {:?}",
+            elapsed
+        );
+        assert!(
+            elapsed <= expected + Duration::from_millis(400),
+            "requests were limited too much, elapsed: {:?}",
+            elapsed
+        );
+        assert!(total_wait > Duration::from_millis(0));
+    }
+
+    #[test]
+    fn burst_requests_do_not_wait() {
+        let limiter = RateLimiter::new(1.0, 5).unwrap();
+        let now = Instant::now();
+        for _ in 0..5 {
+            assert_eq!(Duration::from_millis(0), limiter.reserve(now));
+        }
+        assert_eq!(Duration::from_secs(1), limiter.reserve(now));
+        assert_eq!(Duration::from_secs(2), limiter.reserve(now));
+    }
+}
@@ -1,7 +1,7 @@
-use crate::config::{ClientConfig, Credentials};
-use crate::k8s_types::K8sType;
+use crate::config::{ClientConfig, Credentials, DeletePolicy};
+use crate::k8s_types::{K8sSubresource, K8sType};
 use crate::resource::{K8sResource, ObjectIdRef};
-use crate::runner::client::Error;
+use crate::runner::client::{Error, ObjectTooLargeError};
 
 use http::{header, Method, Request};
 use hyper::Body;
@@ -33,55 +33,78 @@ pub struct Patch {
 }
 
 impl Patch {
-    pub fn remove_finalizer(resource: &K8sResource, finalizer: &str) -> Patch {
-        let finalizers = resource
-            .as_ref()
-            .pointer("/metadata/finalizers")
-            .and_then(Value::as_array)
-            .map(|finalizers| {
-                finalizers
-                    .iter()
-                    .filter(|f| f.as_str() != Some(finalizer))
-                    .collect::<Vec<_>>()
-            })
-            .unwrap_or_default();
-        let patch = serde_json::json!({
-            "metadata": {
-                "namespace": resource.get_object_id().namespace(),
-                "name": resource.get_object_id().name(),
-                "resourceVersion": resource.resource_version(),
-                "finalizers": finalizers,
-            }
-        });
+    pub fn new(merge_strategy: MergeStrategy, value: Value) -> Patch {
         Patch {
-            value: patch,
-            merge_strategy: MergeStrategy::JsonMerge,
+            merge_strategy,
+            value,
         }
     }
 
-    pub fn add_finalizer(resource: &K8sResource, finalizer: &str) -> Patch {
-        let mut finalizers = resource
-            .as_ref()
-            .pointer("/metadata/finalizers")
-            .and_then(Value::as_array)
-            .cloned()
-            .unwrap_or_default();
+    /// Returns a patch that removes the finalizer from the resource, keeping all of the others in the same order, or
+    /// `None` if the resource doesn't have it. The patch is conditional on the `resourceVersion` of the resource, so
+    /// it fails with a 409 instead of overwriting any concurrent change to the finalizers.
+    pub fn remove_finalizer(resource: &K8sResource, finalizer: &str) -> Option<Patch> {
+        let existing = finalizers_of(resource);
+        if !existing.iter().any(|f| f.as_str() == Some(finalizer)) {
+            return None;
+        }
+        let finalizers = existing
+            .into_iter()
+            .filter(|f| f.as_str() != Some(finalizer))
+            .collect();
+        Some(Patch::finalizers(resource, finalizers))
+    }
+
+    /// Returns a patch that adds the finalizer to the end of the resource's finalizers, or `None` if the resource
+    /// already has it. Like `remove_finalizer`, the patch is conditional on the `resourceVersion` of the resource.
+    pub fn add_finalizer(resource: &K8sResource, finalizer: &str) -> Option<Patch> {
+        let mut finalizers = finalizers_of(resource);
+        if finalizers.iter().any(|f| f.as_str() == Some(finalizer)) {
+            return None;
+        }
         finalizers.push(Value::String(finalizer.to_string()));
-        let value = serde_json::json!({
-            "metadata": {
-                "namespace": resource.get_object_id().namespace(),
-                "name": resource.get_object_id().name(),
-                "resourceVersion": resource.resource_version(),
-                "finalizers": finalizers,
-            }
-        });
+        Some(Patch::finalizers(resource, finalizers))
+    }
+
+    /// A merge patch that only changes the finalizers, which replaces the whole list since it's an array
+    fn finalizers(resource: &K8sResource, finalizers: Vec<Value>) -> Patch {
+        let mut fields = serde_json::Map::new();
+        fields.insert("finalizers".to_owned(), Value::Array(finalizers));
+        Patch::metadata(resource, fields)
+    }
+
+    /// Returns a merge patch that only sets the given fields of the resource's `metadata`. A merge patch leaves every
+    /// field that isn't in it as it is, so this never needs the full resource, and changes that other clients make to
+    /// the rest of the resource, like adding a label, are preserved. Arrays are replaced as a whole, though, so the
+    /// patch is conditional on the `resourceVersion` of the resource.
+    fn metadata(resource: &K8sResource, fields: serde_json::Map<String, Value>) -> Patch {
+        let id = resource.get_object_id();
+        let mut metadata = serde_json::Map::new();
+        if let Some(namespace) = id.namespace() {
+            metadata.insert("namespace".to_owned(), namespace.into());
+        }
+        metadata.insert("name".to_owned(), id.name().into());
+        metadata.insert(
+            "resourceVersion".to_owned(),
+            resource.resource_version().into(),
+        );
+        metadata.extend(fields);
         Patch {
-            value,
+            value: serde_json::json!({ "metadata": metadata }),
             merge_strategy: MergeStrategy::JsonMerge,
         }
     }
 }
 
+fn finalizers_of(resource: &K8sResource) -> Vec<Value> {
+    resource
+        .as_ref()
+        .pointer("/metadata/finalizers")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default()
+}
+
 pub fn patch_request(
     client_config: &ClientConfig,
     k8s_type: &K8sType,
@@ -92,12 +115,38 @@ pub fn patch_request(
     let header_value = patch.merge_strategy.content_type();
     let builder =
         make_req(url, Method::PATCH, client_config).header(header::CONTENT_TYPE, header_value);
-    let body = serde_json::to_vec(&patch.value)?;
+    let body = serialize_body(client_config, &patch.value, || {
+        format!("{} {}", k8s_type, id)
+    })?;
     let req = builder.body(Body::from(body)).unwrap();
     Ok(req)
 }
 
-#[cfg(feature = "testkit")]
+/// Creates a server-side apply request. The resource is sent as json, which is also valid yaml
+pub fn apply_request(
+    client_config: &ClientConfig,
+    k8s_type: &K8sType,
+    id: &ObjectIdRef<'_>,
+    resource: &Value,
+    field_manager: &str,
+    force: bool,
+) -> Result<Request<Body>, Error> {
+    let mut url = make_url(client_config, k8s_type, id.namespace(), Some(id.name()));
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("fieldManager", field_manager);
+        if force {
+            query.append_pair("force", "true");
+        }
+    }
+    let body = serialize_body(client_config, resource, || format!("{} {}", k8s_type, id))?;
+    let req = make_req(url, Method::PATCH, client_config)
+        .header(header::CONTENT_TYPE, "application/apply-patch+yaml")
+        .body(Body::from(body))
+        .unwrap();
+    Ok(req)
+}
+
 pub fn get_request(
     client_config: &ClientConfig,
     k8s_type: &K8sType,
@@ -105,7 +154,8 @@ pub fn get_request(
 ) -> Result<Request<Body>, Error> {
     let url = make_url(client_config, k8s_type, id.namespace(), Some(id.name()));
 
-    let req = make_req(url, Method::GET, client_config)
+    let builder = make_req(url, Method::GET, client_config);
+    let req = add_accept_encoding(builder, client_config)
         .body(Body::empty())
         .unwrap();
     Ok(req)
@@ -119,7 +169,19 @@ pub fn create_request(
     let url = make_url(client_config, k8s_type, get_namespace(resource), None);
 
     let builder = make_req(url, Method::POST, client_config);
-    let as_vec = serde_json::to_vec(resource)?;
+    let as_vec = serialize_body(client_config, resource, || {
+        let name = resource
+            .pointer("/metadata/name")
+            .or_else(|| resource.pointer("/metadata/generateName"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        format!(
+            "{} {}/{}",
+            k8s_type,
+            get_namespace(resource).unwrap_or_default(),
+            name
+        )
+    })?;
     let req = builder.body(Body::from(as_vec)).unwrap();
     Ok(req)
 }
@@ -131,25 +193,33 @@ pub fn replace_request(
     resource: &Value,
 ) -> Result<Request<Body>, Error> {
     let url = make_url(client_config, k8s_type, id.namespace(), Some(id.name()));
-    let as_vec = serde_json::to_vec(resource)?;
+    let as_vec = serialize_body(client_config, resource, || format!("{} {}", k8s_type, id))?;
     let req = make_req(url, Method::PUT, client_config)
         .body(Body::from(as_vec))
         .unwrap();
     Ok(req)
 }
 
-pub fn update_status_request(
+pub fn get_subresource_request(
     client_config: &ClientConfig,
-    k8s_type: &K8sType,
+    subresource: K8sSubresource<'_>,
     id: &ObjectIdRef<'_>,
-    new_status: &Value,
 ) -> Result<Request<Body>, Error> {
-    let mut url = make_url(client_config, k8s_type, id.namespace(), Some(id.name()));
-    {
-        let mut path = url.path_segments_mut().unwrap();
-        path.push("status");
-    }
-    let as_vec = serde_json::to_vec(new_status)?;
+    let url = make_subresource_url(client_config, subresource, id);
+    let req = make_req(url, Method::GET, client_config)
+        .body(Body::empty())
+        .unwrap();
+    Ok(req)
+}
+
+pub fn replace_subresource_request(
+    client_config: &ClientConfig,
+    subresource: K8sSubresource<'_>,
+    id: &ObjectIdRef<'_>,
+    body: &Value,
+) -> Result<Request<Body>, Error> {
+    let url = make_subresource_url(client_config, subresource, id);
+    let as_vec = serialize_body(client_config, body, || format!("{} {}", subresource, id))?;
     let req = make_req(url, Method::PUT, client_config)
         .body(Body::from(as_vec))
         .unwrap();
@@ -160,8 +230,13 @@ pub fn delete_request(
     client_config: &ClientConfig,
     k8s_type: &K8sType,
     id: &ObjectIdRef<'_>,
+    delete_policy: Option<DeletePolicy>,
 ) -> Result<Request<Body>, Error> {
-    let url = make_url(client_config, k8s_type, id.namespace(), Some(id.name()));
+    let mut url = make_url(client_config, k8s_type, id.namespace(), Some(id.name()));
+    if let Some(policy) = delete_policy {
+        url.query_pairs_mut()
+            .append_pair("propagationPolicy", policy.as_str());
+    }
     let req = make_req(url, Method::DELETE, client_config)
         .body(Body::empty())
         .unwrap();
@@ -173,6 +248,7 @@ pub fn watch_request(
     k8s_type: &K8sType,
     resource_version: Option<&str>,
     label_selector: Option<&str>,
+    field_selector: Option<&str>,
     timeout_seconds: Option<u32>,
     namespace: Option<&str>,
 ) -> Result<Request<Body>, Error> {
@@ -180,12 +256,16 @@ pub fn watch_request(
     {
         let mut query = url.query_pairs_mut();
         query.append_pair("watch", "true");
+        query.append_pair("allowWatchBookmarks", "true");
         if let Some(vers) = resource_version {
             query.append_pair("resourceVersion", vers);
         }
         if let Some(selector) = label_selector {
             query.append_pair("labelSelector", selector);
         }
+        if let Some(selector) = field_selector {
+            query.append_pair("fieldSelector", selector);
+        }
         if let Some(timeout) = timeout_seconds {
             let as_str = format!("{}", timeout);
             query.append_pair("timeoutSeconds", &as_str);
@@ -198,23 +278,96 @@ pub fn watch_request(
     Ok(req)
 }
 
+/// Creates a request for the discovery document of the given apiVersion, which lists each of its resources
+pub fn discovery_request(
+    client_config: &ClientConfig,
+    api_version: &str,
+) -> Result<Request<Body>, Error> {
+    let mut url = url::Url::parse(client_config.api_server_endpoint.as_str()).unwrap();
+    {
+        let mut segments = url.path_segments_mut().unwrap();
+        match api_version.find('/') {
+            Some(slash_idx) => {
+                segments.push("apis");
+                segments.push(&api_version[..slash_idx]);
+                segments.push(&api_version[(slash_idx + 1)..]);
+            }
+            None => {
+                segments.push("api");
+                segments.push(api_version);
+            }
+        }
+    }
+    let req = make_req(url, Method::GET, client_config)
+        .body(Body::empty())
+        .unwrap();
+    Ok(req)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn list_request(
     client_config: &ClientConfig,
     k8s_type: &K8sType,
     label_selector: Option<&str>,
+    field_selector: Option<&str>,
+    limit: Option<u32>,
+    continue_token: Option<&str>,
+    resource_version: Option<&str>,
     namespace: Option<&str>,
 ) -> Result<Request<Body>, Error> {
     let mut url = make_url(client_config, k8s_type, namespace, None);
-    if let Some(selector) = label_selector {
+    {
         let mut query = url.query_pairs_mut();
-        query.append_pair("labelSelector", selector);
+        if let Some(selector) = label_selector {
+            query.append_pair("labelSelector", selector);
+        }
+        if let Some(selector) = field_selector {
+            query.append_pair("fieldSelector", selector);
+        }
+        if let Some(limit) = limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+        if let Some(token) = continue_token {
+            query.append_pair("continue", token);
+        }
+        if let Some(vers) = resource_version {
+            query.append_pair("resourceVersion", vers);
+        }
     }
-    let req = make_req(url, Method::GET, client_config)
+    let builder = make_req(url, Method::GET, client_config);
+    let req = add_accept_encoding(builder, client_config)
         .body(Body::empty())
         .unwrap();
     Ok(req)
 }
 
+/// The `Accept` header for a list of `PartialObjectMetadata`, which the api server falls back from to the full
+/// objects if it doesn't support it
+pub const PARTIAL_METADATA_LIST: &str =
+    "application/json;as=PartialObjectMetadataList;g=meta.k8s.io;v=v1,application/json";
+/// The `Accept` header for a single `PartialObjectMetadata`, which is also what's used for each watch event
+pub const PARTIAL_METADATA: &str =
+    "application/json;as=PartialObjectMetadata;g=meta.k8s.io;v=v1,application/json";
+
+/// Replaces the `Accept` header so that the api server only returns the metadata of the resources
+pub fn accept_partial_metadata(req: &mut Request<Body>, accept: &'static str) {
+    req.headers_mut()
+        .insert(header::ACCEPT, header::HeaderValue::from_static(accept));
+}
+
+/// Asks the api server to gzip the response, if compression is enabled. This is only used for get and list
+/// requests, since watch responses need to be read incrementally.
+fn add_accept_encoding(
+    builder: http::request::Builder,
+    client_config: &ClientConfig,
+) -> http::request::Builder {
+    if client_config.accept_compression {
+        builder.header(header::ACCEPT_ENCODING, "gzip")
+    } else {
+        builder
+    }
+}
+
 fn make_req(
     url: Url,
     method: http::Method,
@@ -226,6 +379,7 @@ fn make_req(
         .header(header::ACCEPT, "application/json")
         .header(header::USER_AGENT, client_config.user_agent.as_str());
 
+    let builder = add_impersonation_headers(builder, client_config);
     if let Credentials::Header(ref value) = client_config.credentials {
         builder.header(header::AUTHORIZATION, value)
     } else {
@@ -233,12 +387,85 @@ fn make_req(
     }
 }
 
+/// Adds the headers for impersonating a user. The groups, uid, and extra fields are only sent along with a user,
+/// since the api server rejects them otherwise, and headers are only added for the fields that are populated.
+fn add_impersonation_headers(
+    mut builder: http::request::Builder,
+    client_config: &ClientConfig,
+) -> http::request::Builder {
+    let user = match client_config.impersonate.as_ref() {
+        Some(user) => user,
+        None => return builder,
+    };
+    builder = builder.header("Impersonate-User", user.as_str());
+    for group in client_config.impersonate_groups.iter() {
+        builder = builder.header("Impersonate-Group", group.as_str());
+    }
+    if let Some(uid) = client_config.impersonate_uid.as_ref() {
+        builder = builder.header("Impersonate-Uid", uid.as_str());
+    }
+    for (key, values) in client_config.impersonate_extra.iter() {
+        // keys may contain characters that aren't allowed in header names, so they're percent encoded, which the
+        // api server decodes
+        let name = format!("Impersonate-Extra-{}", urlencoding::encode(key));
+        for value in values {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+    builder
+}
+
+/// Serializes the body of a write, returning an `ObjectTooLarge` error if it's larger than the `max_object_size`. The
+/// `describe` function is only called if the body is large enough to be logged or returned in an error.
+fn serialize_body(
+    client_config: &ClientConfig,
+    body: &Value,
+    describe: impl Fn() -> String,
+) -> Result<Vec<u8>, Error> {
+    let as_vec = serde_json::to_vec(body)?;
+    let size = as_vec.len();
+    if let Some(limit) = client_config.max_object_size.filter(|limit| size > *limit) {
+        return Err(Error::ObjectTooLarge(Box::new(ObjectTooLargeError {
+            object: describe(),
+            size,
+            limit,
+        })));
+    }
+    if let Some(threshold) = client_config
+        .object_size_warning_threshold
+        .filter(|threshold| size > *threshold)
+    {
+        log::warn!(
+            "Writing {} with a size of {} bytes, which is larger than the warning threshold of {} bytes",
+            describe(),
+            size,
+            threshold
+        );
+    }
+    Ok(as_vec)
+}
+
 fn get_namespace(resource: &Value) -> Option<&str> {
     resource
         .pointer("/metadata/namespace")
         .and_then(Value::as_str)
 }
 
+fn make_subresource_url(
+    client_config: &ClientConfig,
+    subresource: K8sSubresource<'_>,
+    id: &ObjectIdRef<'_>,
+) -> Url {
+    let mut url = make_url(
+        client_config,
+        subresource.k8s_type,
+        id.namespace(),
+        Some(id.name()),
+    );
+    url.path_segments_mut().unwrap().push(subresource.name);
+    url
+}
+
 fn make_url(
     client_config: &ClientConfig,
     k8s_type: &K8sType,
@@ -258,7 +485,8 @@ fn make_url(
             segments.push(group);
         }
         segments.push(k8s_type.version());
-        if let Some(ns) = namespace {
+        // cluster scoped resources don't have a namespace segment, even if the resource has a namespace
+        if let Some(ns) = namespace.filter(|_| k8s_type.is_namespaced()) {
             segments.push("namespaces");
             segments.push(ns);
         }
@@ -270,3 +498,297 @@ fn make_url(
     }
     url
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RetryPolicy;
+    use crate::k8s_types::core::v1::Pod;
+
+    use std::collections::HashMap;
+
+    fn finalizers_after(patch: Option<Patch>) -> Value {
+        patch
+            .unwrap()
+            .value
+            .pointer("/metadata/finalizers")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn finalizer_patches_preserve_other_finalizers_and_are_idempotent() {
+        let resource = K8sResource::from_value(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "namespace": "ns",
+                "name": "foo",
+                "uid": "foo-uid",
+                "resourceVersion": "5",
+                "finalizers": ["a", "mine", "b"],
+            }
+        }))
+        .unwrap();
+        assert!(Patch::add_finalizer(&resource, "mine").is_none());
+        assert_eq!(
+            serde_json::json!(["a", "mine", "b", "other"]),
+            finalizers_after(Patch::add_finalizer(&resource, "other"))
+        );
+        assert_eq!(
+            serde_json::json!(["a", "b"]),
+            finalizers_after(Patch::remove_finalizer(&resource, "mine"))
+        );
+        assert!(Patch::remove_finalizer(&resource, "other").is_none());
+
+        let patch = Patch::add_finalizer(&resource, "other").unwrap();
+        assert_eq!(MergeStrategy::JsonMerge, patch.merge_strategy);
+        assert_eq!(
+            Some("5"),
+            patch
+                .value
+                .pointer("/metadata/resourceVersion")
+                .and_then(Value::as_str)
+        );
+    }
+
+    fn client_config(impersonate: Option<&str>) -> ClientConfig {
+        let mut impersonate_extra = HashMap::new();
+        impersonate_extra.insert(
+            "scopes.example.com/team".to_owned(),
+            vec!["a".to_owned(), "b".to_owned()],
+        );
+        ClientConfig {
+            api_server_endpoint: "https://foo.com".to_owned(),
+            credentials: Credentials::Header("Bearer foo".to_owned()),
+            ca_data: None,
+            user_agent: "test".to_owned(),
+            verify_ssl_certs: true,
+            trust_system_roots: true,
+            tls_server_name: None,
+            impersonate: impersonate.map(String::from),
+            impersonate_groups: vec!["admins".to_owned(), "devs".to_owned()],
+            impersonate_uid: Some("user-uid".to_owned()),
+            impersonate_extra,
+            qps: crate::config::DEFAULT_QPS,
+            burst: crate::config::DEFAULT_BURST,
+            request_timeout: crate::config::DEFAULT_REQUEST_TIMEOUT,
+            watch_timeout: crate::config::DEFAULT_WATCH_TIMEOUT,
+            list_page_size: crate::config::DEFAULT_LIST_PAGE_SIZE,
+            consistent_initial_list: false,
+            max_idle_connections: crate::config::DEFAULT_MAX_IDLE_CONNECTIONS,
+            idle_timeout: Some(crate::config::DEFAULT_IDLE_TIMEOUT),
+            tcp_keepalive: Some(crate::config::DEFAULT_TCP_KEEPALIVE),
+            retry_policy: RetryPolicy::default(),
+            proxy: None,
+            no_proxy: Vec::new(),
+            accept_compression: true,
+            capture_request_bodies: false,
+            max_object_size: Some(crate::config::DEFAULT_MAX_OBJECT_SIZE),
+            object_size_warning_threshold: Some(
+                crate::config::DEFAULT_OBJECT_SIZE_WARNING_THRESHOLD,
+            ),
+            extra_headers: HashMap::new(),
+        }
+    }
+
+    fn header_values<'a>(request: &'a Request<Body>, name: &str) -> Vec<&'a str> {
+        request
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn subresource_requests_are_sent_to_the_subresource_url() {
+        let config = client_config(None);
+        let id = ObjectIdRef::new("ns", "foo");
+        let scale = crate::k8s_types::apps::v1::Deployment.subresource("scale");
+        let request = get_subresource_request(&config, scale, &id).unwrap();
+        assert_eq!(Method::GET, request.method());
+        assert_eq!(
+            "https://foo.com/apis/apps/v1/namespaces/ns/deployments/foo/scale",
+            request.uri().to_string()
+        );
+
+        let body = serde_json::json!({"spec": {"replicas": 3}});
+        let request =
+            replace_subresource_request(&config, Pod.subresource("status"), &id, &body).unwrap();
+        assert_eq!(Method::PUT, request.method());
+        assert_eq!(
+            "https://foo.com/api/v1/namespaces/ns/pods/foo/status",
+            request.uri().to_string()
+        );
+    }
+
+    #[test]
+    fn cluster_scoped_urls_do_not_include_the_namespace() {
+        use crate::k8s_types::rbac_authorization_k8s_io::v1::ClusterRole;
+
+        let config = client_config(None);
+        let request = get_request(&config, ClusterRole, &ObjectIdRef::new("", "admin")).unwrap();
+        assert_eq!(
+            "https://foo.com/apis/rbac.authorization.k8s.io/v1/clusterroles/admin",
+            request.uri().to_string()
+        );
+
+        let role = serde_json::json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": {"namespace": "ns", "name": "admin"},
+        });
+        let request = create_request(&config, ClusterRole, &role).unwrap();
+        assert_eq!(
+            "https://foo.com/apis/rbac.authorization.k8s.io/v1/clusterroles",
+            request.uri().to_string()
+        );
+
+        let request = get_request(&config, Pod, &ObjectIdRef::new("ns", "foo")).unwrap();
+        assert_eq!(
+            "https://foo.com/api/v1/namespaces/ns/pods/foo",
+            request.uri().to_string()
+        );
+    }
+
+    #[test]
+    fn compression_is_only_accepted_for_get_and_list_requests() {
+        let mut config = client_config(None);
+        let id = ObjectIdRef::new("ns", "foo");
+        let get = get_request(&config, Pod, &id).unwrap();
+        assert_eq!(vec!["gzip"], header_values(&get, "Accept-Encoding"));
+        let list = list_request(&config, Pod, None, None, None, None, None, Some("ns")).unwrap();
+        assert_eq!(vec!["gzip"], header_values(&list, "Accept-Encoding"));
+        let watch = watch_request(&config, Pod, None, None, None, Some(30), Some("ns")).unwrap();
+        assert!(header_values(&watch, "Accept-Encoding").is_empty());
+
+        config = config.accept_compression(false);
+        let get = get_request(&config, Pod, &id).unwrap();
+        assert!(header_values(&get, "Accept-Encoding").is_empty());
+    }
+
+    #[test]
+    fn delete_requests_include_the_propagation_policy() {
+        let config = client_config(None);
+        let id = ObjectIdRef::new("ns", "foo");
+        let request = delete_request(&config, Pod, &id, None).unwrap();
+        assert_eq!(
+            "https://foo.com/api/v1/namespaces/ns/pods/foo",
+            request.uri().to_string()
+        );
+        let request = delete_request(&config, Pod, &id, Some(DeletePolicy::Foreground)).unwrap();
+        assert_eq!(
+            "https://foo.com/api/v1/namespaces/ns/pods/foo?propagationPolicy=Foreground",
+            request.uri().to_string()
+        );
+        assert_eq!(&Method::DELETE, request.method());
+    }
+
+    #[test]
+    fn watch_requests_allow_bookmarks() {
+        let config = client_config(None);
+        let request =
+            watch_request(&config, Pod, Some("123"), None, None, Some(30), Some("ns")).unwrap();
+        assert_eq!(
+            "https://foo.com/api/v1/namespaces/ns/pods?watch=true&allowWatchBookmarks=true&resourceVersion=123&timeoutSeconds=30",
+            request.uri().to_string()
+        );
+    }
+
+    #[test]
+    fn partial_metadata_replaces_the_accept_header() {
+        let config = client_config(None);
+        let mut list =
+            list_request(&config, Pod, None, None, None, None, None, Some("ns")).unwrap();
+        accept_partial_metadata(&mut list, PARTIAL_METADATA_LIST);
+        assert_eq!(
+            vec![
+                "application/json;as=PartialObjectMetadataList;g=meta.k8s.io;v=v1,application/json"
+            ],
+            header_values(&list, "Accept")
+        );
+    }
+
+    #[test]
+    fn discovery_requests_use_the_group_and_version() {
+        let config = client_config(None);
+        let request = discovery_request(&config, "networking.k8s.io/v1").unwrap();
+        assert_eq!(
+            "https://foo.com/apis/networking.k8s.io/v1",
+            request.uri().to_string()
+        );
+        let request = discovery_request(&config, "v1").unwrap();
+        assert_eq!("https://foo.com/api/v1", request.uri().to_string());
+    }
+
+    #[test]
+    fn impersonation_headers_are_added_to_requests() {
+        let config = client_config(Some("jane"));
+        let request = get_request(&config, Pod, &ObjectIdRef::new("ns", "foo")).unwrap();
+        assert_eq!(vec!["jane"], header_values(&request, "Impersonate-User"));
+        assert_eq!(
+            vec!["admins", "devs"],
+            header_values(&request, "Impersonate-Group")
+        );
+        assert_eq!(vec!["user-uid"], header_values(&request, "Impersonate-Uid"));
+        assert_eq!(
+            vec!["a", "b"],
+            header_values(&request, "Impersonate-Extra-scopes.example.com%2Fteam")
+        );
+    }
+
+    #[test]
+    fn impersonation_headers_are_not_added_without_a_user() {
+        let mut config = client_config(None);
+        let request = get_request(&config, Pod, &ObjectIdRef::new("ns", "foo")).unwrap();
+        assert!(request
+            .headers()
+            .keys()
+            .all(|name| !name.as_str().starts_with("impersonate-")));
+
+        config.impersonate = Some("jane".to_owned());
+        config.impersonate_groups.clear();
+        config.impersonate_uid = None;
+        config.impersonate_extra.clear();
+        let request = get_request(&config, Pod, &ObjectIdRef::new("ns", "foo")).unwrap();
+        let names = request
+            .headers()
+            .keys()
+            .filter(|name| name.as_str().starts_with("impersonate-"))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["impersonate-user"], names);
+    }
+
+    #[test]
+    fn writes_larger_than_the_max_object_size_are_rejected() {
+        let config = client_config(None).max_object_size(Some(100));
+        let id = ObjectIdRef::new("ns", "foo");
+        let small = serde_json::json!({"metadata": {"namespace": "ns", "name": "foo"}});
+        assert!(replace_request(&config, Pod, &id, &small).is_ok());
+
+        let large = serde_json::json!({
+            "metadata": {"namespace": "ns", "name": "foo"},
+            "status": {"items": vec!["x"; 100]},
+        });
+        let size = serde_json::to_vec(&large).unwrap().len();
+        let err = create_request(&config, Pod, &large).unwrap_err();
+        match err {
+            Error::ObjectTooLarge(ref e) => {
+                assert_eq!(size, e.size);
+                assert_eq!(100, e.limit);
+                assert!(e.object.ends_with("ns/foo"), "object was: {}", e.object);
+            }
+            other => panic!("expected ObjectTooLarge, got: {:?}", other),
+        }
+        assert!(err.to_string().contains(&size.to_string()));
+
+        let status = K8sSubresource {
+            k8s_type: Pod,
+            name: "status",
+        };
+        assert!(replace_subresource_request(&config, status, &id, &large).is_err());
+        let config = config.max_object_size(None);
+        assert!(replace_request(&config, Pod, &id, &large).is_ok());
+    }
+}
@@ -0,0 +1,98 @@
+//! Determines which failed requests may be retried, and how long to wait before retrying them
+use crate::config::RetryPolicy;
+
+use backoff::backoff::Backoff;
+use backoff::ExponentialBackoff;
+use http::{Method, Response, StatusCode};
+use hyper::Body;
+
+use std::time::Duration;
+
+/// Tracks the retries of a single request
+pub(crate) struct RetryState {
+    backoff: ExponentialBackoff,
+    attempts: u32,
+    max_attempts: u32,
+    max_delay: Duration,
+}
+
+impl RetryState {
+    pub fn new(policy: &RetryPolicy) -> RetryState {
+        let mut backoff = ExponentialBackoff {
+            initial_interval: policy.base_delay,
+            max_interval: policy.max_delay,
+            multiplier: 2.0,
+            max_elapsed_time: None,
+            ..Default::default()
+        };
+        backoff.reset();
+        RetryState {
+            backoff,
+            attempts: 1,
+            max_attempts: policy.max_attempts,
+            max_delay: policy.max_delay,
+        }
+    }
+
+    /// Returns the delay before the next attempt, or `None` if there are no attempts remaining. A
+    /// `retry_after` value from the api server takes precedence over the computed backoff.
+    pub fn next_delay(&mut self, retry_after: Option<Duration>) -> Option<Duration> {
+        if self.attempts >= self.max_attempts {
+            return None;
+        }
+        self.attempts += 1;
+        let backoff = self.backoff.next_backoff().unwrap_or(self.max_delay);
+        Some(retry_after.unwrap_or(backoff).min(self.max_delay))
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+/// Idempotent requests can be safely retried even if they may have already been processed by the api server
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    *method == Method::GET || *method == Method::HEAD || *method == Method::DELETE
+}
+
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    [429, 500, 502, 503].contains(&status.as_u16())
+}
+
+/// Returns the value of the `Retry-After` header for 429 and 503 responses. Only the delay-seconds form
+/// of the header is supported, since that's what the api server uses.
+pub(crate) fn get_retry_after(response: &Response<Body>) -> Option<Duration> {
+    let status = response.status().as_u16();
+    if status != 429 && status != 503 {
+        return None;
+    }
+    response
+        .headers()
+        .get(http::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retry_delays_are_capped_and_limited_by_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        let mut state = RetryState::new(&policy);
+        let first = state.next_delay(None).expect("first retry");
+        assert!(first <= Duration::from_millis(150));
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            state.next_delay(Some(Duration::from_secs(30)))
+        );
+        assert_eq!(None, state.next_delay(None));
+        assert_eq!(3, state.attempts());
+    }
+}
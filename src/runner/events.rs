@@ -0,0 +1,395 @@
+//! Records Kubernetes `Event`s (`events.k8s.io/v1`) that reference the parent, so that the outcome of each sync is
+//! visible in `kubectl describe`. Repeated occurrences of the same event are aggregated into a single `Event` by
+//! incrementing its `series.count`, rather than creating a new object every time, so that a parent whose sync is
+//! failing repeatedly won't flood the namespace with events.
+use crate::k8s_types::events_k8s_io::v1::Event;
+use crate::resource::{K8sResource, ObjectIdRef};
+use crate::runner::client::{Client, Error as ClientError, MergeStrategy, Patch};
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Occurrences of the same event within this window are added to the existing series. This is the same window that
+/// client-go uses.
+const SERIES_WINDOW: Duration = Duration::from_secs(6 * 60);
+
+/// The maximum number of distinct events to keep track of. Once this is reached, the oldest events are forgotten,
+/// and any further occurrences will start a new series.
+const MAX_CACHED_EVENTS: usize = 4096;
+
+/// The api server rejects notes that are longer than this
+const MAX_NOTE_BYTES: usize = 1024;
+
+/// Events are created in the namespace of the parent. If the parent is cluster scoped, then they're created in the
+/// operator's own namespace, or this namespace if that isn't known.
+const CLUSTER_SCOPED_EVENT_NAMESPACE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum EventKind {
+    Normal,
+    Warning,
+}
+
+impl EventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Normal => "Normal",
+            EventKind::Warning => "Warning",
+        }
+    }
+}
+
+/// The object that an event is about, which is always a parent. This is extracted from the observed parent up front,
+/// so that events can still be recorded after the `SyncRequest` has been consumed.
+#[derive(Debug, Clone)]
+pub(crate) struct EventTarget {
+    uid: String,
+    name: String,
+    namespace: Option<String>,
+    reference: Value,
+}
+
+impl EventTarget {
+    pub fn of(parent: &K8sResource) -> EventTarget {
+        let ObjectIdRef { namespace, name } = parent.get_object_id();
+        let namespace = Some(namespace).filter(|ns| !ns.is_empty());
+        let mut reference = json!({
+            "apiVersion": parent.api_version(),
+            "kind": parent.kind(),
+            "name": name,
+            "uid": parent.uid(),
+            "resourceVersion": parent.resource_version(),
+        });
+        if let Some(ns) = namespace {
+            let obj = reference.as_object_mut().unwrap();
+            obj.insert("namespace".to_owned(), Value::String(ns.to_owned()));
+        }
+        EventTarget {
+            uid: parent.uid().to_owned(),
+            name: name.to_owned(),
+            namespace: namespace.map(String::from),
+            reference,
+        }
+    }
+
+    fn event_namespace<'a>(&'a self, cluster_scoped_namespace: &'a str) -> &'a str {
+        self.namespace
+            .as_deref()
+            .unwrap_or(cluster_scoped_namespace)
+    }
+}
+
+/// Identifies events that are considered to be repeats of one another
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct EventKey {
+    parent_uid: String,
+    kind: &'static str,
+    reason: &'static str,
+    action: &'static str,
+    note: String,
+}
+
+#[derive(Debug)]
+struct CachedEvent {
+    name: String,
+    count: u32,
+    last_seen: Instant,
+}
+
+/// What needs to be sent to the api server for a given occurrence of an event
+#[derive(Debug, PartialEq)]
+enum Occurrence {
+    /// A new `Event` must be created with the given name
+    New(String),
+    /// The existing `Event` with the given name must have its series updated to the given count
+    Repeat(String, u32),
+}
+
+#[derive(Debug, Default)]
+struct EventCache {
+    events: HashMap<EventKey, CachedEvent>,
+}
+
+impl EventCache {
+    fn observe(
+        &mut self,
+        key: EventKey,
+        now: Instant,
+        new_name: impl FnOnce() -> String,
+    ) -> Occurrence {
+        self.events
+            .retain(|_, cached| now.duration_since(cached.last_seen) < SERIES_WINDOW);
+        if let Some(cached) = self.events.get_mut(&key) {
+            cached.count += 1;
+            cached.last_seen = now;
+            return Occurrence::Repeat(cached.name.clone(), cached.count);
+        }
+
+        if self.events.len() >= MAX_CACHED_EVENTS {
+            let oldest = self
+                .events
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_seen)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.events.remove(&oldest);
+            }
+        }
+        let name = new_name();
+        self.events.insert(
+            key,
+            CachedEvent {
+                name: name.clone(),
+                count: 1,
+                last_seen: now,
+            },
+        );
+        Occurrence::New(name)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct EventRecorder {
+    client: Client,
+    reporting_controller: String,
+    reporting_instance: String,
+    /// The namespace of events for cluster-scoped parents
+    cluster_scoped_namespace: String,
+    cache: Arc<Mutex<EventCache>>,
+}
+
+impl Debug for EventRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "EventRecorder({}, {})",
+            self.reporting_controller, self.reporting_instance
+        )
+    }
+}
+
+impl EventRecorder {
+    /// Creates a recorder that records events for cluster-scoped parents in the `operator_namespace`, if it's known
+    pub fn new(
+        client: Client,
+        operator_name: &str,
+        operator_namespace: Option<String>,
+    ) -> EventRecorder {
+        let reporting_instance = std::env::var("HOSTNAME")
+            .ok()
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| operator_name.to_owned());
+        EventRecorder {
+            client,
+            reporting_controller: operator_name.to_owned(),
+            reporting_instance,
+            cluster_scoped_namespace: operator_namespace
+                .unwrap_or_else(|| CLUSTER_SCOPED_EVENT_NAMESPACE.to_owned()),
+            cache: Arc::new(Mutex::new(EventCache::default())),
+        }
+    }
+
+    /// Records an event in the background. Failures to record events are only logged, since they should never cause
+    /// a sync to fail.
+    pub fn record(
+        &self,
+        target: &EventTarget,
+        kind: EventKind,
+        reason: &'static str,
+        action: &'static str,
+        note: String,
+    ) {
+        let note = truncate_note(note);
+        let key = EventKey {
+            parent_uid: target.uid.clone(),
+            kind: kind.as_str(),
+            reason,
+            action,
+            note: note.clone(),
+        };
+        let occurrence = {
+            let mut cache = self.cache.lock().unwrap();
+            cache.observe(key, Instant::now(), || new_event_name(target.name.as_str()))
+        };
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+        let event = json!({
+            "apiVersion": Event.api_version,
+            "kind": Event.kind,
+            "metadata": {
+                "namespace": target.event_namespace(&self.cluster_scoped_namespace),
+            },
+            "eventTime": now,
+            "reportingController": self.reporting_controller,
+            "reportingInstance": self.reporting_instance,
+            "type": kind.as_str(),
+            "reason": reason,
+            "action": action,
+            "note": note,
+            "regarding": target.reference,
+        });
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            if let Err(err) = send_event(&client, event, occurrence, now.as_str()).await {
+                log::warn!("Failed to record {} event: {}", reason, err);
+            }
+        });
+    }
+}
+
+async fn send_event(
+    client: &Client,
+    mut event: Value,
+    occurrence: Occurrence,
+    now: &str,
+) -> Result<(), ClientError> {
+    let name = match occurrence {
+        Occurrence::New(name) => name,
+        Occurrence::Repeat(name, count) => {
+            let series = json!({
+                "count": count,
+                "lastObservedTime": now,
+            });
+            let namespace = event.pointer("/metadata/namespace").and_then(Value::as_str);
+            let id = ObjectIdRef::new(namespace.unwrap_or_default(), name.as_str());
+            let patch = Patch::new(MergeStrategy::JsonMerge, json!({ "series": series }));
+            match client.patch_resource(Event, &id, &patch).await {
+                // the event may have expired, or been created during a dry run, so we'll just create it again
+                Err(ref err) if err.is_http_status(404) => {}
+                other => return other,
+            }
+            event
+                .as_object_mut()
+                .unwrap()
+                .insert("series".to_owned(), series);
+            name
+        }
+    };
+    let meta = event
+        .pointer_mut("/metadata")
+        .and_then(Value::as_object_mut)
+        .unwrap();
+    meta.insert("name".to_owned(), Value::String(name));
+    match client.create_resource(Event, &event).await {
+        Err(ref err) if err.is_http_status(409) => Ok(()),
+        other => other,
+    }
+}
+
+/// Uses the same naming scheme as client-go, which is the name of the object followed by the current time in hex
+fn new_event_name(parent_name: &str) -> String {
+    let nanos = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    format!("{}.{:x}", parent_name, nanos)
+}
+
+fn truncate_note(mut note: String) -> String {
+    if note.len() > MAX_NOTE_BYTES {
+        let mut end = MAX_NOTE_BYTES;
+        while !note.is_char_boundary(end) {
+            end -= 1;
+        }
+        note.truncate(end);
+    }
+    note
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(note: &str) -> EventKey {
+        EventKey {
+            parent_uid: "parent-uid".to_owned(),
+            kind: EventKind::Warning.as_str(),
+            reason: "SyncFailed",
+            action: "Sync",
+            note: note.to_owned(),
+        }
+    }
+
+    #[test]
+    fn repeated_events_are_aggregated_into_a_series() {
+        let mut cache = EventCache::default();
+        let start = Instant::now();
+        let first = cache.observe(key("oh no"), start, || "foo.1".to_owned());
+        assert_eq!(Occurrence::New("foo.1".to_owned()), first);
+
+        let second = cache.observe(key("oh no"), start + Duration::from_secs(10), || {
+            panic!("should not create a new event")
+        });
+        assert_eq!(Occurrence::Repeat("foo.1".to_owned(), 2), second);
+
+        let third = cache.observe(key("oh no"), start + Duration::from_secs(20), || {
+            panic!("should not create a new event")
+        });
+        assert_eq!(Occurrence::Repeat("foo.1".to_owned(), 3), third);
+
+        let different = cache.observe(key("something else"), start, || "foo.2".to_owned());
+        assert_eq!(Occurrence::New("foo.2".to_owned()), different);
+    }
+
+    #[test]
+    fn a_new_series_is_started_after_the_window_has_elapsed() {
+        let mut cache = EventCache::default();
+        let start = Instant::now();
+        cache.observe(key("oh no"), start, || "foo.1".to_owned());
+
+        let later = start + SERIES_WINDOW + Duration::from_secs(1);
+        let result = cache.observe(key("oh no"), later, || "foo.2".to_owned());
+        assert_eq!(Occurrence::New("foo.2".to_owned()), result);
+        assert_eq!(1, cache.events.len());
+    }
+
+    #[test]
+    fn long_notes_are_truncated_on_a_char_boundary() {
+        let note = "é".repeat(MAX_NOTE_BYTES);
+        let truncated = truncate_note(note);
+        assert!(truncated.len() <= MAX_NOTE_BYTES);
+        assert!(truncated.chars().all(|c| c == 'é'));
+
+        assert_eq!("short", truncate_note("short".to_owned()));
+    }
+
+    #[test]
+    fn event_target_references_the_parent() {
+        let parent = K8sResource::from_value(json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "metadata": {
+                "namespace": "ns",
+                "name": "foo",
+                "uid": "foo-uid",
+                "resourceVersion": "7",
+            }
+        }))
+        .unwrap();
+        let target = EventTarget::of(&parent);
+        let expected = json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "namespace": "ns",
+            "name": "foo",
+            "uid": "foo-uid",
+            "resourceVersion": "7",
+        });
+        assert_eq!(expected, target.reference);
+        assert_eq!("ns", target.event_namespace("operators"));
+
+        let cluster_scoped = K8sResource::from_value(json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "metadata": {
+                "name": "foo",
+                "uid": "foo-uid",
+                "resourceVersion": "7",
+            }
+        }))
+        .unwrap();
+        let target = EventTarget::of(&cluster_scoped);
+        assert_eq!("operators", target.event_namespace("operators"));
+    }
+}
@@ -1,14 +1,15 @@
+use crate::config::{OperatorConfig, ParentMapper};
+use crate::handler::cache::CachedType;
 use crate::k8s_types::K8sType;
-use crate::resource::{InvalidResourceError, K8sResource, ObjectId};
+use crate::resource::{InvalidResourceError, K8sResource, ObjectId, ObjectIdRef};
 use anyhow::Error;
 
-#[cfg(feature = "testkit")]
-use crate::resource::ObjectIdRef;
-
 use crate::runner::client::{ApiError, Client, Error as ClientError, ObjectList, WatchEvent};
 use crate::runner::metrics::WatcherMetrics;
+use crate::runner::reconcile::SyncFailure;
 use crate::runner::resource_map::{IdSet, ResourceMap};
 
+use futures::executor::block_on;
 use serde_json::Value;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::{error::SendError, Sender};
@@ -16,29 +17,63 @@ use tokio::sync::{Mutex, MutexGuard};
 
 use std::collections::HashMap;
 use std::fmt::{self, Debug, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// How long to wait before resuming a watch after the connection to the api server was lost
+const WATCH_RESUME_DELAY: Duration = Duration::from_secs(1);
+
+/// How child resources are associated with their parent
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChildTracking {
+    /// Children have a label with the given name, whose value is the uid of the parent
+    Label(String),
+    /// Children have an `ownerReference` to a parent of the given type, with `controller: true`
+    OwnerReference(&'static K8sType),
+}
 
+/// Indexes child resources by the uid of their parent
 #[derive(Debug)]
-pub struct LabelToIdIndex {
-    label_name: String,
+pub struct ParentUidIndex {
+    tracking: ChildTracking,
     entries: HashMap<String, IdSet>,
 }
 
-impl LabelToIdIndex {
-    pub fn new(label_name: String) -> Self {
+impl ChildTracking {
+    pub(crate) fn for_config(config: &OperatorConfig) -> ChildTracking {
+        if config.track_via_owner_references {
+            ChildTracking::OwnerReference(config.parent)
+        } else {
+            ChildTracking::Label(config.tracking_label_name.clone())
+        }
+    }
+
+    /// Returns the uid of the parent of the given child, if it has one
+    pub(crate) fn parent_uid<'a>(&self, child: &'a K8sResource) -> Option<&'a str> {
+        match *self {
+            ChildTracking::Label(ref label_name) => child.get_label_value(label_name.as_str()),
+            ChildTracking::OwnerReference(parent_type) => {
+                get_controller_uid(child.as_ref(), parent_type)
+            }
+        }
+    }
+}
+
+impl ParentUidIndex {
+    pub fn new(tracking: ChildTracking) -> Self {
         Self {
-            label_name,
+            tracking,
             entries: HashMap::new(),
         }
     }
 }
 
-impl ReverseIndex for LabelToIdIndex {
+impl ReverseIndex for ParentUidIndex {
     type Value = IdSet;
 
     fn get_key<'a, 'b>(&'a self, res: &'b K8sResource) -> Option<&'b str> {
-        res.get_label_value(self.label_name.as_str())
+        self.tracking.parent_uid(res)
     }
 
     fn insert(&mut self, key: &str, res: &K8sResource) {
@@ -71,6 +106,21 @@ impl ReverseIndex for LabelToIdIndex {
     }
 }
 
+/// Returns the uid from the controller `ownerReference` of the resource, as long as the controller has the given type
+fn get_controller_uid<'a>(resource: &'a Value, parent_type: &K8sType) -> Option<&'a str> {
+    resource
+        .pointer("/metadata/ownerReferences")
+        .and_then(Value::as_array)?
+        .iter()
+        .find(|owner| {
+            owner.get("controller").and_then(Value::as_bool) == Some(true)
+                && owner.get("apiVersion").and_then(Value::as_str) == Some(parent_type.api_version)
+                && owner.get("kind").and_then(Value::as_str) == Some(parent_type.kind)
+        })
+        .and_then(|owner| owner.get("uid"))
+        .and_then(Value::as_str)
+}
+
 #[derive(Debug)]
 pub struct UidToIdIndex(HashMap<String, ObjectId>);
 
@@ -177,7 +227,7 @@ impl CacheAndIndex<UidToIdIndex> {
     }
 }
 
-impl CacheAndIndex<LabelToIdIndex> {
+impl CacheAndIndex<ParentUidIndex> {
     pub fn get_all_resources_by_index_key(&self, key: &str) -> Vec<K8sResource> {
         let mut results = Vec::new();
         if let Some(ids) = self.index.lookup(key) {
@@ -200,11 +250,25 @@ pub enum EventType {
     Finalizing,
     Deleted,
     UpdateOperationComplete {
-        result: Result<Option<Duration>, ()>,
+        result: Result<Option<Duration>, SyncFailure>,
+    },
+    /// Sent instead of `UpdateOperationComplete` when the finalize handler reports that cleanup isn't done yet
+    FinalizeIncomplete {
+        retry: Duration,
     },
     TriggerResync {
         resync_round: u32,
     },
+    /// Sent for every parent once per `resync_interval`, regardless of whether anything has changed
+    PeriodicResync,
+    /// Sent from `OperatorHandle::trigger_reconcile`, and for each parent that a change to a resource of a type
+    /// that was added with `OperatorHandle::watch_additional` maps to. These messages have no `index_key`, since
+    /// the uid of the parent isn't known, so it's looked up from the parent's id instead.
+    ReconcileRequested,
+    /// Sent from `OperatorHandle::trigger_reconcile_all` to sync every parent
+    ReconcileAllRequested,
+    /// Sent from `OperatorHandle::watch_additional` to start watching the `resource_type`
+    WatchRequested(ParentMapper),
 }
 
 #[derive(Debug)]
@@ -215,42 +279,143 @@ pub struct ResourceMessage {
     pub index_key: Option<String>,
 }
 
-pub struct ResourceState<'a, I: ReverseIndex>(MutexGuard<'a, CacheAndIndex<I>>);
+/// Holds the locks for the caches of every namespace that's being watched
+pub struct ResourceState<'a, I: ReverseIndex>(Vec<MutexGuard<'a, CacheAndIndex<I>>>);
 
 impl<'a, I: ReverseIndex> ResourceState<'a, I> {
-    #[cfg(feature = "testkit")]
     pub fn get_by_id(&self, id: &ObjectIdRef<'_>) -> Option<K8sResource> {
-        self.0.cache.get_copy(id)
+        self.0
+            .iter()
+            .filter_map(|lock| lock.cache.get_copy(id))
+            .next()
     }
 }
 
 impl<'a> ResourceState<'a, UidToIdIndex> {
     pub fn get_by_uid(&self, uid: &str) -> Option<K8sResource> {
-        self.0.get_by_uid(uid)
+        self.0.iter().filter_map(|lock| lock.get_by_uid(uid)).next()
+    }
+
+    /// returns the uid and id of every resource in the cache
+    pub fn get_all_ids(&self) -> Vec<(String, ObjectId)> {
+        self.0
+            .iter()
+            .flat_map(|lock| lock.index.0.iter())
+            .map(|(uid, id)| (uid.clone(), id.clone()))
+            .collect()
     }
 }
 
-impl<'a> ResourceState<'a, LabelToIdIndex> {
+impl<'a> ResourceState<'a, ParentUidIndex> {
     pub fn get_all_resources_by_index_key(&self, key: &str) -> Vec<K8sResource> {
-        self.0.get_all_resources_by_index_key(key)
+        let mut results = Vec::new();
+        for lock in self.0.iter() {
+            results.extend(lock.get_all_resources_by_index_key(key));
+        }
+        results
     }
 }
 
-#[derive(Debug, Clone)]
+/// The frontend for watching a single resource type. There's a separate cache for each namespace
+/// that's being watched, since each one is seeded and re-synced independently.
+#[derive(Debug)]
 pub struct ResourceMonitor<I: ReverseIndex> {
-    cache_and_index: Arc<Mutex<CacheAndIndex<I>>>,
+    caches: Vec<Arc<Mutex<CacheAndIndex<I>>>>,
+    /// the status of the watch of each namespace, which is shared with the health server
+    statuses: Vec<Arc<WatchStatus>>,
+}
+
+/// The statuses of all of the operator's watches, which grows when `OperatorHandle::watch_additional` starts a new one
+pub(crate) type WatchStatuses = Arc<std::sync::Mutex<Vec<Arc<WatchStatus>>>>;
+
+/// The state of a single watch that's needed by the readiness check, which is kept outside of the cache lock so that
+/// it can be read without waiting on it
+#[derive(Debug)]
+pub struct WatchStatus {
+    /// set once the initial list has completed, and never unset, even if the cache is re-seeded
+    synced: AtomicBool,
+    /// the last time that a list completed, a watch was established, or an event or bookmark was received
+    last_activity: std::sync::Mutex<Instant>,
+}
+
+impl WatchStatus {
+    pub fn new() -> WatchStatus {
+        WatchStatus {
+            synced: AtomicBool::new(false),
+            last_activity: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Relaxed)
+    }
+
+    pub fn set_synced(&self) {
+        self.synced.store(true, Ordering::Relaxed);
+    }
+
+    /// Records that the api server has just responded to the watch
+    pub fn record_activity(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns true if nothing has been received within the given `threshold`
+    pub fn is_stale(&self, threshold: Duration) -> bool {
+        self.last_activity.lock().unwrap().elapsed() > threshold
+    }
+}
+
+// implemented manually since deriving it would require `I: Clone`
+impl<I: ReverseIndex> Clone for ResourceMonitor<I> {
+    fn clone(&self) -> Self {
+        ResourceMonitor {
+            caches: self.caches.clone(),
+            statuses: self.statuses.clone(),
+        }
+    }
 }
 
 impl<I: ReverseIndex> ResourceMonitor<I> {
     pub async fn lock_state(&self) -> Result<ResourceState<'_, I>, Error> {
-        let mut lock = self.cache_and_index.lock().await;
-        if let Some(err) = lock.error.take() {
-            Err(err)
-        } else if !lock.is_initialized {
-            Err(MonitorBackendErr::StateUnininitialized.into_boxed_error())
-        } else {
-            Ok(ResourceState(lock))
+        let mut locks = Vec::with_capacity(self.caches.len());
+        for cache_and_index in self.caches.iter() {
+            let mut lock = cache_and_index.lock().await;
+            if let Some(err) = lock.error.take() {
+                return Err(err);
+            } else if !lock.is_initialized {
+                return Err(MonitorBackendErr::StateUnininitialized.into_boxed_error());
+            }
+            locks.push(lock);
+        }
+        Ok(ResourceState(locks))
+    }
+
+    /// Returns the status of the watch of each namespace. These are only read by the readiness check, which can't
+    /// wait on the cache locks.
+    pub fn watch_statuses(&self) -> impl Iterator<Item = Arc<WatchStatus>> + '_ {
+        self.statuses.iter().cloned()
+    }
+}
+
+// Lookups from the handler are always made from the blocking thread pool, so it's fine to block on acquiring the
+// locks here. Errors are ignored, since they're already checked when each `SyncRequest` is created.
+impl<I: ReverseIndex> CachedType for ResourceMonitor<I> {
+    fn get(&self, id: &ObjectIdRef<'_>) -> Option<K8sResource> {
+        self.caches
+            .iter()
+            .filter_map(|cache_and_index| {
+                block_on(cache_and_index.lock()).cache.get_copy(id.clone())
+            })
+            .next()
+    }
+
+    fn list(&self, namespace: Option<&str>) -> Vec<K8sResource> {
+        let mut results = Vec::new();
+        for cache_and_index in self.caches.iter() {
+            let lock = block_on(cache_and_index.lock());
+            results.extend(lock.cache.iter_namespace(namespace).cloned());
         }
+        results
     }
 }
 
@@ -260,7 +425,7 @@ enum MonitorBackendErr {
     ClientErr(ClientError),
     ResourceVersionExpired,
     InvalidResource(InvalidResourceError),
-    Api(ApiError),
+    Api(Box<ApiError>),
     StateUnininitialized,
 }
 
@@ -282,7 +447,7 @@ impl std::error::Error for MonitorBackendErr {
         match self {
             MonitorBackendErr::ClientErr(err) => Some(err),
             MonitorBackendErr::InvalidResource(e) => Some(e),
-            MonitorBackendErr::Api(e) => Some(e),
+            MonitorBackendErr::Api(e) => Some(e.as_ref()),
             _ => None,
         }
     }
@@ -313,7 +478,7 @@ impl From<ApiError> for MonitorBackendErr {
         if err.code == 410 {
             MonitorBackendErr::ResourceVersionExpired
         } else {
-            MonitorBackendErr::Api(err)
+            MonitorBackendErr::Api(Box::new(err))
         }
     }
 }
@@ -340,77 +505,211 @@ impl<T> From<SendError<T>> for MonitorBackendErr {
     }
 }
 
+/// Returns the label selector for watching children, which requires the tracking label in addition to the
+/// selector from the `ChildDeclaration`, if there is one
+fn child_label_selector(
+    tracking: &ChildTracking,
+    label_selector: Option<String>,
+) -> Option<String> {
+    // children that are tracked by their owner references can't be selected by the api server, so we have to watch
+    // all of them and filter them using the index
+    let tracking_selector = match tracking {
+        ChildTracking::Label(ref label_name) => Some(label_name.clone()),
+        ChildTracking::OwnerReference(_) => None,
+    };
+    match (tracking_selector, label_selector) {
+        (Some(tracking), Some(selector)) => Some(format!("{},{}", tracking, selector)),
+        (tracking, selector) => tracking.or(selector),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn start_child_monitor(
     executor: Handle,
-    label_name: String,
-    namespace: Option<String>,
+    tracking: ChildTracking,
+    namespaces: Option<Vec<String>>,
+    label_selector: Option<String>,
+    ignore_status_changes: bool,
+    metadata_only: bool,
     k8s_type: &'static K8sType,
     client: Client,
     sender: Sender<ResourceMessage>,
     watcher_metrics: WatcherMetrics,
-) -> ResourceMonitor<LabelToIdIndex> {
-    let index = LabelToIdIndex::new(label_name.clone());
+) -> ResourceMonitor<ParentUidIndex> {
+    let label_selector = child_label_selector(&tracking, label_selector);
     start_monitor(
         executor,
-        index,
+        move || ParentUidIndex::new(tracking.clone()),
         k8s_type,
-        namespace,
-        Some(label_name),
+        namespaces,
+        label_selector,
+        None,
+        ignore_status_changes,
+        metadata_only,
         client,
-        sender,
+        Some(sender),
+        None,
         watcher_metrics,
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn start_parent_monitor(
     executor: Handle,
-    namespace: Option<String>,
+    namespaces: Option<Vec<String>>,
+    label_selector: Option<String>,
+    field_selector: Option<String>,
+    k8s_type: &'static K8sType,
+    client: Client,
+    sender: Sender<ResourceMessage>,
+    watcher_metrics: WatcherMetrics,
+) -> ResourceMonitor<UidToIdIndex> {
+    start_monitor(
+        executor,
+        UidToIdIndex::new,
+        k8s_type,
+        namespaces,
+        label_selector,
+        field_selector,
+        false,
+        false,
+        client,
+        Some(sender),
+        None,
+        watcher_metrics,
+    )
+}
+
+/// Starts a cluster-wide watch of the `Namespace`s that match the label selector, which sends a message whenever a
+/// namespace starts or stops matching
+pub fn start_namespace_monitor(
+    executor: Handle,
+    label_selector: String,
+    client: Client,
+    sender: Sender<ResourceMessage>,
+    watcher_metrics: WatcherMetrics,
+) -> ResourceMonitor<UidToIdIndex> {
+    start_monitor(
+        executor,
+        UidToIdIndex::new,
+        crate::k8s_types::core::v1::Namespace,
+        None,
+        Some(label_selector),
+        None,
+        true,
+        true,
+        client,
+        Some(sender),
+        None,
+        watcher_metrics,
+    )
+}
+
+/// Starts a monitor for a type that's only cached for lookups from the handler. No messages are sent for changes
+/// to these resources, since they never trigger a sync.
+pub fn start_watched_monitor(
+    executor: Handle,
+    namespaces: Option<Vec<String>>,
+    k8s_type: &'static K8sType,
+    client: Client,
+    watcher_metrics: WatcherMetrics,
+) -> ResourceMonitor<UidToIdIndex> {
+    start_monitor(
+        executor,
+        UidToIdIndex::new,
+        k8s_type,
+        namespaces,
+        None,
+        None,
+        false,
+        false,
+        client,
+        None,
+        None,
+        watcher_metrics,
+    )
+}
+
+/// Starts a monitor for a type that was added after the operator started. Rather than sending messages for the
+/// resources themselves, a change to any of them requests a sync of each of the parents that it's mapped to.
+pub fn start_mapped_monitor(
+    executor: Handle,
+    namespaces: Option<Vec<String>>,
     k8s_type: &'static K8sType,
     client: Client,
     sender: Sender<ResourceMessage>,
+    parent_mapper: ParentMapper,
     watcher_metrics: WatcherMetrics,
 ) -> ResourceMonitor<UidToIdIndex> {
     start_monitor(
         executor,
-        UidToIdIndex::new(),
+        UidToIdIndex::new,
         k8s_type,
-        namespace,
+        namespaces,
+        None,
         None,
+        false,
+        false,
         client,
-        sender,
+        Some(sender),
+        Some(parent_mapper),
         watcher_metrics,
     )
 }
 
+/// Starts a separate watch for each of the given namespaces, or a single cluster-wide watch if `namespaces`
+/// is `None` or the type is cluster scoped. All of the watches send their events to the same `sender`, if there
+/// is one. If `ignore_status_changes` is true, then no message is sent for updates that only change the status. If
+/// `metadata_only` is true, then only the metadata of each resource is requested and cached. If there's a
+/// `parent_mapper`, then changes request syncs of the parents that it returns, instead of being sent as is.
 #[allow(clippy::too_many_arguments)]
 fn start_monitor<I: ReverseIndex>(
     executor: Handle,
-    index: I,
+    new_index: impl Fn() -> I,
     k8s_type: &'static K8sType,
-    namespace: Option<String>,
+    namespaces: Option<Vec<String>>,
     label_selector: Option<String>,
+    field_selector: Option<String>,
+    ignore_status_changes: bool,
+    metadata_only: bool,
     client: Client,
-    sender: Sender<ResourceMessage>,
+    sender: Option<Sender<ResourceMessage>>,
+    parent_mapper: Option<ParentMapper>,
     watcher_metrics: WatcherMetrics,
 ) -> ResourceMonitor<I> {
-    let cache_and_index = Arc::new(Mutex::new(CacheAndIndex::new(index)));
-    let frontend = ResourceMonitor {
-        cache_and_index: cache_and_index.clone(),
+    let namespaces = match namespaces.filter(|_| k8s_type.is_namespaced()) {
+        Some(list) => list.into_iter().map(Some).collect(),
+        None => vec![None],
     };
 
-    let backend = ResourceMonitorBackend {
-        metrics: watcher_metrics,
-        cache_and_index,
-        client,
-        k8s_type,
-        sender,
-        label_selector,
-        namespace,
-    };
-    executor.spawn(Box::pin(async move {
-        backend.run().await;
-    }));
-    frontend
+    let mut caches = Vec::with_capacity(namespaces.len());
+    let mut statuses = Vec::with_capacity(namespaces.len());
+    for namespace in namespaces {
+        let cache_and_index = Arc::new(Mutex::new(CacheAndIndex::new(new_index())));
+        caches.push(cache_and_index.clone());
+        let status = Arc::new(WatchStatus::new());
+        statuses.push(status.clone());
+
+        let backend = ResourceMonitorBackend {
+            metrics: watcher_metrics.clone(),
+            cache_and_index,
+            client: client.clone(),
+            k8s_type,
+            sender: sender.clone(),
+            parent_mapper: parent_mapper.clone(),
+            label_selector: label_selector.clone(),
+            field_selector: field_selector.clone(),
+            ignore_status_changes,
+            metadata_only,
+            namespace,
+            resource_count: 0,
+            status,
+        };
+        executor.spawn(Box::pin(async move {
+            backend.run().await;
+        }));
+    }
+    ResourceMonitor { caches, statuses }
 }
 
 struct ResourceMonitorBackend<I: ReverseIndex> {
@@ -418,9 +717,15 @@ struct ResourceMonitorBackend<I: ReverseIndex> {
     cache_and_index: Arc<Mutex<CacheAndIndex<I>>>,
     client: Client,
     k8s_type: &'static K8sType,
-    sender: Sender<ResourceMessage>,
+    sender: Option<Sender<ResourceMessage>>,
+    parent_mapper: Option<ParentMapper>,
     label_selector: Option<String>,
+    field_selector: Option<String>,
+    ignore_status_changes: bool,
+    metadata_only: bool,
     namespace: Option<String>,
+    resource_count: usize,
+    status: Arc<WatchStatus>,
 }
 
 impl<I: ReverseIndex> ResourceMonitorBackend<I> {
@@ -431,10 +736,15 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
             self.label_selector
         );
 
+        // only the first list may be served from the api server's cache, since a stale relist could go back in time
+        let mut initial_list = true;
         loop {
-            let result = self.seed_cache().await;
+            let result = self.seed_cache(initial_list).await;
             match result {
                 Ok(resource_version) => {
+                    initial_list = false;
+                    self.status.set_synced();
+                    self.status.record_activity();
                     let result = self.run_inner(resource_version).await;
                     log::info!("Watch ended with result: {:?}", result);
                     if let Err(err) = result {
@@ -470,7 +780,9 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
         lock.error = Some(error.into_boxed_error());
         lock.is_initialized = false;
 
-        if !is_http_410 {
+        if is_http_410 {
+            self.metrics.relisted();
+        } else {
             self.metrics.error();
             let duration = std::time::Duration::from_secs(10);
             tokio::time::delay_for(duration).await;
@@ -479,10 +791,13 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
         !is_send_err
     }
 
+    /// Watches continuously, starting from the given `resource_version`. Each watch resumes from the version of the
+    /// last event or bookmark that was received, so the cache only needs to be re-seeded with a full list if the api
+    /// server no longer has that version, in which case this returns `ResourceVersionExpired`.
     async fn run_inner(&mut self, mut resource_version: String) -> Result<(), MonitorBackendErr> {
         loop {
             self.metrics.request_started();
-            let result = self.do_watch(&resource_version).await;
+            let result = self.do_watch(&mut resource_version).await;
             log::debug!(
                 "Watch of {:?} ended with result: {:?}",
                 self.k8s_type,
@@ -490,14 +805,21 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
             );
 
             match result {
-                Ok(Some(vers)) => {
-                    resource_version = vers;
-                }
-                Ok(None) => {}
+                Ok(()) => {}
                 Err(MonitorBackendErr::ResourceVersionExpired) => {
                     log::warn!("ResourceVersion is too old for type: {:?}", self.k8s_type);
                     return Err(MonitorBackendErr::ResourceVersionExpired);
                 }
+                Err(MonitorBackendErr::ClientErr(ref err)) if is_connection_error(err) => {
+                    log::warn!(
+                        "Lost connection while watching {:?}, resuming from resourceVersion: {}, err: {}",
+                        self.k8s_type,
+                        resource_version,
+                        err
+                    );
+                    self.metrics.error();
+                    tokio::time::delay_for(WATCH_RESUME_DELAY).await;
+                }
                 Err(err) => {
                     return Err(err);
                 }
@@ -505,10 +827,8 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
         }
     }
 
-    async fn do_watch(
-        &mut self,
-        resource_version: &str,
-    ) -> Result<Option<String>, MonitorBackendErr> {
+    /// Watches until the api server ends the watch, updating `resource_version` as each event is received
+    async fn do_watch(&mut self, resource_version: &mut String) -> Result<(), MonitorBackendErr> {
         log::debug!(
             "Starting watch of: {:?} with resourceVersion: {:?}",
             self.k8s_type,
@@ -520,28 +840,49 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
             .watch(
                 &*self.k8s_type,
                 self.namespace.as_ref().map(String::as_str),
-                Some(resource_version),
+                Some(resource_version.as_str()),
                 self.label_selector.as_ref().map(String::as_str),
+                self.field_selector.as_deref(),
+                self.metadata_only,
             )
             .await?;
+        self.metrics.watch_connected();
+        self.status.record_activity();
 
-        let mut new_version: Option<String> = None;
+        // The api server should end the watch on its own once the `watch_timeout` elapses, but a connection can
+        // also go quiet without ever being closed. Giving up on the watch after this deadline ensures that it will
+        // always be re-established, rather than silently dying.
+        let deadline = Instant::now() + self.client.max_watch_duration();
         loop {
-            let maybe_next = lines.next().await;
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .unwrap_or_else(|| Duration::from_millis(0));
+            let maybe_next = match tokio::time::timeout(remaining, lines.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    log::warn!(
+                        "Watch of {:?} was not closed by the api server before the deadline, re-establishing it",
+                        self.k8s_type
+                    );
+                    break;
+                }
+            };
             if let Some(result) = maybe_next {
-                self.metrics.event_received();
+                self.status.record_activity();
                 let event = result?;
-                let event_version = self.handle_event(event).await?;
-                new_version = Some(event_version);
+                self.metrics.event_received(event.type_name());
+                *resource_version = self.handle_event(event).await?;
             } else {
                 break;
             }
         }
-        Ok(new_version)
+        Ok(())
     }
 
+    /// Updates the cache and sends a message for the event, and returns the `resourceVersion` to resume watching from
     async fn handle_event(&mut self, event: WatchEvent) -> Result<String, MonitorBackendErr> {
-        let (event_type, object) = match event {
+        let (event_type, mut object) = match event {
+            WatchEvent::Bookmark(object) => return bookmark_version(object),
             WatchEvent::Added(res) => (EventType::Created, res),
             WatchEvent::Deleted(res) => (EventType::Deleted, res),
             WatchEvent::Modified(res) => (get_update_event_type(&res), res),
@@ -554,13 +895,25 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
                 return Err(err.into());
             }
         };
+        if self.metadata_only {
+            // the api server sends these as `PartialObjectMetadata`, so they need the real type just like list items
+            self.add_metadata_to_list_object(&mut object)?;
+        }
         let resource = K8sResource::from_value(object)?;
         let resource_version = resource.resource_version().to_owned();
 
         let resource_id = resource.get_object_id().to_owned();
         let resource_type = self.k8s_type;
+        let mapped_parents = self.mapped_parents(&resource);
         let mut cache_and_index = self.cache_and_index.lock().await;
         let index_key = cache_and_index.index.get_key(&resource).map(String::from);
+        let is_status_only = self.ignore_status_changes
+            && matches!(event_type, EventType::Updated)
+            && cache_and_index
+                .cache
+                .get(resource_id.as_id_ref())
+                .map(|previous| is_status_only_change(previous, &resource))
+                .unwrap_or(false);
 
         match event_type {
             EventType::Deleted => {
@@ -571,19 +924,32 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
             }
         }
 
+        let count = cache_and_index.resource_count();
         self.metrics
-            .set_resource_count(cache_and_index.resource_count());
+            .update_resource_count(self.resource_count, count);
+        self.resource_count = count;
+        // the lock has to be released before sending, since the operator may need to read from the cache before it
+        // can make room in a full channel
+        drop(cache_and_index);
+        if is_status_only {
+            log::trace!(
+                "Ignoring status change to {} {}",
+                resource_type,
+                resource_id
+            );
+            return Ok(resource_version);
+        }
         let to_send = ResourceMessage {
             event_type,
             resource_type,
             resource_id,
             index_key,
         };
-        self.sender.send(to_send).await?;
+        Self::send(self.sender.as_mut(), to_send, mapped_parents).await?;
         Ok(resource_version)
     }
 
-    async fn seed_cache(&mut self) -> Result<String, MonitorBackendErr> {
+    async fn seed_cache(&mut self, initial_list: bool) -> Result<String, MonitorBackendErr> {
         log::info!(
             "Seeding resources of type: {:?} with selector: {:?}",
             self.k8s_type,
@@ -601,6 +967,9 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
                 &*self.k8s_type,
                 self.namespace.as_ref().map(String::as_str),
                 self.label_selector.as_ref().map(String::as_str),
+                self.field_selector.as_deref(),
+                self.metadata_only,
+                initial_list,
             )
             .await?;
         // safe unwrap since RawApi can only fail when setting the request body, but it's hard coded to an empty veec
@@ -612,6 +981,7 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
                 value: Value::Null,
             })?;
 
+        let mut to_send = Vec::with_capacity(items.len());
         for mut object in items {
             self.add_metadata_to_list_object(&mut object)?;
             let resource = K8sResource::from_value(object)?;
@@ -625,18 +995,67 @@ impl<I: ReverseIndex> ResourceMonitorBackend<I> {
                 resource_id,
                 index_key,
             };
+            let mapped_parents = self.mapped_parents(&resource);
 
             cache_and_index.add(resource);
-            self.sender.send(message).await?;
+            to_send.push((message, mapped_parents));
         }
+        let count = cache_and_index.resource_count();
         self.metrics
-            .set_resource_count(cache_and_index.resource_count());
+            .update_resource_count(self.resource_count, count);
+        self.resource_count = count;
         // set the initialization flag, which will allow the frontend to read from the cache
         cache_and_index.is_initialized = true;
-        // drop the cache_and_index lock when we exit this function, which allows consumers to read from it
+        // the messages are only sent once the lock is released, since the operator may need to read from the cache
+        // before it can make room in a full channel
+        drop(cache_and_index);
+        for (message, mapped_parents) in to_send {
+            Self::send(self.sender.as_mut(), message, mapped_parents).await?;
+        }
         Ok(resource_version)
     }
 
+    /// Sends the message, unless the resource was mapped to parents, in which case a sync of each of those parents
+    /// is requested instead
+    async fn send(
+        sender: Option<&mut Sender<ResourceMessage>>,
+        message: ResourceMessage,
+        mapped_parents: Option<Vec<ObjectId>>,
+    ) -> Result<(), MonitorBackendErr> {
+        let sender = match sender {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
+        match mapped_parents {
+            Some(parent_ids) => {
+                for parent_id in parent_ids {
+                    log::debug!(
+                        "Requesting sync of parent: {} due to event: {:?} on {} {}",
+                        parent_id,
+                        message.event_type,
+                        message.resource_type,
+                        message.resource_id
+                    );
+                    let request = ResourceMessage {
+                        event_type: EventType::ReconcileRequested,
+                        resource_type: message.resource_type,
+                        resource_id: parent_id,
+                        index_key: None,
+                    };
+                    sender.send(request).await?;
+                }
+            }
+            None => sender.send(message).await?,
+        }
+        Ok(())
+    }
+
+    fn mapped_parents(&self, resource: &K8sResource) -> Option<Vec<ObjectId>> {
+        self.parent_mapper
+            .as_ref()
+            .map(|mapper| mapper.parents_of(resource))
+    }
+
     /// For some reason, it seems that apiVersion and kind are missing from the individual response items in the list response
     fn add_metadata_to_list_object(
         &self,
@@ -667,6 +1086,311 @@ fn get_update_event_type(resource: &Value) -> EventType {
     }
 }
 
+/// Returns true if the only differences between the two versions of a resource are in its status, or in the metadata
+/// that's updated along with it
+fn is_status_only_change(previous: &Value, current: &Value) -> bool {
+    fn without_status(resource: &Value) -> Value {
+        let mut resource = resource.clone();
+        if let Some(obj) = resource.as_object_mut() {
+            obj.remove("status");
+        }
+        if let Some(metadata) = resource
+            .pointer_mut("/metadata")
+            .and_then(Value::as_object_mut)
+        {
+            metadata.remove("managedFields");
+            metadata.remove("resourceVersion");
+        }
+        resource
+    }
+    without_status(previous) == without_status(current)
+}
+
 fn is_finalizing(resource: &Value) -> bool {
     resource.pointer("/metadata/deletionTimestamp").is_some()
 }
+
+/// Returns true if the error means that the connection to the api server was lost or never established, as opposed
+/// to an error response from the api server
+fn is_connection_error(err: &ClientError) -> bool {
+    matches!(err, ClientError::Io(_) | ClientError::Timeout(_))
+}
+
+/// Bookmarks only contain the `resourceVersion`, which tells us that the watch can be resumed from that version
+/// even if there haven't been any changes to the resources that we're watching
+fn bookmark_version(object: Value) -> Result<String, MonitorBackendErr> {
+    object
+        .pointer("/metadata/resourceVersion")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .ok_or_else(|| {
+            InvalidResourceError::new("bookmark is missing metadata.resourceVersion", object).into()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::k8s_types::apps::v1::Deployment;
+    use serde_json::json;
+
+    #[test]
+    fn children_are_indexed_by_the_uid_of_their_controller_owner() {
+        let child = K8sResource::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {
+                "namespace": "ns",
+                "name": "child",
+                "uid": "child-uid",
+                "resourceVersion": "1",
+                "ownerReferences": [
+                    {
+                        "apiVersion": "example.com/v1",
+                        "kind": "Other",
+                        "name": "other",
+                        "uid": "other-uid",
+                        "controller": true,
+                    },
+                    {
+                        "apiVersion": "apps/v1",
+                        "kind": "Deployment",
+                        "name": "not-controller",
+                        "uid": "not-controller-uid",
+                    },
+                    {
+                        "apiVersion": "apps/v1",
+                        "kind": "Deployment",
+                        "name": "parent",
+                        "uid": "parent-uid",
+                        "controller": true,
+                    }
+                ]
+            }
+        }))
+        .unwrap();
+
+        let index = ParentUidIndex::new(ChildTracking::OwnerReference(Deployment));
+        assert_eq!(Some("parent-uid"), index.get_key(&child));
+
+        let index = ParentUidIndex::new(ChildTracking::Label("parent-label".to_owned()));
+        assert_eq!(None, index.get_key(&child));
+    }
+
+    #[test]
+    fn child_label_selector_requires_the_tracking_label() {
+        let by_label = ChildTracking::Label("parent-label".to_owned());
+        let by_owner = ChildTracking::OwnerReference(Deployment);
+        assert_eq!(
+            Some("parent-label".to_owned()),
+            child_label_selector(&by_label, None)
+        );
+        assert_eq!(
+            Some("parent-label,tier=web".to_owned()),
+            child_label_selector(&by_label, Some("tier=web".to_owned()))
+        );
+        assert_eq!(None, child_label_selector(&by_owner, None));
+        assert_eq!(
+            Some("tier=web".to_owned()),
+            child_label_selector(&by_owner, Some("tier=web".to_owned()))
+        );
+    }
+
+    #[test]
+    fn cached_resources_are_looked_up_across_namespaces() {
+        let resource = |namespace: &str| {
+            K8sResource::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": {
+                    "namespace": namespace,
+                    "name": "creds",
+                    "uid": format!("{}-uid", namespace),
+                    "resourceVersion": "1",
+                },
+            }))
+            .unwrap()
+        };
+        let mut caches = Vec::new();
+        for namespace in &["a", "b"] {
+            let mut cache_and_index = CacheAndIndex::new(UidToIdIndex::new());
+            cache_and_index.add(resource(namespace));
+            caches.push(Arc::new(Mutex::new(cache_and_index)));
+        }
+        let monitor = ResourceMonitor {
+            caches,
+            statuses: Vec::new(),
+        };
+
+        let found = CachedType::get(&monitor, &ObjectIdRef::new("b", "creds")).unwrap();
+        assert_eq!("b-uid", found.uid());
+        assert!(CachedType::get(&monitor, &ObjectIdRef::new("c", "creds")).is_none());
+        assert_eq!(2, monitor.list(None).len());
+        assert_eq!(1, monitor.list(Some("a")).len());
+    }
+
+    #[test]
+    fn changes_to_mapped_resources_request_a_sync_of_each_parent() {
+        let mapper = ParentMapper::new(|secret: &K8sResource| {
+            vec![
+                ObjectId::new("ns".to_owned(), format!("{}-a", secret.name())),
+                ObjectId::new("ns".to_owned(), format!("{}-b", secret.name())),
+            ]
+        });
+        let secret = K8sResource::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": {"namespace": "ns", "name": "creds", "uid": "secret-uid", "resourceVersion": "1"},
+        }))
+        .unwrap();
+        let message = ResourceMessage {
+            event_type: EventType::Updated,
+            resource_type: crate::k8s_types::core::v1::Secret,
+            resource_id: secret.get_object_id().to_owned(),
+            index_key: None,
+        };
+
+        let (mut tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let mapped_parents = Some(mapper.parents_of(&secret));
+        block_on(ResourceMonitorBackend::<UidToIdIndex>::send(
+            Some(&mut tx),
+            message,
+            mapped_parents,
+        ))
+        .unwrap();
+        drop(tx);
+
+        let requested = block_on(async {
+            let mut requested = Vec::new();
+            while let Some(message) = rx.recv().await {
+                assert!(matches!(message.event_type, EventType::ReconcileRequested));
+                requested.push(message.resource_id.to_string());
+            }
+            requested
+        });
+        assert_eq!(vec!["ns/creds-a", "ns/creds-b"], requested);
+    }
+
+    #[test]
+    fn a_flood_of_events_pauses_the_watch_without_dropping_any() {
+        use crate::k8s_types::core::v1::ConfigMap;
+        use std::sync::atomic::AtomicUsize;
+
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client_config = serde_json::from_value(json!({
+            "apiServerEndpoint": "https://localhost:6443",
+            "userAgent": "test-operator",
+            "credentials": { "header": "Bearer abc" },
+        }))
+        .unwrap();
+        let metrics = crate::runner::metrics::Metrics::new();
+        let client = Client::new(client_config, metrics.client_metrics()).unwrap();
+        let cache_and_index = Arc::new(Mutex::new(CacheAndIndex::new(UidToIdIndex::new())));
+        let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+        let mut backend = ResourceMonitorBackend {
+            metrics: metrics.watcher_metrics(ConfigMap),
+            cache_and_index: cache_and_index.clone(),
+            client,
+            k8s_type: ConfigMap,
+            sender: Some(tx),
+            parent_mapper: None,
+            label_selector: None,
+            field_selector: None,
+            ignore_status_changes: false,
+            metadata_only: false,
+            namespace: None,
+            resource_count: 0,
+            status: Arc::new(WatchStatus::new()),
+        };
+        let handled = Arc::new(AtomicUsize::new(0));
+        let handled_by_watch = handled.clone();
+
+        runtime.block_on(async move {
+            let watch = tokio::spawn(async move {
+                for i in 0..100 {
+                    let event = WatchEvent::Added(json!({
+                        "apiVersion": "v1",
+                        "kind": "ConfigMap",
+                        "metadata": {"namespace": "ns", "name": format!("cm-{}", i), "uid": format!("uid-{}", i), "resourceVersion": "1"},
+                    }));
+                    backend.handle_event(event).await.unwrap();
+                    handled_by_watch.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            tokio::time::delay_for(Duration::from_millis(50)).await;
+
+            // the watch is blocked on the full channel, but it doesn't hold the lock on the cache while it waits
+            let paused_at = handled.load(Ordering::SeqCst);
+            assert!(paused_at < 10, "handled {} events", paused_at);
+            let cached = cache_and_index
+                .try_lock()
+                .expect("cache should not be locked")
+                .resource_count();
+            assert!(cached <= paused_at + 1);
+
+            let mut received = Vec::new();
+            while received.len() < 100 {
+                let message = rx.recv().await.unwrap();
+                received.push(message.resource_id.name().to_owned());
+            }
+            watch.await.unwrap();
+            let expected = (0..100).map(|i| format!("cm-{}", i)).collect::<Vec<_>>();
+            assert_eq!(expected, received);
+            assert_eq!(100, cache_and_index.lock().await.resource_count());
+        });
+    }
+
+    #[test]
+    fn bookmarks_return_their_resource_version() {
+        let event: WatchEvent = serde_json::from_value(json!({
+            "type": "BOOKMARK",
+            "object": {
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"resourceVersion": "12746"},
+            },
+        }))
+        .unwrap();
+        let object = match event {
+            WatchEvent::Bookmark(object) => object,
+            _ => panic!("expected a bookmark event"),
+        };
+        assert_eq!("12746", bookmark_version(object).unwrap());
+        assert!(bookmark_version(json!({"metadata": {}})).is_err());
+    }
+
+    #[test]
+    fn status_only_changes_are_detected() {
+        let pod = |resource_version: &str, image: &str, phase: &str| {
+            json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": {
+                    "namespace": "ns",
+                    "name": "pod",
+                    "resourceVersion": resource_version,
+                    "managedFields": [{"manager": resource_version}],
+                },
+                "spec": {"containers": [{"name": "app", "image": image}]},
+                "status": {"phase": phase},
+            })
+        };
+        let previous = pod("1", "app:1", "Pending");
+        assert!(is_status_only_change(
+            &previous,
+            &pod("2", "app:1", "Running")
+        ));
+        assert!(!is_status_only_change(
+            &previous,
+            &pod("2", "app:2", "Pending")
+        ));
+
+        let mut labeled = pod("2", "app:1", "Pending");
+        labeled["metadata"]["labels"] = json!({"foo": "bar"});
+        assert!(!is_status_only_change(&previous, &labeled));
+    }
+}
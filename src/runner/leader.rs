@@ -0,0 +1,292 @@
+//! Leader election using a `Lease` from the `coordination.k8s.io/v1` api group. This follows the same
+//! basic algorithm as client-go. Expiration of another replica's lease is determined using the time that
+//! _we_ observed the lease change, rather than the `renewTime` in the lease, so that clock skew between
+//! replicas doesn't matter.
+use crate::config::LeaderElectionConfig;
+use crate::k8s_types::coordination_k8s_io::v1::Lease;
+use crate::resource::ObjectIdRef;
+use crate::runner::client::{Client, Error as ClientError};
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Returned from `run_operator` when the operator shut down because it lost leadership
+#[derive(Debug)]
+pub struct LeadershipLostError {
+    pub identity: String,
+}
+
+impl Display for LeadershipLostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Operator shutdown because '{}' failed to renew the leader election lease",
+            self.identity
+        )
+    }
+}
+
+impl std::error::Error for LeadershipLostError {}
+
+#[derive(Debug, PartialEq)]
+struct ObservedLease {
+    holder: Option<String>,
+    renew_time: Option<String>,
+}
+
+pub(crate) struct LeaderElector {
+    config: LeaderElectionConfig,
+    client: Client,
+    is_leader: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    observed: Option<(ObservedLease, Instant)>,
+}
+
+impl LeaderElector {
+    pub fn new(
+        config: LeaderElectionConfig,
+        client: Client,
+        is_leader: Arc<AtomicBool>,
+        running: Arc<AtomicBool>,
+    ) -> LeaderElector {
+        LeaderElector {
+            config,
+            client,
+            is_leader,
+            running,
+            observed: None,
+        }
+    }
+
+    /// Blocks until this replica acquires the lease. Returns false if the operator was shutdown before then.
+    pub async fn acquire(&mut self) -> bool {
+        log::info!(
+            "Attempting to acquire leader election lease: {}/{} as: '{}'",
+            self.config.lease_namespace,
+            self.config.lease_name,
+            self.config.identity
+        );
+        while self.running.load(Ordering::Relaxed) {
+            if self.try_with_deadline().await {
+                log::info!(
+                    "Acquired leader election lease: {}/{}",
+                    self.config.lease_namespace,
+                    self.config.lease_name
+                );
+                self.is_leader.store(true, Ordering::Relaxed);
+                return true;
+            }
+            tokio::time::delay_for(self.config.retry_period).await;
+        }
+        false
+    }
+
+    /// Keeps renewing the lease until either the operator is shutdown or the lease could not be renewed within
+    /// the `renew_deadline`. Returns an error only if leadership was lost.
+    pub async fn renew_until_lost(&mut self) -> Result<(), LeadershipLostError> {
+        let mut last_renewal = Instant::now();
+        while self.running.load(Ordering::Relaxed) {
+            tokio::time::delay_for(self.config.retry_period).await;
+            if self.try_with_deadline().await {
+                last_renewal = Instant::now();
+            } else if last_renewal.elapsed() >= self.config.renew_deadline {
+                log::error!(
+                    "Failed to renew leader election lease: {}/{} within the renew deadline, giving up leadership",
+                    self.config.lease_namespace,
+                    self.config.lease_name
+                );
+                self.is_leader.store(false, Ordering::Relaxed);
+                self.running.store(false, Ordering::Relaxed);
+                return Err(LeadershipLostError {
+                    identity: self.config.identity.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives up the lease if we hold it, so that another replica can acquire it right away instead of waiting for
+    /// it to expire. Errors are only logged, since the lease will expire on its own anyway.
+    pub async fn release(&mut self) {
+        if !self.is_leader.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let deadline = self.config.renew_deadline;
+        match tokio::time::timeout(deadline, self.try_release()).await {
+            Ok(Ok(())) => log::info!(
+                "Released leader election lease: {}/{}",
+                self.config.lease_namespace,
+                self.config.lease_name
+            ),
+            Ok(Err(err)) => log::warn!("Error releasing leader election lease: {}", err),
+            Err(_) => log::warn!("Timed out releasing leader election lease"),
+        }
+    }
+
+    async fn try_release(&mut self) -> Result<(), ClientError> {
+        let namespace = self.config.lease_namespace.clone();
+        let name = self.config.lease_name.clone();
+        let id = ObjectIdRef::new(namespace.as_str(), name.as_str());
+        let mut lease = match self.client.get_resource(Lease, &id).await? {
+            Some(lease) => lease,
+            None => return Ok(()),
+        };
+        if get_spec_str(&lease, "holderIdentity").as_ref() != Some(&self.config.identity) {
+            return Ok(());
+        }
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+        let transitions = lease
+            .pointer("/spec/leaseTransitions")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        lease
+            .as_object_mut()
+            .expect("lease must be an object")
+            .insert(
+                "spec".to_owned(),
+                released_lease_spec(now.as_str(), transitions),
+            );
+        // a conflict means that the lease was already taken over by another replica
+        ignore_conflict(self.client.replace_resource(Lease, &id, &lease).await).map(|_| ())
+    }
+
+    async fn try_with_deadline(&mut self) -> bool {
+        let deadline = self.config.renew_deadline;
+        match tokio::time::timeout(deadline, self.try_acquire_or_renew()).await {
+            Ok(Ok(acquired)) => acquired,
+            Ok(Err(err)) => {
+                log::warn!("Error acquiring or renewing leader election lease: {}", err);
+                false
+            }
+            Err(_) => {
+                log::warn!("Timed out acquiring or renewing leader election lease");
+                false
+            }
+        }
+    }
+
+    async fn try_acquire_or_renew(&mut self) -> Result<bool, ClientError> {
+        let namespace = self.config.lease_namespace.clone();
+        let name = self.config.lease_name.clone();
+        let id = ObjectIdRef::new(namespace.as_str(), name.as_str());
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+        let existing = self.client.get_resource(Lease, &id).await?;
+
+        let mut lease = match existing {
+            Some(lease) => lease,
+            None => {
+                let lease = json!({
+                    "apiVersion": Lease.api_version,
+                    "kind": Lease.kind,
+                    "metadata": {
+                        "namespace": self.config.lease_namespace,
+                        "name": self.config.lease_name,
+                    },
+                    "spec": self.lease_spec(now.as_str(), now.as_str(), 0),
+                });
+                return ignore_conflict(self.client.create_resource(Lease, &lease).await);
+            }
+        };
+
+        let observed = ObservedLease {
+            holder: get_spec_str(&lease, "holderIdentity"),
+            renew_time: get_spec_str(&lease, "renewTime"),
+        };
+        let held_by_us = observed.holder.as_ref() == Some(&self.config.identity);
+        let held_by_other = observed.holder.is_some() && !held_by_us;
+        let observed_at = self.observe(observed);
+        if held_by_other && observed_at.elapsed() < self.config.lease_duration {
+            log::debug!(
+                "Leader election lease is currently held by: {:?}",
+                get_spec_str(&lease, "holderIdentity")
+            );
+            return Ok(false);
+        }
+
+        let transitions = lease
+            .pointer("/spec/leaseTransitions")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        let spec = if held_by_us {
+            let acquire_time = get_spec_str(&lease, "acquireTime").unwrap_or_else(|| now.clone());
+            self.lease_spec(acquire_time.as_str(), now.as_str(), transitions)
+        } else {
+            self.lease_spec(now.as_str(), now.as_str(), transitions + 1)
+        };
+        lease
+            .as_object_mut()
+            .expect("lease must be an object")
+            .insert("spec".to_owned(), spec);
+        // the lease still has the resourceVersion from when we read it, so the replace will fail with a
+        // 409 if another replica has modified it in the meantime
+        ignore_conflict(self.client.replace_resource(Lease, &id, &lease).await)
+    }
+
+    fn lease_spec(&self, acquire_time: &str, renew_time: &str, transitions: u64) -> Value {
+        json!({
+            "holderIdentity": self.config.identity,
+            "leaseDurationSeconds": self.config.lease_duration.as_secs().max(1),
+            "acquireTime": acquire_time,
+            "renewTime": renew_time,
+            "leaseTransitions": transitions,
+        })
+    }
+
+    /// records the lease, and returns the time at which we first observed its current state
+    fn observe(&mut self, lease: ObservedLease) -> Instant {
+        match self.observed.as_ref() {
+            Some((previous, observed_at)) if *previous == lease => *observed_at,
+            _ => {
+                let now = Instant::now();
+                self.observed = Some((lease, now));
+                now
+            }
+        }
+    }
+}
+
+/// The spec of a lease that isn't held by anyone, which other replicas may acquire immediately. This is the same as
+/// what client-go does when `ReleaseOnCancel` is set.
+fn released_lease_spec(now: &str, transitions: u64) -> Value {
+    json!({
+        "holderIdentity": "",
+        "leaseDurationSeconds": 1,
+        "acquireTime": now,
+        "renewTime": now,
+        "leaseTransitions": transitions,
+    })
+}
+
+fn get_spec_str(lease: &Value, field: &str) -> Option<String> {
+    lease
+        .get("spec")
+        .and_then(|spec| spec.get(field))
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+}
+
+fn ignore_conflict(result: Result<(), ClientError>) -> Result<bool, ClientError> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(ref err) if err.is_http_status(409) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn released_lease_has_no_holder() {
+        let lease = json!({ "spec": released_lease_spec("2020-01-01T00:00:00.000000Z", 3) });
+        assert_eq!(None, get_spec_str(&lease, "holderIdentity"));
+        assert_eq!(Some(&json!(3)), lease.pointer("/spec/leaseTransitions"));
+    }
+}
@@ -1,16 +1,20 @@
 use crate::k8s_types::K8sType;
 use crate::resource::ObjectIdRef;
+use crate::runner::reconcile::SyncFailure;
 
 use prometheus::{
-    exponential_buckets, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
-    IntGaugeVec, Opts, Registry,
+    exponential_buckets, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
 };
 
 use std::fmt::{self, Debug};
+use std::time::{Duration, SystemTime};
 
 pub struct Metrics {
     registry: Registry,
     api_server_request_times: Histogram,
+    rate_limiter_wait_times: Histogram,
+    tls_info: IntGaugeVec,
     total_watch_events_received: IntCounter,
     sync_count_by_parent: IntCounterVec,
     sync_errors_by_parent: IntCounterVec,
@@ -18,6 +22,14 @@ pub struct Metrics {
     watcher_requests_by_type: IntCounterVec,
     watcher_errors_by_type: IntCounterVec,
     watch_events_by_type: IntCounterVec,
+    watch_events_by_event_type: IntCounterVec,
+    watch_connects_by_type: IntCounterVec,
+    watch_relists_by_type: IntCounterVec,
+    watch_last_event_by_type: GaugeVec,
+    reconcile_durations_by_type: HistogramVec,
+    reconcile_outcomes_by_type: IntCounterVec,
+    handler_panics_by_type: IntCounterVec,
+    work_queue_depth: IntGauge,
 }
 
 impl Debug for Metrics {
@@ -35,6 +47,12 @@ fn id_labels<'a, 'b>(id: &'a ObjectIdRef<'b>) -> [&'a str; 2] {
 
 const NAMESPACE_AND_NAME: &[&str] = &["namespace", "name"];
 const API_VERSION_AND_KIND: &[&str] = &["apiVersion", "kind"];
+const API_VERSION_KIND_AND_OUTCOME: &[&str] = &["apiVersion", "kind", "outcome"];
+const API_VERSION_KIND_AND_TYPE: &[&str] = &["apiVersion", "kind", "type"];
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
 
 impl Metrics {
     pub fn new() -> Metrics {
@@ -51,6 +69,25 @@ impl Metrics {
             .register(Box::new(api_server_request_times.clone()))
             .unwrap();
 
+        let rate_limiter_opts = HistogramOpts::new(
+            "rate_limiter_wait_time",
+            "Time that requests spent waiting on the client-side rate limiter before being sent",
+        )
+        .subsystem("client")
+        .buckets(exponential_buckets(0.005, 2.0, 12).unwrap());
+        let rate_limiter_wait_times = Histogram::with_opts(rate_limiter_opts).unwrap();
+        registry
+            .register(Box::new(rate_limiter_wait_times.clone()))
+            .unwrap();
+
+        let tls_info_opts = Opts::new(
+            "tls_info",
+            "Always 1, with a verify_certs label that's false if the client does not verify the api server's certificate",
+        )
+        .subsystem("client");
+        let tls_info = IntGaugeVec::new(tls_info_opts, &["verify_certs"]).unwrap();
+        registry.register(Box::new(tls_info.clone())).unwrap();
+
         let watch_events_opts = Opts::new("events_received", "total number of events processed by the operator, including from watches and initial seeds");
         let total_watch_events_received = IntCounter::with_opts(watch_events_opts).unwrap();
         registry
@@ -121,9 +158,107 @@ impl Metrics {
             .register(Box::new(watch_events_by_type.clone()))
             .unwrap();
 
+        let watch_event_type_opts = Opts::new(
+            "watch_events_by_event_type",
+            "number of watch events received by watchers, by the type of event, which is one of ADDED, MODIFIED, DELETED, BOOKMARK, or ERROR",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind")
+        .variable_label("type");
+        let watch_events_by_event_type =
+            IntCounterVec::new(watch_event_type_opts, API_VERSION_KIND_AND_TYPE).unwrap();
+        registry
+            .register(Box::new(watch_events_by_event_type.clone()))
+            .unwrap();
+
+        let watch_connect_opts = Opts::new(
+            "watch_connects",
+            "number of times that a watch was established, including each time it was re-established",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind");
+        let watch_connects_by_type =
+            IntCounterVec::new(watch_connect_opts, API_VERSION_AND_KIND).unwrap();
+        registry
+            .register(Box::new(watch_connects_by_type.clone()))
+            .unwrap();
+
+        let watch_relist_opts = Opts::new(
+            "watch_relists",
+            "number of times that the cache was re-listed because the resourceVersion of a watch expired (410 Gone)",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind");
+        let watch_relists_by_type =
+            IntCounterVec::new(watch_relist_opts, API_VERSION_AND_KIND).unwrap();
+        registry
+            .register(Box::new(watch_relists_by_type.clone()))
+            .unwrap();
+
+        let watch_last_event_opts = Opts::new(
+            "watch_last_event_timestamp_seconds",
+            "unix time of the last event, including bookmarks, that was received by the watchers of each type",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind");
+        let watch_last_event_by_type =
+            GaugeVec::new(watch_last_event_opts, API_VERSION_AND_KIND).unwrap();
+        registry
+            .register(Box::new(watch_last_event_by_type.clone()))
+            .unwrap();
+
+        let reconcile_duration_opts = HistogramOpts::new(
+            "reconcile_duration_seconds",
+            "Time taken to sync or finalize a parent, including invoking the handler and updating the children",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind")
+        .buckets(exponential_buckets(0.005, 2.0, 14).unwrap());
+        let reconcile_durations_by_type =
+            HistogramVec::new(reconcile_duration_opts, API_VERSION_AND_KIND).unwrap();
+        registry
+            .register(Box::new(reconcile_durations_by_type.clone()))
+            .unwrap();
+
+        let reconcile_outcome_opts = Opts::new(
+            "reconcile_outcomes",
+            "number of syncs and finalizes by outcome, which is one of success, retryable_error, or fatal_error",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind")
+        .variable_label("outcome");
+        let reconcile_outcomes_by_type =
+            IntCounterVec::new(reconcile_outcome_opts, API_VERSION_KIND_AND_OUTCOME).unwrap();
+        registry
+            .register(Box::new(reconcile_outcomes_by_type.clone()))
+            .unwrap();
+
+        let handler_panic_opts = Opts::new(
+            "handler_panics",
+            "number of times that the handler panicked during a sync or finalize",
+        )
+        .variable_label("apiVersion")
+        .variable_label("kind");
+        let handler_panics_by_type =
+            IntCounterVec::new(handler_panic_opts, API_VERSION_AND_KIND).unwrap();
+        registry
+            .register(Box::new(handler_panics_by_type.clone()))
+            .unwrap();
+
+        let work_queue_depth_opts = Opts::new(
+            "work_queue_depth",
+            "number of parents that are waiting to be synced",
+        );
+        let work_queue_depth = IntGauge::with_opts(work_queue_depth_opts).unwrap();
+        registry
+            .register(Box::new(work_queue_depth.clone()))
+            .unwrap();
+
         Metrics {
             registry,
             api_server_request_times,
+            rate_limiter_wait_times,
+            tls_info,
             total_watch_events_received,
             sync_count_by_parent,
             sync_errors_by_parent,
@@ -131,12 +266,27 @@ impl Metrics {
             watcher_requests_by_type,
             watcher_errors_by_type,
             watch_events_by_type,
+            watch_events_by_event_type,
+            watch_connects_by_type,
+            watch_relists_by_type,
+            watch_last_event_by_type,
+            reconcile_durations_by_type,
+            reconcile_outcomes_by_type,
+            handler_panics_by_type,
+            work_queue_depth,
         }
     }
 
+    /// The registry that all of the operator's metrics are registered with, which users may also add their own to
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
     pub fn client_metrics(&self) -> ClientMetrics {
         ClientMetrics {
             api_server_request_times: self.api_server_request_times.clone(),
+            rate_limiter_wait_times: self.rate_limiter_wait_times.clone(),
+            tls_info: self.tls_info.clone(),
         }
     }
 
@@ -146,7 +296,12 @@ impl Metrics {
             watcher_requests: self.watcher_requests_by_type.with_label_values(labels),
             watcher_errors: self.watcher_errors_by_type.with_label_values(labels),
             watch_events: self.watch_events_by_type.with_label_values(labels),
+            watch_events_by_event_type: self.watch_events_by_event_type.clone(),
+            watch_connects: self.watch_connects_by_type.with_label_values(labels),
+            watch_relists: self.watch_relists_by_type.with_label_values(labels),
+            last_event: self.watch_last_event_by_type.with_label_values(labels),
             resource_count: self.resources_by_type.with_label_values(labels),
+            labels: [k8s_type.api_version, k8s_type.kind],
         }
     }
 
@@ -171,17 +326,52 @@ impl Metrics {
             .inc();
     }
 
+    /// Records the duration and outcome of a sync or finalize of a parent with the given type
+    pub fn reconcile_finished(
+        &self,
+        parent_type: &K8sType,
+        elapsed: Duration,
+        result: &Result<Option<Duration>, SyncFailure>,
+    ) {
+        let outcome = match result {
+            Ok(_) => "success",
+            Err(SyncFailure::Retry(_)) => "retryable_error",
+            Err(SyncFailure::NoRetry) => "fatal_error",
+        };
+        self.reconcile_durations_by_type
+            .with_label_values(&[parent_type.api_version, parent_type.kind])
+            .observe(seconds(elapsed));
+        self.reconcile_outcomes_by_type
+            .with_label_values(&[parent_type.api_version, parent_type.kind, outcome])
+            .inc();
+    }
+
+    pub fn handler_panicked(&self, parent_type: &K8sType) {
+        self.handler_panics_by_type
+            .with_label_values(&[parent_type.api_version, parent_type.kind])
+            .inc();
+    }
+
+    pub fn set_work_queue_depth(&self, depth: usize) {
+        self.work_queue_depth.set(depth as i64);
+    }
+
+    /// Encodes the operator's metrics, along with any that were registered with the prometheus default registry
     pub fn encode_as_text(&self) -> Result<Vec<u8>, prometheus::Error> {
         use prometheus::Encoder;
         let encoder = prometheus::TextEncoder::new();
         let mut buffer = Vec::with_capacity(4096);
-        encoder.encode(self.registry.gather().as_slice(), &mut buffer)?;
+        let mut families = self.registry.gather();
+        families.extend(prometheus::gather());
+        encoder.encode(families.as_slice(), &mut buffer)?;
         Ok(buffer)
     }
 }
 
 pub struct ClientMetrics {
     api_server_request_times: Histogram,
+    rate_limiter_wait_times: Histogram,
+    tls_info: IntGaugeVec,
 }
 
 impl Debug for ClientMetrics {
@@ -193,13 +383,29 @@ impl ClientMetrics {
     pub fn request_started(&self) -> prometheus::HistogramTimer {
         self.api_server_request_times.start_timer()
     }
+
+    pub fn rate_limiter_waited(&self, wait: Duration) {
+        self.rate_limiter_wait_times.observe(seconds(wait));
+    }
+
+    /// Records whether the client verifies the api server's certificate, so that insecure operators can be found
+    pub fn tls_configured(&self, verify_certs: bool) {
+        let label = if verify_certs { "true" } else { "false" };
+        self.tls_info.with_label_values(&[label]).set(1);
+    }
 }
 
+#[derive(Clone)]
 pub struct WatcherMetrics {
     watcher_requests: IntCounter,
     watcher_errors: IntCounter,
     watch_events: IntCounter,
+    watch_events_by_event_type: IntCounterVec,
+    watch_connects: IntCounter,
+    watch_relists: IntCounter,
+    last_event: Gauge,
     resource_count: IntGauge,
+    labels: [&'static str; 2],
 }
 impl Debug for WatcherMetrics {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -208,16 +414,37 @@ impl Debug for WatcherMetrics {
 }
 
 impl WatcherMetrics {
-    pub fn set_resource_count(&self, count: usize) {
-        self.resource_count.set(count as i64);
+    /// Updates the resource count gauge by the difference between the previous and current counts. The same
+    /// metrics are shared by the watches of every namespace, so each one only adjusts it by its own change.
+    pub fn update_resource_count(&self, previous: usize, current: usize) {
+        self.resource_count.add(current as i64 - previous as i64);
     }
 
     pub fn request_started(&self) {
         self.watcher_requests.inc();
     }
 
-    pub fn event_received(&self) {
+    /// Records a watch event of the given type, such as `ADDED` or `BOOKMARK`, and the time that it was received
+    pub fn event_received(&self, event_type: &str) {
         self.watch_events.inc();
+        let [api_version, kind] = self.labels;
+        self.watch_events_by_event_type
+            .with_label_values(&[api_version, kind, event_type])
+            .inc();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.last_event.set(seconds(now));
+    }
+
+    /// Records that a watch was established, which happens again each time the watch is resumed
+    pub fn watch_connected(&self) {
+        self.watch_connects.inc();
+    }
+
+    /// Records that the cache will be re-listed because the watch's `resourceVersion` expired
+    pub fn relisted(&self) {
+        self.watch_relists.inc();
     }
 
     pub fn error(&self) {
@@ -233,4 +460,70 @@ mod test {
     fn metrics_are_created_successfully() {
         let _metrics = Metrics::new();
     }
+
+    #[test]
+    fn reconcile_metrics_are_exported() {
+        let metrics = Metrics::new();
+        let parent_type = crate::k8s_types::apps::v1::Deployment;
+        let elapsed = Duration::from_millis(20);
+        metrics.reconcile_finished(parent_type, elapsed, &Ok(None));
+        metrics.reconcile_finished(parent_type, elapsed, &Err(SyncFailure::Retry(None)));
+        metrics.reconcile_finished(parent_type, elapsed, &Err(SyncFailure::NoRetry));
+        metrics.set_work_queue_depth(3);
+
+        let text = String::from_utf8(metrics.encode_as_text().unwrap()).unwrap();
+        assert!(text.contains(
+            r#"reconcile_duration_seconds_count{apiVersion="apps/v1",kind="Deployment"} 3"#
+        ));
+        for outcome in &["success", "retryable_error", "fatal_error"] {
+            let expected = format!(
+                r#"reconcile_outcomes{{apiVersion="apps/v1",kind="Deployment",outcome="{}"}} 1"#,
+                outcome
+            );
+            assert!(text.contains(expected.as_str()), "missing: {}", expected);
+        }
+        assert!(text.contains("work_queue_depth 3"));
+    }
+
+    #[test]
+    fn watch_metrics_are_exported() {
+        let metrics = Metrics::new();
+        let watcher = metrics.watcher_metrics(crate::k8s_types::core::v1::Pod);
+        watcher.watch_connected();
+        watcher.watch_connected();
+        watcher.event_received("ADDED");
+        watcher.event_received("BOOKMARK");
+        watcher.event_received("BOOKMARK");
+        watcher.relisted();
+
+        let text = String::from_utf8(metrics.encode_as_text().unwrap()).unwrap();
+        for expected in &[
+            r#"watch_connects{apiVersion="v1",kind="Pod"} 2"#,
+            r#"watch_events{apiVersion="v1",kind="Pod"} 3"#,
+            r#"watch_events_by_event_type{apiVersion="v1",kind="Pod",type="ADDED"} 1"#,
+            r#"watch_events_by_event_type{apiVersion="v1",kind="Pod",type="BOOKMARK"} 2"#,
+            r#"watch_relists{apiVersion="v1",kind="Pod"} 1"#,
+        ] {
+            assert!(text.contains(expected), "missing: {}", expected);
+        }
+        let last_event = metrics
+            .watch_last_event_by_type
+            .with_label_values(&["v1", "Pod"])
+            .get();
+        assert!(last_event > 0.0);
+    }
+
+    #[test]
+    fn custom_metrics_registered_with_the_registry_are_exported() {
+        let metrics = Metrics::new();
+        let custom = IntCounter::new("databases_provisioned", "custom metric").unwrap();
+        metrics
+            .registry()
+            .register(Box::new(custom.clone()))
+            .unwrap();
+        custom.inc();
+
+        let text = String::from_utf8(metrics.encode_as_text().unwrap()).unwrap();
+        assert!(text.contains("databases_provisioned 1"));
+    }
 }
@@ -1,57 +1,238 @@
-mod client;
+mod circuit_breaker;
+pub(crate) mod client;
+mod events;
 mod informer;
-mod metrics;
+mod leader;
+pub(crate) mod metrics;
+mod parent_status;
+mod preflight;
 pub(crate) mod reconcile;
 pub(crate) mod resource_map;
+mod resync;
 mod server;
+mod shutdown;
+mod webhook;
+mod work_queue;
 
 #[cfg(feature = "testkit")]
 pub mod testkit;
 
-#[cfg(feature = "testkit")]
-use crate::resource::ObjectIdRef;
-
-use crate::config::{ClientConfig, OperatorConfig, UpdateStrategy};
-use crate::handler::{Handler, SyncRequest};
-use crate::k8s_types::K8sType;
-use crate::resource::{K8sResource, K8sTypeRef, ObjectId};
+use crate::config::{
+    ClientConfig, DeletePolicy, InvalidFieldSelector, OperatorConfig, ParentMapper,
+    ReconcileBackoff, UpdateStrategy, SERVER_MANAGED_PATHS,
+};
+use crate::handler::cache::{CachedType, ResourceCache};
+use crate::handler::{CancellationToken, Handler, SyncRequest};
+use crate::k8s_types::{K8sType, Scope};
+use crate::resource::{K8sResource, K8sTypeRef, ObjectId, ObjectIdRef};
+use crate::runner::circuit_breaker::CircuitBreaker;
+use crate::runner::events::{EventKind, EventRecorder, EventTarget};
 use crate::runner::informer::{
-    EventType, LabelToIdIndex, ResourceMessage, ResourceMonitor, UidToIdIndex,
+    ChildTracking, EventType, ParentUidIndex, ResourceMessage, ResourceMonitor, UidToIdIndex,
+    WatchStatus, WatchStatuses,
 };
-use crate::runner::reconcile::SyncHandler;
+use crate::runner::leader::LeaderElector;
+use crate::runner::parent_status::ParentStatuses;
+use crate::runner::reconcile::{SyncFailure, SyncHandler};
+use crate::runner::work_queue::WorkQueue;
 use anyhow::Error;
 use backoff::{backoff::Backoff, ExponentialBackoff};
 use client::Client;
+use futures_util::future::Either;
 use metrics::Metrics;
+use prometheus::Registry;
 
 use tokio::runtime::{self, Runtime};
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::Notify;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt::{self, Display};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+pub use self::client::{ApiError, StatusCause, StatusDetails};
+pub use self::leader::LeadershipLostError;
+pub use self::parent_status::{ParentStatus, ReconcileOutcome};
 
-/// A handle to a potentially running operator, which allows for shutting it down
+/// A handle to a potentially running operator, which allows for shutting it down. Dropping the handle also shuts down
+/// the operator, but without waiting for it to stop.
 pub struct OperatorHandle {
     running: Arc<AtomicBool>,
+    shutdown: Arc<Notify>,
+    parent_type: &'static K8sType,
+    shared: Arc<SharedState>,
+    /// the thread that the operator's runtime is running on, which is only set by `run_operator_with_handle`
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The parts of the operator's state that are made available to the `OperatorHandle` once the operator has started
+#[derive(Debug, Default)]
+struct SharedState {
+    watch_statuses: Mutex<WatchStatuses>,
+    sender: Mutex<Option<Sender<ResourceMessage>>>,
+    parent_statuses: Mutex<ParentStatuses>,
 }
 
 impl std::ops::Drop for OperatorHandle {
     fn drop(&mut self) {
         self.running.store(false, Ordering::Relaxed);
+        self.shutdown.notify();
     }
 }
 
 impl OperatorHandle {
+    fn new(
+        running: Arc<AtomicBool>,
+        shutdown: Arc<Notify>,
+        parent_type: &'static K8sType,
+    ) -> OperatorHandle {
+        OperatorHandle {
+            running,
+            shutdown,
+            parent_type,
+            shared: Arc::new(SharedState::default()),
+            thread: None,
+        }
+    }
+
+    /// Stops the operator from starting any new syncs. Syncs that are already in progress are given up to the
+    /// `shutdown_grace_period` to finish.
     pub fn shutdown_now(self) {
         self.running.store(false, Ordering::Relaxed);
+        self.shutdown.notify();
+    }
+
+    /// Stops the operator the same as `shutdown_now`, and then blocks until it has stopped. If the operator was
+    /// started with `run_operator_with_handle`, then this waits for in-progress syncs to finish or for the
+    /// `shutdown_grace_period` to elapse, and for the runtime to shut down. Otherwise, the operator is running on
+    /// a runtime that the caller owns, so this returns immediately.
+    pub fn shutdown(mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        self.shutdown.notify();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Operator thread panicked");
+            }
+        }
     }
 
     pub fn is_active(&self) -> bool {
         self.running.load(Ordering::Relaxed)
     }
+
+    /// Returns true if the operator is running and the initial list of every watched type has completed, which
+    /// is the same as what's required by the readiness endpoint, except for leadership
+    pub fn is_ready(&self) -> bool {
+        let statuses = self.shared.watch_statuses.lock().unwrap().clone();
+        let statuses = statuses.lock().unwrap();
+        self.is_active() && !statuses.is_empty() && statuses.iter().all(|s| s.is_synced())
+    }
+
+    /// Blocks until the operator `is_ready`, or the timeout elapses. Returns whether the operator is ready.
+    pub fn await_ready(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while !self.is_ready() {
+            if start.elapsed() >= timeout || !self.is_active() {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    /// Queues the parent with the given namespace and name to be synced, the same as if it had been changed. This
+    /// is meant for tests that want to force a sync without changing anything. Returns false if the request could
+    /// not be sent because the operator hasn't started yet or has already stopped. Requests for parents that don't
+    /// exist are ignored.
+    pub fn trigger_reconcile(&self, namespace: Option<&str>, name: &str) -> bool {
+        self.send(ResourceMessage {
+            event_type: EventType::ReconcileRequested,
+            resource_type: self.parent_type,
+            resource_id: ObjectId::new(namespace.unwrap_or("").to_owned(), name.to_owned()),
+            index_key: None,
+        })
+    }
+
+    /// Queues every parent to be synced, the same as if they had all been changed. This is useful after something
+    /// that all of the parents depend on has changed. Returns false if the request could not be sent because the
+    /// operator hasn't started yet or has already stopped.
+    pub fn trigger_reconcile_all(&self) -> bool {
+        self.send(ResourceMessage {
+            event_type: EventType::ReconcileAllRequested,
+            resource_type: self.parent_type,
+            resource_id: ObjectId::new(String::new(), String::new()),
+            index_key: None,
+        })
+    }
+
+    /// Returns what the operator currently knows about each parent that it has synced, sorted by namespace and name.
+    /// This includes when each parent was last synced, the outcome, and when it will be retried, which is useful for
+    /// seeing which parents are failing. The same information is served as json from `GET /status/objects` when the
+    /// admin endpoints are enabled. Returns an empty list if the operator hasn't started yet.
+    pub fn parent_statuses(&self) -> Vec<ParentStatus> {
+        let statuses = self.shared.parent_statuses.lock().unwrap().clone();
+        parent_status::sorted(&statuses)
+    }
+
+    /// Starts watching and caching the given type, for operators that only discover which types they depend on at
+    /// runtime. The type is watched in the same namespaces as the parent, and is available to the handler from
+    /// `SyncRequest::cache()`. Syncs wait for its initial list to complete, and so does `is_ready`. Whenever a resource of this type is created, updated, or deleted,
+    /// the `mapper` is called with it, and each of the parents whose ids it returns is synced. Returns false if the
+    /// request could not be sent because the operator hasn't started yet or has already stopped. Requests for types
+    /// that are already cached, including the parent and child types, are ignored.
+    ///
+    /// ```no_run
+    /// use roperator::prelude::*;
+    /// use roperator::k8s_types::core::v1::Secret;
+    /// use roperator::resource::ObjectId;
+    /// # static PARENT_TYPE: &K8sType = &K8sType {
+    /// #     api_version: "example.com/v1",
+    /// #     kind: "Database",
+    /// #     plural_kind: "databases",
+    /// #     scope: Scope::Namespaced,
+    /// # };
+    /// # let handler = |_: &SyncRequest| Ok(SyncResponse::new(roperator::serde_json::Value::Null));
+    /// let handle = roperator::runner::run_operator_with_handle(
+    ///     OperatorConfig::new("database-operator", PARENT_TYPE),
+    ///     handler,
+    /// )
+    /// .unwrap();
+    /// // sync the database that's named by the `example.com/database` annotation of each secret
+    /// handle.watch_additional(Secret, |secret: &K8sResource| {
+    ///     let id = secret.get_object_id();
+    ///     secret
+    ///         .pointer("/metadata/annotations/example.com~1database")
+    ///         .and_then(|name| name.as_str())
+    ///         .map(|name| ObjectId::new(id.namespace().unwrap_or("").to_owned(), name.to_owned()))
+    ///         .into_iter()
+    ///         .collect()
+    /// });
+    /// ```
+    pub fn watch_additional<F>(&self, k8s_type: &'static K8sType, mapper: F) -> bool
+    where
+        F: Fn(&K8sResource) -> Vec<ObjectId> + Send + Sync + 'static,
+    {
+        self.send(ResourceMessage {
+            event_type: EventType::WatchRequested(ParentMapper::new(mapper)),
+            resource_type: k8s_type,
+            resource_id: ObjectId::new(String::new(), String::new()),
+            index_key: None,
+        })
+    }
+
+    fn send(&self, message: ResourceMessage) -> bool {
+        let mut sender = match self.shared.sender.lock().unwrap().clone() {
+            Some(sender) => sender,
+            None => return false,
+        };
+        sender.try_send(message).is_ok()
+    }
 }
 
 #[derive(Debug)]
@@ -63,47 +244,127 @@ impl Display for UnexpectedShutdownError {
 }
 impl std::error::Error for UnexpectedShutdownError {}
 
-/// Starts the operator and blocks the current thread indefinitely until the operator shuts down due to an error.
-pub fn run_operator(config: OperatorConfig, handler: impl Handler) -> Error {
-    let client_config = {
-        let user_agent = config.operator_name.as_str();
-        let result = ClientConfig::from_service_account(user_agent).or_else(|_| {
-            log::debug!("Failed to load ClientConfig from service account, so trying to load from kubeconfig");
-            ClientConfig::from_kubeconfig(user_agent)
-        });
-        match result {
-            Ok(conf) => conf,
-            Err(err) => return err.into(),
-        }
-    };
+/// Starts the operator and blocks the current thread until the operator shuts down. Returns `Ok` if the process
+/// received a `SIGTERM` or `SIGINT`, in which case in-progress syncs are given up to the `shutdown_grace_period`
+/// to finish, and the leader election lease is released if it was held. Returns an error if the operator shut down
+/// for any other reason.
+pub fn run_operator(config: OperatorConfig, handler: impl Handler) -> Result<(), Error> {
+    let client_config = load_client_config(&config)?;
     run_operator_with_client_config(config, client_config, handler)
 }
 
-/// Starts the operator and blocks the current thread indefinitely until the operator shuts down due to an error.
+/// Starts the operator and blocks the current thread until the operator shuts down, the same as `run_operator`.
+/// Before the operator starts, `register_metrics` is called with the prometheus `Registry` that roperator uses for
+/// its own metrics, so that any metrics registered there are served from the same `/metrics` endpoint.
+///
+/// ```no_run
+/// use roperator::prelude::*;
+/// use roperator::metrics::prometheus::{IntCounter, Opts};
+/// use roperator::runner::run_operator_with_metrics;
+///
+/// static PARENT_TYPE: &K8sType = &K8sType {
+///     api_version: "example.com/v1",
+///     kind: "Database",
+///     plural_kind: "databases",
+///     scope: Scope::Namespaced,
+/// };
+///
+/// let provisioned = IntCounter::with_opts(Opts::new(
+///     "databases_provisioned",
+///     "number of databases that have been provisioned",
+/// ))
+/// .unwrap();
+/// let counter = provisioned.clone();
+/// let handler = move |request: &SyncRequest| {
+///     counter.inc();
+///     Ok(SyncResponse::new(serde_json::Value::Null))
+/// };
+/// let config = OperatorConfig::new("database-operator", PARENT_TYPE);
+/// run_operator_with_metrics(config, handler, |registry| {
+///     registry.register(Box::new(provisioned))
+/// })
+/// .expect("operator failed");
+/// ```
+pub fn run_operator_with_metrics(
+    config: OperatorConfig,
+    handler: impl Handler,
+    register_metrics: impl FnOnce(&Registry) -> Result<(), prometheus::Error>,
+) -> Result<(), Error> {
+    let client_config = load_client_config(&config)?;
+    let metrics = Metrics::new();
+    register_metrics(metrics.registry())?;
+    run_with_metrics(config, client_config, handler, metrics)
+}
+
+fn load_client_config(config: &OperatorConfig) -> Result<ClientConfig, Error> {
+    let user_agent = config.operator_name.as_str();
+    let result = ClientConfig::from_service_account(user_agent).or_else(|_| {
+        log::debug!(
+            "Failed to load ClientConfig from service account, so trying to load from kubeconfig"
+        );
+        ClientConfig::from_kubeconfig(user_agent)
+    });
+    result.map_err(Error::from)
+}
+
+/// Starts the operator and blocks the current thread until the operator shuts down, the same as `run_operator`.
 pub fn run_operator_with_client_config(
     config: OperatorConfig,
     client_config: ClientConfig,
     handler: impl Handler,
-) -> Error {
+) -> Result<(), Error> {
+    run_with_metrics(config, client_config, handler, Metrics::new())
+}
+
+fn run_with_metrics(
+    config: OperatorConfig,
+    client_config: ClientConfig,
+    handler: impl Handler,
+    metrics: Metrics,
+) -> Result<(), Error> {
+    config.validate()?;
     let handler = Arc::new(handler);
-    let metrics = Metrics::new();
-    let client = match Client::new(client_config, metrics.client_metrics()) {
-        Ok(c) => c,
-        Err(err) => return err.into(),
-    };
-    let mut runtime = match Runtime::new() {
-        Ok(rt) => rt,
-        Err(err) => return err.into(),
-    };
+    let client = Client::new(client_config, metrics.client_metrics())?;
+    let child_clients = create_child_clients(&config, &metrics)?;
+    let mut runtime = Runtime::new()?;
+    runtime.block_on(check_field_selector(&client, &config))?;
+    runtime.block_on(preflight::check_rbac(&client, &child_clients, &config))?;
     let running = Arc::new(AtomicBool::new(true));
+    let shutdown = Arc::new(Notify::new());
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+    let requested = shutdown_requested.clone();
+    let signal_future = shutdown::shutdown_on_signal(running.clone(), shutdown.clone());
+    runtime.spawn(async move {
+        signal_future.await;
+        requested.store(true, Ordering::Relaxed);
+    });
     let executor = runtime.handle().clone();
-    runtime.block_on(async move {
-        run_with_client(executor, metrics, running, config, client, handler).await;
+    let result = runtime.block_on(async move {
+        run_with_client(
+            executor,
+            metrics,
+            running,
+            shutdown,
+            Arc::new(SharedState::default()),
+            config,
+            client,
+            child_clients,
+            handler,
+        )
+        .await
     });
+    if shutdown_requested.load(Ordering::Relaxed) && result.is_ok() {
+        log::info!("Operator stopped, shutting down runtime");
+        // any syncs that are still running have already exceeded the grace period, so we don't wait for them
+        runtime.shutdown_timeout(Duration::from_secs(1));
+        return Ok(());
+    }
     log::warn!("Operator stopped, shutting down runtime");
     runtime.shutdown_timeout(Duration::from_secs(30));
-    // return an error here, since the operator will never exit under normal circumstances
-    Error::new(UnexpectedShutdownError)
+    match result {
+        Err(leadership_lost) => Err(Error::new(leadership_lost)),
+        Ok(()) => Err(Error::new(UnexpectedShutdownError)),
+    }
 }
 
 /// Starts the operator asynchronously using the provided runtime. This function will return immediately with a
@@ -115,38 +376,242 @@ pub fn start_operator_with_runtime(
     client_config: ClientConfig,
     handler: impl Handler,
 ) -> Result<OperatorHandle, Error> {
+    let (handle, _) = spawn_operator(runtime.handle().clone(), config, client_config, handler)?;
+    Ok(handle)
+}
+
+/// Starts the operator on a new thread with its own runtime, and returns immediately with a handle that can be used
+/// to wait for the operator to become ready, trigger syncs, and shut it down. Unlike `run_operator`, the operator
+/// doesn't listen for `SIGTERM` or `SIGINT`, so it's up to the caller to shut it down. This is useful for running
+/// the operator inside of a larger program, or for black-box tests against a real api server.
+///
+/// ```no_run
+/// use roperator::prelude::*;
+/// use roperator::runner::run_operator_with_handle;
+/// use std::time::Duration;
+///
+/// static PARENT_TYPE: &K8sType = &K8sType {
+///     api_version: "example.com/v1",
+///     kind: "Database",
+///     plural_kind: "databases",
+///     scope: Scope::Namespaced,
+/// };
+///
+/// let handler = |_: &SyncRequest| Ok(SyncResponse::new(serde_json::Value::Null));
+/// let config = OperatorConfig::new("database-operator", PARENT_TYPE);
+/// let handle = run_operator_with_handle(config, handler).expect("failed to start operator");
+/// assert!(handle.await_ready(Duration::from_secs(30)));
+/// handle.trigger_reconcile(Some("default"), "my-database");
+/// handle.shutdown();
+/// ```
+pub fn run_operator_with_handle(
+    config: OperatorConfig,
+    handler: impl Handler,
+) -> Result<OperatorHandle, Error> {
+    let client_config = load_client_config(&config)?;
+    let mut runtime = Runtime::new()?;
+    let (mut handle, task) =
+        spawn_operator(runtime.handle().clone(), config, client_config, handler)?;
+    let thread = std::thread::Builder::new()
+        .name("roperator".to_owned())
+        .spawn(move || {
+            let _ = runtime.block_on(task);
+            log::info!("Operator stopped, shutting down runtime");
+            // any syncs that are still running have already exceeded the grace period, so we don't wait for them
+            runtime.shutdown_timeout(Duration::from_secs(1));
+        })?;
+    handle.thread = Some(thread);
+    Ok(handle)
+}
+
+/// Spawns the operator on the executor, and returns the handle along with the task that completes once the
+/// operator has stopped
+fn spawn_operator(
+    executor: runtime::Handle,
+    config: OperatorConfig,
+    client_config: ClientConfig,
+    handler: impl Handler,
+) -> Result<(OperatorHandle, tokio::task::JoinHandle<()>), Error> {
+    config.validate()?;
     let handler = Arc::new(handler);
     let metrics = Metrics::new();
     let client = Client::new(client_config, metrics.client_metrics())?;
+    let child_clients = create_child_clients(&config, &metrics)?;
     let running = Arc::new(AtomicBool::new(true));
-    let handle = OperatorHandle {
-        running: running.clone(),
-    };
-    let executor = runtime.handle().clone();
-    runtime.spawn(async move {
-        run_with_client(executor, metrics, running.clone(), config, client, handler).await;
+    let shutdown = Arc::new(Notify::new());
+    let handle = OperatorHandle::new(running.clone(), shutdown.clone(), config.parent);
+    let shared = handle.shared.clone();
+    let task = executor.clone().spawn(async move {
+        if let Err(err) = check_field_selector(&client, &config).await {
+            log::error!("Not starting operator: {}", err);
+            running.store(false, Ordering::Relaxed);
+            return;
+        }
+        if let Err(err) = preflight::check_rbac(&client, &child_clients, &config).await {
+            log::error!("Not starting operator: {}", err);
+            running.store(false, Ordering::Relaxed);
+            return;
+        }
+        let result = run_with_client(
+            executor,
+            metrics,
+            running.clone(),
+            shutdown,
+            shared,
+            config,
+            client,
+            child_clients,
+            handler,
+        )
+        .await;
+        if let Err(err) = result {
+            log::error!("Operator stopped: {}", err);
+        }
+        running.store(false, Ordering::Relaxed);
     });
-    Ok(handle)
+    Ok((handle, task))
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct ChildRuntimeConfig {
     update_strategy: UpdateStrategy,
     child_type: &'static K8sType,
+    field_manager: String,
+    force_apply: bool,
+    ignored_paths: Vec<String>,
+    delete_policy: DeletePolicy,
+    optimistic_concurrency: bool,
+    metadata_only: bool,
+    adopt_existing: bool,
+    track_last_applied: bool,
+    /// false if children can't have an owner reference to the parent, either because they're in another cluster, or
+    /// because they're cluster scoped and the parent is namespaced. The garbage collector won't delete these children,
+    /// so they're deleted when the parent is finalized instead.
+    has_owner_reference: bool,
 }
 
 #[derive(Debug)]
 pub(crate) struct RuntimeConfig {
     pub metrics: Metrics,
     pub child_types: HashMap<&'static K8sType, ChildRuntimeConfig>,
+    /// the clients for the child types that are in another cluster, which are used instead of the operator's client
+    pub child_clients: HashMap<&'static K8sType, Client>,
     pub parent_type: &'static K8sType,
     pub correlation_label_name: String,
+    pub track_via_owner_references: bool,
     pub controller_label_name: String,
+    pub managed_labels: HashMap<String, String>,
+    pub managed_annotations: HashMap<String, String>,
+    pub propagated_labels: Vec<String>,
+    pub propagated_annotations: Vec<String>,
+    pub pause_annotation: Option<String>,
     pub operator_name: String,
-    pub max_error_backoff: Duration,
+    pub finalizer_name: String,
+    pub status_subresource: bool,
+    pub reconcile_backoff: ReconcileBackoff,
+    pub max_concurrent_reconciles: Option<usize>,
+    pub handler_timeout: Option<Duration>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub child_event_rate_limit: Option<Duration>,
+    pub reconcile_debounce: Duration,
+    pub shutdown_grace_period: Duration,
+    pub events: Option<EventRecorder>,
 }
 
 impl RuntimeConfig {
+    pub(crate) fn new(
+        config: &OperatorConfig,
+        metrics: Metrics,
+        events: Option<EventRecorder>,
+    ) -> RuntimeConfig {
+        let child_types = config
+            .child_types
+            .iter()
+            .map(|declaration| {
+                let child_conf = &declaration.config;
+                let runtime_conf = ChildRuntimeConfig {
+                    child_type: declaration.k8s_type,
+                    update_strategy: child_conf.update_strategy,
+                    field_manager: child_conf
+                        .field_manager
+                        .clone()
+                        .unwrap_or_else(|| config.operator_name.clone()),
+                    force_apply: child_conf.force_apply,
+                    delete_policy: child_conf.delete_policy,
+                    optimistic_concurrency: child_conf.optimistic_concurrency,
+                    metadata_only: child_conf.metadata_only,
+                    adopt_existing: child_conf.adopt_existing,
+                    track_last_applied: child_conf.track_last_applied,
+                    has_owner_reference: child_conf.client.is_none()
+                        && (declaration.k8s_type.is_namespaced() || !config.parent.is_namespaced()),
+                    ignored_paths: SERVER_MANAGED_PATHS
+                        .iter()
+                        .map(|path| (*path).to_owned())
+                        .chain(child_conf.ignored_paths.iter().cloned())
+                        .collect(),
+                };
+                (declaration.k8s_type, runtime_conf)
+            })
+            .collect();
+        RuntimeConfig {
+            metrics,
+            child_types,
+            child_clients: HashMap::new(),
+            parent_type: config.parent,
+            correlation_label_name: config.tracking_label_name.clone(),
+            track_via_owner_references: config.track_via_owner_references,
+            controller_label_name: config.ownership_label_name.clone(),
+            managed_labels: config.managed_labels.clone(),
+            managed_annotations: config.managed_annotations.clone(),
+            propagated_labels: config.propagated_labels.clone(),
+            propagated_annotations: config.propagated_annotations.clone(),
+            pause_annotation: config.pause_annotation.clone(),
+            operator_name: config.operator_name.clone(),
+            finalizer_name: config.finalizer_name.clone(),
+            status_subresource: config.status_subresource,
+            reconcile_backoff: config.reconcile_backoff.clone(),
+            max_concurrent_reconciles: config.max_concurrent_reconciles,
+            handler_timeout: config.handler_timeout,
+            circuit_breaker: config.circuit_breaker.clone().map(CircuitBreaker::new),
+            child_event_rate_limit: config.child_event_rate_limit,
+            reconcile_debounce: config.reconcile_debounce,
+            shutdown_grace_period: config.shutdown_grace_period,
+            events,
+        }
+    }
+
+    /// Returns how children are associated with their parent
+    pub(crate) fn child_tracking(&self) -> ChildTracking {
+        if self.track_via_owner_references {
+            ChildTracking::OwnerReference(self.parent_type)
+        } else {
+            ChildTracking::Label(self.correlation_label_name.clone())
+        }
+    }
+
+    /// Returns true if the parent has the `pause_annotation` set to `"true"`
+    pub(crate) fn is_paused(&self, parent: &K8sResource) -> bool {
+        let annotation = match self.pause_annotation.as_ref() {
+            Some(annotation) => annotation,
+            None => return false,
+        };
+        parent
+            .as_ref()
+            .pointer("/metadata/annotations")
+            .and_then(|annotations| annotations.get(annotation.as_str()))
+            .and_then(serde_json::Value::as_str)
+            == Some("true")
+    }
+
+    /// Returns the client for children of the given type, which is the `default` unless they're in another cluster
+    pub(crate) fn client_for<'a>(
+        &'a self,
+        child_type: &K8sType,
+        default: &'a Client,
+    ) -> &'a Client {
+        self.child_clients.get(child_type).unwrap_or(default)
+    }
+
     pub(crate) fn type_for(&self, type_ref: &K8sTypeRef<'_>) -> Option<&'static K8sType> {
         self.child_types
             .values()
@@ -161,33 +626,184 @@ impl RuntimeConfig {
         self.type_for(type_ref)
             .and_then(|child_type| self.child_types.get(child_type))
     }
+
+    /// Records an event for the parent, if events are enabled
+    pub(crate) fn record_event(
+        &self,
+        target: &EventTarget,
+        kind: EventKind,
+        reason: &'static str,
+        action: &'static str,
+        note: String,
+    ) {
+        if let Some(recorder) = self.events.as_ref() {
+            recorder.record(target, kind, reason, action, note);
+        }
+    }
+}
+
+/// Checks the `field_selector` against the api server, so that a selector on a field that isn't supported for the
+/// parent type is reported on startup, rather than as repeated errors from the watch. Other errors are only logged,
+/// since they'll be retried by the watch anyway.
+pub(crate) async fn check_field_selector(
+    client: &Client,
+    config: &OperatorConfig,
+) -> Result<(), InvalidFieldSelector> {
+    let selector = match config.field_selector.as_ref() {
+        Some(selector) => selector,
+        None => return Ok(()),
+    };
+    let namespace = config
+        .namespaces
+        .as_ref()
+        .and_then(|namespaces| namespaces.first())
+        .map(String::as_str);
+    let label_selector = config.label_selector.as_deref();
+    let result = client
+        .check_list_selectors(config.parent, namespace, label_selector, Some(selector))
+        .await;
+    match result {
+        Ok(None) => Ok(()),
+        Ok(Some(api_error)) => Err(InvalidFieldSelector {
+            selector: selector.clone(),
+            message: api_error.message,
+        }),
+        Err(err) => {
+            log::warn!(
+                "Unable to check field selector: '{}' with the api server: {}",
+                selector,
+                err
+            );
+            Ok(())
+        }
+    }
 }
 
+/// Creates a client for each child type that's in another cluster, which is used to both watch and update them
+pub(crate) fn create_child_clients(
+    config: &OperatorConfig,
+    metrics: &Metrics,
+) -> Result<HashMap<&'static K8sType, Client>, io::Error> {
+    let mut child_clients = HashMap::new();
+    for declaration in config.child_types.iter() {
+        if let Some(client_config) = declaration.config.client.as_ref() {
+            let client = Client::new(client_config.clone(), metrics.client_metrics())?;
+            child_clients.insert(declaration.k8s_type, client);
+        }
+    }
+    Ok(child_clients)
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn run_with_client(
     executor: runtime::Handle,
     metrics: Metrics,
     running: Arc<AtomicBool>,
+    shutdown: Arc<Notify>,
+    shared: Arc<SharedState>,
     config: OperatorConfig,
     client: Client,
+    child_clients: HashMap<&'static K8sType, Client>,
     handler: Arc<dyn Handler>,
-) {
+) -> Result<(), LeadershipLostError> {
     log::debug!("Starting operator with configuration: {:?}", config);
-    let server_port = config.server_port;
-    let expose_metrics = config.expose_metrics;
-    let expose_health = config.expose_health;
-    let mut state = create_operator_state(executor.clone(), metrics, running, config, client).await;
-    if expose_metrics || expose_health {
-        let server_future = server::start(
-            executor,
-            server_port,
-            state.runtime_config.clone(),
-            expose_metrics,
-            expose_health,
-        );
-        let operator_future = state.run(handler);
-        futures_util::future::join(server_future, operator_future).await;
-    } else {
-        state.run(handler).await;
+    let metrics_port = Some(config.metrics_server_port()).filter(|_| config.expose_metrics);
+    let health_port = Some(config.health_server_port()).filter(|_| config.expose_health);
+    let admin_port = Some(config.server_port).filter(|_| config.admin_endpoints);
+    let watch_staleness_threshold = config.watch_staleness_threshold;
+    let webhook_config = config.webhook.clone();
+    let leader_election = config.resolved_leader_election();
+    let is_leader = leader_election
+        .as_ref()
+        .map(|_| Arc::new(AtomicBool::new(false)));
+    let mut state = create_operator_state(
+        executor.clone(),
+        metrics,
+        running.clone(),
+        shutdown,
+        config,
+        client.clone(),
+        child_clients,
+    )
+    .await;
+
+    let runtime_config = state.runtime_config.clone();
+    let server_is_leader = is_leader.clone();
+    let server_running = running.clone();
+    let watch_statuses = state.watch_statuses.clone();
+    *shared.watch_statuses.lock().unwrap() = watch_statuses.clone();
+    *shared.sender.lock().unwrap() = Some(state.sender.clone());
+    *shared.parent_statuses.lock().unwrap() = state.parent_statuses.clone();
+    let admin = server::Admin {
+        sender: state.sender.clone(),
+        parent_statuses: state.parent_statuses.clone(),
+    };
+    let operator_future = async move {
+        match leader_election {
+            Some(election_config) => {
+                let mut elector = LeaderElector::new(
+                    election_config,
+                    client,
+                    is_leader.clone().unwrap(),
+                    running,
+                );
+                if !elector.acquire().await {
+                    return Ok(());
+                }
+                let result = {
+                    // if leadership is lost, then the operator future is dropped so that we stop reconciling
+                    // immediately, but on shutdown we still let the in-progress syncs finish
+                    let renew_future = Box::pin(elector.renew_until_lost());
+                    let run_future = Box::pin(state.run(handler));
+                    match futures_util::future::select(renew_future, run_future).await {
+                        Either::Left((Ok(()), run_future)) => {
+                            run_future.await;
+                            Ok(())
+                        }
+                        Either::Left((Err(leadership_lost), _)) => Err(leadership_lost),
+                        Either::Right(_) => Ok(()),
+                    }
+                };
+                if result.is_ok() {
+                    elector.release().await;
+                }
+                result
+            }
+            None => {
+                state.run(handler).await;
+                Ok(())
+            }
+        }
+    };
+    let mut servers: Vec<Pin<Box<dyn Future<Output = ()> + Send>>> = Vec::new();
+    // metrics, health checks, and admin endpoints share a single server unless they're configured to use different
+    // ports
+    let admin = admin_port.map(|port| (port, admin));
+    let server_ports = server::endpoints_by_port(metrics_port, health_port, admin);
+    for (port, endpoints) in server_ports {
+        servers.push(Box::pin(server::start(
+            executor.clone(),
+            port,
+            runtime_config.clone(),
+            server_is_leader.clone(),
+            server_running.clone(),
+            watch_statuses.clone(),
+            watch_staleness_threshold,
+            endpoints,
+        )));
+    }
+    if let Some(webhook_config) = webhook_config {
+        servers.push(Box::pin(webhook::start(webhook_config)));
+    }
+    if servers.is_empty() {
+        return operator_future.await;
+    }
+    // the servers normally run forever, so we only wait for them until the operator stops
+    let servers_future = futures_util::future::join_all(servers);
+    let operator_future = Box::pin(operator_future);
+    match futures_util::future::select(servers_future, operator_future).await {
+        Either::Left((_, operator_future)) => operator_future.await,
+        Either::Right((result, _)) => result,
     }
 }
 
@@ -195,74 +811,160 @@ async fn create_operator_state(
     executor: runtime::Handle,
     metrics: Metrics,
     running: Arc<AtomicBool>,
+    shutdown: Arc<Notify>,
     config: OperatorConfig,
     client: Client,
+    child_clients: HashMap<&'static K8sType, Client>,
 ) -> OperatorState {
+    // only the requests made while reconciling are dry runs, since the informers need to actually watch resources
+    let reconcile_client = client.with_dry_run(config.dry_run);
+    let events = if config.emit_events {
+        Some(EventRecorder::new(
+            reconcile_client.clone(),
+            config.operator_name.as_str(),
+            config.operator_namespace(),
+        ))
+    } else {
+        None
+    };
+    let child_tracking = ChildTracking::for_config(&config);
+    let mut runtime_config = RuntimeConfig::new(&config, metrics, events);
+    runtime_config.child_clients = child_clients
+        .iter()
+        .map(|(child_type, child_client)| (*child_type, child_client.with_dry_run(config.dry_run)))
+        .collect();
+    let runtime_config = Arc::new(runtime_config);
+    let metrics = &runtime_config.metrics;
     let OperatorConfig {
         parent,
         child_types,
-        namespace,
-        operator_name,
-        tracking_label_name,
-        ownership_label_name,
-        max_error_backoff,
+        watched_types,
+        mut watched_type_mappers,
+        namespaces,
+        namespace_selector,
+        label_selector,
+        field_selector,
+        resync_interval,
+        reconcile_on_child_status_change,
+        event_buffer_size,
         ..
     } = config;
 
-    let (tx, rx) = tokio::sync::mpsc::channel::<ResourceMessage>(1024);
+    // the informers wait for room in the channel before reading the next event, which is what bounds the memory that
+    // a flood of events can use
+    let (tx, rx) = tokio::sync::mpsc::channel::<ResourceMessage>(event_buffer_size.max(1));
+
+    let namespace_filter = namespace_selector.map(|selector| {
+        informer::start_namespace_monitor(
+            executor.clone(),
+            selector,
+            client.clone(),
+            tx.clone(),
+            metrics.watcher_metrics(crate::k8s_types::core::v1::Namespace),
+        )
+    });
 
     let parent_metrics = metrics.watcher_metrics(parent);
     let parent_monitor = informer::start_parent_monitor(
         executor.clone(),
-        namespace.clone(),
+        namespaces.clone(),
+        label_selector,
+        field_selector,
         parent,
         client.clone(),
         tx.clone(),
         parent_metrics,
     );
 
-    let mut child_runtime_config = HashMap::with_capacity(4);
+    if let Some(interval) = resync_interval {
+        executor.spawn(resync::run_periodic_resync(
+            parent_monitor.clone(),
+            tx.clone(),
+            parent,
+            interval,
+            running.clone(),
+        ));
+    }
+
     let mut children = HashMap::with_capacity(4);
 
-    for (child_type, child_conf) in child_types {
-        let child_metrics = metrics.watcher_metrics(&child_type);
-        let runtime_conf = ChildRuntimeConfig {
-            child_type,
-            update_strategy: child_conf.update_strategy,
+    for declaration in child_types {
+        let child_type = declaration.k8s_type;
+        let child_metrics = metrics.watcher_metrics(child_type);
+        let child_namespaces = match declaration.scope {
+            Scope::Namespaced => namespaces.clone(),
+            Scope::Cluster => None,
         };
-        child_runtime_config.insert(child_type, runtime_conf);
         let child_monitor = informer::start_child_monitor(
             executor.clone(),
-            tracking_label_name.clone(),
-            namespace.clone(),
+            child_tracking.clone(),
+            child_namespaces,
+            declaration.label_selector,
+            !reconcile_on_child_status_change,
+            declaration.config.metadata_only,
             child_type,
-            client.clone(),
+            child_clients.get(child_type).unwrap_or(&client).clone(),
             tx.clone(),
             child_metrics,
         );
         children.insert(child_type, child_monitor);
     }
-    let runtime_config = Arc::new(RuntimeConfig {
-        metrics,
-        child_types: child_runtime_config,
-        parent_type: parent,
-        correlation_label_name: tracking_label_name,
-        controller_label_name: ownership_label_name,
-        operator_name,
-        max_error_backoff,
-    });
 
-    OperatorState {
+    let mut cached_types: HashMap<&'static K8sType, Arc<dyn CachedType>> = HashMap::new();
+    cached_types.insert(parent, Arc::new(parent_monitor.clone()));
+    for (child_type, child_monitor) in children.iter() {
+        cached_types.insert(*child_type, Arc::new(child_monitor.clone()));
+    }
+    let mut watched = Vec::with_capacity(watched_types.len());
+    for watched_type in watched_types {
+        if cached_types.contains_key(watched_type) {
+            continue;
+        }
+        let watched_monitor = match watched_type_mappers.remove(watched_type) {
+            Some(parent_mapper) => informer::start_mapped_monitor(
+                executor.clone(),
+                namespaces.clone(),
+                watched_type,
+                client.clone(),
+                tx.clone(),
+                parent_mapper,
+                metrics.watcher_metrics(watched_type),
+            ),
+            None => informer::start_watched_monitor(
+                executor.clone(),
+                namespaces.clone(),
+                watched_type,
+                client.clone(),
+                metrics.watcher_metrics(watched_type),
+            ),
+        };
+        cached_types.insert(watched_type, Arc::new(watched_monitor.clone()));
+        watched.push(watched_monitor);
+    }
+
+    let state = OperatorState {
         running,
+        shutdown,
         parents: parent_monitor,
         children,
+        watched,
+        watch_statuses: WatchStatuses::default(),
+        cache: ResourceCache::new(cached_types),
+        namespaces,
+        namespace_filter,
+        changed_namespaces: Vec::new(),
         sender: tx,
         receiver: rx,
         parent_states: HashMap::new(),
-        client,
+        parent_statuses: ParentStatuses::default(),
+        requested_reconciles: Vec::new(),
+        reconcile_all_requested: false,
+        client: reconcile_client,
         runtime_config,
         executor,
-    }
+    };
+    *state.watch_statuses.lock().unwrap() = state.collect_watch_statuses();
+    state
 }
 
 type HandlerRef = Arc<dyn Handler>;
@@ -270,6 +972,8 @@ type HandlerRef = Arc<dyn Handler>;
 #[derive(Debug)]
 struct InProgressUpdate {
     start_time: Instant,
+    /// shared with the `SyncRequest`, so that the handler can tell when it's no longer needed
+    cancellation: CancellationToken,
 }
 
 #[derive(Debug)]
@@ -300,13 +1004,17 @@ impl Default for CappedBackoff {
 }
 
 impl CappedBackoff {
-    fn new(max_backoff: Duration) -> CappedBackoff {
-        CappedBackoff(ExponentialBackoff {
-            initial_interval: Duration::from_millis(100),
+    fn new(policy: &ReconcileBackoff) -> CappedBackoff {
+        let mut backoff = ExponentialBackoff {
+            initial_interval: policy.base_delay,
+            multiplier: policy.multiplier.max(1.0),
+            randomization_factor: policy.jitter.clamp(0.0, 1.0),
+            max_interval: policy.max_delay,
             max_elapsed_time: None,
-            max_interval: max_backoff,
             ..Default::default()
-        })
+        };
+        backoff.reset();
+        CappedBackoff(backoff)
     }
 }
 
@@ -318,35 +1026,78 @@ struct Resync(Duration, u32);
 #[derive(Debug, Default)]
 struct ParentState {
     in_progress: Option<InProgressUpdate>,
+    /// when the most recent sync was started, which is used to rate limit the syncs that are triggered by children
+    last_sync_start: Option<Instant>,
     sync_counter: u32,
     error_backoff: CappedBackoff,
+    finalize_backoff: CappedBackoff,
+    /// the wall clock time that the most recent sync was started, which is only used for reporting
+    last_sync_time: Option<SystemTime>,
+    last_outcome: Option<ReconcileOutcome>,
+    /// the delay before the next sync that was scheduled when the most recent sync completed
+    retry_after: Option<Duration>,
 }
 
 impl ParentState {
-    fn new(backoff: CappedBackoff) -> ParentState {
+    fn new(policy: &ReconcileBackoff) -> ParentState {
         ParentState {
             in_progress: None,
+            last_sync_start: None,
             sync_counter: 0,
-            error_backoff: backoff,
+            error_backoff: CappedBackoff::new(policy),
+            finalize_backoff: CappedBackoff::new(policy),
+            last_sync_time: None,
+            last_outcome: None,
+            retry_after: None,
         }
     }
 
-    fn start_sync(&mut self) {
+    fn status(&self, uid: &str, id: &ObjectId) -> ParentStatus {
+        ParentStatus {
+            uid: uid.to_owned(),
+            id: id.clone(),
+            in_progress: self.is_update_in_progress(),
+            sync_count: self.sync_counter,
+            last_sync_time: self.last_sync_time,
+            last_outcome: self.last_outcome,
+            retry_after: self.retry_after,
+        }
+    }
+
+    fn start_sync(&mut self, cancellation: CancellationToken) {
+        let start_time = Instant::now();
         self.sync_counter += 1;
+        self.last_sync_start = Some(start_time);
+        self.last_sync_time = Some(SystemTime::now());
         self.in_progress = Some(InProgressUpdate {
-            start_time: Instant::now(),
+            start_time,
+            cancellation,
         })
     }
 
+    /// Cancels the sync that's in progress, if any. Returns true if there was one.
+    fn cancel_in_progress(&self) -> bool {
+        match self.in_progress.as_ref() {
+            Some(in_progress) => {
+                in_progress.cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
     fn sync_finished(
         &mut self,
         parent_id: &ObjectId,
         parent_uid: &str,
-        sync_result: Result<Option<Duration>, ()>,
+        sync_result: Result<Option<Duration>, SyncFailure>,
     ) -> Option<Resync> {
         if let Some(in_progress) = self.in_progress.take() {
             let duration_millis = duration_to_millis(in_progress.start_time.elapsed());
-            let needs_resync = sync_result.as_ref().map(Option::is_some).unwrap_or(true);
+            let needs_resync = match sync_result {
+                Ok(resync) => resync.is_some(),
+                Err(failure) => failure != SyncFailure::NoRetry,
+            };
             log::info!(
                 "Completed sync of parent: {} with uid: {} in {}ms, needs retry: {}",
                 parent_id,
@@ -355,18 +1106,24 @@ impl ParentState {
                 needs_resync,
             );
             let sync_count = self.sync_counter;
+            self.last_outcome = Some(ReconcileOutcome::from_result(&sync_result));
 
-            match sync_result {
+            let resync = match sync_result {
                 Ok(resync) => {
                     // always reset the error backoff if the result was successful
                     self.error_backoff.reset();
+                    self.finalize_backoff.reset();
                     resync.map(|duration| Resync(duration, sync_count))
                 }
-                Err(()) => self
+                Err(SyncFailure::Retry(Some(delay))) => Some(Resync(delay, sync_count)),
+                Err(SyncFailure::Retry(None)) => self
                     .error_backoff
                     .next_backoff()
                     .map(|duration| Resync(duration, sync_count)),
-            }
+                Err(SyncFailure::NoRetry) => None,
+            };
+            self.retry_after = resync.as_ref().map(|Resync(delay, _)| *delay);
+            resync
         } else {
             log::error!(
                 "Got updateOperationComplete when there was no in-progress operation for uid: {}",
@@ -376,6 +1133,37 @@ impl ParentState {
         }
     }
 
+    /// Called when the finalize handler reports that it isn't done yet. The retry delay requested by the handler
+    /// is treated as a minimum, and is increased by a backoff so that a handler that keeps asking to retry
+    /// immediately doesn't end up spinning.
+    fn finalize_incomplete(
+        &mut self,
+        parent_id: &ObjectId,
+        parent_uid: &str,
+        retry: Duration,
+    ) -> Option<Resync> {
+        if let Some(in_progress) = self.in_progress.take() {
+            let backoff = self.finalize_backoff.next_backoff().unwrap_or(retry);
+            let delay = retry.max(backoff);
+            log::info!(
+                "Finalize of parent: {} with uid: {} is incomplete after {}ms, will retry in {}ms",
+                parent_id,
+                parent_uid,
+                duration_to_millis(in_progress.start_time.elapsed()),
+                duration_to_millis(delay),
+            );
+            self.last_outcome = Some(ReconcileOutcome::FinalizeIncomplete);
+            self.retry_after = Some(delay);
+            Some(Resync(delay, self.sync_counter))
+        } else {
+            log::error!(
+                "Got finalizeIncomplete when there was no in-progress operation for uid: {}",
+                parent_uid
+            );
+            None
+        }
+    }
+
     fn is_update_in_progress(&self) -> bool {
         self.in_progress.is_some()
     }
@@ -384,11 +1172,35 @@ impl ParentState {
 #[derive(Debug)]
 struct OperatorState {
     running: Arc<AtomicBool>,
+    /// notified when the operator is asked to shut down, so that we don't have to wait for the next message
+    shutdown: Arc<Notify>,
     parents: ResourceMonitor<UidToIdIndex>,
-    children: HashMap<&'static K8sType, ResourceMonitor<LabelToIdIndex>>,
+    children: HashMap<&'static K8sType, ResourceMonitor<ParentUidIndex>>,
+    watched: Vec<ResourceMonitor<UidToIdIndex>>,
+    /// the statuses of all of the watches above, including the `namespace_filter`, which are shared with the
+    /// readiness check and the `OperatorHandle`
+    watch_statuses: WatchStatuses,
+    cache: ResourceCache,
+    /// the namespaces that the parent is watched in, which types that are added with
+    /// `OperatorHandle::watch_additional` are also watched in
+    namespaces: Option<Vec<String>>,
+    /// the namespaces that match the `namespace_selector`, if there is one. Only the parents in these namespaces are
+    /// synced, unless they're being deleted.
+    namespace_filter: Option<ResourceMonitor<UidToIdIndex>>,
+    /// the names of namespaces that started or stopped matching the `namespace_selector`, whose parents still need
+    /// to be queued
+    changed_namespaces: Vec<String>,
     sender: Sender<ResourceMessage>,
     receiver: Receiver<ResourceMessage>,
     parent_states: HashMap<String, ParentState>,
+    /// a copy of what's known about each parent in the `parent_states`, which is shared with the `OperatorHandle` and
+    /// admin endpoints
+    parent_statuses: ParentStatuses,
+    /// parents that were requested to be synced using `OperatorHandle::trigger_reconcile`, whose uids still need to
+    /// be looked up
+    requested_reconciles: Vec<ObjectId>,
+    /// set by `OperatorHandle::trigger_reconcile_all`, until all of the parents have been queued
+    reconcile_all_requested: bool,
     client: Client,
     runtime_config: Arc<RuntimeConfig>,
     executor: runtime::Handle,
@@ -396,22 +1208,66 @@ struct OperatorState {
 
 impl OperatorState {
     async fn run(&mut self, handler: HandlerRef) {
-        let mut parent_ids_to_sync = HashSet::with_capacity(16);
+        let mut parent_ids_to_sync = WorkQueue::new();
         while self.running.load(Ordering::Relaxed) {
             let timeout = if parent_ids_to_sync.is_empty() {
                 Duration::from_secs(3600)
             } else {
-                Duration::from_secs(1)
+                // wake up in time for the next delayed parent, so that short delays aren't stretched out
+                parent_ids_to_sync
+                    .next_ready_at()
+                    .map(|ready_at| ready_at.saturating_duration_since(Instant::now()))
+                    .unwrap_or(Duration::from_secs(1))
+                    .min(Duration::from_secs(1))
+                    .max(Duration::from_millis(1))
             };
             self.run_once(&mut parent_ids_to_sync, &handler, timeout)
                 .await;
         }
         log::info!("Shutting down operator");
+        for parent_state in self.parent_states.values() {
+            parent_state.cancel_in_progress();
+        }
+        self.finish_in_progress_syncs().await;
+    }
+
+    /// Waits up to the `shutdown_grace_period` for the syncs that are already in progress to complete. No new syncs
+    /// are started, even if their parents change in the meantime.
+    async fn finish_in_progress_syncs(&mut self) {
+        let grace_period = self.runtime_config.shutdown_grace_period;
+        let start_time = Instant::now();
+        let mut ignored = WorkQueue::new();
+        while self.is_any_update_in_progress() {
+            let remaining = match grace_period.checked_sub(start_time.elapsed()) {
+                Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+                _ => {
+                    let in_progress = self
+                        .parent_states
+                        .iter()
+                        .filter(|(_, state)| state.is_update_in_progress())
+                        .map(|(uid, _)| uid.as_str())
+                        .collect::<Vec<_>>();
+                    log::warn!(
+                        "Shutdown grace period of {}ms elapsed before syncs of parents with uids: {:?} completed, shutting down anyway",
+                        duration_to_millis(grace_period),
+                        in_progress
+                    );
+                    return;
+                }
+            };
+            match tokio::time::timeout(remaining, self.receiver.recv()).await {
+                Ok(Some(message)) => self.handle_received_message(message, &mut ignored),
+                Ok(None) => return,
+                Err(_) => { /* the grace period has elapsed, which will be logged on the next iteration */
+                }
+            }
+        }
+        log::info!("All in-progress syncs have completed");
     }
 
     async fn run_once(
         &mut self,
-        parent_ids_to_sync: &mut HashSet<String>,
+        parent_ids_to_sync: &mut WorkQueue,
         handler: &HandlerRef,
         timeout: Duration,
     ) {
@@ -421,31 +1277,35 @@ impl OperatorState {
         );
         self.get_parent_uids_to_update(parent_ids_to_sync, timeout)
             .await;
+        self.queue_requested_reconciles(parent_ids_to_sync).await;
+        self.queue_parents_in_changed_namespaces(parent_ids_to_sync)
+            .await;
         if !self.running.load(Ordering::Relaxed) {
             // getting the uids to update can take quite a while, so we'll do an extra check to see
             // if the operator has been shutdown in the meantime
             return;
         }
 
-        let mut synced_parents = Vec::new();
-        for parent_uid in parent_ids_to_sync.iter() {
-            if !self.is_update_in_progress(parent_uid) {
-                let result = self.sync_parent(parent_uid.as_str(), handler.clone()).await;
-                if let Err(err) = result {
-                    log::error!(
-                        "Cannot sync parent with uid: {} due to error: {:?}",
-                        parent_uid,
-                        err
-                    );
-                } else {
-                    synced_parents.push(parent_uid.clone());
-                }
+        // parents that are already being synced stay in the queue, so that they're synced again once the
+        // current sync completes, no matter how many events were received for them in the meantime
+        let available = self.available_reconcile_slots();
+        let ready = parent_ids_to_sync.take_ready(available, |parent_uid| {
+            !self.is_update_in_progress(parent_uid)
+        });
+        for parent_uid in ready {
+            let result = self.sync_parent(parent_uid.as_str(), handler.clone()).await;
+            if let Err(err) = result {
+                log::error!(
+                    "Cannot sync parent with uid: {} due to error: {:?}",
+                    parent_uid,
+                    err
+                );
+                parent_ids_to_sync.insert(parent_uid);
             }
         }
-
-        for id in synced_parents {
-            parent_ids_to_sync.remove(&id);
-        }
+        self.runtime_config
+            .metrics
+            .set_work_queue_depth(parent_ids_to_sync.len());
     }
 
     #[cfg(feature = "testkit")]
@@ -453,13 +1313,46 @@ impl OperatorState {
         self.running.load(Ordering::Relaxed)
     }
 
-    #[cfg(feature = "testkit")]
+    /// Returns the status of the watch of each of the informers, which tells whether its initial list has completed
+    /// and when it last heard from the api server
+    fn collect_watch_statuses(&self) -> Vec<Arc<WatchStatus>> {
+        let children = self
+            .children
+            .values()
+            .flat_map(ResourceMonitor::watch_statuses);
+        let watched = self
+            .watched
+            .iter()
+            .chain(self.namespace_filter.iter())
+            .flat_map(ResourceMonitor::watch_statuses);
+        self.parents
+            .watch_statuses()
+            .chain(children)
+            .chain(watched)
+            .collect()
+    }
+
     fn is_any_update_in_progress(&self) -> bool {
         self.parent_states
             .values()
             .any(ParentState::is_update_in_progress)
     }
 
+    /// Returns the number of syncs that can be started without exceeding `max_concurrent_reconciles`
+    fn available_reconcile_slots(&self) -> usize {
+        match self.runtime_config.max_concurrent_reconciles {
+            Some(max) => {
+                let in_progress = self
+                    .parent_states
+                    .values()
+                    .filter(|state| state.is_update_in_progress())
+                    .count();
+                max.saturating_sub(in_progress)
+            }
+            None => usize::MAX,
+        }
+    }
+
     fn is_update_in_progress(&self, parent_uid: &str) -> bool {
         self.parent_states
             .get(parent_uid)
@@ -475,6 +1368,14 @@ impl OperatorState {
                 return Ok(());
             }
         };
+        // parents that are being deleted are still finalized, since they'd be stuck with the finalizer otherwise
+        if !parent.is_deletion_timestamp_set() && !self.is_namespace_selected(&parent).await? {
+            log::debug!(
+                "Not syncing parent: '{}' because its namespace does not match the namespace_selector",
+                parent.get_object_id()
+            );
+            return Ok(());
+        }
 
         log::info!(
             "Starting sync request for parent: '{}' with uid: '{}'",
@@ -484,8 +1385,10 @@ impl OperatorState {
 
         let request = self.create_sync_request(parent).await?;
 
+        let parent_id = request.parent.get_object_id().to_owned();
         let parent_state = self.get_or_create_parent_state(parent_uid);
-        parent_state.start_sync();
+        parent_state.start_sync(request.cancellation.clone());
+        self.publish_status(parent_uid, &parent_id);
 
         let handler = SyncHandler {
             sender: self.sender.clone(),
@@ -499,25 +1402,56 @@ impl OperatorState {
         Ok(())
     }
 
+    /// Returns true unless there's a `namespace_selector` that the parent's namespace doesn't match. Cluster scoped
+    /// parents are always selected.
+    async fn is_namespace_selected(&self, parent: &K8sResource) -> Result<bool, Error> {
+        let (namespace_filter, namespace) =
+            match (self.namespace_filter.as_ref(), parent.namespace()) {
+                (Some(filter), Some(namespace)) => (filter, namespace),
+                _ => return Ok(true),
+            };
+        let namespaces = namespace_filter.lock_state().await?;
+        Ok(namespaces
+            .get_by_id(&ObjectIdRef::new("", namespace))
+            .is_some())
+    }
+
     fn get_or_create_parent_state<'a, 'b>(
         &'a mut self,
         parent_uid: &'b str,
     ) -> &'a mut ParentState {
         if !self.parent_states.contains_key(parent_uid) {
-            let parent_state =
-                ParentState::new(CappedBackoff::new(self.runtime_config.max_error_backoff));
+            let parent_state = ParentState::new(&self.runtime_config.reconcile_backoff);
             self.parent_states
                 .insert(parent_uid.to_owned(), parent_state);
         }
         self.parent_states.get_mut(parent_uid).unwrap()
     }
 
+    /// Updates the shared status of the parent to match its `ParentState`
+    fn publish_status(&self, parent_uid: &str, parent_id: &ObjectId) {
+        if let Some(parent_state) = self.parent_states.get(parent_uid) {
+            self.parent_statuses.lock().unwrap().insert(
+                parent_uid.to_owned(),
+                parent_state.status(parent_uid, parent_id),
+            );
+        }
+    }
+
     async fn create_sync_request(&self, parent: K8sResource) -> Result<SyncRequest, Error> {
         let children = self.get_all_children(parent.uid()).await?;
-        Ok(SyncRequest { parent, children })
+        // the handler may look up any of the watched types, so they must all be initialized before it's called
+        for watched_monitor in self.watched.iter() {
+            watched_monitor.lock_state().await?;
+        }
+        Ok(SyncRequest {
+            parent,
+            children,
+            cache: self.cache.clone(),
+            cancellation: CancellationToken::new(),
+        })
     }
 
-    #[cfg(feature = "testkit")]
     async fn get_parent_by_id(
         &self,
         parent_id: &ObjectIdRef<'_>,
@@ -546,11 +1480,7 @@ impl OperatorState {
     /// Tries to receive a whole batch of messages, so that we can consolidate them by parent id.
     /// The `max_timeout` is treated as a soft limit, which may be exceeded by a bit in case there are
     /// tons of messages to process.
-    async fn get_parent_uids_to_update(
-        &mut self,
-        to_sync: &mut HashSet<String>,
-        max_timeout: Duration,
-    ) {
+    async fn get_parent_uids_to_update(&mut self, to_sync: &mut WorkQueue, max_timeout: Duration) {
         let starting_to_sync_len = to_sync.len();
         let start_time = Instant::now();
         let mut first_receive_time = start_time;
@@ -593,7 +1523,145 @@ impl OperatorState {
         );
     }
 
-    fn handle_received_message(&mut self, message: ResourceMessage, to_sync: &mut HashSet<String>) {
+    /// Adds the parents that were requested using `OperatorHandle::trigger_reconcile` or `trigger_reconcile_all` to
+    /// the queue
+    async fn queue_requested_reconciles(&mut self, to_sync: &mut WorkQueue) {
+        if self.reconcile_all_requested {
+            match self.parents.lock_state().await {
+                Ok(parents) => {
+                    let all_parents = parents.get_all_ids();
+                    log::info!(
+                        "Triggering requested sync of all {} parents",
+                        all_parents.len()
+                    );
+                    for (parent_uid, _) in all_parents {
+                        to_sync.insert(parent_uid);
+                    }
+                    self.reconcile_all_requested = false;
+                }
+                Err(err) => log::warn!(
+                    "Cannot sync all parents yet because parents are unavailable: {}",
+                    err
+                ),
+            }
+        }
+        for parent_id in std::mem::take(&mut self.requested_reconciles) {
+            match self.get_parent_by_id(&parent_id.as_id_ref()).await {
+                Ok(Some(parent)) => {
+                    log::info!("Triggering requested sync of parent: {}", parent_id);
+                    to_sync.insert(parent.uid().to_owned());
+                }
+                Ok(None) => log::warn!(
+                    "Ignoring requested sync of parent: {} because it does not exist",
+                    parent_id
+                ),
+                Err(err) => log::warn!(
+                    "Ignoring requested sync of parent: {} because parents are unavailable: {}",
+                    parent_id,
+                    err
+                ),
+            }
+        }
+    }
+
+    /// Adds all of the parents in namespaces that started or stopped matching the `namespace_selector` to the queue.
+    /// Parents in namespaces that no longer match are skipped once they're synced, unless they're being deleted.
+    async fn queue_parents_in_changed_namespaces(&mut self, to_sync: &mut WorkQueue) {
+        if self.changed_namespaces.is_empty() {
+            return;
+        }
+        let changed_namespaces = std::mem::take(&mut self.changed_namespaces);
+        let parents = match self.parents.lock_state().await {
+            Ok(parents) => parents.get_all_ids(),
+            Err(err) => {
+                log::warn!(
+                    "Cannot queue the parents in namespaces: {:?} because parents are unavailable: {}",
+                    changed_namespaces,
+                    err
+                );
+                return;
+            }
+        };
+        for (parent_uid, parent_id) in parents {
+            let namespace = parent_id.namespace().unwrap_or("");
+            if changed_namespaces
+                .iter()
+                .any(|changed| changed == namespace)
+            {
+                log::info!(
+                    "Triggering sync of parent: {} because its namespace was changed",
+                    parent_id
+                );
+                to_sync.insert(parent_uid);
+            }
+        }
+    }
+
+    /// Starts watching a type that was requested using `OperatorHandle::watch_additional`, and adds it to the cache
+    fn start_requested_watch(&mut self, k8s_type: &'static K8sType, parent_mapper: ParentMapper) {
+        if self.cache.is_watched(k8s_type) {
+            log::warn!(
+                "Ignoring request to watch {} because it is already being watched",
+                k8s_type
+            );
+            return;
+        }
+        if !self.running.load(Ordering::Relaxed) {
+            return;
+        }
+        log::info!("Starting watch of additional type: {}", k8s_type);
+        let monitor = informer::start_mapped_monitor(
+            self.executor.clone(),
+            self.namespaces.clone(),
+            k8s_type,
+            self.client.with_dry_run(false),
+            self.sender.clone(),
+            parent_mapper,
+            self.runtime_config.metrics.watcher_metrics(k8s_type),
+        );
+        self.cache = self.cache.with_type(k8s_type, Arc::new(monitor.clone()));
+        // like the types that are watched from the start, syncs wait for its initial list, and so does readiness
+        self.watch_statuses
+            .lock()
+            .unwrap()
+            .extend(monitor.watch_statuses());
+        self.watched.push(monitor);
+    }
+
+    fn handle_received_message(&mut self, message: ResourceMessage, to_sync: &mut WorkQueue) {
+        let message = match message {
+            ResourceMessage {
+                event_type: EventType::WatchRequested(parent_mapper),
+                resource_type,
+                ..
+            } => {
+                self.start_requested_watch(resource_type, parent_mapper);
+                return;
+            }
+            other => other,
+        };
+        if let EventType::ReconcileRequested = message.event_type {
+            self.requested_reconciles.push(message.resource_id);
+            return;
+        }
+        if let EventType::ReconcileAllRequested = message.event_type {
+            self.reconcile_all_requested = true;
+            return;
+        }
+        // changes to the namespaces that match the `namespace_selector`, which may also be the parent or child type
+        if message.resource_type == crate::k8s_types::core::v1::Namespace
+            && self.namespace_filter.is_some()
+            && message.resource_type != self.runtime_config.parent_type
+            && !self.children.contains_key(message.resource_type)
+        {
+            log::debug!(
+                "Namespace: {} was {:?}",
+                message.resource_id.name(),
+                message.event_type
+            );
+            self.changed_namespaces.push(message.resource_id.name);
+            return;
+        }
         self.runtime_config.metrics.watch_event_received();
         if message.index_key.is_none() {
             // TODO: change resourceMessage so that index_key is not an Option
@@ -617,6 +1685,18 @@ impl OperatorState {
                 } else {
                     None
                 };
+                self.publish_status(&uid, &resource_id);
+                if let Some(Resync(duration, sync_counter)) = maybe_resync {
+                    self.schedule_resync(&uid, resource_id, duration, sync_counter);
+                }
+            }
+            EventType::FinalizeIncomplete { retry } => {
+                let maybe_resync = if let Some(parent_state) = self.parent_states.get_mut(&uid) {
+                    parent_state.finalize_incomplete(&resource_id, &uid, retry)
+                } else {
+                    None
+                };
+                self.publish_status(&uid, &resource_id);
                 if let Some(Resync(duration, sync_counter)) = maybe_resync {
                     self.schedule_resync(&uid, resource_id, duration, sync_counter);
                 }
@@ -627,6 +1707,7 @@ impl OperatorState {
                     .metrics
                     .parent_deleted(&resource_id.as_id_ref());
                 let _ = self.parent_states.remove(&uid);
+                let _ = self.parent_statuses.lock().unwrap().remove(&uid);
             }
             EventType::TriggerResync { resync_round } => {
                 let current = self
@@ -644,8 +1725,41 @@ impl OperatorState {
                     log::debug!("Skipping scheduled resync for parent: {} because a sync was already completed since this was scheduled", resource_id);
                 }
             }
-            _ => {
+            EventType::PeriodicResync => {
+                // a periodic resync doesn't mean that anything has changed, so the sync that's in progress is
+                // still up to date and is left to finish, and there's no burst of changes to wait out with the
+                // `reconcile_debounce`
                 if to_sync.insert(uid) {
+                    log::debug!("Triggering periodic resync of parent: {}", resource_id);
+                }
+            }
+            _ if resource_type != self.runtime_config.parent_type => {
+                let ready_at = self
+                    .child_event_ready_at(&uid)
+                    .into_iter()
+                    .chain(self.debounced_ready_at())
+                    .max();
+                self.cancel_in_progress_sync(&uid, &resource_id);
+                let inserted = match ready_at {
+                    Some(ready_at) => to_sync.insert_delayed(uid, ready_at),
+                    None => to_sync.insert(uid),
+                };
+                if inserted {
+                    log::info!(
+                        "Triggering sync due to event: {:?}, on child resource: {} {} ",
+                        event_type,
+                        resource_type,
+                        resource_id
+                    );
+                }
+            }
+            _ => {
+                self.cancel_in_progress_sync(&uid, &resource_id);
+                let inserted = match self.debounced_ready_at() {
+                    Some(ready_at) => to_sync.insert_ready_by(uid, ready_at),
+                    None => to_sync.insert(uid),
+                };
+                if inserted {
                     log::info!(
                         "Triggering sync due to event: {:?}, on resource: {} {} ",
                         event_type,
@@ -657,6 +1771,38 @@ impl OperatorState {
         }
     }
 
+    /// Cancels the sync of the parent that's in progress, if any, since a newer change was observed that will cause
+    /// the parent to be synced again as soon as it completes
+    fn cancel_in_progress_sync(&self, parent_uid: &str, changed: &ObjectId) {
+        let cancelled = self
+            .parent_states
+            .get(parent_uid)
+            .map(ParentState::cancel_in_progress)
+            .unwrap_or(false);
+        if cancelled {
+            log::debug!(
+                "Cancelling in-progress sync of parent with uid: {} due to a change to: {}",
+                parent_uid,
+                changed
+            );
+        }
+    }
+
+    /// Returns the time at which a sync that's triggered by a change to a child may start, if the
+    /// `child_event_rate_limit` requires it to be delayed
+    fn child_event_ready_at(&self, parent_uid: &str) -> Option<Instant> {
+        let rate_limit = self.runtime_config.child_event_rate_limit?;
+        let last_sync_start = self.parent_states.get(parent_uid)?.last_sync_start?;
+        Some(last_sync_start + rate_limit).filter(|ready_at| *ready_at > Instant::now())
+    }
+
+    /// Returns the time at which a sync that's triggered by an event may start, if the `reconcile_debounce`
+    /// requires it to be delayed
+    fn debounced_ready_at(&self) -> Option<Instant> {
+        let debounce = self.runtime_config.reconcile_debounce;
+        Some(Instant::now() + debounce).filter(|_| debounce > Duration::from_millis(0))
+    }
+
     fn schedule_resync(
         &mut self,
         uid: &str,
@@ -696,7 +1842,13 @@ impl OperatorState {
     }
 
     async fn recv_next(&mut self, timeout: Duration) -> Option<ResourceMessage> {
-        match tokio::time::timeout(timeout, self.receiver.recv()).await {
+        let recv_future = Box::pin(tokio::time::timeout(timeout, self.receiver.recv()));
+        let shutdown_future = Box::pin(self.shutdown.notified());
+        let result = match futures_util::future::select(recv_future, shutdown_future).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => return None,
+        };
+        match result {
             Err(_) => None,
             Ok(Some(val)) => Some(val),
             Ok(None) => {
@@ -721,6 +1873,52 @@ pub(crate) fn duration_to_millis(duration: Duration) -> u64 {
 mod test {
     use super::*;
 
+    fn backoff_with_max(max_delay: Duration) -> ReconcileBackoff {
+        ReconcileBackoff {
+            max_delay,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parent_status_reports_the_last_outcome_and_retry() {
+        let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
+        let mut subject = ParentState::new(&ReconcileBackoff {
+            jitter: 0.0,
+            ..Default::default()
+        });
+        let status = subject.status("test-uid", &parent_id);
+        assert_eq!(
+            (0, None, None),
+            (
+                status.sync_count,
+                status.last_sync_time,
+                status.last_outcome
+            )
+        );
+
+        subject.start_sync(CancellationToken::new());
+        let status = subject.status("test-uid", &parent_id);
+        assert!(status.in_progress);
+        assert!(status.last_sync_time.is_some());
+        assert_eq!(None, status.last_outcome);
+
+        let Resync(delay, _) = subject
+            .sync_finished(&parent_id, "test-uid", Err(SyncFailure::Retry(None)))
+            .unwrap();
+        let status = subject.status("test-uid", &parent_id);
+        assert!(!status.in_progress);
+        assert_eq!(Some(ReconcileOutcome::RetryableError), status.last_outcome);
+        assert_eq!(Some(delay), status.retry_after);
+
+        subject.start_sync(CancellationToken::new());
+        subject.sync_finished(&parent_id, "test-uid", Err(SyncFailure::NoRetry));
+        let status = subject.status("test-uid", &parent_id);
+        assert_eq!(2, status.sync_count);
+        assert_eq!(Some(ReconcileOutcome::FatalError), status.last_outcome);
+        assert_eq!(None, status.retry_after);
+    }
+
     #[test]
     fn parent_state_backoff_increases_exponentially() {
         let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
@@ -729,19 +1927,17 @@ mod test {
 
         // we set this up to disable the jitter for the test, so we can reliably assert that
         // it gets bigger after each error
-        let backoff = CappedBackoff(ExponentialBackoff {
-            initial_interval: Duration::from_millis(100),
-            randomization_factor: 0.0,
-            max_interval: max_backoff,
-            max_elapsed_time: None,
+        let mut subject = ParentState::new(&ReconcileBackoff {
+            jitter: 0.0,
+            max_delay: max_backoff,
             ..Default::default()
         });
-        let mut subject = ParentState::new(backoff);
 
         let mut last_duration = Duration::from_secs(0);
         for i in 1..20 {
-            subject.start_sync();
-            let result = subject.sync_finished(&parent_id, parent_uid, Err(()));
+            subject.start_sync(CancellationToken::new());
+            let result =
+                subject.sync_finished(&parent_id, parent_uid, Err(SyncFailure::Retry(None)));
             let Resync(duration, counter) =
                 result.expect("expected result to be Some but it was None");
 
@@ -753,28 +1949,49 @@ mod test {
         assert_eq!(last_duration, max_backoff);
     }
 
+    #[test]
+    fn parent_state_only_cancels_the_sync_that_is_in_progress() {
+        let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
+        let mut subject = ParentState::new(&ReconcileBackoff::default());
+        assert!(!subject.cancel_in_progress());
+
+        let first = CancellationToken::new();
+        subject.start_sync(first.clone());
+        assert!(subject.cancel_in_progress());
+        assert!(first.is_cancelled());
+        subject.sync_finished(&parent_id, "test-uid", Ok(None));
+
+        let second = CancellationToken::new();
+        subject.start_sync(second.clone());
+        assert!(!second.is_cancelled());
+        subject.sync_finished(&parent_id, "test-uid", Ok(None));
+        assert!(!subject.cancel_in_progress());
+        assert!(!second.is_cancelled());
+    }
+
     #[test]
     fn parent_state_backoff_is_reset_after_successful_sync() {
         let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
         let parent_uid = "test-uid";
         let max_backoff = Duration::from_secs(10);
 
-        let mut subject = ParentState::new(CappedBackoff::new(max_backoff));
+        let mut subject = ParentState::new(&backoff_with_max(max_backoff));
         let mut last_duration = Duration::from_secs(0);
         for _ in 0..10 {
-            subject.start_sync();
-            let result = subject.sync_finished(&parent_id, parent_uid, Err(()));
+            subject.start_sync(CancellationToken::new());
+            let result =
+                subject.sync_finished(&parent_id, parent_uid, Err(SyncFailure::Retry(None)));
             let Resync(duration, _) = result.expect("expected result to be Some but it was None");
             last_duration = duration;
         }
 
-        subject.start_sync();
+        subject.start_sync(CancellationToken::new());
         let result = subject.sync_finished(&parent_id, parent_uid, Ok(None));
         assert!(result.is_none());
 
-        subject.start_sync();
+        subject.start_sync(CancellationToken::new());
         let Resync(duration, counter) = subject
-            .sync_finished(&parent_id, parent_uid, Err(()))
+            .sync_finished(&parent_id, parent_uid, Err(SyncFailure::Retry(None)))
             .expect("expected result to be Some but it was None");
 
         // the duration should have started incrementing from the beginning
@@ -782,19 +1999,540 @@ mod test {
         assert_eq!(12, counter);
     }
 
+    #[test]
+    fn parent_state_respects_the_failure_returned_from_the_handler() {
+        let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
+        let parent_uid = "test-uid";
+
+        let mut subject = ParentState::new(&backoff_with_max(Duration::from_secs(10)));
+
+        let requested = Duration::from_secs(42);
+        subject.start_sync(CancellationToken::new());
+        let Resync(duration, _) = subject
+            .sync_finished(
+                &parent_id,
+                parent_uid,
+                Err(SyncFailure::Retry(Some(requested))),
+            )
+            .expect("expected result to be a Resync but was None");
+        assert_eq!(requested, duration);
+
+        subject.start_sync(CancellationToken::new());
+        let result = subject.sync_finished(&parent_id, parent_uid, Err(SyncFailure::NoRetry));
+        assert!(result.is_none());
+    }
+
     #[test]
     fn parent_state_returns_resync_when_duration_is_present_in_sync_result() {
         let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
         let parent_uid = "test-uid";
 
-        let mut subject = ParentState::new(CappedBackoff::new(Duration::from_secs(10)));
+        let mut subject = ParentState::new(&backoff_with_max(Duration::from_secs(10)));
 
         let desired_period = Duration::from_secs(42);
-        subject.start_sync();
+        subject.start_sync(CancellationToken::new());
         let Resync(duration, _) = subject
             .sync_finished(&parent_id, parent_uid, Ok(Some(desired_period)))
             .expect("expected result to be a Resync but was None");
 
         assert_eq!(desired_period, duration);
     }
+
+    #[test]
+    fn parent_state_backs_off_when_finalize_is_incomplete() {
+        let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
+        let parent_uid = "test-uid";
+
+        let mut subject = ParentState::new(&backoff_with_max(Duration::from_secs(10)));
+
+        let mut last_duration = Duration::from_secs(0);
+        for _ in 0..10 {
+            subject.start_sync(CancellationToken::new());
+            let Resync(duration, _) = subject
+                .finalize_incomplete(&parent_id, parent_uid, Duration::from_secs(0))
+                .expect("expected result to be a Resync but was None");
+            assert!(duration > Duration::from_secs(0));
+            last_duration = duration;
+        }
+        assert!(last_duration > Duration::from_secs(1));
+
+        // a longer delay requested by the handler is always respected
+        subject.start_sync(CancellationToken::new());
+        let Resync(duration, _) = subject
+            .finalize_incomplete(&parent_id, parent_uid, Duration::from_secs(60))
+            .expect("expected result to be a Resync but was None");
+        assert_eq!(Duration::from_secs(60), duration);
+    }
+
+    #[test]
+    fn parent_state_backoff_uses_the_configured_policy() {
+        let parent_id = ObjectId::new("foo".to_owned(), "bar".to_owned());
+        let parent_uid = "test-uid";
+        let mut subject = ParentState::new(&ReconcileBackoff {
+            base_delay: Duration::from_secs(1),
+            multiplier: 3.0,
+            max_delay: Duration::from_secs(20),
+            jitter: 0.0,
+        });
+
+        let mut durations = Vec::new();
+        for _ in 0..5 {
+            subject.start_sync(CancellationToken::new());
+            let Resync(duration, _) = subject
+                .sync_finished(&parent_id, parent_uid, Err(SyncFailure::Retry(None)))
+                .expect("expected result to be a Resync but was None");
+            durations.push(duration.as_secs());
+        }
+        assert_eq!(vec![1, 3, 9, 20, 20], durations);
+    }
+
+    #[test]
+    fn run_operator_refuses_to_start_when_the_preflight_rbac_check_fails() {
+        use crate::k8s_types::core::v1::Pod;
+
+        // the operator creates its own runtime, so the api server needs to run on a separate one
+        let (addr_tx, addr_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut runtime = runtime::Builder::new()
+                .basic_scheduler()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                let make_service = hyper::service::make_service_fn(|_| async {
+                    Ok::<_, hyper::Error>(hyper::service::service_fn(|_req| async {
+                        let body = r#"{"status": {"allowed": false}}"#;
+                        Ok::<_, hyper::Error>(hyper::Response::new(hyper::Body::from(body)))
+                    }))
+                });
+                let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                    .serve(make_service);
+                addr_tx.send(server.local_addr()).unwrap();
+                let _ = server.await;
+            });
+        });
+        let addr = addr_rx.recv().unwrap();
+        let client_config: ClientConfig = serde_json::from_value(serde_json::json!({
+            "apiServerEndpoint": format!("http://{}", addr),
+            "userAgent": "test-operator",
+            "credentials": {"header": "Bearer abc"},
+        }))
+        .unwrap();
+        let config = OperatorConfig::new("test-operator", Pod)
+            .within_namespace("ns")
+            .expose_metrics(false)
+            .expose_health(false)
+            .emit_events(false)
+            .preflight_rbac_check(true);
+        let handler =
+            |_: &SyncRequest| Ok(crate::handler::SyncResponse::new(serde_json::Value::Null));
+
+        let err = run_with_metrics(config, client_config, handler, Metrics::new())
+            .expect_err("expected the operator to refuse to start");
+        assert!(
+            err.to_string().contains("Missing 5 RBAC permission(s)"),
+            "error was: {}",
+            err
+        );
+    }
+
+    /// Creates the state of an operator whose client talks to the api server at the given endpoint
+    async fn test_operator_state(
+        config: OperatorConfig,
+        api_server_endpoint: &str,
+    ) -> OperatorState {
+        let client_config: ClientConfig = serde_json::from_value(serde_json::json!({
+            "apiServerEndpoint": api_server_endpoint,
+            "userAgent": "test-operator",
+            "credentials": {"header": "Bearer abc"},
+        }))
+        .unwrap();
+        let metrics = Metrics::new();
+        let client = Client::new(client_config, metrics.client_metrics()).unwrap();
+        create_operator_state(
+            runtime::Handle::current(),
+            metrics,
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(Notify::new()),
+            config,
+            client,
+            HashMap::new(),
+        )
+        .await
+    }
+
+    fn parent_message(event_type: EventType) -> ResourceMessage {
+        ResourceMessage {
+            event_type,
+            resource_type: crate::k8s_types::core::v1::Pod,
+            resource_id: ObjectId::new("ns".to_owned(), "parent".to_owned()),
+            index_key: Some("parent-uid".to_owned()),
+        }
+    }
+
+    #[test]
+    fn periodic_resyncs_do_not_cancel_the_sync_in_progress() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let config = OperatorConfig::new("test-operator", crate::k8s_types::core::v1::Pod)
+                .within_namespace("ns");
+            let mut state = test_operator_state(config, "http://127.0.0.1:1").await;
+            let mut parent_state = ParentState::new(&ReconcileBackoff::default());
+            let cancellation = CancellationToken::new();
+            parent_state.start_sync(cancellation.clone());
+            state
+                .parent_states
+                .insert("parent-uid".to_owned(), parent_state);
+
+            let mut to_sync = WorkQueue::new();
+            state.handle_received_message(parent_message(EventType::PeriodicResync), &mut to_sync);
+            assert_eq!(1, to_sync.len());
+            assert!(!cancellation.is_cancelled());
+
+            state.handle_received_message(parent_message(EventType::Updated), &mut to_sync);
+            assert_eq!(1, to_sync.len());
+            assert!(cancellation.is_cancelled());
+            state.running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn only_changes_to_the_parent_are_debounced() {
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let config = OperatorConfig::new("test-operator", crate::k8s_types::core::v1::Pod)
+                .within_namespace("ns")
+                .reconcile_debounce(Duration::from_secs(60));
+            let mut state = test_operator_state(config, "http://127.0.0.1:1").await;
+            let mut to_sync = WorkQueue::new();
+
+            state.handle_received_message(parent_message(EventType::PeriodicResync), &mut to_sync);
+            assert_eq!(
+                vec!["parent-uid".to_owned()],
+                to_sync.take_ready(10, |_| true)
+            );
+
+            let mut requested = parent_message(EventType::ReconcileRequested);
+            requested.index_key = None;
+            state.handle_received_message(requested, &mut to_sync);
+            assert_eq!(1, state.requested_reconciles.len());
+            assert!(to_sync.is_empty());
+
+            state.handle_received_message(parent_message(EventType::Updated), &mut to_sync);
+            assert!(to_sync.take_ready(10, |_| true).is_empty());
+            assert!(to_sync.next_ready_at().unwrap() > Instant::now() + Duration::from_secs(50));
+            state.running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    /// The bodies of the watches that a mock api server is streaming, by request path
+    type WatchBodies = Arc<Mutex<HashMap<String, Vec<hyper::body::Sender>>>>;
+
+    /// Starts an api server that responds to lists with the given items for each path, or an empty list, and keeps
+    /// every watch open until an event is sent to it using `send_watch_event`
+    async fn start_mock_api_server(
+        lists: Vec<(&'static str, Vec<serde_json::Value>)>,
+    ) -> (std::net::SocketAddr, WatchBodies) {
+        let lists: Arc<HashMap<&'static str, Vec<serde_json::Value>>> =
+            Arc::new(lists.into_iter().collect());
+        let watches = WatchBodies::default();
+        let recorded_watches = watches.clone();
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let (lists, watches) = (lists.clone(), recorded_watches.clone());
+            async move {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(
+                    move |req: http::Request<hyper::Body>| {
+                        let path = req.uri().path().to_owned();
+                        let is_watch = req.uri().query().unwrap_or("").contains("watch=true");
+                        let body = if is_watch {
+                            let (sender, body) = hyper::Body::channel();
+                            let mut watches = watches.lock().unwrap();
+                            watches.entry(path).or_default().push(sender);
+                            body
+                        } else {
+                            let items = lists.get(path.as_str()).cloned().unwrap_or_default();
+                            let list = serde_json::json!({
+                                "metadata": {"resourceVersion": "1"},
+                                "items": items,
+                            });
+                            hyper::Body::from(list.to_string())
+                        };
+                        async move { Ok::<_, hyper::Error>(http::Response::new(body)) }
+                    },
+                ))
+            }
+        });
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, watches)
+    }
+
+    /// Waits for a watch of the given path to be started, and then sends the event to it
+    async fn send_watch_event(watches: &WatchBodies, path: &str, event: serde_json::Value) {
+        let start = Instant::now();
+        loop {
+            let sender = watches.lock().unwrap().get_mut(path).and_then(Vec::pop);
+            if let Some(mut sender) = sender {
+                let line = format!("{}\n", event);
+                sender.send_data(line.into()).await.unwrap();
+                watches
+                    .lock()
+                    .unwrap()
+                    .entry(path.to_owned())
+                    .or_default()
+                    .push(sender);
+                return;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "no watch of {} was started",
+                path
+            );
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Handles the messages that the informers send until one matches the predicate
+    async fn handle_messages_until(
+        state: &mut OperatorState,
+        to_sync: &mut WorkQueue,
+        predicate: impl Fn(&ResourceMessage) -> bool,
+    ) {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), state.receiver.recv())
+                .await
+                .expect("timed out waiting for a message")
+                .expect("channel was closed");
+            let matched = predicate(&message);
+            state.handle_received_message(message, to_sync);
+            if matched {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn changes_to_an_additional_watched_type_queue_the_mapped_parents() {
+        use crate::k8s_types::core::v1::{Pod, Secret};
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let parent = serde_json::json!({
+                "metadata": {"namespace": "ns", "name": "parent", "uid": "parent-uid", "resourceVersion": "1"},
+            });
+            let (addr, watches) =
+                start_mock_api_server(vec![("/api/v1/namespaces/ns/pods", vec![parent])]).await;
+            let config = OperatorConfig::new("test-operator", Pod).within_namespace("ns");
+            let mut state = test_operator_state(config, &format!("http://{}", addr)).await;
+            let mut to_sync = WorkQueue::new();
+            handle_messages_until(&mut state, &mut to_sync, |message| {
+                message.resource_type == Pod
+            })
+            .await;
+            to_sync.take_ready(10, |_| true);
+
+            let mapper = ParentMapper::new(|_| {
+                vec![ObjectId::new("ns".to_owned(), "parent".to_owned())]
+            });
+            let request = ResourceMessage {
+                event_type: EventType::WatchRequested(mapper),
+                resource_type: Secret,
+                resource_id: ObjectId::new(String::new(), String::new()),
+                index_key: None,
+            };
+            state.handle_received_message(request, &mut to_sync);
+            assert_eq!(1, state.watched.len());
+            assert_eq!(2, state.watch_statuses.lock().unwrap().len());
+
+            let secret = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": {"namespace": "ns", "name": "secret", "uid": "secret-uid", "resourceVersion": "2"},
+            });
+            let event = serde_json::json!({"type": "ADDED", "object": secret});
+            send_watch_event(&watches, "/api/v1/namespaces/ns/secrets", event).await;
+            handle_messages_until(&mut state, &mut to_sync, |message| {
+                matches!(message.event_type, EventType::ReconcileRequested)
+            })
+            .await;
+            state.queue_requested_reconciles(&mut to_sync).await;
+            assert_eq!(
+                vec!["parent-uid".to_owned()],
+                to_sync.take_ready(10, |_| true)
+            );
+            assert!(state.watch_statuses.lock().unwrap().iter().all(|s| s.is_synced()));
+            state.running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn namespace_label_changes_queue_the_parents_in_that_namespace() {
+        use crate::k8s_types::core::v1::{Namespace, Pod};
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let parents = vec![
+                serde_json::json!({
+                    "metadata": {"namespace": "tenant-a", "name": "parent", "uid": "uid-a", "resourceVersion": "1"},
+                }),
+                serde_json::json!({
+                    "metadata": {"namespace": "tenant-b", "name": "parent", "uid": "uid-b", "resourceVersion": "1"},
+                }),
+            ];
+            let tenant_a = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Namespace",
+                "metadata": {"name": "tenant-a", "uid": "ns-uid-a", "resourceVersion": "1", "labels": {"tenant": "true"}},
+            });
+            let (addr, watches) = start_mock_api_server(vec![
+                ("/api/v1/pods", parents),
+                ("/api/v1/namespaces", vec![tenant_a.clone()]),
+            ])
+            .await;
+            let config = OperatorConfig::new("test-operator", Pod).namespaces_matching("tenant=true");
+            let mut state = test_operator_state(config, &format!("http://{}", addr)).await;
+            let mut to_sync = WorkQueue::new();
+            let received = std::cell::Cell::new(0);
+            handle_messages_until(&mut state, &mut to_sync, |_| {
+                received.set(received.get() + 1);
+                received.get() == 3
+            })
+            .await;
+            state.queue_parents_in_changed_namespaces(&mut to_sync).await;
+            to_sync.take_ready(10, |_| true);
+
+            let parent_a = K8sResource::from_value(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": {"namespace": "tenant-a", "name": "parent", "uid": "uid-a", "resourceVersion": "1"},
+            }))
+            .unwrap();
+            let parent_b = K8sResource::from_value(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": {"namespace": "tenant-b", "name": "parent", "uid": "uid-b", "resourceVersion": "1"},
+            }))
+            .unwrap();
+            assert!(state.is_namespace_selected(&parent_a).await.unwrap());
+            assert!(!state.is_namespace_selected(&parent_b).await.unwrap());
+
+            // tenant-b is labeled, so it starts to match the selector
+            let tenant_b = serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Namespace",
+                "metadata": {"name": "tenant-b", "uid": "ns-uid-b", "resourceVersion": "2", "labels": {"tenant": "true"}},
+            });
+            let event = serde_json::json!({"type": "ADDED", "object": tenant_b});
+            send_watch_event(&watches, "/api/v1/namespaces", event).await;
+            handle_messages_until(&mut state, &mut to_sync, |message| {
+                message.resource_type == Namespace
+            })
+            .await;
+            assert!(to_sync.is_empty());
+            state.queue_parents_in_changed_namespaces(&mut to_sync).await;
+            assert_eq!(vec!["uid-b".to_owned()], to_sync.take_ready(10, |_| true));
+            assert!(state.is_namespace_selected(&parent_b).await.unwrap());
+
+            // tenant-a's label is removed, so its parent is queued, but it's no longer selected when it's synced
+            let event = serde_json::json!({"type": "DELETED", "object": tenant_a});
+            send_watch_event(&watches, "/api/v1/namespaces", event).await;
+            handle_messages_until(&mut state, &mut to_sync, |message| {
+                message.resource_type == Namespace
+            })
+            .await;
+            state.queue_parents_in_changed_namespaces(&mut to_sync).await;
+            assert_eq!(vec!["uid-a".to_owned()], to_sync.take_ready(10, |_| true));
+            assert!(!state.is_namespace_selected(&parent_a).await.unwrap());
+            state.running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn changes_to_namespace_parents_are_not_mistaken_for_namespace_selector_changes() {
+        use crate::k8s_types::core::v1::Namespace;
+
+        let mut runtime = runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let config =
+                OperatorConfig::new("test-operator", Namespace).namespaces_matching("tenant=true");
+            let mut state = test_operator_state(config, "http://127.0.0.1:1").await;
+            let mut to_sync = WorkQueue::new();
+
+            let message = ResourceMessage {
+                event_type: EventType::Updated,
+                resource_type: Namespace,
+                resource_id: ObjectId::new(String::new(), "tenant-a".to_owned()),
+                index_key: Some("ns-uid-a".to_owned()),
+            };
+            state.handle_received_message(message, &mut to_sync);
+            assert!(state.changed_namespaces.is_empty());
+            assert_eq!(
+                vec!["ns-uid-a".to_owned()],
+                to_sync.take_ready(10, |_| true)
+            );
+            state.running.store(false, Ordering::Relaxed);
+        });
+    }
+
+    #[test]
+    fn operator_handle_waits_for_watches_and_sends_reconcile_requests() {
+        use crate::k8s_types::core::v1::Pod;
+
+        let handle = OperatorHandle::new(
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(Notify::new()),
+            Pod,
+        );
+        assert!(!handle.trigger_reconcile(Some("default"), "foo"));
+        assert!(!handle.await_ready(Duration::from_millis(20)));
+
+        let status = Arc::new(WatchStatus::new());
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        *handle.shared.watch_statuses.lock().unwrap() = Arc::new(Mutex::new(vec![status.clone()]));
+        *handle.shared.sender.lock().unwrap() = Some(tx);
+        assert!(!handle.is_ready());
+        status.set_synced();
+        assert!(handle.await_ready(Duration::from_millis(20)));
+
+        assert!(handle.trigger_reconcile(Some("default"), "foo"));
+        let message = rx.try_recv().unwrap();
+        assert!(matches!(message.event_type, EventType::ReconcileRequested));
+        assert_eq!(ObjectIdRef::new("default", "foo"), message.resource_id);
+        assert_eq!(None, message.index_key);
+
+        assert!(handle.watch_additional(crate::k8s_types::core::v1::Secret, |_| Vec::new()));
+        let message = rx.try_recv().unwrap();
+        assert!(matches!(message.event_type, EventType::WatchRequested(_)));
+        assert_eq!(crate::k8s_types::core::v1::Secret, message.resource_type);
+
+        handle.shutdown_now();
+        assert!(rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,95 @@
+//! A read-only view of what the operator keeps track of for each parent, for dashboards and admin tooling
+use crate::resource::ObjectId;
+use crate::runner::reconcile::SyncFailure;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The statuses of all of the parents that have been synced, keyed by uid, which are shared between the operator and
+/// the `OperatorHandle` and admin endpoints
+pub(crate) type ParentStatuses = Arc<Mutex<HashMap<String, ParentStatus>>>;
+
+/// Returns a copy of all of the statuses, sorted by namespace and name
+pub(crate) fn sorted(statuses: &ParentStatuses) -> Vec<ParentStatus> {
+    let mut sorted = statuses
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    sorted.sort_by(|a, b| (a.id.namespace(), a.id.name()).cmp(&(b.id.namespace(), b.id.name())));
+    sorted
+}
+
+/// The outcome of the most recent sync or finalize of a parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcileOutcome {
+    Success,
+    /// The sync failed, and will be retried after a backoff
+    RetryableError,
+    /// The sync failed, and won't be retried until the parent is changed
+    FatalError,
+    /// The finalize handler reported that cleanup isn't done yet
+    FinalizeIncomplete,
+}
+
+impl ReconcileOutcome {
+    pub(crate) fn from_result(result: &Result<Option<Duration>, SyncFailure>) -> ReconcileOutcome {
+        match result {
+            Ok(_) => ReconcileOutcome::Success,
+            Err(SyncFailure::Retry(_)) => ReconcileOutcome::RetryableError,
+            Err(SyncFailure::NoRetry) => ReconcileOutcome::FatalError,
+        }
+    }
+
+    /// Returns the outcome in snake case, which is the same as the `outcome` label of the `reconcile_outcomes` metric
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReconcileOutcome::Success => "success",
+            ReconcileOutcome::RetryableError => "retryable_error",
+            ReconcileOutcome::FatalError => "fatal_error",
+            ReconcileOutcome::FinalizeIncomplete => "finalize_incomplete",
+        }
+    }
+}
+
+/// What the operator currently knows about one of the parents that it's synced, as returned by
+/// `OperatorHandle::parent_statuses`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentStatus {
+    pub uid: String,
+    pub id: ObjectId,
+    /// Whether the parent is being synced or finalized right now
+    pub in_progress: bool,
+    /// The number of times that the parent has been synced or finalized since the operator started
+    pub sync_count: u32,
+    /// When the most recent sync or finalize was started
+    pub last_sync_time: Option<SystemTime>,
+    /// The outcome of the most recent sync or finalize that completed, or `None` if none have completed yet
+    pub last_outcome: Option<ReconcileOutcome>,
+    /// How long after the most recent sync completed that the parent is scheduled to be synced again, which is either
+    /// the error backoff or the delay that was requested by the handler. `None` if it's only synced again when
+    /// something changes.
+    pub retry_after: Option<Duration>,
+}
+
+impl ParentStatus {
+    pub(crate) fn to_json(&self) -> Value {
+        serde_json::json!({
+            "uid": self.uid,
+            "namespace": self.id.namespace(),
+            "name": self.id.name(),
+            "inProgress": self.in_progress,
+            "syncCount": self.sync_count,
+            "lastSyncTime": self.last_sync_time.map(|time| {
+                DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+            }),
+            "lastOutcome": self.last_outcome.map(ReconcileOutcome::as_str),
+            "retryAfterMillis": self.retry_after.map(|delay| delay.as_millis() as u64),
+        })
+    }
+}
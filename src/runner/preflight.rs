@@ -0,0 +1,330 @@
+//! Checks that the operator has all of the RBAC permissions that it needs before it starts
+use crate::config::{OperatorConfig, UpdateStrategy};
+use crate::k8s_types::apps::v1::{Deployment, ReplicaSet, StatefulSet};
+use crate::k8s_types::coordination_k8s_io::v1::Lease;
+use crate::k8s_types::core::v1::{Namespace, ReplicationController};
+use crate::k8s_types::events_k8s_io::v1::Event;
+use crate::k8s_types::K8sType;
+use crate::runner::client::Client;
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+/// A single verb on a type of resource that the operator needs to be allowed to perform
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Permission {
+    pub verb: &'static str,
+    pub k8s_type: &'static K8sType,
+    pub subresource: Option<&'static str>,
+    /// The namespace that the permission is needed in, or `None` if it's needed across the whole cluster
+    pub namespace: Option<String>,
+}
+
+impl Permission {
+    fn to_resource_attributes(&self) -> Value {
+        serde_json::json!({
+            "verb": self.verb,
+            "group": self.k8s_type.group(),
+            "version": self.k8s_type.version(),
+            "resource": self.k8s_type.plural_kind,
+            "subresource": self.subresource.unwrap_or_default(),
+            "namespace": self.namespace.as_deref().unwrap_or_default(),
+        })
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.verb, self.k8s_type)?;
+        if let Some(subresource) = self.subresource {
+            write!(f, "/{}", subresource)?;
+        }
+        match self.namespace.as_ref() {
+            Some(namespace) => write!(f, " in namespace '{}'", namespace),
+            None => f.write_str(" cluster-wide"),
+        }
+    }
+}
+
+/// Error returned when the `preflight_rbac_check` finds that the operator is missing some permissions
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MissingPermissions(pub Vec<Permission>);
+
+impl Display for MissingPermissions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Missing {} RBAC permission(s): ", self.0.len())?;
+        for (i, permission) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            Display::fmt(permission, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingPermissions {}
+
+/// The built-in types that have a `scale` subresource, which is used to scale children that are returned in the
+/// `child_scales` of a `SyncResponse`
+static SCALABLE_TYPES: &[&K8sType] = &[Deployment, ReplicaSet, StatefulSet, ReplicationController];
+
+/// Returns every permission that the operator needs, given its configuration
+pub(crate) fn required_permissions(config: &OperatorConfig) -> Vec<Permission> {
+    let mut permissions = Vec::new();
+
+    // `get` is used to re-read the parent when patching its finalizers conflicts with a concurrent change
+    let mut parent_verbs = vec!["get", "list", "watch", "patch"];
+    if !config.status_subresource {
+        parent_verbs.push("update");
+    }
+    add_permissions(&mut permissions, config, config.parent, None, &parent_verbs);
+    if config.status_subresource {
+        add_permissions(
+            &mut permissions,
+            config,
+            config.parent,
+            Some("status"),
+            &["update"],
+        );
+    }
+
+    for child in config.child_types.iter() {
+        // `get` is used to re-read the children after a conflicting update, and to read an existing child when adopting it
+        let mut child_verbs = vec!["get", "list", "watch", "create", "delete"];
+        match child.config.update_strategy {
+            UpdateStrategy::Replace => child_verbs.push("update"),
+            UpdateStrategy::Patch | UpdateStrategy::ServerSideApply => child_verbs.push("patch"),
+            UpdateStrategy::Recreate | UpdateStrategy::OnDelete => {}
+        }
+        add_permissions(&mut permissions, config, child.k8s_type, None, &child_verbs);
+        if SCALABLE_TYPES.contains(&child.k8s_type) {
+            // the current replicas of metadata only children are read from the scale subresource
+            let scale_verbs: &[&'static str] = if child.config.metadata_only {
+                &["get", "update"]
+            } else {
+                &["update"]
+            };
+            add_permissions(
+                &mut permissions,
+                config,
+                child.k8s_type,
+                Some("scale"),
+                scale_verbs,
+            );
+        }
+    }
+
+    for watched in config.watched_types.iter() {
+        add_permissions(&mut permissions, config, watched, None, &["list", "watch"]);
+    }
+
+    if config.namespace_selector.is_some() {
+        add_permissions(
+            &mut permissions,
+            config,
+            Namespace,
+            None,
+            &["list", "watch"],
+        );
+    }
+    if config.emit_events {
+        add_permissions(&mut permissions, config, Event, None, &["create", "patch"]);
+    }
+    if let Some(leader_election) = config.resolved_leader_election() {
+        for verb in ["get", "create", "update"].iter() {
+            permissions.push(Permission {
+                verb,
+                k8s_type: Lease,
+                subresource: None,
+                namespace: Some(leader_election.lease_namespace.clone()),
+            });
+        }
+    }
+    permissions
+}
+
+/// Adds the verbs for the given type in each of the configured namespaces, or across the whole cluster
+fn add_permissions(
+    permissions: &mut Vec<Permission>,
+    config: &OperatorConfig,
+    k8s_type: &'static K8sType,
+    subresource: Option<&'static str>,
+    verbs: &[&'static str],
+) {
+    let namespaces = match config.namespaces.as_ref() {
+        Some(namespaces) if k8s_type.is_namespaced() => {
+            namespaces.iter().cloned().map(Some).collect::<Vec<_>>()
+        }
+        _ => vec![None],
+    };
+    for namespace in namespaces {
+        for verb in verbs {
+            permissions.push(Permission {
+                verb,
+                k8s_type,
+                subresource,
+                namespace: namespace.clone(),
+            });
+        }
+    }
+}
+
+/// Checks each of the required permissions with the api server, if the `preflight_rbac_check` is enabled. Permissions
+/// that can't be checked, because the review request itself failed, are logged and otherwise ignored, so that a flaky
+/// api server doesn't prevent the operator from starting. Permissions for child types that are in another cluster are
+/// checked with that cluster's api server.
+pub(crate) async fn check_rbac(
+    client: &Client,
+    child_clients: &HashMap<&'static K8sType, Client>,
+    config: &OperatorConfig,
+) -> Result<(), MissingPermissions> {
+    if !config.preflight_rbac_check {
+        return Ok(());
+    }
+    let mut missing = Vec::new();
+    for permission in required_permissions(config) {
+        match child_clients
+            .get(permission.k8s_type)
+            .unwrap_or(client)
+            .check_access(permission.to_resource_attributes())
+            .await
+        {
+            Ok(true) => {
+                log::debug!("RBAC check passed for: {}", permission);
+            }
+            Ok(false) => missing.push(permission),
+            Err(err) => {
+                log::warn!("Unable to check RBAC permission to {}: {}", permission, err);
+            }
+        }
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(MissingPermissions(missing))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ChildConfig;
+    use crate::k8s_types::apps::v1::Deployment;
+    use crate::k8s_types::core::v1::{ConfigMap, Secret};
+
+    static PARENT_TYPE: &K8sType = &K8sType {
+        api_version: "example.com/v1",
+        kind: "Parent",
+        plural_kind: "parents",
+        scope: crate::k8s_types::Scope::Namespaced,
+    };
+
+    fn describe(permissions: &[Permission]) -> Vec<String> {
+        permissions.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn required_permissions_include_parent_children_and_watched_types_in_each_namespace() {
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .within_namespaces(vec!["ns-a".to_owned(), "ns-b".to_owned()])
+            .with_child(Deployment, ChildConfig::patch())
+            .with_child(ConfigMap, ChildConfig::replace())
+            .with_watched_type(Secret)
+            .emit_events(false);
+
+        let actual = describe(&required_permissions(&config));
+        let expected = vec![
+            "get example.com/v1/parents in namespace 'ns-a'",
+            "list example.com/v1/parents in namespace 'ns-a'",
+            "watch example.com/v1/parents in namespace 'ns-a'",
+            "patch example.com/v1/parents in namespace 'ns-a'",
+            "get example.com/v1/parents in namespace 'ns-b'",
+            "list example.com/v1/parents in namespace 'ns-b'",
+            "watch example.com/v1/parents in namespace 'ns-b'",
+            "patch example.com/v1/parents in namespace 'ns-b'",
+            "update example.com/v1/parents/status in namespace 'ns-a'",
+            "update example.com/v1/parents/status in namespace 'ns-b'",
+            "get apps/v1/deployments in namespace 'ns-a'",
+            "list apps/v1/deployments in namespace 'ns-a'",
+            "watch apps/v1/deployments in namespace 'ns-a'",
+            "create apps/v1/deployments in namespace 'ns-a'",
+            "delete apps/v1/deployments in namespace 'ns-a'",
+            "patch apps/v1/deployments in namespace 'ns-a'",
+            "get apps/v1/deployments in namespace 'ns-b'",
+            "list apps/v1/deployments in namespace 'ns-b'",
+            "watch apps/v1/deployments in namespace 'ns-b'",
+            "create apps/v1/deployments in namespace 'ns-b'",
+            "delete apps/v1/deployments in namespace 'ns-b'",
+            "patch apps/v1/deployments in namespace 'ns-b'",
+            "update apps/v1/deployments/scale in namespace 'ns-a'",
+            "update apps/v1/deployments/scale in namespace 'ns-b'",
+            "get v1/configmaps in namespace 'ns-a'",
+            "list v1/configmaps in namespace 'ns-a'",
+            "watch v1/configmaps in namespace 'ns-a'",
+            "create v1/configmaps in namespace 'ns-a'",
+            "delete v1/configmaps in namespace 'ns-a'",
+            "update v1/configmaps in namespace 'ns-a'",
+            "get v1/configmaps in namespace 'ns-b'",
+            "list v1/configmaps in namespace 'ns-b'",
+            "watch v1/configmaps in namespace 'ns-b'",
+            "create v1/configmaps in namespace 'ns-b'",
+            "delete v1/configmaps in namespace 'ns-b'",
+            "update v1/configmaps in namespace 'ns-b'",
+            "list v1/secrets in namespace 'ns-a'",
+            "watch v1/secrets in namespace 'ns-a'",
+            "list v1/secrets in namespace 'ns-b'",
+            "watch v1/secrets in namespace 'ns-b'",
+        ];
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn required_permissions_are_cluster_wide_without_namespaces() {
+        let mut config = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .with_leader_election("test-lease", "operators")
+            .namespaces_matching("tenant=true");
+        config.status_subresource = false;
+
+        let actual = describe(&required_permissions(&config));
+        let expected = vec![
+            "get example.com/v1/parents cluster-wide",
+            "list example.com/v1/parents cluster-wide",
+            "watch example.com/v1/parents cluster-wide",
+            "patch example.com/v1/parents cluster-wide",
+            "update example.com/v1/parents cluster-wide",
+            "list v1/namespaces cluster-wide",
+            "watch v1/namespaces cluster-wide",
+            "create events.k8s.io/v1/events cluster-wide",
+            "patch events.k8s.io/v1/events cluster-wide",
+            "get coordination.k8s.io/v1/leases in namespace 'operators'",
+            "create coordination.k8s.io/v1/leases in namespace 'operators'",
+            "update coordination.k8s.io/v1/leases in namespace 'operators'",
+        ];
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn missing_permissions_lists_each_permission() {
+        let err = MissingPermissions(vec![
+            Permission {
+                verb: "watch",
+                k8s_type: Deployment,
+                subresource: None,
+                namespace: None,
+            },
+            Permission {
+                verb: "update",
+                k8s_type: PARENT_TYPE,
+                subresource: Some("status"),
+                namespace: Some("ns-a".to_owned()),
+            },
+        ]);
+        assert_eq!(
+            "Missing 2 RBAC permission(s): watch apps/v1/deployments cluster-wide, update example.com/v1/parents/status in namespace 'ns-a'",
+            err.to_string()
+        );
+    }
+}
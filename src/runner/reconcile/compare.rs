@@ -101,24 +101,263 @@ enum Segment<'a> {
 /// the items in the desired array are objects that contain a `name` field with a String value, then
 /// we'll consider the array to be associative.
 pub fn compare_values<'a>(existing: &'a Value, desired: &'a Value) -> Diffs<'a> {
+    compare_values_ignoring(existing, desired, &[])
+}
+
+/// The same as `compare_values`, except that differences at any of the `ignored_paths`, or nested within them, are
+/// not included in the diff. Each path is a JSON pointer, where a `*` matches any key or array index, for example
+/// `/spec/ports/*/nodePort`. A desired value of `null` is also considered to be the same as a missing value, since
+/// the api server doesn't store nulls.
+pub fn compare_values_ignoring<'a>(
+    existing: &'a Value,
+    desired: &'a Value,
+    ignored_paths: &[String],
+) -> Diffs<'a> {
+    let ignored = IgnoredPaths::new(ignored_paths);
     let mut diffs = Vec::new();
     let mut path = Vec::with_capacity(8);
-    compare(&mut diffs, &mut path, existing, desired);
+    compare(&mut diffs, &mut path, &ignored, existing, desired);
     Diffs(diffs)
 }
 
+/// Creates a merge patch that will update `existing` to include everything in `desired`. The patch only
+/// includes the fields that `compare_values` reports as different, so fields that were set by other
+/// controllers or by the api server are left alone. Arrays are always included in their entirety, since
+/// neither merge patch format allows for patching individual array elements by index. Fields at any of the
+/// `ignored_paths` are never changed by the patch. If one is inside an array that has to be included, then the
+/// array element keeps the existing value of that field, or leaves it out if there isn't one.
+pub fn make_patch(existing: &Value, desired: &Value, ignored_paths: &[String]) -> Value {
+    let ignored = IgnoredPaths::new(ignored_paths);
+    let mut path = Vec::with_capacity(8);
+    patch_value(&mut path, &ignored, existing, desired)
+}
+
+/// Adds a `null` to the `patch` for each field that's in the `last_applied` state and the `existing` value, but isn't
+/// in `desired`, so that fields that were previously set by the operator are removed once they're no longer desired.
+/// Fields that were never in `last_applied` were set by someone else, so they're left alone. This is the same
+/// three-way merge that `kubectl apply` does. Fields at any of the `ignored_paths` are never removed.
+pub fn add_removed_fields(
+    patch: &mut Value,
+    existing: &Value,
+    desired: &Value,
+    last_applied: &Value,
+    ignored_paths: &[String],
+) {
+    let ignored = IgnoredPaths::new(ignored_paths);
+    let mut path = Vec::with_capacity(8);
+    if let Some(patch_obj) = patch.as_object_mut() {
+        remove_fields(
+            &mut path,
+            &ignored,
+            patch_obj,
+            existing,
+            desired,
+            last_applied,
+        );
+    }
+}
+
+fn remove_fields<'a>(
+    path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
+    patch: &mut JsonObject,
+    existing: &'a Value,
+    desired: &'a Value,
+    last_applied: &'a Value,
+) {
+    let (existing_map, last_applied_map) = match (existing, last_applied) {
+        (Value::Object(e), Value::Object(l)) => (e, l),
+        _ => return,
+    };
+    for (key, last_applied_val) in last_applied_map.iter() {
+        let existing_val = match existing_map.get(key) {
+            Some(val) if !val.is_null() => val,
+            _ => continue,
+        };
+        path.push(Segment::Key(key));
+        if ignored.matches(path) {
+            path.pop();
+            continue;
+        }
+        match desired.get(key).filter(|val| !val.is_null()) {
+            None => {
+                patch.insert(key.clone(), Value::Null);
+            }
+            Some(desired_val)
+                if desired_val.is_object()
+                    && existing_val.is_object()
+                    && last_applied_val.is_object() =>
+            {
+                let mut nested = match patch.remove(key) {
+                    Some(Value::Object(nested)) => nested,
+                    _ => JsonObject::new(),
+                };
+                remove_fields(
+                    path,
+                    ignored,
+                    &mut nested,
+                    existing_val,
+                    desired_val,
+                    last_applied_val,
+                );
+                if !nested.is_empty() {
+                    patch.insert(key.clone(), Value::Object(nested));
+                }
+            }
+            Some(_) => {}
+        }
+        path.pop();
+    }
+}
+
+fn patch_value<'a>(
+    path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
+    existing: &'a Value,
+    desired: &'a Value,
+) -> Value {
+    match (existing, desired) {
+        (Value::Object(ref existing_map), Value::Object(ref desired_map)) => {
+            let mut patch = JsonObject::new();
+            for (key, desired_val) in desired_map.iter() {
+                path.push(Segment::Key(key));
+                if ignored.matches(path) {
+                    path.pop();
+                    continue;
+                }
+                match existing_map.get(key) {
+                    Some(existing_val) if existing_val.is_object() && desired_val.is_object() => {
+                        let nested = patch_value(path, ignored, existing_val, desired_val);
+                        if nested.as_object().map(|o| !o.is_empty()).unwrap_or(true) {
+                            patch.insert(key.clone(), nested);
+                        }
+                    }
+                    Some(existing_val) if is_same(path, ignored, existing_val, desired_val) => {}
+                    None if desired_val.is_null() => {}
+                    existing_val => {
+                        if let Some(value) =
+                            without_ignored(path, ignored, existing_val, desired_val)
+                        {
+                            patch.insert(key.clone(), value);
+                        }
+                    }
+                }
+                path.pop();
+            }
+            Value::Object(patch)
+        }
+        (_, other) => other.clone(),
+    }
+}
+
+/// Returns a copy of `desired` where each of the `ignored_paths` has the value from `existing` instead, or is left out
+/// if `existing` doesn't have it. Array elements are matched up the same way as in `compare_arrays`. Returns `None` if
+/// the path of `desired` itself is ignored and there's no existing value.
+fn without_ignored<'a>(
+    path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
+    existing: Option<&'a Value>,
+    desired: &'a Value,
+) -> Option<Value> {
+    if ignored.matches(path) {
+        return existing.cloned();
+    }
+    match desired {
+        Value::Object(desired_map) => {
+            let mut copy = JsonObject::new();
+            for (key, desired_val) in desired_map.iter() {
+                path.push(Segment::Key(key));
+                let existing_val = existing.and_then(|e| e.get(key));
+                if let Some(value) = without_ignored(path, ignored, existing_val, desired_val) {
+                    copy.insert(key.clone(), value);
+                }
+                path.pop();
+            }
+            Some(Value::Object(copy))
+        }
+        Value::Array(desired_array) => {
+            let existing_array = existing
+                .and_then(Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let associative = is_associative(existing_array, desired_array);
+            let mut copy = Vec::with_capacity(desired_array.len());
+            for (i, desired_item) in desired_array.iter().enumerate() {
+                let existing_item = if associative {
+                    let name = desired_item.get("name");
+                    existing_array.iter().find(|e| e.get("name") == name)
+                } else {
+                    existing_array.get(i)
+                };
+                path.push(Segment::Index(i));
+                if let Some(value) = without_ignored(path, ignored, existing_item, desired_item) {
+                    copy.push(value);
+                }
+                path.pop();
+            }
+            Some(Value::Array(copy))
+        }
+        other => Some(other.clone()),
+    }
+}
+
+fn is_same<'a>(
+    path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
+    existing: &'a Value,
+    desired: &'a Value,
+) -> bool {
+    let mut diffs = Vec::new();
+    compare(&mut diffs, path, ignored, existing, desired);
+    diffs.is_empty()
+}
+
+/// The parsed form of the JSON pointers that are ignored when comparing values
+struct IgnoredPaths(Vec<Vec<String>>);
+
+impl IgnoredPaths {
+    fn new(paths: &[String]) -> IgnoredPaths {
+        let parsed = paths
+            .iter()
+            .map(|path| {
+                path.split('/')
+                    .skip(1)
+                    .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+                    .collect()
+            })
+            .collect();
+        IgnoredPaths(parsed)
+    }
+
+    /// Returns true if the given path is exactly one of the ignored paths
+    fn matches(&self, path: &[Segment]) -> bool {
+        self.0.iter().any(|ignored| {
+            ignored.len() == path.len()
+                && ignored
+                    .iter()
+                    .zip(path.iter())
+                    .all(|(ignored, segment)| match segment {
+                        _ if ignored == "*" => true,
+                        Segment::Key(key) => ignored == key,
+                        Segment::Index(i) => ignored.parse::<usize>() == Ok(*i),
+                    })
+        })
+    }
+}
+
 fn compare<'a>(
     diffs: &mut Vec<Diff<'a>>,
     path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
     superset: &'a Value,
     subset: &'a Value,
 ) {
     match (superset, subset) {
         (Value::Object(ref super_map), Value::Object(ref sub_map)) => {
-            compare_objects(diffs, path, super_map, sub_map);
+            compare_objects(diffs, path, ignored, super_map, sub_map);
         }
         (Value::Array(ref super_array), Value::Array(ref sub_array)) => {
-            compare_arrays(diffs, path, super_array, sub_array);
+            compare_arrays(diffs, path, ignored, super_array, sub_array);
         }
         (a, b) if a != b => {
             diffs.push(diff(&*path, a, b));
@@ -130,37 +369,44 @@ fn compare<'a>(
 fn compare_objects<'a>(
     diffs: &mut Vec<Diff<'a>>,
     path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
     existing: &'a JsonObject,
     desired: &'a JsonObject,
 ) {
     for (key, desired_val) in desired.iter() {
-        check_value(diffs, path, existing, key, desired_val);
+        check_value(diffs, path, ignored, existing, key, desired_val);
     }
 }
 
 fn compare_arrays<'a>(
     diffs: &mut Vec<Diff<'a>>,
     path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
     existing: &'a Vec<Value>,
     desired: &'a Vec<Value>,
 ) {
     if is_associative(existing, desired) {
-        compare_associative_arrays(diffs, path, existing, desired);
+        compare_associative_arrays(diffs, path, ignored, existing, desired);
     } else {
-        compare_non_associative_arrays(diffs, path, existing, desired);
+        compare_non_associative_arrays(diffs, path, ignored, existing, desired);
     }
 }
 
 fn compare_non_associative_arrays<'a>(
     diffs: &mut Vec<Diff<'a>>,
     path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
     existing: &'a Vec<Value>,
     desired: &'a Vec<Value>,
 ) {
     for (i, desired_item) in desired.iter().enumerate() {
         path.push(Segment::Index(i));
+        if ignored.matches(path) {
+            path.pop();
+            continue;
+        }
         if existing.len() > i {
-            compare(diffs, path, &existing[i], desired_item);
+            compare(diffs, path, ignored, &existing[i], desired_item);
         } else {
             diffs.push(diff(&*path, &Value::Null, desired_item));
         }
@@ -171,11 +417,16 @@ fn compare_non_associative_arrays<'a>(
 fn compare_associative_arrays<'a>(
     diffs: &mut Vec<Diff<'a>>,
     path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
     existing: &'a Vec<Value>,
     desired: &'a Vec<Value>,
 ) {
     for (i, desired_val) in desired.iter().enumerate() {
         path.push(Segment::Index(i));
+        if ignored.matches(path) {
+            path.pop();
+            continue;
+        }
         // These are only safe unwraps because we check them in `is_associative`
         let name = desired_val.get("name").unwrap().as_str().unwrap();
 
@@ -186,7 +437,7 @@ fn compare_associative_arrays<'a>(
                 .unwrap_or(false)
         });
         if let Some(existing_match) = existing_item {
-            compare(diffs, path, existing_match, desired_val);
+            compare(diffs, path, ignored, existing_match, desired_val);
         } else {
             diffs.push(diff(&*path, &Value::Null, desired_val));
         }
@@ -205,6 +456,7 @@ fn is_associative(_existing: &[Value], desired: &[Value]) -> bool {
 fn check_value<'a>(
     diffs: &mut Vec<Diff<'a>>,
     path: &mut Vec<Segment<'a>>,
+    ignored: &IgnoredPaths,
     existing: &'a JsonObject,
     key: &'a str,
     value: &'a Value,
@@ -212,9 +464,11 @@ fn check_value<'a>(
     path.push(Segment::Key(key));
 
     match existing.get(key) {
+        _ if ignored.matches(path) => {}
         Some(super_val) => {
-            compare(diffs, path, super_val, value);
+            compare(diffs, path, ignored, super_val, value);
         }
+        None if value.is_null() => {}
         None => {
             diffs.push(diff(&*path, &Value::Null, value));
         }
@@ -345,6 +599,162 @@ mod test {
         assert_all_diffs_present(expected, actual);
     }
 
+    #[test]
+    fn make_patch_only_includes_differences() {
+        let existing = json! {{
+            "metadata": {
+                "name": "foo",
+                "labels": {"app": "foo"},
+                "resourceVersion": "7",
+            },
+            "spec": {
+                "replicas": 5,
+                "paused": false,
+                "template": {
+                    "containers": [
+                        {"name": "app", "image": "app:v1"},
+                        {"name": "sidecar", "image": "sidecar:v1"},
+                    ]
+                }
+            }
+        }};
+        let desired = json! {{
+            "metadata": {
+                "name": "foo",
+                "labels": {"app": "foo", "tier": "web"},
+            },
+            "spec": {
+                "paused": false,
+                "template": {
+                    "containers": [
+                        {"name": "app", "image": "app:v2"},
+                    ]
+                }
+            }
+        }};
+        let expected = json! {{
+            "metadata": {
+                "labels": {"tier": "web"},
+            },
+            "spec": {
+                "template": {
+                    "containers": [
+                        {"name": "app", "image": "app:v2"},
+                    ]
+                }
+            }
+        }};
+        assert_eq!(expected, make_patch(&existing, &desired, &[]));
+    }
+
+    #[test]
+    fn fields_that_are_no_longer_desired_are_removed_if_they_were_last_applied() {
+        let existing = json! {{
+            "metadata": {
+                "name": "foo",
+                "labels": {"app": "foo", "tier": "web", "team": "a"},
+            },
+            "spec": {
+                "replicas": 2,
+                "paused": true,
+                "nodeName": "node-a",
+            }
+        }};
+        let last_applied = json! {{
+            "metadata": {
+                "name": "foo",
+                "labels": {"app": "foo", "tier": "web"},
+            },
+            "spec": {
+                "replicas": 2,
+                "paused": true,
+                "minReadySeconds": 5,
+            }
+        }};
+        let desired = json! {{
+            "metadata": {
+                "name": "foo",
+                "labels": {"app": "foo"},
+            },
+            "spec": {
+                "replicas": 3,
+            }
+        }};
+        let mut patch = make_patch(&existing, &desired, &[]);
+        add_removed_fields(
+            &mut patch,
+            &existing,
+            &desired,
+            &last_applied,
+            &["/spec/paused".to_owned()],
+        );
+        // the team label and nodeName were set by others, and minReadySeconds was already removed
+        let expected = json! {{
+            "metadata": {
+                "labels": {"tier": null},
+            },
+            "spec": {
+                "replicas": 3,
+            }
+        }};
+        assert_eq!(expected, patch);
+    }
+
+    #[test]
+    fn ignored_paths_and_nulls_are_not_different() {
+        let existing = json! {{
+            "metadata": {"name": "foo", "creationTimestamp": "2020-01-01T00:00:00Z"},
+            "spec": {
+                "clusterIP": "10.0.0.7",
+                "ports": [
+                    {"name": "http", "port": 80, "nodePort": 30080},
+                ],
+            }
+        }};
+        let desired = json! {{
+            "metadata": {"name": "foo", "creationTimestamp": null, "labels": null},
+            "spec": {
+                "clusterIP": "",
+                "ports": [
+                    {"name": "http", "port": 80, "nodePort": 0},
+                ],
+            }
+        }};
+        let ignored = vec![
+            "/metadata/creationTimestamp".to_owned(),
+            "/spec/clusterIP".to_owned(),
+            "/spec/ports/*/nodePort".to_owned(),
+        ];
+        assert!(compare_values_ignoring(&existing, &desired, &ignored).is_empty());
+        assert_eq!(3, compare_values(&existing, &desired).len());
+
+        let mut desired = desired;
+        desired["spec"]["ports"][0]["port"] = json!(8080);
+        // the whole array has to be sent, but the server-assigned nodePort is kept
+        let expected = json! {{
+            "spec": {
+                "ports": [
+                    {"name": "http", "port": 8080, "nodePort": 30080},
+                ],
+            }
+        }};
+        assert_eq!(expected, make_patch(&existing, &desired, &ignored));
+
+        desired["spec"]["ports"] = json!([
+            {"name": "https", "port": 443, "nodePort": 0},
+            {"name": "http", "port": 8080, "nodePort": 0},
+        ]);
+        let expected = json! {{
+            "spec": {
+                "ports": [
+                    {"name": "https", "port": 443},
+                    {"name": "http", "port": 8080, "nodePort": 30080},
+                ],
+            }
+        }};
+        assert_eq!(expected, make_patch(&existing, &desired, &ignored));
+    }
+
     fn assert_all_diffs_present(expected: Vec<Diff>, mut actual: Diffs) {
         for expected_diff in expected.iter() {
             if !actual.0.contains(expected_diff) {
@@ -1,10 +1,17 @@
-use super::{does_finalizer_exist, update_status_if_different, SyncHandler, UpdateError};
-use crate::handler::{FinalizeResponse, Handler, SyncRequest};
+use super::{
+    does_finalizer_exist, patch_finalizers, update_status_if_different, SyncHandler, UpdateError,
+};
+use crate::handler::{
+    condition, Condition, FinalizeResponse, Handler, SyncRequest, TERMINATING_CONDITION,
+};
 use crate::resource::K8sResource;
 use crate::runner::client::{Client, Patch};
+use crate::runner::events::{EventKind, EventTarget};
 use crate::runner::informer::{EventType, ResourceMessage};
 use crate::runner::{duration_to_millis, RuntimeConfig};
 
+use serde_json::Value;
+
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -19,28 +26,43 @@ pub(crate) async fn handle_finalize(handler: SyncHandler) {
     } = handler;
 
     let parent_id = request.parent.get_object_id().to_owned();
-    let parent_id_ref = parent_id.as_id_ref();
     let parent_type = runtime_config.parent_type;
+    let event_target = EventTarget::of(&request.parent);
 
-    let result = get_finalize_result(request, handler, client, &*runtime_config).await;
-    let update_result = match result {
-        Ok(retry) => {
+    let start_time = Instant::now();
+    let result = get_finalize_result(request, handler, client, &runtime_config).await;
+    let outcome = match result {
+        Ok(_) => Ok(None),
+        Err(ref err) => Err(err.failure()),
+    };
+    runtime_config
+        .metrics
+        .reconcile_finished(parent_type, start_time.elapsed(), &outcome);
+    let event_type = match result {
+        Ok(Some(retry)) => EventType::FinalizeIncomplete { retry },
+        Ok(None) => {
             log::debug!(
                 "Finalize handler for parent: {} completed without error",
                 parent_id
             );
-            Ok(retry)
+            EventType::UpdateOperationComplete { result: Ok(None) }
         }
         Err(err) => {
-            runtime_config.metrics.parent_sync_error(&parent_id_ref);
-            log::error!("Failed to finalize parent: {}, err: {}", parent_id, err);
-            Err(())
+            err.report("finalize", &parent_id, &runtime_config);
+            runtime_config.record_event(
+                &event_target,
+                EventKind::Warning,
+                err.event_reason("FinalizeFailed"),
+                "Finalize",
+                err.to_string(),
+            );
+            EventType::UpdateOperationComplete {
+                result: Err(err.failure()),
+            }
         }
     };
     let message = ResourceMessage {
-        event_type: EventType::UpdateOperationComplete {
-            result: update_result,
-        },
+        event_type,
         resource_type: parent_type,
         resource_id: parent_id,
         index_key: Some(parent_index_key),
@@ -59,7 +81,10 @@ async fn get_finalize_result(
         return Ok(None);
     }
 
+    // the span isn't propagated to the blocking thread automatically
+    let span = tracing::Span::current();
     let (req, finalize_result) = tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
         let start_time = Instant::now();
         let result = handler
             .finalize(&request)
@@ -74,37 +99,273 @@ async fn get_finalize_result(
         (request, result)
     })
     .await?;
-    let FinalizeResponse { retry, status } = finalize_result?;
-
     let request: SyncRequest = req;
     let parent_id = request.parent.get_object_id();
+    let FinalizeResponse { retry, status } = match finalize_result {
+        Ok(response) => response,
+        Err(err) => {
+            report_finalize_failure(&client, runtime_config, &request.parent, &err).await;
+            return Err(err);
+        }
+    };
 
-    if let Some(delay) = retry {
+    if retry.is_some() {
         log::info!(
             "handler response indicates that parent: {} has not been finalized. Will re-try later",
             parent_id
         );
         update_status_if_different(&request.parent, &client, runtime_config, status).await?;
-        tokio::time::delay_for(delay).await;
     } else {
         log::info!(
             "handler response indicates that parent: {} has been finalized",
             parent_id
         );
+        delete_children_without_owner_references(&client, runtime_config, &request).await?;
         remove_finalizer(&client, runtime_config, &request.parent).await?;
     }
 
     Ok(retry)
 }
 
+/// Sets the `TERMINATING_CONDITION` on the parent to say why it couldn't be finalized, for example because an external
+/// system is unreachable. The finalizer is kept, and the finalize is retried with the normal error backoff. Failing to
+/// update the status is only logged, since the original error is what's reported.
+async fn report_finalize_failure(
+    client: &Client,
+    runtime_config: &RuntimeConfig,
+    parent: &K8sResource,
+    err: &UpdateError,
+) {
+    let mut status = parent.status().cloned().unwrap_or(Value::Null);
+    condition::upsert_condition(
+        &mut status,
+        Condition::new(
+            TERMINATING_CONDITION,
+            true,
+            "FinalizeFailed",
+            err.to_string(),
+        ),
+    );
+    if let Err(status_err) =
+        update_status_if_different(parent, client, runtime_config, status).await
+    {
+        log::warn!(
+            "Failed to update the status of parent: {} after its finalize failed: {}",
+            parent.get_object_id(),
+            status_err
+        );
+    }
+}
+
+/// Deletes the children that don't have an owner reference to the parent, because they're in another cluster or are
+/// cluster scoped, so the garbage collector won't delete them once the parent is gone
+async fn delete_children_without_owner_references(
+    client: &Client,
+    runtime_config: &RuntimeConfig,
+    request: &SyncRequest,
+) -> Result<(), UpdateError> {
+    for child in request.children.iter() {
+        let child_config = match runtime_config.get_child_config(&child.get_type_ref()) {
+            Some(child_config) if !child_config.has_owner_reference => child_config,
+            _ => continue,
+        };
+        let child_type = child_config.child_type;
+        let child_id = child.get_object_id();
+        log::info!(
+            "Deleting child {} {} of parent: {}, since it doesn't have an owner reference to the parent",
+            child_type.kind,
+            child_id,
+            request.parent.get_object_id()
+        );
+        runtime_config
+            .client_for(child_type, client)
+            .delete_resource(child_type, &child_id, Some(child_config.delete_policy))
+            .await?;
+        runtime_config.record_event(
+            &EventTarget::of(&request.parent),
+            EventKind::Normal,
+            "Deleted",
+            "Delete",
+            format!("Deleted {} {}", child_type.kind, child_id),
+        );
+    }
+    Ok(())
+}
+
 async fn remove_finalizer<'a>(
     client: &Client,
     runtime_config: &RuntimeConfig,
     parent: &K8sResource,
 ) -> Result<(), UpdateError> {
-    let id = parent.get_object_id();
-    let k8s_type = &*runtime_config.parent_type;
-    let patch = Patch::remove_finalizer(parent, runtime_config.operator_name.as_str());
-    client.patch_resource(k8s_type, &id, &patch).await?;
+    patch_finalizers(client, runtime_config, parent, Patch::remove_finalizer).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{ClientConfig, OperatorConfig};
+    use crate::handler::SyncResponse;
+    use crate::k8s_types::core::v1::Pod;
+    use crate::runner::metrics::Metrics;
+    use crate::runner::reconcile::SyncFailure;
+
+    /// A handler whose `finalize` is the given function
+    struct FinalizeHandler<F>(F);
+
+    impl<F> Handler for FinalizeHandler<F>
+    where
+        F: Fn(&SyncRequest) -> Result<FinalizeResponse, anyhow::Error> + Send + Sync + 'static,
+    {
+        fn sync(&self, _request: &SyncRequest) -> Result<SyncResponse, anyhow::Error> {
+            Ok(SyncResponse::new(Value::Null))
+        }
+
+        fn finalize(&self, request: &SyncRequest) -> Result<FinalizeResponse, anyhow::Error> {
+            (self.0)(request)
+        }
+    }
+
+    fn test_client(api_server_endpoint: &str) -> Client {
+        let client_config: ClientConfig = serde_json::from_value(serde_json::json!({
+            "apiServerEndpoint": api_server_endpoint,
+            "userAgent": "test-operator",
+            "credentials": {"header": "Bearer abc"},
+        }))
+        .unwrap();
+        Client::new(client_config, Metrics::new().client_metrics()).unwrap()
+    }
+
+    /// The method, path, and body of each request that a mock api server received
+    type Requests = Arc<std::sync::Mutex<Vec<(String, String, Value)>>>;
+
+    /// Starts an api server that records each request, and responds with the request's body
+    async fn start_recording_api_server() -> (std::net::SocketAddr, Requests) {
+        let requests = Requests::default();
+        let recorded = requests.clone();
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(
+                    move |req: http::Request<hyper::Body>| {
+                        let recorded = recorded.clone();
+                        async move {
+                            let method = req.method().to_string();
+                            let path = req.uri().path().to_owned();
+                            let body = hyper::body::to_bytes(req.into_body()).await?;
+                            let value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                            recorded.lock().unwrap().push((method, path, value));
+                            Ok::<_, hyper::Error>(http::Response::new(hyper::Body::from(body)))
+                        }
+                    },
+                ))
+            }
+        });
+        let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+            .serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, requests)
+    }
+
+    /// A parent that's being deleted, and still has the operator's finalizer
+    fn deleted_parent(runtime_config: &RuntimeConfig) -> SyncRequest {
+        let parent = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "namespace": "ns",
+                "name": "parent",
+                "uid": "parent-uid",
+                "resourceVersion": "7",
+                "deletionTimestamp": "2020-01-01T00:00:00Z",
+                "finalizers": [runtime_config.finalizer_name],
+            },
+        });
+        SyncRequest {
+            parent: K8sResource::from_value(parent).unwrap(),
+            children: Vec::new(),
+            cache: Default::default(),
+            cancellation: Default::default(),
+        }
+    }
+
+    #[test]
+    fn finalize_panics_are_not_retried_and_leave_the_finalizer() {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let handler = FinalizeHandler(|_: &SyncRequest| panic!("bad spec: {}", 7));
+
+        // the panic is returned before any requests are made, so the finalizer is never removed
+        let err = runtime
+            .block_on(get_finalize_result(
+                deleted_parent(&runtime_config),
+                Arc::new(handler),
+                test_client("http://127.0.0.1:1"),
+                &runtime_config,
+            ))
+            .unwrap_err();
+        assert_eq!(SyncFailure::NoRetry, err.failure());
+        assert_eq!("HandlerPanicked", err.event_reason("FinalizeFailed"));
+        assert!(
+            err.to_string().contains("bad spec: 7"),
+            "error was: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn finalize_errors_set_the_terminating_condition_and_leave_the_finalizer() {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let handler = FinalizeHandler(|_: &SyncRequest| {
+            Err(anyhow::anyhow!("external system is unavailable"))
+        });
+
+        let (err, requests) = runtime.block_on(async {
+            let (addr, requests) = start_recording_api_server().await;
+            let err = get_finalize_result(
+                deleted_parent(&runtime_config),
+                Arc::new(handler),
+                test_client(&format!("http://{}", addr)),
+                &runtime_config,
+            )
+            .await
+            .unwrap_err();
+            (err, requests)
+        });
+        assert!(matches!(err, UpdateError::HandlerError(_)));
+        assert_eq!(SyncFailure::Retry(None), err.failure());
+
+        // the only request is the status update, so the finalizer is left in place
+        let requests = requests.lock().unwrap();
+        assert_eq!(1, requests.len(), "requests were: {:?}", requests);
+        let (method, path, body) = &requests[0];
+        assert_eq!("PUT", method);
+        assert_eq!("/api/v1/namespaces/ns/pods/parent/status", path);
+        let conditions = body
+            .pointer("/status/conditions")
+            .and_then(Value::as_array)
+            .expect("expected the status to have conditions");
+        let terminating = conditions
+            .iter()
+            .find(|condition| condition["type"] == TERMINATING_CONDITION)
+            .expect("expected a Terminating condition");
+        assert_eq!("True", terminating["status"]);
+        assert_eq!("FinalizeFailed", terminating["reason"]);
+        assert!(terminating["message"]
+            .as_str()
+            .unwrap()
+            .contains("external system is unavailable"));
+    }
+}
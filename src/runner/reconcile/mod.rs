@@ -1,19 +1,25 @@
 pub(crate) mod compare;
 mod finalize;
-mod sync;
+pub(crate) mod sync;
 
-use crate::handler::{Handler, SyncRequest};
-use crate::resource::{InvalidResourceError, K8sResource};
-use crate::runner::client::{self, Client};
-use crate::runner::informer::ResourceMessage;
-use crate::runner::RuntimeConfig;
+use crate::config::MAX_CONFLICT_RETRIES;
+use crate::handler::{
+    condition, Condition, Handler, ReconcileError, SyncRequest, PAUSED_CONDITION,
+};
+use crate::k8s_types::K8sType;
+use crate::resource::{InvalidResourceError, K8sResource, ObjectId};
+use crate::runner::client::{self, Client, Patch};
+use crate::runner::informer::{EventType, ResourceMessage};
+use crate::runner::{duration_to_millis, RuntimeConfig};
 use anyhow::Error;
 
 use serde_json::Value;
 use tokio::sync::mpsc::Sender;
+use tracing::Instrument;
 
 use std::fmt::{self, Display};
 use std::sync::Arc;
+use std::time::Duration;
 
 pub(crate) struct SyncHandler {
     pub sender: Sender<ResourceMessage>,
@@ -26,16 +32,41 @@ pub(crate) struct SyncHandler {
 
 impl SyncHandler {
     pub fn start_sync(self) {
-        self.runtime_config
-            .metrics
-            .parent_sync_started(&self.request.parent.get_object_id());
-        tokio::spawn(async move {
-            if self.should_finalize() {
-                self::finalize::handle_finalize(self).await;
-            } else {
-                self::sync::handle_sync(self).await;
+        let paused = self.runtime_config.is_paused(&self.request.parent);
+        if !paused {
+            self.runtime_config
+                .metrics
+                .parent_sync_started(&self.request.parent.get_object_id());
+        }
+        let span = self.reconcile_span();
+        tokio::spawn(
+            async move {
+                if paused {
+                    handle_paused(self).await;
+                } else if self.should_finalize() {
+                    self::finalize::handle_finalize(self).await;
+                } else {
+                    self::sync::handle_sync(self).await;
+                }
             }
-        });
+            .instrument(span),
+        );
+    }
+
+    /// Creates the span that all of the logs and client requests of this sync or finalize are nested under, so that
+    /// they can be correlated with the parent when a `tracing` subscriber is installed
+    fn reconcile_span(&self) -> tracing::Span {
+        let parent = &self.request.parent;
+        let parent_id = parent.get_object_id();
+        tracing::info_span!(
+            "reconcile",
+            kind = self.runtime_config.parent_type.kind,
+            namespace = parent_id.namespace().unwrap_or(""),
+            name = parent_id.name(),
+            uid = parent.uid(),
+            resource_version = parent.resource_version(),
+            finalize = self.should_finalize(),
+        )
     }
 
     fn should_finalize(&self) -> bool {
@@ -43,12 +74,102 @@ impl SyncHandler {
     }
 }
 
+/// Sets the `PAUSED_CONDITION` on a parent that has the `pause_annotation`, without invoking the handler or touching
+/// its children. The parent isn't retried, since removing the annotation changes the parent, which syncs it again.
+async fn handle_paused(handler: SyncHandler) {
+    let SyncHandler {
+        mut sender,
+        request,
+        client,
+        runtime_config,
+        parent_index_key,
+        ..
+    } = handler;
+    let parent_id = request.parent.get_object_id().to_owned();
+    log::info!(
+        "Skipping {} of parent: {} because it has the '{}' annotation",
+        if request.parent.is_deletion_timestamp_set() {
+            "finalize"
+        } else {
+            "sync"
+        },
+        parent_id,
+        runtime_config
+            .pause_annotation
+            .as_deref()
+            .unwrap_or_default()
+    );
+    let status = paused_status(&request.parent, &runtime_config);
+    let result = update_status_if_different(&request.parent, &client, &runtime_config, status)
+        .await
+        .map(|_| None)
+        .map_err(|err| {
+            err.report("pause", &parent_id, &runtime_config);
+            err.failure()
+        });
+    let message = ResourceMessage {
+        event_type: EventType::UpdateOperationComplete { result },
+        resource_id: parent_id,
+        resource_type: runtime_config.parent_type,
+        index_key: Some(parent_index_key),
+    };
+    let _ = sender.send(message).await;
+}
+
+/// Returns the existing status of the parent with the `PAUSED_CONDITION` set
+fn paused_status(parent: &K8sResource, runtime_config: &RuntimeConfig) -> Value {
+    let mut status = parent.status().cloned().unwrap_or(Value::Null);
+    condition::upsert_condition(
+        &mut status,
+        Condition::new(
+            PAUSED_CONDITION,
+            true,
+            "PauseAnnotation",
+            format!(
+                "Reconciliation is paused by the '{}' annotation",
+                runtime_config
+                    .pause_annotation
+                    .as_deref()
+                    .unwrap_or_default()
+            ),
+        ),
+    );
+    status
+}
+
+/// The `PAUSED_CONDITION` for a parent that was paused, but isn't anymore
+fn unpaused_condition(runtime_config: &RuntimeConfig) -> Condition {
+    Condition::new(
+        PAUSED_CONDITION,
+        false,
+        "PauseAnnotationRemoved",
+        format!(
+            "Reconciliation resumed after the '{}' annotation was removed",
+            runtime_config
+                .pause_annotation
+                .as_deref()
+                .unwrap_or_default()
+        ),
+    )
+}
+
 #[derive(Debug)]
 pub enum UpdateError {
     Client(client::Error),
+    /// An update of a child with `optimistic_concurrency` enabled was rejected because the child was changed since
+    /// it was observed
+    ChildConflict(&'static K8sType, ObjectId, client::Error),
+    /// A desired child already exists, but it belongs to the parent with the given uid. The child is left alone
+    /// rather than having two parents fight over it.
+    ChildCollision(&'static K8sType, ObjectId, String),
     InvalidHandlerResponse(InvalidResourceError),
     UnknownChildType(String, String),
     HandlerError(Error),
+    /// The handler didn't return within the `handler_timeout`
+    HandlerTimeout(Duration),
+    /// The handler wasn't invoked because the circuit breaker is open. The duration is how long it is until the next
+    /// probe is allowed.
+    CircuitOpen(Duration),
     TaskCancelled,
 }
 
@@ -56,6 +177,16 @@ impl Display for UpdateError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             UpdateError::Client(e) => write!(f, "Client Error: {}", e),
+            UpdateError::ChildConflict(child_type, child_id, e) => write!(
+                f,
+                "Conflict updating child {} {}: {}",
+                child_type.kind, child_id, e
+            ),
+            UpdateError::ChildCollision(child_type, child_id, other_parent_uid) => write!(
+                f,
+                "Child {} {} already exists and belongs to the parent with uid: {}",
+                child_type.kind, child_id, other_parent_uid
+            ),
             UpdateError::InvalidHandlerResponse(e) => {
                 write!(f, "Invalid response from Handler: {}", e)
             }
@@ -65,17 +196,153 @@ impl Display for UpdateError {
                 api_version, kind
             ),
             UpdateError::HandlerError(err) => write!(f, "Handler error: {}", err),
+            UpdateError::HandlerTimeout(timeout) => write!(
+                f,
+                "Handler did not return within {}ms",
+                duration_to_millis(*timeout)
+            ),
+            UpdateError::CircuitOpen(remaining) => write!(
+                f,
+                "Circuit breaker is open, the handler will not be invoked for another {}ms",
+                duration_to_millis(*remaining)
+            ),
             UpdateError::TaskCancelled => write!(f, "Task was cancelled"),
         }
     }
 }
 
+/// How the runner should respond to a failed sync or finalize
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SyncFailure {
+    /// Retry after the given delay, or after the normal error backoff if it's `None`
+    Retry(Option<Duration>),
+    /// Don't retry until the parent or one of its children is changed
+    NoRetry,
+}
+
+impl UpdateError {
+    fn reconcile_error(&self) -> Option<&ReconcileError> {
+        match self {
+            UpdateError::HandlerError(err) => err.downcast_ref::<ReconcileError>(),
+            _ => None,
+        }
+    }
+
+    fn is_handler_panic(&self) -> bool {
+        match self {
+            UpdateError::HandlerError(err) => err.is::<HandlerPanic>(),
+            _ => false,
+        }
+    }
+
+    /// Determines how the failure should be handled. Only handler errors can opt out of being retried, since any
+    /// other error may be resolved by the next attempt. A handler that panics is treated the same as one that returns
+    /// a fatal error, since it will most likely panic again on the same parent.
+    pub(crate) fn failure(&self) -> SyncFailure {
+        if self.is_handler_panic() {
+            return SyncFailure::NoRetry;
+        }
+        if let UpdateError::CircuitOpen(remaining) = self {
+            return SyncFailure::Retry(Some(*remaining));
+        }
+        match self.reconcile_error() {
+            Some(ReconcileError::Retryable { backoff, .. }) => SyncFailure::Retry(*backoff),
+            Some(ReconcileError::Fatal(_)) | Some(ReconcileError::InvalidResource(_)) => {
+                SyncFailure::NoRetry
+            }
+            None => SyncFailure::Retry(None),
+        }
+    }
+
+    /// Returns the reason to use for the `Event` that's recorded for this error
+    pub(crate) fn event_reason(&self, failed_reason: &'static str) -> &'static str {
+        match self {
+            UpdateError::ChildCollision(..) => return "ChildCollision",
+            UpdateError::HandlerTimeout(_) => return "HandlerTimeout",
+            UpdateError::CircuitOpen(_) => return "CircuitOpen",
+            _ => {}
+        }
+        if self.is_handler_panic() {
+            return "HandlerPanicked";
+        }
+        match self.reconcile_error() {
+            Some(ReconcileError::InvalidResource(_)) => "InvalidResource",
+            _ => failed_reason,
+        }
+    }
+
+    /// Returns true if this is the outcome of invoking the handler that counts as a failure for the circuit breaker,
+    /// which is a retryable error or a timeout. Errors that the handler says won't be resolved by retrying, and panics,
+    /// are most likely because of the parent rather than the handler's dependencies.
+    pub(crate) fn is_circuit_breaker_failure(&self) -> bool {
+        match self {
+            UpdateError::HandlerTimeout(_) => true,
+            UpdateError::HandlerError(_) => self.failure() != SyncFailure::NoRetry,
+            _ => false,
+        }
+    }
+
+    /// Logs the error and records it in the metrics, unless it's because the parent is invalid or the circuit breaker
+    /// is open, in which case it's only logged as a warning
+    pub(crate) fn report(
+        &self,
+        action: &str,
+        parent_id: &ObjectId,
+        runtime_config: &RuntimeConfig,
+    ) {
+        if let UpdateError::CircuitOpen(_) = self {
+            log::warn!("Skipping {} of parent: {}: {}", action, parent_id, self);
+        } else if matches!(
+            self.reconcile_error(),
+            Some(ReconcileError::InvalidResource(_))
+        ) {
+            log::warn!(
+                "Skipping {} of invalid parent: {} until it is changed: {}",
+                action,
+                parent_id,
+                self
+            );
+        } else {
+            runtime_config
+                .metrics
+                .parent_sync_error(&parent_id.as_id_ref());
+            if self.is_handler_panic() {
+                runtime_config
+                    .metrics
+                    .handler_panicked(runtime_config.parent_type);
+            }
+            if self.failure() == SyncFailure::NoRetry {
+                log::error!(
+                    "Fatal error during {} of parent: {}, will not retry until it is changed: {:?}",
+                    action,
+                    parent_id,
+                    self
+                );
+            } else {
+                log::error!(
+                    "Error during {} of parent: {}: {:?}",
+                    action,
+                    parent_id,
+                    self
+                );
+            }
+        }
+    }
+}
+
 impl From<tokio::task::JoinError> for UpdateError {
     fn from(err: tokio::task::JoinError) -> UpdateError {
         if err.is_cancelled() {
             UpdateError::TaskCancelled
         } else {
-            UpdateError::HandlerError(anyhow::Error::from(HandlerPanic))
+            let payload = err.into_panic();
+            // panic payloads are almost always either a &str or a String
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| (*s).to_owned())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            UpdateError::HandlerError(anyhow::Error::from(HandlerPanic(message)))
         }
     }
 }
@@ -92,13 +359,15 @@ impl From<InvalidResourceError> for UpdateError {
     }
 }
 
+/// A panic in the handler, along with its message, which is caught so that one bad parent can't take down the whole
+/// operator
 #[derive(Debug)]
-struct HandlerPanic;
+struct HandlerPanic(String);
 impl std::error::Error for HandlerPanic {}
 
 impl Display for HandlerPanic {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str("Handler paniced")
+        write!(f, "Handler panicked: {}", self.0)
     }
 }
 
@@ -106,16 +375,51 @@ pub(crate) async fn update_status_if_different(
     existing_parent: &K8sResource,
     client: &Client,
     runtime_config: &RuntimeConfig,
-    mut new_status: Value,
+    new_status: Value,
 ) -> Result<(), UpdateError> {
+    let new_status = match status_to_update(existing_parent, runtime_config, new_status) {
+        Some(status) => status,
+        None => return Ok(()),
+    };
+    let parent_id = existing_parent.get_object_id();
+    let k8s_type = &*runtime_config.parent_type;
+    if runtime_config.status_subresource {
+        let body = make_status_update(existing_parent, k8s_type, new_status);
+        client.update_status(k8s_type, &parent_id, &body).await?;
+    } else {
+        let body = make_parent_with_status(existing_parent, new_status);
+        client.replace_resource(k8s_type, &parent_id, &body).await?;
+    }
+    Ok(())
+}
+
+/// Returns the status that should be written to the parent, or `None` if the existing status is already the same
+pub(crate) fn status_to_update(
+    existing_parent: &K8sResource,
+    runtime_config: &RuntimeConfig,
+    mut new_status: Value,
+) -> Option<Value> {
     let parent_id = existing_parent.get_object_id();
     let old_status = existing_parent.status();
-    let parent_resource_version = existing_parent.resource_version();
-    let current_gen = existing_parent.generation();
 
-    if let Some(s) = new_status.as_object_mut() {
-        s.insert("observedGeneration".to_owned(), current_gen.into());
+    // without the status subresource, setting observedGeneration would increment the generation, so
+    // the status would never stop changing
+    let observed_generation =
+        Some(existing_parent.generation()).filter(|_| runtime_config.status_subresource);
+    if let Some(current_gen) = observed_generation {
+        if let Some(s) = new_status.as_object_mut() {
+            s.insert("observedGeneration".to_owned(), current_gen.into());
+        }
+    }
+    // the status is compared as a subset, so a `Paused` condition from when the parent was paused would otherwise
+    // stay around forever if the handler doesn't return any conditions
+    if !runtime_config.is_paused(existing_parent)
+        && condition::has_condition(old_status, PAUSED_CONDITION)
+        && !condition::has_condition(Some(&new_status), PAUSED_CONDITION)
+    {
+        condition::upsert_condition(&mut new_status, unpaused_condition(runtime_config));
     }
+    condition::preserve_transition_times(old_status, &mut new_status, observed_generation);
     let should_update = if let Some(old) = old_status {
         let diffs = compare::compare_values(old, &new_status);
         let update_required = diffs.non_empty();
@@ -137,30 +441,86 @@ pub(crate) async fn update_status_if_different(
         !new_status.is_null()
     };
 
+    Some(new_status).filter(|_| should_update)
+}
+
+/// Returns the body of a request to the `/status` subresource, which only needs enough metadata to identify the parent
+fn make_status_update(
+    existing_parent: &K8sResource,
+    k8s_type: &K8sType,
+    new_status: Value,
+) -> Value {
+    let parent_id = existing_parent.get_object_id();
     let mut metadata = serde_json::json!({
         "name": parent_id.name(),
-        "resourceVersion": parent_resource_version,
+        "resourceVersion": existing_parent.resource_version(),
     });
     if let Some(ns) = parent_id.namespace() {
         let obj = metadata.as_object_mut().unwrap();
         obj.insert("namespace".to_owned(), Value::String(ns.to_owned()));
     }
-    let new_status = serde_json::json!({
-        "apiVersion": runtime_config.parent_type.api_version,
-        "kind": runtime_config.parent_type.kind,
+    serde_json::json!({
+        "apiVersion": k8s_type.api_version,
+        "kind": k8s_type.kind,
         "metadata": metadata,
         "status": new_status,
-    });
-    if should_update {
-        client
-            .update_status(&*runtime_config.parent_type, &parent_id, &new_status)
-            .await?;
+    })
+}
+
+/// Returns the whole parent with the new status, for updating parents that don't have a `/status` subresource. The
+/// parent still has the `resourceVersion` that was synced, so the replace will fail if it's been modified since.
+fn make_parent_with_status(existing_parent: &K8sResource, new_status: Value) -> Value {
+    let mut parent = existing_parent.clone().into_value();
+    if let Some(obj) = parent.as_object_mut() {
+        obj.insert("status".to_owned(), new_status);
+    }
+    parent
+}
+
+/// Patches the finalizers of the parent using the patch from `make_patch`, which is conditional on the parent's
+/// `resourceVersion`. If another controller changed the parent in the meantime, then the patch is rejected with a
+/// 409, and the parent is read again so that the patch can be re-computed and retried, up to `MAX_CONFLICT_RETRIES`
+/// times. This way, concurrent changes to the finalizers are never overwritten. Nothing is done once `make_patch`
+/// returns `None`, which means that the finalizers are already as they should be, or if the parent no longer exists.
+pub(crate) async fn patch_finalizers(
+    client: &Client,
+    runtime_config: &RuntimeConfig,
+    parent: &K8sResource,
+    make_patch: impl Fn(&K8sResource, &str) -> Option<Patch>,
+) -> Result<(), client::Error> {
+    let k8s_type = runtime_config.parent_type;
+    let finalizer_name = runtime_config.finalizer_name.as_str();
+    let parent_id = parent.get_object_id();
+    let mut current = parent.clone();
+    let mut conflicts = 0;
+    loop {
+        let patch = match make_patch(&current, finalizer_name) {
+            Some(patch) => patch,
+            None => return Ok(()),
+        };
+        match client.patch_resource(k8s_type, &parent_id, &patch).await {
+            Err(ref err) if err.is_http_status(409) && conflicts < MAX_CONFLICT_RETRIES => {
+                conflicts += 1;
+                log::info!(
+                    "Finalizers of parent: {} were changed concurrently, re-reading it and retrying (attempt {} of {})",
+                    parent_id,
+                    conflicts,
+                    MAX_CONFLICT_RETRIES
+                );
+                current = match client.get_resource(k8s_type, &parent_id).await? {
+                    Some(value) => K8sResource::from_value(value).map_err(|err| {
+                        client::Error::Serde(serde::de::Error::custom(err.to_string()))
+                    })?,
+                    None => return Ok(()),
+                };
+            }
+            other => return other,
+        }
     }
-    Ok(())
 }
 
 fn does_finalizer_exist(resource: &Value, runtime_config: &RuntimeConfig) -> bool {
-    let finalizer_name = runtime_config.operator_name.as_str();
+    let finalizer_name = runtime_config.finalizer_name.as_str();
     resource
         .pointer("/metadata/finalizers")
         .and_then(Value::as_array)
@@ -171,3 +531,354 @@ fn does_finalizer_exist(resource: &Value, runtime_config: &RuntimeConfig) -> boo
         })
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::k8s_types::Scope;
+    use serde_json::json;
+
+    #[test]
+    fn handler_errors_are_retried_unless_they_say_otherwise() {
+        let plain = UpdateError::HandlerError(anyhow::anyhow!("oh no"));
+        assert_eq!(SyncFailure::Retry(None), plain.failure());
+
+        let delay = std::time::Duration::from_secs(5);
+        let retryable = UpdateError::HandlerError(
+            ReconcileError::retry_after(anyhow::anyhow!("oh no"), delay).into(),
+        );
+        assert_eq!(SyncFailure::Retry(Some(delay)), retryable.failure());
+
+        let fatal =
+            UpdateError::HandlerError(ReconcileError::fatal(anyhow::anyhow!("oh no")).into());
+        assert_eq!(SyncFailure::NoRetry, fatal.failure());
+
+        let invalid =
+            UpdateError::HandlerError(ReconcileError::invalid_resource("bad spec").into());
+        assert_eq!(SyncFailure::NoRetry, invalid.failure());
+
+        let cancelled = UpdateError::TaskCancelled;
+        assert_eq!(SyncFailure::Retry(None), cancelled.failure());
+
+        let circuit_open = UpdateError::CircuitOpen(delay);
+        assert_eq!(SyncFailure::Retry(Some(delay)), circuit_open.failure());
+        assert!(!circuit_open.is_circuit_breaker_failure());
+        assert!(UpdateError::HandlerTimeout(delay).is_circuit_breaker_failure());
+        assert!(plain.is_circuit_breaker_failure());
+        assert!(!fatal.is_circuit_breaker_failure());
+    }
+
+    #[test]
+    fn handler_panics_are_caught_and_not_retried() {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let err = runtime.block_on(async {
+            tokio::task::spawn_blocking(|| panic!("bad spec: {}", 7))
+                .await
+                .map_err(UpdateError::from)
+                .unwrap_err()
+        });
+        assert_eq!(SyncFailure::NoRetry, err.failure());
+        assert_eq!("HandlerPanicked", err.event_reason("SyncFailed"));
+        assert!(
+            err.to_string().contains("bad spec: 7"),
+            "error was: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn reconcile_error_is_recovered_from_anyhow_error() {
+        let err: anyhow::Error = ReconcileError::fatal(anyhow::anyhow!("oh no")).into();
+        match ReconcileError::from(err) {
+            ReconcileError::Fatal(source) => assert_eq!("oh no", source.to_string()),
+            other => panic!("expected fatal error, got: {:?}", other),
+        }
+        match ReconcileError::from(anyhow::anyhow!("other")) {
+            ReconcileError::Retryable { backoff: None, .. } => {}
+            other => panic!("expected retryable error, got: {:?}", other),
+        }
+    }
+
+    fn parent() -> K8sResource {
+        K8sResource::from_value(json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "metadata": {
+                "namespace": "ns",
+                "name": "foo",
+                "uid": "abc",
+                "resourceVersion": "42",
+                "generation": 3,
+            },
+            "spec": { "replicas": 2 },
+            "status": { "ready": false },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parents_are_only_paused_when_the_annotation_is_true() {
+        use crate::config::OperatorConfig;
+        use crate::k8s_types::core::v1::Pod;
+        use crate::runner::metrics::Metrics;
+
+        let with_annotation = |value: &str| {
+            let mut parent = parent().into_value();
+            parent["metadata"]["annotations"] = json!({ "example.com/paused": value });
+            K8sResource::from_value(parent).unwrap()
+        };
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        assert!(!runtime_config.is_paused(&with_annotation("true")));
+
+        let config = config.pause_annotation("example.com/paused");
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        assert!(runtime_config.is_paused(&with_annotation("true")));
+        assert!(!runtime_config.is_paused(&with_annotation("false")));
+        assert!(!runtime_config.is_paused(&parent()));
+
+        let status = paused_status(&with_annotation("true"), &runtime_config);
+        assert_eq!(Some(&json!(false)), status.get("ready"));
+        let condition = &status["conditions"][0];
+        assert_eq!(Some(PAUSED_CONDITION), condition["type"].as_str());
+        assert_eq!(Some("True"), condition["status"].as_str());
+        assert_eq!(Some("PauseAnnotation"), condition["reason"].as_str());
+    }
+
+    #[test]
+    fn the_paused_condition_is_cleared_once_the_parent_is_unpaused() {
+        use crate::config::OperatorConfig;
+        use crate::k8s_types::core::v1::Pod;
+        use crate::runner::metrics::Metrics;
+
+        let config =
+            OperatorConfig::new("test-operator", Pod).pause_annotation("example.com/paused");
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let with_status = |annotation: &str, status: Value| {
+            let mut parent = parent().into_value();
+            parent["metadata"]["annotations"] = json!({ "example.com/paused": annotation });
+            parent["status"] = status;
+            K8sResource::from_value(parent).unwrap()
+        };
+
+        // pause the parent
+        let paused = with_status("true", json!({ "ready": false }));
+        let status = status_to_update(
+            &paused,
+            &runtime_config,
+            paused_status(&paused, &runtime_config),
+        )
+        .unwrap();
+
+        // unpause it, and the handler's status doesn't have any conditions
+        let unpaused = with_status("false", status);
+        let status = status_to_update(&unpaused, &runtime_config, json!({ "ready": true }))
+            .expect("expected the Paused condition to be updated");
+        let condition = &status["conditions"][0];
+        assert_eq!(Some(PAUSED_CONDITION), condition["type"].as_str());
+        assert_eq!(Some("False"), condition["status"].as_str());
+        assert_eq!(Some("PauseAnnotationRemoved"), condition["reason"].as_str());
+
+        // once it's been cleared, the status stays the same
+        let unpaused = with_status("false", status);
+        assert_eq!(
+            None,
+            status_to_update(&unpaused, &runtime_config, json!({ "ready": true }))
+        );
+    }
+
+    /// Applies a json merge patch the same way that the api server does
+    fn merge(target: &mut Value, patch: &Value) {
+        match (target.as_object_mut(), patch.as_object()) {
+            (Some(target), Some(patch)) => {
+                for (key, value) in patch {
+                    if value.is_null() {
+                        target.remove(key);
+                    } else {
+                        merge(target.entry(key.as_str()).or_insert(Value::Null), value);
+                    }
+                }
+            }
+            _ => *target = patch.clone(),
+        }
+    }
+
+    #[test]
+    fn a_label_added_concurrently_survives_the_finalizer_patch() {
+        use crate::config::{ClientConfig, OperatorConfig};
+        use crate::runner::metrics::Metrics;
+        use hyper::{Body, Request, Response};
+        use std::sync::Mutex;
+
+        static PARENT_TYPE: &K8sType = &K8sType {
+            api_version: "example.com/v1",
+            kind: "Foo",
+            plural_kind: "foos",
+            scope: Scope::Namespaced,
+        };
+
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            // another client has added a label since the parent was cached
+            let mut stored = parent().into_value();
+            stored["metadata"]["labels"] = json!({ "team": "a" });
+            stored["metadata"]["resourceVersion"] = json!("43");
+            let stored = Arc::new(Mutex::new(stored));
+            let requests = Arc::new(Mutex::new(Vec::new()));
+
+            let (server_stored, server_requests) = (stored.clone(), requests.clone());
+            let make_service = hyper::service::make_service_fn(move |_| {
+                let (stored, requests) = (server_stored.clone(), server_requests.clone());
+                async move {
+                    Ok::<_, hyper::Error>(hyper::service::service_fn(move |req: Request<Body>| {
+                        let (stored, requests) = (stored.clone(), requests.clone());
+                        async move {
+                            let method = req.method().clone();
+                            let body = hyper::body::to_bytes(req.into_body()).await?;
+                            requests.lock().unwrap().push(method.to_string());
+                            let mut stored = stored.lock().unwrap();
+                            if method == http::Method::PATCH {
+                                let patch: Value = serde_json::from_slice(&body).unwrap();
+                                if patch.pointer("/metadata/resourceVersion")
+                                    != stored.pointer("/metadata/resourceVersion")
+                                {
+                                    let status = json!({"kind": "Status", "code": 409, "reason": "Conflict"});
+                                    return Ok::<_, hyper::Error>(
+                                        Response::builder()
+                                            .status(409)
+                                            .body(Body::from(status.to_string()))
+                                            .unwrap(),
+                                    );
+                                }
+                                merge(&mut stored, &patch);
+                                stored["metadata"]["resourceVersion"] = json!("44");
+                            }
+                            Ok(Response::new(Body::from(stored.to_string())))
+                        }
+                    }))
+                }
+            });
+            let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            let client_config: ClientConfig = serde_json::from_value(json!({
+                "apiServerEndpoint": format!("http://{}", addr),
+                "userAgent": "test-operator",
+                "credentials": {"header": "Bearer abc"},
+            }))
+            .unwrap();
+            let metrics = Metrics::new();
+            let client = Client::new(client_config, metrics.client_metrics()).unwrap();
+            let config = OperatorConfig::new("test-operator", PARENT_TYPE);
+            let runtime_config = RuntimeConfig::new(&config, metrics, None);
+
+            patch_finalizers(&client, &runtime_config, &parent(), Patch::add_finalizer)
+                .await
+                .unwrap();
+
+            assert_eq!(vec!["PATCH", "GET", "PATCH"], *requests.lock().unwrap());
+            let stored = stored.lock().unwrap();
+            assert_eq!(json!({ "team": "a" }), stored["metadata"]["labels"]);
+            assert_eq!(json!(["test-operator"]), stored["metadata"]["finalizers"]);
+            assert_eq!(json!({ "replicas": 2 }), stored["spec"]);
+        });
+    }
+
+    #[test]
+    fn status_subresource_update_only_includes_metadata_and_status() {
+        let parent_type = K8sType {
+            api_version: "example.com/v1",
+            kind: "Foo",
+            plural_kind: "foos",
+            scope: Scope::Namespaced,
+        };
+        let body = make_status_update(&parent(), &parent_type, json!({ "ready": true }));
+        let expected = json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "metadata": {
+                "namespace": "ns",
+                "name": "foo",
+                "resourceVersion": "42",
+            },
+            "status": { "ready": true },
+        });
+        assert_eq!(expected, body);
+    }
+
+    #[test]
+    fn parent_with_status_keeps_the_rest_of_the_parent() {
+        let body = make_parent_with_status(&parent(), json!({ "ready": true }));
+        assert_eq!(Some(&json!({ "replicas": 2 })), body.get("spec"));
+        assert_eq!(
+            Some(&json!("42")),
+            body.pointer("/metadata/resourceVersion")
+        );
+        assert_eq!(Some(&json!({ "ready": true })), body.get("status"));
+    }
+
+    #[test]
+    fn unchanged_conditions_do_not_update_the_status() {
+        use crate::config::OperatorConfig;
+        use crate::handler::{Condition, SyncResponse};
+        use crate::runner::metrics::Metrics;
+
+        static PARENT_TYPE: &K8sType = &K8sType {
+            api_version: "example.com/v1",
+            kind: "Foo",
+            plural_kind: "foos",
+            scope: Scope::Namespaced,
+        };
+        let runtime_config = RuntimeConfig::new(
+            &OperatorConfig::new("test-operator", PARENT_TYPE),
+            Metrics::new(),
+            None,
+        );
+        let mut parent = parent().into_value();
+        parent["status"] = json!({
+            "observedGeneration": 3,
+            "conditions": [{
+                "type": "Ready",
+                "status": "True",
+                "reason": "Reconciled",
+                "message": "",
+                "lastTransitionTime": "2020-01-01T00:00:00Z",
+                "observedGeneration": 3,
+            }],
+        });
+        let parent = K8sResource::from_value(parent).unwrap();
+
+        let mut response = SyncResponse::new(Value::Null);
+        response.set_condition(Condition::new("Ready", true, "Reconciled", ""));
+        assert_eq!(
+            None,
+            status_to_update(&parent, &runtime_config, response.status)
+        );
+
+        let mut response = SyncResponse::new(Value::Null);
+        response.set_condition(Condition::new("Ready", false, "Failed", ""));
+        let status = status_to_update(&parent, &runtime_config, response.status).unwrap();
+        assert_ne!(
+            Some("2020-01-01T00:00:00Z"),
+            status
+                .pointer("/conditions/0/lastTransitionTime")
+                .and_then(Value::as_str)
+        );
+        assert_eq!(
+            Some(3),
+            status
+                .pointer("/conditions/0/observedGeneration")
+                .and_then(Value::as_i64)
+        );
+    }
+}
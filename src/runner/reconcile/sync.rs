@@ -1,17 +1,23 @@
-use crate::config::UpdateStrategy;
-use crate::handler::{Handler, SyncRequest, SyncResponse};
-use crate::resource::{InvalidResourceError, JsonObject, K8sResource, ObjectIdRef, ResourceJson};
-use crate::runner::client::{self, Client};
+use crate::config::{UpdateStrategy, LAST_APPLIED_ANNOTATION, MAX_CONFLICT_RETRIES};
+use crate::handler::{Cancelled, ChildScale, Handler, Scale, SyncRequest, SyncResponse};
+use crate::k8s_types::{K8sType, Scope};
+use crate::resource::{
+    parse_last_applied, InvalidResourceError, JsonObject, K8sResource, K8sTypeRef, ObjectId,
+    ObjectIdRef, ResourceJson,
+};
+use crate::runner::client::{self, Client, MergeStrategy, Patch};
+use crate::runner::events::{EventKind, EventTarget};
 use crate::runner::informer::{EventType, ResourceMessage};
-use crate::runner::reconcile::compare::compare_values;
+use crate::runner::reconcile::compare::{add_removed_fields, compare_values_ignoring, make_patch};
 use crate::runner::reconcile::{
-    does_finalizer_exist, update_status_if_different, SyncHandler, UpdateError,
+    does_finalizer_exist, patch_finalizers, update_status_if_different, SyncHandler, UpdateError,
 };
 use crate::runner::resource_map::IdSet;
 use crate::runner::{duration_to_millis, ChildRuntimeConfig, RuntimeConfig};
 
 use serde_json::{json, Value};
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -25,10 +31,10 @@ pub(crate) async fn handle_sync(handler: SyncHandler) {
         parent_index_key,
     } = handler;
     let parent_id = request.parent.get_object_id().to_owned();
-    let parent_id_ref = parent_id.as_id_ref();
+    let event_target = EventTarget::of(&request.parent);
 
     let start_time = Instant::now();
-    let result = private_handle_sync(start_time, request, handler, client, &*runtime_config).await;
+    let result = private_handle_sync(start_time, request, handler, client, &runtime_config).await;
 
     let update_result = match result {
         Ok(duration) => {
@@ -36,11 +42,22 @@ pub(crate) async fn handle_sync(handler: SyncHandler) {
             Ok(duration)
         }
         Err(err) => {
-            runtime_config.metrics.parent_sync_error(&parent_id_ref);
-            log::error!("Error while syncing parent: {}: {:?}", parent_id, err);
-            Err(())
+            err.report("sync", &parent_id, &runtime_config);
+            runtime_config.record_event(
+                &event_target,
+                EventKind::Warning,
+                err.event_reason("SyncFailed"),
+                "Sync",
+                err.to_string(),
+            );
+            Err(err.failure())
         }
     };
+    runtime_config.metrics.reconcile_finished(
+        runtime_config.parent_type,
+        start_time.elapsed(),
+        &update_result,
+    );
     let message = ResourceMessage {
         event_type: EventType::UpdateOperationComplete {
             result: update_result,
@@ -63,7 +80,8 @@ async fn private_handle_sync(
     client: Client,
     runtime_config: &RuntimeConfig,
 ) -> Result<Option<Duration>, UpdateError> {
-    if !does_finalizer_exist(&request.parent, runtime_config) {
+    // In a dry run, the finalizer would never actually be added, so we'd re-sync forever if we waited to observe it
+    if !does_finalizer_exist(&request.parent, runtime_config) && !client.is_dry_run() {
         // We'll only add the finalizer this time, and then immediately re-sync
         // This is because adding the finalizer will change the resourceVersion, so
         // we need to observe the new one before attempting to sync
@@ -71,56 +89,228 @@ async fn private_handle_sync(
         log::info!(
             "Observed new parent: {} and added '{}' as a finalizer",
             request.parent.get_object_id(),
-            runtime_config.operator_name
+            runtime_config.finalizer_name
         );
         Ok(Some(Duration::from_secs(0)))
     } else {
-        let (request, result) = {
-            tokio::task::spawn_blocking(move || {
-                let result = handler.sync(&request);
-                log::debug!(
-                    "finished invoking handler for parent: {} in {}ms",
-                    request.parent.get_object_id(),
-                    duration_to_millis(start_time.elapsed())
-                );
-                (request, result)
-            })
-            .await?
-        };
-        let response = result.map_err(UpdateError::HandlerError)?;
-        let resync = response.resync;
-        update_all(request, response, client, runtime_config).await?;
-        Ok(resync)
+        let parent_uid = request.parent.uid().to_owned();
+        if let Some(breaker) = runtime_config.circuit_breaker.as_ref() {
+            breaker
+                .try_acquire(&parent_uid, Instant::now())
+                .map_err(UpdateError::CircuitOpen)?;
+        }
+        let mut request = request;
+        let mut conflicts = 0;
+        loop {
+            let invoked = invoke_handler(
+                start_time,
+                request,
+                handler.clone(),
+                runtime_config.handler_timeout,
+            )
+            .await;
+            let (req, result) = match invoked {
+                Ok(invoked) => invoked,
+                Err(err) => {
+                    record_handler_outcome(runtime_config, &parent_uid, Some(&err));
+                    return Err(err);
+                }
+            };
+            request = req;
+            let response = match result {
+                Ok(response) => {
+                    record_handler_outcome(runtime_config, &parent_uid, None);
+                    response
+                }
+                // the handler gave up because the parent is already queued to be synced again, or else the operator
+                // is shutting down. Any other error is still a failure, even if the sync was cancelled in the meantime,
+                // so that a handler that keeps failing still backs off.
+                Err(err)
+                    if request.cancellation.is_cancelled()
+                        && err.downcast_ref::<Cancelled>().is_some() =>
+                {
+                    log::info!(
+                        "Ignoring handler error for parent: {} because the sync was cancelled: {}",
+                        request.parent.get_object_id(),
+                        err
+                    );
+                    return Ok(None);
+                }
+                Err(err) => {
+                    let err = UpdateError::HandlerError(err);
+                    record_handler_outcome(runtime_config, &parent_uid, Some(&err));
+                    return Err(err);
+                }
+            };
+            let resync = response.resync;
+            match update_all(&request, response, &client, runtime_config).await {
+                Err(UpdateError::ChildConflict(child_type, child_id, err))
+                    if conflicts < MAX_CONFLICT_RETRIES =>
+                {
+                    if request.cancellation.is_cancelled() {
+                        log::info!(
+                            "Not retrying the sync of parent: {} after a conflict because it was cancelled",
+                            request.parent.get_object_id()
+                        );
+                        return Ok(None);
+                    }
+                    conflicts += 1;
+                    log::info!(
+                        "Update of child {} {} of parent: {} conflicted with a concurrent change, re-reading and retrying the sync (attempt {} of {}): {}",
+                        child_type.kind,
+                        child_id,
+                        request.parent.get_object_id(),
+                        conflicts,
+                        MAX_CONFLICT_RETRIES,
+                        err
+                    );
+                    if !reread_request(&mut request, child_type, &child_id, &client, runtime_config)
+                        .await?
+                    {
+                        log::info!(
+                            "Parent: {} no longer exists, so the sync will not be retried",
+                            request.parent.get_object_id()
+                        );
+                        return Ok(None);
+                    }
+                }
+                other => return other.map(|_| resync),
+            }
+        }
     }
 }
 
-async fn update_all(
+/// Tells the circuit breaker, if there is one, how invoking the handler went. Errors that don't say anything about
+/// the health of the handler's dependencies, like fatal errors, aren't recorded at all.
+fn record_handler_outcome(
+    runtime_config: &RuntimeConfig,
+    parent_uid: &str,
+    error: Option<&UpdateError>,
+) {
+    let breaker = match runtime_config.circuit_breaker.as_ref() {
+        Some(breaker) => breaker,
+        None => return,
+    };
+    match error {
+        None => breaker.record(parent_uid, false, Instant::now()),
+        Some(err) if err.is_circuit_breaker_failure() => {
+            breaker.record(parent_uid, true, Instant::now())
+        }
+        Some(_) => {}
+    }
+}
+
+/// Invokes the handler on a blocking thread, and returns the request along with the result. If the handler doesn't
+/// return within the `timeout`, then the request is cancelled and this returns without waiting for it. The parent
+/// is then no longer in progress, so the abandoned handler may still be running when the next sync invokes it again.
+async fn invoke_handler(
+    start_time: Instant,
     request: SyncRequest,
+    handler: Arc<dyn Handler>,
+    timeout: Option<Duration>,
+) -> Result<(SyncRequest, Result<SyncResponse, anyhow::Error>), UpdateError> {
+    let cancellation = request.cancellation.clone();
+    // the span isn't propagated to the blocking thread automatically
+    let span = tracing::Span::current();
+    let task = tokio::task::spawn_blocking(move || {
+        let _entered = span.enter();
+        handler.before_sync(&request);
+        let result = handler.sync(&request);
+        handler.after_sync(&request, &result);
+        log::debug!(
+            "finished invoking handler for parent: {} in {}ms",
+            request.parent.get_object_id(),
+            duration_to_millis(start_time.elapsed())
+        );
+        (request, result)
+    });
+    let result = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, task).await {
+            Ok(joined) => joined?,
+            Err(_) => {
+                cancellation.cancel();
+                return Err(UpdateError::HandlerTimeout(timeout));
+            }
+        },
+        None => task.await?,
+    };
+    Ok(result)
+}
+
+/// Replaces the parent and children in the request with their current state from the api server, since the cache
+/// may not have observed the change that caused the conflict yet, or the updates that were already made during this
+/// sync. The conflicting child is read as well, in case it wasn't in the request at all. Returns false if the parent
+/// no longer exists.
+async fn reread_request(
+    request: &mut SyncRequest,
+    child_type: &'static K8sType,
+    child_id: &ObjectId,
+    client: &Client,
+    runtime_config: &RuntimeConfig,
+) -> Result<bool, UpdateError> {
+    let parent = client
+        .get_resource(runtime_config.parent_type, &request.parent.get_object_id())
+        .await?;
+    match parent {
+        Some(parent) => request.parent = K8sResource::from_value(parent)?,
+        None => return Ok(false),
+    }
+
+    let mut to_read = Vec::with_capacity(request.children.len() + 1);
+    for existing in request.children.iter() {
+        if let Some(existing_type) = runtime_config.type_for(&existing.get_type_ref()) {
+            to_read.push((existing_type, existing.get_object_id().to_owned()));
+        }
+    }
+    if !to_read
+        .iter()
+        .any(|(k8s_type, id)| *k8s_type == child_type && id == child_id)
+    {
+        to_read.push((child_type, child_id.clone()));
+    }
+
+    let mut children = Vec::with_capacity(to_read.len());
+    for (k8s_type, id) in to_read {
+        let child_client = runtime_config.client_for(k8s_type, client);
+        if let Some(child) = child_client.get_resource(k8s_type, &id.as_id_ref()).await? {
+            children.push(K8sResource::from_value(child)?);
+        }
+    }
+    request.children = children;
+    Ok(true)
+}
+
+async fn update_all(
+    request: &SyncRequest,
     handler_response: SyncResponse,
-    client: Client,
+    client: &Client,
     runtime_config: &RuntimeConfig,
 ) -> Result<(), UpdateError> {
     let start_time = Instant::now();
     let SyncResponse {
-        status, children, ..
+        status,
+        children,
+        child_scales,
+        ..
     } = handler_response;
     let parent_id = request.parent.get_object_id().to_owned();
-    update_status_if_different(&request.parent, &client, runtime_config, status).await?;
+    update_status_if_different(&request.parent, client, runtime_config, status).await?;
     log::debug!(
         "Successfully updated status for parent: {} in {}ms",
         parent_id,
         duration_to_millis(start_time.elapsed())
     );
-    let child_ids = update_children(&client, runtime_config, &request, children).await?;
+    let child_ids = update_children(client, runtime_config, request, children).await?;
     log::debug!(
         "Successfully updated all {} children of parent: {} in {}ms",
         child_ids.len(),
         parent_id,
         duration_to_millis(start_time.elapsed())
     );
+    scale_children(client, runtime_config, request, child_scales).await?;
 
     // now that all the child updates have completed successfully, we'll delete any children that are no longer desired
-    delete_undesired_children(&client, runtime_config, &child_ids, &request).await?;
+    delete_undesired_children(client, runtime_config, &child_ids, request).await?;
     Ok(())
 }
 
@@ -129,11 +319,7 @@ async fn add_finalizer_to_parent(
     client: &Client,
     runtime_config: &RuntimeConfig,
 ) -> Result<(), client::Error> {
-    let patch =
-        crate::runner::client::Patch::add_finalizer(parent, runtime_config.operator_name.as_str());
-    client
-        .patch_resource(runtime_config.parent_type, &parent.get_object_id(), &patch)
-        .await
+    patch_finalizers(client, runtime_config, parent, Patch::add_finalizer).await
 }
 
 async fn delete_undesired_children(
@@ -142,83 +328,253 @@ async fn delete_undesired_children(
     desired_children: &IdSet,
     sync_request: &SyncRequest,
 ) -> Result<(), client::Error> {
-    for existing_child in sync_request.children.iter() {
+    for (child_type, existing_child) in
+        undesired_children(runtime_config, desired_children, sync_request)
+    {
         let child_id = existing_child.get_object_id();
-        if !desired_children.contains(&child_id) && !existing_child.is_deletion_timestamp_set() {
+        let delete_policy = runtime_config
+            .child_types
+            .get(child_type)
+            .map(|child_config| child_config.delete_policy);
+        runtime_config
+            .client_for(child_type, client)
+            .delete_resource(child_type, &child_id, delete_policy)
+            .await?;
+        runtime_config.record_event(
+            &EventTarget::of(&sync_request.parent),
+            EventKind::Normal,
+            "Deleted",
+            "Delete",
+            format!("Deleted {} {}", child_type.kind, child_id),
+        );
+    }
+    Ok(())
+}
+
+/// Returns the existing children that aren't in the set of desired children, and so need to be deleted
+pub(crate) fn undesired_children<'a>(
+    runtime_config: &'a RuntimeConfig,
+    desired_children: &'a IdSet,
+    sync_request: &'a SyncRequest,
+) -> impl Iterator<Item = (&'static K8sType, &'a K8sResource)> + 'a {
+    sync_request
+        .children
+        .iter()
+        .filter(move |existing_child| {
+            !desired_children.contains(existing_child.get_object_id())
+                && !existing_child.is_deletion_timestamp_set()
+        })
+        .map(move |existing_child| {
             log::info!("Need to delete child: {} of parent: {} because it was not included in the handler response",
-                    child_id, sync_request.parent.get_object_id());
+                    existing_child.get_object_id(), sync_request.parent.get_object_id());
             let child_type = runtime_config
                 .type_for(&existing_child.get_type_ref())
                 .expect("No configuration found for existing child type");
-            client.delete_resource(child_type, &child_id).await?;
+            (child_type, existing_child)
+        })
+}
+
+/// A desired child from the handler response, along with whatever update is needed to make the existing child match it
+#[derive(Debug)]
+pub(crate) struct ChildUpdate<'a> {
+    pub child_id: ObjectId,
+    pub child_config: &'a ChildRuntimeConfig,
+    /// `None` if the existing child is already up to date
+    pub update_type: Option<UpdateType>,
+    /// The desired child, with the references to the parent added
+    pub child: Value,
+    pub exists: bool,
+}
+
+impl<'a> ChildUpdate<'a> {
+    /// The reason and action of the event that's recorded for the update
+    pub fn event_reason_and_action(&self) -> (&'static str, &'static str) {
+        match (&self.update_type, self.exists) {
+            (Some(UpdateType::Delete), _) => ("Deleted", "Delete"),
+            (_, false) => ("Created", "Create"),
+            (_, true) => ("Updated", "Update"),
         }
     }
-    Ok(())
 }
 
-async fn update_children(
-    client: &Client,
-    runtime_config: &RuntimeConfig,
+/// Validates a desired child from the handler response, and determines how the existing child needs to be updated
+pub(crate) fn plan_child_update<'a>(
+    runtime_config: &'a RuntimeConfig,
     req: &SyncRequest,
-    response_children: Vec<Value>,
-) -> Result<IdSet, UpdateError> {
-    let parent_uid = req.parent.uid();
+    mut child: Value,
+) -> Result<ChildUpdate<'a>, UpdateError> {
     let parent_id = req.parent.get_object_id();
-    let mut child_ids = IdSet::new();
-    for mut child in response_children {
-        let child_id = child
-            .get_id_ref()
-            .ok_or_else(|| InvalidResourceError::new("missing name", child.clone()))?
-            .to_owned();
-
-        // ensure that the child has the same namespace as the parent. This is a deliberate constraint that
-        // we place on users of this library, as having non-namespaced children of namespaced parents would
-        // add considerable complexity.
-        let valid_namespaces = match (parent_id.namespace(), child_id.namespace()) {
-            (None, None) => true,
-            (None, Some(_)) => true,
-            (Some(p), Some(c)) => p == c,
-            (Some(_), None) => false,
-        };
+    let child_id = child
+        .get_id_ref()
+        .ok_or_else(|| InvalidResourceError::new("missing name", child.clone()))?
+        .to_owned();
 
-        if !valid_namespaces {
+    let child_config: &'a ChildRuntimeConfig = {
+        let child_type_ref = child.get_type_ref().ok_or_else(|| {
+            InvalidResourceError::new("missing either apiVersion or kind", child.clone())
+        })?;
+
+        // get the configuration for this child type, and bail if it doesn't exist
+        runtime_config
+            .get_child_config(&child_type_ref)
+            .ok_or_else(|| {
+                UpdateError::UnknownChildType(
+                    child_type_ref.api_version().to_string(),
+                    child_type_ref.kind().to_string(),
+                )
+            })?
+    };
+    // ensure that namespaced children have the same namespace as the parent. This is a deliberate constraint that
+    // we place on users of this library, as having children in other namespaces would add considerable
+    // complexity. Cluster scoped children don't have a namespace at all, so they're allowed for any parent, and
+    // cluster scoped parents may have namespaced children in any namespace, as long as it's given explicitly.
+    let invalid_namespace_message = match (
+        child_config.child_type.scope,
+        parent_id.namespace(),
+        child_id.namespace(),
+    ) {
+        (Scope::Cluster, _, None) => None,
+        (Scope::Cluster, _, Some(_)) => Some("Cluster scoped child must not have a namespace"),
+        (Scope::Namespaced, None, Some(_)) => None,
+        (Scope::Namespaced, None, None) => {
+            Some("Namespaced child of a cluster scoped parent must have a namespace")
+        }
+        (Scope::Namespaced, Some(p), Some(c)) if p == c => None,
+        (Scope::Namespaced, Some(_), _) => {
+            Some("Child namespace does not match the namespace of the parent")
+        }
+    };
+
+    if let Some(message) = invalid_namespace_message {
+        log::error!(
+            "Child {} has an invalid namespace for parent: {}, {}",
+            child_id,
+            parent_id,
+            message
+        );
+        return Err(InvalidResourceError::new(message, child.clone()).into());
+    }
+
+    let existing_child = req
+        .children()
+        .of_type(child_config.child_type)
+        .get(&child_id);
+    if existing_child.is_none() {
+        check_for_collision(runtime_config, req, child_config.child_type, &child_id)?;
+    }
+    if child_config.track_last_applied {
+        // added before comparing, so that removing a field from the desired state still results in an update
+        add_last_applied(&mut child)?;
+    }
+    // added before comparing, so that changes to the propagated and managed labels and annotations are applied to
+    // existing children too
+    add_parent_references(runtime_config, &req.parent, &mut child)?;
+    let update_required = is_child_update_required(
+        &parent_id,
+        child_config,
+        existing_child,
+        &child_id.as_id_ref(),
+        &child,
+    )?;
+    Ok(ChildUpdate {
+        child_id,
+        child_config,
+        update_type: update_required,
+        child,
+        exists: existing_child.is_some(),
+    })
+}
+
+/// Stores the desired state of the child, as it was returned by the handler, in the `LAST_APPLIED_ANNOTATION`. The
+/// status, resourceVersion, and the previous annotation are left out, since they're not part of the desired state.
+fn add_last_applied(child: &mut Value) -> Result<(), InvalidResourceError> {
+    let mut last_applied = child.clone();
+    if let Some(obj) = last_applied.as_object_mut() {
+        obj.remove("status");
+    }
+    if let Some(meta) = last_applied
+        .pointer_mut("/metadata")
+        .and_then(Value::as_object_mut)
+    {
+        meta.remove("resourceVersion");
+        if let Some(annotations) = meta.get_mut("annotations").and_then(Value::as_object_mut) {
+            annotations.remove(LAST_APPLIED_ANNOTATION);
+            if annotations.is_empty() {
+                meta.remove("annotations");
+            }
+        }
+    }
+    let serialized = last_applied.to_string();
+    let annotations = require_object_mut(child, "/metadata", "child object is missing 'metadata'")?
+        .entry("annotations")
+        .or_insert_with(|| Value::Object(JsonObject::new()));
+    match annotations.as_object_mut() {
+        Some(annotations) => {
+            annotations.insert(
+                LAST_APPLIED_ANNOTATION.to_owned(),
+                Value::String(serialized),
+            );
+            Ok(())
+        }
+        None => Err(InvalidResourceError::new(
+            "child 'metadata.annotations' must be an object",
+            child.clone(),
+        )),
+    }
+}
+
+/// Returns an error if a child with the given id already exists, but is tracked by a different parent. Without this
+/// check, two parents that both want a child with the same name would keep overwriting each other's changes.
+fn check_for_collision(
+    runtime_config: &RuntimeConfig,
+    req: &SyncRequest,
+    child_type: &'static K8sType,
+    child_id: &ObjectId,
+) -> Result<(), UpdateError> {
+    let existing = match req.cache.get(child_type, child_id.as_id_ref()) {
+        Some(existing) => existing,
+        None => return Ok(()),
+    };
+    let tracking = runtime_config.child_tracking();
+    match tracking.parent_uid(&existing) {
+        Some(other_parent_uid) if other_parent_uid != req.parent.uid() => {
             log::error!(
-                "Child {} is not in the same namespace as parent: {}",
+                "Desired child {} {} of parent: {} already exists and belongs to the parent with uid: {}",
+                child_type.kind,
                 child_id,
-                parent_id
+                req.parent.get_object_id(),
+                other_parent_uid
             );
-            const MESSAGE: &str = "Child namespace does not match the namespace of the parent";
-            return Err(InvalidResourceError::new(MESSAGE, child.clone()).into());
+            Err(UpdateError::ChildCollision(
+                child_type,
+                child_id.clone(),
+                other_parent_uid.to_owned(),
+            ))
         }
+        _ => Ok(()),
+    }
+}
 
-        let child_config: &ChildRuntimeConfig = {
-            let child_type_ref = child.get_type_ref().ok_or_else(|| {
-                InvalidResourceError::new("missing either apiVersion or kind", child.clone())
-            })?;
-
-            // get the configuration for this child type, and bail if it doesn't exist
-            runtime_config
-                .get_child_config(&child_type_ref)
-                .ok_or_else(|| {
-                    UpdateError::UnknownChildType(
-                        child_type_ref.api_version().to_string(),
-                        child_type_ref.kind().to_string(),
-                    )
-                })?
-        };
-        let existing_child = req
-            .children()
-            .of_type(child_config.child_type)
-            .get(&child_id);
-        let update_required = is_child_update_required(
-            &parent_id,
+async fn update_children(
+    client: &Client,
+    runtime_config: &RuntimeConfig,
+    req: &SyncRequest,
+    response_children: Vec<Value>,
+) -> Result<IdSet, UpdateError> {
+    let parent_uid = req.parent.uid();
+    let mut child_ids = IdSet::new();
+    for child in response_children {
+        let planned = plan_child_update(runtime_config, req, child)?;
+        let (reason, action) = planned.event_reason_and_action();
+        let ChildUpdate {
+            child_id,
             child_config,
-            existing_child,
-            &child_id.as_id_ref(),
-            &child,
-        )?;
-        add_parent_references(runtime_config, parent_id.name(), parent_uid, &mut child)?;
-        if let Some(update_type) = update_required {
+            update_type,
+            child,
+            ..
+        } = planned;
+        if let Some(update_type) = update_type {
+            let client = runtime_config.client_for(child_config.child_type, client);
             let start_time = Instant::now();
             log::debug!(
                 "Starting child update for parent_uid: {}, child_type: {}, child_id: {}",
@@ -226,7 +582,15 @@ async fn update_children(
                 child_config.child_type,
                 child_id
             );
-            let result = do_child_update(update_type, child_config, client, child).await;
+            let result = match update_type {
+                UpdateType::Create if child_config.adopt_existing => {
+                    create_or_adopt(runtime_config, req, child_config, client, child).await
+                }
+                update_type => do_child_update(update_type, child_config, client, child)
+                    .await
+                    .map(|_| false)
+                    .map_err(UpdateError::Client),
+            };
             let total_millis = duration_to_millis(start_time.elapsed());
             log::debug!(
                 "Finshed child update for {} in {}ms with result: {:?}",
@@ -234,13 +598,214 @@ async fn update_children(
                 total_millis,
                 result
             );
-            result?; // return early if it failed
+            // return early if it failed
+            let adopted = match result {
+                Err(UpdateError::Client(err))
+                    if child_config.optimistic_concurrency && err.is_http_status(409) =>
+                {
+                    return Err(UpdateError::ChildConflict(
+                        child_config.child_type,
+                        child_id,
+                        err,
+                    ));
+                }
+                other => other?,
+            };
+            let (reason, action) = if adopted {
+                ("Adopted", "Adopt")
+            } else {
+                (reason, action)
+            };
+            runtime_config.record_event(
+                &EventTarget::of(&req.parent),
+                EventKind::Normal,
+                reason,
+                action,
+                format!("{} {} {}", reason, child_config.child_type.kind, child_id),
+            );
         }
         child_ids.insert(child_id);
     }
     Ok(child_ids)
 }
 
+async fn scale_children(
+    client: &Client,
+    runtime_config: &RuntimeConfig,
+    req: &SyncRequest,
+    child_scales: Vec<ChildScale>,
+) -> Result<(), UpdateError> {
+    for child_scale in child_scales {
+        let ChildScaleUpdate {
+            child_type,
+            child_id,
+            scale,
+        } = match plan_child_scale(runtime_config, req, child_scale)? {
+            Some(update) => update,
+            None => continue,
+        };
+        let child_client = runtime_config.client_for(child_type, client);
+        let metadata_only = runtime_config
+            .child_types
+            .get(child_type)
+            .map(|child_config| child_config.metadata_only)
+            .unwrap_or(false);
+        // the cache doesn't have the spec of metadata only children, so their current replicas are read from the
+        // scale subresource instead, so that they aren't scaled again on every sync
+        if metadata_only {
+            let current = child_client
+                .get_subresource(child_type.subresource("scale"), &child_id.as_id_ref())
+                .await?;
+            let current_replicas = current
+                .as_ref()
+                .and_then(|scale| scale.pointer("/spec/replicas"))
+                .and_then(Value::as_i64);
+            if current_replicas == Some(i64::from(scale.spec.replicas)) {
+                continue;
+            }
+        }
+        let body = serde_json::to_value(&scale).expect("Scale is always serializable");
+        child_client
+            .replace_subresource(
+                child_type.subresource("scale"),
+                &child_id.as_id_ref(),
+                &body,
+            )
+            .await?;
+        runtime_config.record_event(
+            &EventTarget::of(&req.parent),
+            EventKind::Normal,
+            "Scaled",
+            "Scale",
+            format!(
+                "Scaled {} {} to {} replicas",
+                child_type.kind, child_id, scale.spec.replicas
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// A change to the desired replicas of an existing child, which is made using its `scale` subresource
+#[derive(Debug)]
+pub(crate) struct ChildScaleUpdate {
+    pub child_type: &'static K8sType,
+    pub child_id: ObjectId,
+    pub scale: Scale,
+}
+
+/// Determines whether a child needs to be scaled. Returns `None` if the child already has the desired replicas, or if
+/// it doesn't exist yet, in which case it'll be scaled on the sync that's triggered once it's been created. The
+/// replicas of `metadata_only` children aren't known, so they're always planned, and checked when they're scaled.
+pub(crate) fn plan_child_scale(
+    runtime_config: &RuntimeConfig,
+    req: &SyncRequest,
+    child_scale: ChildScale,
+) -> Result<Option<ChildScaleUpdate>, UpdateError> {
+    let ChildScale {
+        api_version,
+        kind,
+        namespace,
+        name,
+        replicas,
+    } = child_scale;
+    let child_type = runtime_config
+        .type_for(&K8sTypeRef::new(api_version.as_str(), kind.as_str()))
+        .ok_or_else(|| UpdateError::UnknownChildType(api_version.clone(), kind.clone()))?;
+    let namespace = match child_type.scope {
+        Scope::Namespaced => namespace.or_else(|| req.parent.namespace().map(str::to_owned)),
+        Scope::Cluster => None,
+    };
+    let child_id = ObjectId::new(namespace.unwrap_or_default(), name);
+    let existing_child = match req.children().of_type(child_type).get(&child_id) {
+        Some(child) => child,
+        None => {
+            log::debug!(
+                "Not scaling child {} {} of parent: {} because it doesn't exist yet",
+                kind,
+                child_id,
+                req.parent.get_object_id()
+            );
+            return Ok(None);
+        }
+    };
+    let current_replicas = existing_child
+        .pointer("/spec/replicas")
+        .and_then(Value::as_i64);
+    if current_replicas == Some(i64::from(replicas)) {
+        return Ok(None);
+    }
+    let scale = Scale::new(child_id.namespace(), child_id.name(), replicas);
+    Ok(Some(ChildScaleUpdate {
+        child_type,
+        child_id,
+        scale,
+    }))
+}
+
+/// Creates the child, or if `adopt_existing` is enabled and a resource with the same name already exists, then updates
+/// the existing resource to match the desired child, which adds the references to the parent. Resources that belong
+/// to another parent are never adopted. Returns true if an existing resource was adopted.
+async fn create_or_adopt(
+    runtime_config: &RuntimeConfig,
+    req: &SyncRequest,
+    child_config: &ChildRuntimeConfig,
+    client: &Client,
+    child: Value,
+) -> Result<bool, UpdateError> {
+    let create_err =
+        match do_child_update(UpdateType::Create, child_config, client, child.clone()).await {
+            Ok(()) => return Ok(false),
+            Err(err) if err.is_http_status(409) => err,
+            Err(err) => return Err(err.into()),
+        };
+    let child_type = child_config.child_type;
+    let child_id = child
+        .get_id_ref()
+        .expect("failed to get id from desired child resource")
+        .to_owned();
+    // the existing resource isn't in the cache, since it's not tracked yet, so it has to be read from the api server
+    let existing = match client
+        .get_resource(child_type, &child_id.as_id_ref())
+        .await?
+    {
+        Some(existing) => K8sResource::from_value(existing)?,
+        // it was deleted in the meantime, so it'll be created on the next sync
+        None => return Err(create_err.into()),
+    };
+    if let Some(other_parent_uid) = runtime_config.child_tracking().parent_uid(&existing) {
+        if other_parent_uid != req.parent.uid() {
+            return Err(UpdateError::ChildCollision(
+                child_type,
+                child_id,
+                other_parent_uid.to_owned(),
+            ));
+        }
+    }
+    // adopting a child should never delete it, or leave it without the references to the parent
+    let update_strategy = match child_config.update_strategy {
+        UpdateStrategy::Recreate | UpdateStrategy::OnDelete => UpdateStrategy::Replace,
+        other => other,
+    };
+    let update_type = match determine_update_type(
+        &existing,
+        update_strategy,
+        child_config.optimistic_concurrency,
+    ) {
+        Some(update_type) => update_type,
+        // it's being deleted, so it'll be created once it's gone
+        None => return Err(create_err.into()),
+    };
+    log::info!(
+        "Adopting existing child {} {} for parent: {}",
+        child_type.kind,
+        child_id,
+        req.parent.get_object_id()
+    );
+    do_child_update(update_type, child_config, client, child).await?;
+    Ok(true)
+}
+
 async fn do_child_update(
     update_type: UpdateType,
     child_config: &ChildRuntimeConfig,
@@ -270,23 +835,114 @@ async fn do_child_update(
                 .replace_resource(k8s_type, &child_id, &desired_child)
                 .await
         }
+        UpdateType::Patch(existing_child) => {
+            let resource_version = existing_child
+                .pointer("/metadata/resourceVersion")
+                .cloned()
+                .unwrap_or(Value::Null);
+            let mut patch_value =
+                make_patch(&existing_child, &desired_child, &child_config.ignored_paths);
+            let last_applied = Some(&existing_child)
+                .filter(|_| child_config.track_last_applied)
+                .and_then(parse_last_applied);
+            if let Some(last_applied) = last_applied {
+                add_removed_fields(
+                    &mut patch_value,
+                    &existing_child,
+                    &desired_child,
+                    &last_applied,
+                    &child_config.ignored_paths,
+                );
+            }
+            if let Some(patch_obj) = patch_value.as_object_mut() {
+                // include the resourceVersion as a precondition, the same as we would for a replace
+                let meta = patch_obj
+                    .entry("metadata")
+                    .or_insert_with(|| Value::Object(JsonObject::new()));
+                if let Some(meta_obj) = meta.as_object_mut() {
+                    meta_obj.insert("resourceVersion".to_owned(), resource_version);
+                }
+            }
+            let child_id = desired_child
+                .get_id_ref()
+                .expect("failed to get id from desired child resource");
+            let patch = Patch::new(MergeStrategy::StrategicMerge, patch_value.clone());
+            match client.patch_resource(k8s_type, &child_id, &patch).await {
+                // custom resources don't support strategic merge patches, so we fall back to a json merge patch
+                Err(ref err) if err.is_http_status(415) => {
+                    log::debug!(
+                        "Strategic merge patch is not supported for child type: {}, falling back to json merge patch",
+                        k8s_type
+                    );
+                    let patch = Patch::new(MergeStrategy::JsonMerge, patch_value);
+                    client.patch_resource(k8s_type, &child_id, &patch).await
+                }
+                other => other,
+            }
+        }
+        UpdateType::Apply(resource_version) => {
+            if let Some(resource_version) = resource_version {
+                // the api server only applies the request if the child still has this resourceVersion
+                if let Some(meta) = desired_child
+                    .pointer_mut("/metadata")
+                    .and_then(Value::as_object_mut)
+                {
+                    meta.insert(
+                        "resourceVersion".to_owned(),
+                        Value::String(resource_version),
+                    );
+                }
+            }
+            let child_id = desired_child
+                .get_id_ref()
+                .expect("failed to get id from desired child resource");
+            client
+                .apply_resource(
+                    k8s_type,
+                    &child_id,
+                    &desired_child,
+                    child_config.field_manager.as_str(),
+                    child_config.force_apply,
+                )
+                .await
+        }
         UpdateType::Delete => {
             let child_id = desired_child
                 .get_id_ref()
                 .expect("failed to get id from desired child resource");
             // TODO: Deleting a resource could return a 409 error if it's already being deleted. Figure out how to deal with that
-            client.delete_resource(k8s_type, &child_id).await
+            client
+                .delete_resource(k8s_type, &child_id, Some(child_config.delete_policy))
+                .await
         }
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
-enum UpdateType {
+pub(crate) enum UpdateType {
     Create,
     Replace(String),
+    /// holds the existing child, which is needed in order to compute the patch
+    Patch(Value),
+    /// holds the `resourceVersion` of the existing child if the apply should be conditional on it, which is only
+    /// the case when `optimistic_concurrency` is enabled
+    Apply(Option<String>),
     Delete,
 }
 
+/// Returns a copy of the resource with only its `apiVersion`, `kind`, and `metadata`
+fn only_metadata(resource: &Value) -> Value {
+    let mut metadata = serde_json::Map::new();
+    if let Some(obj) = resource.as_object() {
+        for key in &["apiVersion", "kind", "metadata"] {
+            if let Some(value) = obj.get(*key) {
+                metadata.insert((*key).to_owned(), value.clone());
+            }
+        }
+    }
+    Value::Object(metadata)
+}
+
 fn is_child_update_required(
     parent_id: &ObjectIdRef<'_>,
     child_config: &ChildRuntimeConfig,
@@ -301,7 +957,19 @@ fn is_child_update_required(
             None
         }
         (Some(existing_child), update_strategy) => {
-            let diffs = compare_values(existing_child.as_ref(), child);
+            // only the metadata of the existing child is cached, so that's all that can be compared
+            let metadata_only;
+            let desired = if child_config.metadata_only {
+                metadata_only = only_metadata(child);
+                &metadata_only
+            } else {
+                child
+            };
+            let diffs = compare_values_ignoring(
+                existing_child.as_ref(),
+                desired,
+                &child_config.ignored_paths,
+            );
             if diffs.non_empty() {
                 log::info!(
                     "Found {} diffs in child of parent: {} with type: {} and id: {}, diffs: {}",
@@ -311,7 +979,11 @@ fn is_child_update_required(
                     child_id,
                     diffs
                 );
-                determine_update_type(existing_child, update_strategy)
+                determine_update_type(
+                    existing_child,
+                    update_strategy,
+                    child_config.optimistic_concurrency,
+                )
             } else {
                 log::debug!(
                     "No difference in child of parent: {}, with type: {} and id: {}",
@@ -322,14 +994,19 @@ fn is_child_update_required(
                 None
             }
         }
-        (None, _) => {
+        (None, update_strategy) => {
             log::debug!(
                 "No existing child of parent: {} with type: {} and id: {}",
                 parent_id,
                 child_config.child_type,
                 child_id
             );
-            Some(UpdateType::Create)
+            if update_strategy == UpdateStrategy::ServerSideApply {
+                // apply will create the resource, and it ensures that the operator owns the fields from the start
+                Some(UpdateType::Apply(None))
+            } else {
+                Some(UpdateType::Create)
+            }
         }
     };
     Ok(update_type)
@@ -340,6 +1017,7 @@ fn is_child_update_required(
 fn determine_update_type(
     existing_child: &K8sResource,
     update_strategy: UpdateStrategy,
+    optimistic_concurrency: bool,
 ) -> Option<UpdateType> {
     if existing_child.is_deletion_timestamp_set() {
         log::debug!(
@@ -354,24 +1032,49 @@ fn determine_update_type(
         // once the delete has finished. This allows us to continue to make progress on the rest of the sync operations
         // since deletion can sometimes take quite a while due to finalizers needing to run.
         Some(UpdateType::Delete)
+    } else if update_strategy == UpdateStrategy::ServerSideApply {
+        let resource_version = Some(existing_child.resource_version())
+            .filter(|_| optimistic_concurrency)
+            .map(str::to_owned);
+        Some(UpdateType::Apply(resource_version))
+    } else if update_strategy == UpdateStrategy::Patch {
+        Some(UpdateType::Patch(existing_child.as_ref().clone()))
     } else {
         let resource_version = existing_child.resource_version();
         Some(UpdateType::Replace(resource_version.to_owned()))
     }
 }
 
-fn add_parent_references(
+pub(crate) fn add_parent_references(
     runtime_config: &RuntimeConfig,
-    parent_name: &str,
-    parent_uid: &str,
+    parent: &K8sResource,
     child: &mut Value,
 ) -> Result<(), InvalidResourceError> {
+    let parent_name = parent.name();
+    let parent_uid = parent.uid();
+    // an owner reference to a parent in another cluster would look like a reference to a parent that doesn't exist,
+    // so the garbage collector would delete the child right away. The same goes for a cluster scoped child of a
+    // namespaced parent, since the garbage collector would look for the parent at the cluster scope.
+    let has_owner_reference = child
+        .get_type_ref()
+        .and_then(|type_ref| runtime_config.get_child_config(&type_ref))
+        .map(|child_config| child_config.has_owner_reference)
+        .unwrap_or(true);
     let meta = require_object_mut(child, "/metadata", "child object is missing 'metadata'")?;
-    if !meta.contains_key("labels") || !meta.get("labels").unwrap().is_object() {
-        meta.insert("labels".to_owned(), Value::Object(JsonObject::new()));
-    }
-    {
-        let labels = meta.get_mut("labels").unwrap().as_object_mut().unwrap(); // we just ensured this above
+    // the propagated and managed labels and annotations were explicitly configured, so they're added regardless of how
+    // children are tracked. The tracking labels are added afterwards, so they always win.
+    copy_from_parent(meta, "labels", parent, &runtime_config.propagated_labels);
+    copy_from_parent(
+        meta,
+        "annotations",
+        parent,
+        &runtime_config.propagated_annotations,
+    );
+    add_all(meta, "labels", &runtime_config.managed_labels);
+    add_all(meta, "annotations", &runtime_config.managed_annotations);
+    // when tracking via owner references, we don't add any labels, since the child type may not allow them
+    if !runtime_config.track_via_owner_references {
+        let labels = object_field(meta, "labels");
         labels.insert(
             runtime_config.correlation_label_name.clone(),
             parent_uid.into(),
@@ -381,6 +1084,9 @@ fn add_parent_references(
             runtime_config.operator_name.as_str().into(),
         );
     }
+    if !has_owner_reference {
+        return Ok(());
+    }
     if !meta.contains_key("ownerReferences") || !meta.get("ownerReferences").unwrap().is_array() {
         meta.insert("ownerReferences".to_owned(), Value::Array(Vec::new()));
     }
@@ -396,14 +1102,58 @@ fn add_parent_references(
     Ok(())
 }
 
+/// Returns the object at the given key of the metadata, replacing anything that isn't an object
+fn object_field<'a>(meta: &'a mut JsonObject, key: &str) -> &'a mut JsonObject {
+    let field = meta
+        .entry(key)
+        .or_insert_with(|| Value::Object(JsonObject::new()));
+    if !field.is_object() {
+        *field = Value::Object(JsonObject::new());
+    }
+    field.as_object_mut().unwrap() // we just ensured this above
+}
+
+/// Copies each of the named labels or annotations from the parent, if the parent has them
+fn copy_from_parent(meta: &mut JsonObject, key: &str, parent: &K8sResource, names: &[String]) {
+    let parent_values = match parent
+        .as_ref()
+        .pointer(&format!("/metadata/{}", key))
+        .and_then(Value::as_object)
+    {
+        Some(values) => values,
+        None => return,
+    };
+    for name in names {
+        if let Some(value) = parent_values.get(name) {
+            object_field(meta, key).insert(name.clone(), value.clone());
+        }
+    }
+}
+
+fn add_all(meta: &mut JsonObject, key: &str, values: &HashMap<String, String>) {
+    if values.is_empty() {
+        return;
+    }
+    let obj = object_field(meta, key);
+    for (name, value) in values {
+        obj.insert(name.clone(), Value::String(value.clone()));
+    }
+}
+
 fn make_owner_ref(parent_uid: &str, parent_name: &str, runtime_config: &RuntimeConfig) -> Value {
-    json!({
+    let mut owner_ref = json!({
         "apiVersion": runtime_config.parent_type.api_version,
         "controller": true,
         "kind": runtime_config.parent_type.kind,
         "name": parent_name,
         "uid": parent_uid,
-    })
+    });
+    if runtime_config.track_via_owner_references {
+        // the owner reference is the only link to the parent, so the parent shouldn't disappear before its children
+        let obj = owner_ref.as_object_mut().unwrap();
+        obj.insert("blockOwnerDeletion".to_owned(), Value::Bool(true));
+    }
+    owner_ref
 }
 
 fn require_object_mut<'a>(
@@ -424,3 +1174,464 @@ fn require_object_mut<'a>(
         Err(InvalidResourceError::new(err_msg, value.clone()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{ChildConfig, OperatorConfig};
+    use crate::handler::cache::ResourceCache;
+    use crate::k8s_types::core::v1::{ConfigMap, Pod};
+    use crate::runner::metrics::Metrics;
+    use crate::runner::reconcile::SyncFailure;
+
+    #[test]
+    fn only_cancelled_errors_are_ignored_once_the_sync_is_cancelled() {
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let client_config: crate::config::ClientConfig = serde_json::from_value(json!({
+            "apiServerEndpoint": "http://127.0.0.1:1",
+            "userAgent": "test-operator",
+            "credentials": {"header": "Bearer abc"},
+        }))
+        .unwrap();
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut sync_with = |handler: fn(&SyncRequest) -> Result<SyncResponse, anyhow::Error>| {
+            let client = Client::new(
+                client_config.clone(),
+                runtime_config.metrics.client_metrics(),
+            )
+            .unwrap();
+            let mut parent = test_parent().into_value();
+            parent["metadata"]["finalizers"] = json!(["test-operator"]);
+            let request = SyncRequest {
+                parent: K8sResource::from_value(parent).unwrap(),
+                children: Vec::new(),
+                cache: ResourceCache::default(),
+                cancellation: Default::default(),
+            };
+            runtime.block_on(private_handle_sync(
+                Instant::now(),
+                request,
+                Arc::new(handler),
+                client,
+                &runtime_config,
+            ))
+        };
+
+        let result = sync_with(|request| {
+            request.cancellation().cancel();
+            request.cancellation().check()?;
+            Ok(SyncResponse::new(Value::Null))
+        });
+        assert!(matches!(result, Ok(None)), "result was: {:?}", result);
+
+        // the handler failed on its own, so the failure backs off as usual
+        let result = sync_with(|request| {
+            request.cancellation().cancel();
+            Err(anyhow::anyhow!("external system is down"))
+        });
+        match result {
+            Err(err @ UpdateError::HandlerError(_)) => {
+                assert_eq!(SyncFailure::Retry(None), err.failure())
+            }
+            other => panic!("expected a handler error, got: {:?}", other),
+        }
+    }
+
+    fn test_parent() -> K8sResource {
+        K8sResource::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "namespace": "default",
+                "name": "parent",
+                "uid": "parent-uid",
+                "resourceVersion": "1",
+                "labels": { "team": "payments", "tier": "web" },
+                "annotations": { "example.com/owner": "someone@example.com" },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn planned_update(child_config: ChildConfig) -> Option<UpdateType> {
+        let config = OperatorConfig::new("test-operator", Pod).with_child(ConfigMap, child_config);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let parent = K8sResource::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": { "namespace": "default", "name": "parent", "uid": "parent-uid", "resourceVersion": "1" },
+        }))
+        .unwrap();
+        // the existing child has the tracking label and owner reference that were added when it was created
+        let mut existing = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child", "uid": "child-uid", "resourceVersion": "7" },
+            "data": { "foo": "bar" },
+        });
+        add_parent_references(&runtime_config, &parent, &mut existing).unwrap();
+        let request = SyncRequest {
+            parent,
+            children: vec![K8sResource::from_value(existing).unwrap()],
+            cache: ResourceCache::default(),
+            cancellation: Default::default(),
+        };
+        let desired = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child" },
+            "data": { "foo": "baz" },
+        });
+        plan_child_update(&runtime_config, &request, desired)
+            .unwrap()
+            .update_type
+    }
+
+    #[test]
+    fn desired_state_is_stored_in_the_last_applied_annotation() {
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(ConfigMap, ChildConfig::patch().track_last_applied(true));
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let request = SyncRequest {
+            parent: K8sResource::from_value(json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "namespace": "default", "name": "parent", "uid": "parent-uid", "resourceVersion": "1" },
+            }))
+            .unwrap(),
+            children: Vec::new(),
+            cache: ResourceCache::default(),
+            cancellation: Default::default(),
+        };
+        let desired = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child", "resourceVersion": "3" },
+            "data": { "foo": "baz" },
+        });
+        let planned = plan_child_update(&runtime_config, &request, desired).unwrap();
+        let expected = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child" },
+            "data": { "foo": "baz" },
+        });
+        assert_eq!(Some(expected), parse_last_applied(&planned.child));
+    }
+
+    #[test]
+    fn apply_is_conditional_on_the_resource_version_with_optimistic_concurrency() {
+        assert_eq!(
+            Some(UpdateType::Apply(None)),
+            planned_update(ChildConfig::server_side_apply("test"))
+        );
+        assert_eq!(
+            Some(UpdateType::Apply(Some("7".to_owned()))),
+            planned_update(ChildConfig::server_side_apply("test").optimistic_concurrency(true))
+        );
+        assert_eq!(
+            Some(UpdateType::Replace("7".to_owned())),
+            planned_update(ChildConfig::replace().optimistic_concurrency(true))
+        );
+    }
+
+    #[test]
+    fn only_metadata_is_compared_for_metadata_only_children() {
+        assert_eq!(
+            None,
+            planned_update(ChildConfig::replace().metadata_only(true))
+        );
+    }
+
+    #[test]
+    fn managed_labels_and_annotations_are_added_without_replacing_the_tracking_labels() {
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(ConfigMap, ChildConfig::replace())
+            .with_managed_labels(vec![
+                ("team".to_owned(), "platform".to_owned()),
+                (
+                    crate::config::DEFAULT_TRACKING_LABEL_NAME.to_owned(),
+                    "nope".to_owned(),
+                ),
+            ])
+            .with_managed_annotations(vec![("cost-center".to_owned(), "1234".to_owned())]);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let mut child = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child", "labels": { "team": "other", "app": "foo" } },
+        });
+        add_parent_references(&runtime_config, &test_parent(), &mut child).unwrap();
+
+        let labels = child.pointer("/metadata/labels").unwrap();
+        assert_eq!(json!("platform"), labels["team"]);
+        assert_eq!(json!("foo"), labels["app"]);
+        assert_eq!(
+            json!("parent-uid"),
+            labels[crate::config::DEFAULT_TRACKING_LABEL_NAME]
+        );
+        assert_eq!(
+            Some("1234"),
+            child
+                .pointer("/metadata/annotations/cost-center")
+                .and_then(Value::as_str)
+        );
+    }
+
+    #[test]
+    fn propagated_labels_and_annotations_are_copied_from_the_parent() {
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(ConfigMap, ChildConfig::replace())
+            .propagate_labels(vec![
+                "team".to_owned(),
+                "tier".to_owned(),
+                "missing".to_owned(),
+            ])
+            .propagate_annotations(vec!["example.com/owner".to_owned()])
+            .with_managed_labels(vec![("tier".to_owned(), "managed".to_owned())]);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let mut child = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child", "labels": { "team": "other" } },
+        });
+        add_parent_references(&runtime_config, &test_parent(), &mut child).unwrap();
+
+        let labels = child.pointer("/metadata/labels").unwrap();
+        assert_eq!(json!("payments"), labels["team"]);
+        // managed labels win over propagated ones
+        assert_eq!(json!("managed"), labels["tier"]);
+        assert!(labels.get("missing").is_none());
+        assert_eq!(
+            Some("someone@example.com"),
+            child
+                .pointer("/metadata/annotations/example.com~1owner")
+                .and_then(Value::as_str)
+        );
+    }
+
+    #[test]
+    fn changing_a_propagated_label_on_the_parent_updates_existing_children() {
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(ConfigMap, ChildConfig::patch())
+            .propagate_labels(vec!["team".to_owned()]);
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let desired = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child" },
+            "data": { "foo": "bar" },
+        });
+        let mut existing = desired.clone();
+        existing["metadata"]["uid"] = json!("child-uid");
+        existing["metadata"]["resourceVersion"] = json!("7");
+        add_parent_references(&runtime_config, &test_parent(), &mut existing).unwrap();
+
+        let mut parent = test_parent().into_value();
+        let request_for = |parent: &Value| SyncRequest {
+            parent: K8sResource::from_value(parent.clone()).unwrap(),
+            children: vec![K8sResource::from_value(existing.clone()).unwrap()],
+            cache: ResourceCache::default(),
+            cancellation: Default::default(),
+        };
+        let planned =
+            plan_child_update(&runtime_config, &request_for(&parent), desired.clone()).unwrap();
+        assert_eq!(None, planned.update_type);
+
+        parent["metadata"]["labels"]["team"] = json!("platform");
+        let planned = plan_child_update(&runtime_config, &request_for(&parent), desired).unwrap();
+        assert!(
+            matches!(planned.update_type, Some(UpdateType::Patch(_))),
+            "expected a patch, got: {:?}",
+            planned.update_type
+        );
+        assert_eq!(
+            Some("platform"),
+            planned
+                .child
+                .pointer("/metadata/labels/team")
+                .and_then(Value::as_str)
+        );
+    }
+
+    #[test]
+    fn children_in_another_cluster_are_tracked_without_owner_references() {
+        let other_cluster: crate::config::ClientConfig = serde_json::from_value(json!({
+            "apiServerEndpoint": "https://other:6443",
+            "userAgent": "test-operator",
+            "credentials": { "header": "Bearer abc" },
+        }))
+        .unwrap();
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(ConfigMap, ChildConfig::replace().with_client(other_cluster));
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let mut child = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child" },
+        });
+        add_parent_references(&runtime_config, &test_parent(), &mut child).unwrap();
+
+        assert_eq!(
+            Some("parent-uid"),
+            child
+                .pointer("/metadata/labels")
+                .and_then(|labels| labels[crate::config::DEFAULT_TRACKING_LABEL_NAME].as_str())
+        );
+        assert_eq!(None, child.pointer("/metadata/ownerReferences"));
+    }
+
+    #[test]
+    fn cluster_scoped_children_of_namespaced_parents_are_tracked_without_owner_references() {
+        use crate::k8s_types::core::v1::Namespace;
+
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(Namespace, ChildConfig::replace())
+            .with_child(ConfigMap, ChildConfig::replace());
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let mut child = json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": "child" },
+        });
+        add_parent_references(&runtime_config, &test_parent(), &mut child).unwrap();
+
+        assert_eq!(
+            Some("parent-uid"),
+            child
+                .pointer("/metadata/labels")
+                .and_then(|labels| labels[crate::config::DEFAULT_TRACKING_LABEL_NAME].as_str())
+        );
+        assert_eq!(None, child.pointer("/metadata/ownerReferences"));
+
+        let mut namespaced_child = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "namespace": "default", "name": "child" },
+        });
+        add_parent_references(&runtime_config, &test_parent(), &mut namespaced_child).unwrap();
+        assert!(namespaced_child
+            .pointer("/metadata/ownerReferences/0")
+            .is_some());
+    }
+
+    #[test]
+    fn metadata_only_children_are_only_scaled_when_their_scale_is_different() {
+        use crate::handler::ChildScale;
+        use crate::k8s_types::apps::v1::Deployment;
+
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let config = OperatorConfig::new("test-operator", Pod)
+            .with_child(Deployment, ChildConfig::replace().metadata_only(true));
+        let runtime_config = RuntimeConfig::new(&config, Metrics::new(), None);
+        let existing = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "namespace": "default", "name": "web", "uid": "web-uid", "resourceVersion": "3" },
+        });
+        let request = SyncRequest {
+            parent: test_parent(),
+            children: vec![K8sResource::from_value(existing).unwrap()],
+            cache: ResourceCache::default(),
+            cancellation: Default::default(),
+        };
+
+        let requests = runtime.block_on(async {
+            let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let recorded = requests.clone();
+            let make_service = hyper::service::make_service_fn(move |_| {
+                let recorded = recorded.clone();
+                async move {
+                    Ok::<_, hyper::Error>(hyper::service::service_fn(
+                        move |req: http::Request<hyper::Body>| {
+                            recorded.lock().unwrap().push(format!(
+                                "{} {}",
+                                req.method(),
+                                req.uri().path()
+                            ));
+                            let scale = json!({
+                                "apiVersion": "autoscaling/v1",
+                                "kind": "Scale",
+                                "metadata": { "namespace": "default", "name": "web" },
+                                "spec": { "replicas": 3 },
+                            });
+                            async move {
+                                Ok::<_, hyper::Error>(http::Response::new(hyper::Body::from(
+                                    scale.to_string(),
+                                )))
+                            }
+                        },
+                    ))
+                }
+            });
+            let server = hyper::Server::bind(&std::net::SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+            let client_config: crate::config::ClientConfig = serde_json::from_value(json!({
+                "apiServerEndpoint": format!("http://{}", addr),
+                "userAgent": "test-operator",
+                "credentials": { "header": "Bearer abc" },
+            }))
+            .unwrap();
+            let client = Client::new(client_config, Metrics::new().client_metrics()).unwrap();
+
+            let unchanged = vec![ChildScale::new(Deployment, "web", 3)];
+            scale_children(&client, &runtime_config, &request, unchanged)
+                .await
+                .unwrap();
+            let changed = vec![ChildScale::new(Deployment, "web", 5)];
+            scale_children(&client, &runtime_config, &request, changed)
+                .await
+                .unwrap();
+            requests
+        });
+        let scale_path = "/apis/apps/v1/namespaces/default/deployments/web/scale";
+        assert_eq!(
+            vec![
+                format!("GET {}", scale_path),
+                format!("GET {}", scale_path),
+                format!("PUT {}", scale_path),
+            ],
+            *requests.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn handler_that_takes_too_long_is_cancelled() {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        let request = crate::handler::request::test_request();
+        let cancellation = request.cancellation.clone();
+        let handler = |request: &SyncRequest| {
+            while !request.cancellation().is_cancelled() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            Ok(SyncResponse::new(Value::Null))
+        };
+        let timeout = Duration::from_millis(20);
+        let result = runtime.block_on(invoke_handler(
+            Instant::now(),
+            request,
+            Arc::new(handler),
+            Some(timeout),
+        ));
+        match result {
+            Err(UpdateError::HandlerTimeout(actual)) => assert_eq!(timeout, actual),
+            other => panic!("expected a timeout, got: {:?}", other.map(|(_, r)| r)),
+        }
+        assert!(cancellation.is_cancelled());
+    }
+}
@@ -60,6 +60,17 @@ impl IdMap<K8sResource> {
     pub fn get_copy<'a>(&self, id: impl Into<ObjectIdRef<'a>>) -> Option<K8sResource> {
         self.get(id).cloned()
     }
+
+    /// Returns every resource in the given namespace, or in all namespaces if `namespace` is `None`
+    pub fn iter_namespace<'a>(
+        &'a self,
+        namespace: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a K8sResource> {
+        self.0
+            .iter()
+            .filter(move |(ns, _)| namespace.filter(|n| *n != ns.as_str()).is_none())
+            .flat_map(|(_, by_name)| by_name.values())
+    }
 }
 
 impl IdMap<()> {
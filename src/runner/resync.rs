@@ -0,0 +1,117 @@
+//! Periodically re-queues every parent for sync, regardless of whether any watch events were received.
+//! This is a safety net for recovering from missed events, or from drift in external systems that the
+//! operator has no way to watch.
+use crate::k8s_types::K8sType;
+use crate::runner::duration_to_millis;
+use crate::runner::informer::{EventType, ResourceMessage, ResourceMonitor, UidToIdIndex};
+
+use tokio::sync::mpsc::Sender;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Very short intervals would just keep the operator busy re-syncing, so the interval is never allowed to be
+/// shorter than this
+const MIN_RESYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs until the operator is stopped. Rather than re-queueing every parent at the start of each interval,
+/// each parent is given a stable offset within the interval, so that the syncs are spread out evenly and
+/// don't all hit the api server at once.
+pub(crate) async fn run_periodic_resync(
+    parents: ResourceMonitor<UidToIdIndex>,
+    mut sender: Sender<ResourceMessage>,
+    parent_type: &'static K8sType,
+    interval: Duration,
+    running: Arc<AtomicBool>,
+) {
+    let interval = interval.max(MIN_RESYNC_INTERVAL);
+    log::info!(
+        "Starting periodic resync of all parents every {}ms",
+        duration_to_millis(interval)
+    );
+    while running.load(Ordering::Relaxed) {
+        let round_start = Instant::now();
+        let parent_ids = match parents.lock_state().await {
+            Ok(state) => state.get_all_ids(),
+            Err(err) => {
+                log::warn!(
+                    "Skipping periodic resync because parents are unavailable: {}",
+                    err
+                );
+                Vec::new()
+            }
+        };
+
+        let mut scheduled = parent_ids
+            .into_iter()
+            .map(|(uid, id)| (resync_offset(uid.as_str(), interval), uid, id))
+            .collect::<Vec<_>>();
+        scheduled.sort_by_key(|(offset, _, _)| *offset);
+        log::debug!(
+            "Starting periodic resync round for {} parents",
+            scheduled.len()
+        );
+
+        for (offset, uid, resource_id) in scheduled {
+            delay_until(round_start + offset).await;
+            if !running.load(Ordering::Relaxed) {
+                return;
+            }
+            let message = ResourceMessage {
+                event_type: EventType::PeriodicResync,
+                resource_type: parent_type,
+                resource_id,
+                index_key: Some(uid),
+            };
+            if sender.send(message).await.is_err() {
+                log::warn!("Unable to send periodic resync message, stopping periodic resync");
+                return;
+            }
+        }
+        delay_until(round_start + interval).await;
+    }
+}
+
+async fn delay_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now {
+        tokio::time::delay_for(deadline - now).await;
+    }
+}
+
+/// Returns the offset within the interval at which the given parent should be re-queued. This is derived
+/// from a hash of the uid, so each parent is synced at roughly the same point in each interval.
+fn resync_offset(uid: &str, interval: Duration) -> Duration {
+    let interval_millis = duration_to_millis(interval).max(1);
+    let mut hasher = DefaultHasher::new();
+    uid.hash(&mut hasher);
+    Duration::from_millis(hasher.finish() % interval_millis)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resync_offsets_are_stable_and_within_the_interval() {
+        let interval = Duration::from_secs(300);
+        let offsets = (0..100)
+            .map(|i| resync_offset(format!("uid-{}", i).as_str(), interval))
+            .collect::<Vec<_>>();
+        for (i, offset) in offsets.iter().enumerate() {
+            assert!(*offset < interval);
+            assert_eq!(
+                *offset,
+                resync_offset(format!("uid-{}", i).as_str(), interval)
+            );
+        }
+        let first = offsets[0];
+        assert!(
+            offsets.iter().any(|o| *o != first),
+            "offsets should be spread across the interval"
+        );
+    }
+}
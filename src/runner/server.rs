@@ -1,29 +1,47 @@
+use crate::resource::ObjectId;
+use crate::runner::informer::{EventType, ResourceMessage, WatchStatuses};
+use crate::runner::parent_status::{self, ParentStatuses};
 use crate::runner::RuntimeConfig;
 
 use hyper::server::Server;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response};
 use tokio::runtime::Handle;
+use tokio::sync::mpsc::Sender;
 
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn start(
     _executor: Handle,
     port: u16,
     runtime_config: Arc<RuntimeConfig>,
-    serve_metrics: bool,
-    serve_health: bool,
+    is_leader: Option<Arc<AtomicBool>>,
+    running: Arc<AtomicBool>,
+    watch_statuses: WatchStatuses,
+    watch_staleness_threshold: Duration,
+    endpoints: Endpoints,
 ) {
     let address: SocketAddr = ([0u8; 4], port).into();
     log::info!(
-        "Starting server on address: {}, exposing '/metrics': {}, '/health': {}",
+        "Starting server on address: {}, exposing '/metrics': {}, '/health', '/healthz', and '/readyz': {}, '/reconcile' and '/status/objects': {}",
         address,
-        serve_metrics,
-        serve_health
+        endpoints.metrics,
+        endpoints.health,
+        endpoints.admin.is_some()
     );
 
-    let svc = Svc::new(runtime_config.clone(), serve_metrics, serve_health);
+    let svc = Svc::new(
+        runtime_config.clone(),
+        is_leader,
+        running,
+        watch_statuses,
+        watch_staleness_threshold,
+        endpoints,
+    );
     let service = make_service_fn(move |_| {
         let service = svc.clone();
         async move {
@@ -40,19 +58,52 @@ pub(crate) async fn start(
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
+/// Which endpoints are served by a single server
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Endpoints {
+    pub metrics: bool,
+    pub health: bool,
+    /// only present if the admin endpoints are enabled
+    pub admin: Option<Admin>,
+}
+
+/// What's needed to serve the admin endpoints
+#[derive(Debug, Clone)]
+pub(crate) struct Admin {
+    /// used to queue the parents that are requested to be reconciled
+    pub sender: Sender<ResourceMessage>,
+    pub parent_statuses: ParentStatuses,
+}
+
 #[derive(Debug, Clone)]
 struct Svc {
     runtime_config: Arc<RuntimeConfig>,
-    serve_metrics: bool,
-    serve_health: bool,
+    /// only present if leader election is enabled
+    is_leader: Option<Arc<AtomicBool>>,
+    /// set to false once the operator starts shutting down
+    running: Arc<AtomicBool>,
+    /// tells whether the initial list of each informer has completed, and when it last heard from the api server
+    watch_statuses: WatchStatuses,
+    watch_staleness_threshold: Duration,
+    endpoints: Endpoints,
 }
 
 impl Svc {
-    fn new(runtime_config: Arc<RuntimeConfig>, serve_metrics: bool, serve_health: bool) -> Svc {
+    fn new(
+        runtime_config: Arc<RuntimeConfig>,
+        is_leader: Option<Arc<AtomicBool>>,
+        running: Arc<AtomicBool>,
+        watch_statuses: WatchStatuses,
+        watch_staleness_threshold: Duration,
+        endpoints: Endpoints,
+    ) -> Svc {
         Svc {
             runtime_config,
-            serve_metrics,
-            serve_health,
+            is_leader,
+            running,
+            watch_statuses,
+            watch_staleness_threshold,
+            endpoints,
         }
     }
 
@@ -62,9 +113,71 @@ impl Svc {
     }
 
     fn health(&self, _request: &Request<Body>) -> Result<Response<Body>, Error> {
+        // fail readiness probes while we're shutting down, so that this replica is taken out of rotation
+        if !self.running.load(Ordering::Relaxed) {
+            let body = serde_json::json!({ "status": "shutting down" });
+            let resp = Response::builder()
+                .status(503)
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))?;
+            return Ok(resp);
+        }
+        // standby replicas are still healthy, so the status is always 200 and the body tells whether we hold the lease
+        let resp = match self.is_leader.as_ref() {
+            Some(is_leader) => {
+                let body = serde_json::json!({ "leader": is_leader.load(Ordering::Relaxed) });
+                Response::builder()
+                    .status(200)
+                    .header(http::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string()))?
+            }
+            None => Response::builder().status(200).body(Body::empty())?,
+        };
+        Ok(resp)
+    }
+    /// Liveness only depends on whether the server is able to respond, so it stays healthy while the operator is
+    /// shutting down, in order to give in-progress syncs a chance to finish
+    fn liveness(&self, _request: &Request<Body>) -> Result<Response<Body>, Error> {
         let resp = Response::builder().status(200).body(Body::empty())?;
         Ok(resp)
     }
+
+    fn readiness(&self, _request: &Request<Body>) -> Result<Response<Body>, Error> {
+        let (status, body) = match self.not_ready_reason() {
+            Some(reason) => (503, serde_json::json!({ "ready": false, "reason": reason })),
+            None => (200, serde_json::json!({ "ready": true })),
+        };
+        let resp = Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))?;
+        Ok(resp)
+    }
+
+    /// Returns the reason that the operator isn't ready, or `None` if it is
+    fn not_ready_reason(&self) -> Option<&'static str> {
+        let watch_statuses = self.watch_statuses.lock().unwrap();
+        if !self.running.load(Ordering::Relaxed) {
+            Some("shutting down")
+        } else if !watch_statuses.iter().all(|status| status.is_synced()) {
+            Some("waiting for the initial list of resources")
+        } else if watch_statuses
+            .iter()
+            .any(|status| status.is_stale(self.watch_staleness_threshold))
+        {
+            Some("a watch has not heard from the api server within the staleness threshold")
+        } else if self
+            .is_leader
+            .as_ref()
+            .map(|is_leader| !is_leader.load(Ordering::Relaxed))
+            .unwrap_or(false)
+        {
+            Some("waiting to acquire the leader election lease")
+        } else {
+            None
+        }
+    }
+
     fn metrics(&self, _request: &Request<Body>) -> Result<Response<Body>, Error> {
         let body = self.runtime_config.metrics.encode_as_text()?;
         let resp = Response::builder()
@@ -74,15 +187,84 @@ impl Svc {
         Ok(resp)
     }
 
+    /// Queues either all parents, or the one with the given id, to be synced. The parent is looked up once the
+    /// request is received by the operator, so a successful response only means that the sync was queued.
+    fn reconcile(
+        &self,
+        sender: &Sender<ResourceMessage>,
+        parent_id: Option<ObjectId>,
+    ) -> Result<Response<Body>, Error> {
+        let (event_type, resource_id, body) = match parent_id {
+            Some(id) => {
+                let body = serde_json::json!({
+                    "queued": { "namespace": id.namespace(), "name": id.name() }
+                });
+                (EventType::ReconcileRequested, id, body)
+            }
+            None => (
+                EventType::ReconcileAllRequested,
+                ObjectId::new(String::new(), String::new()),
+                serde_json::json!({ "queued": "all" }),
+            ),
+        };
+        let message = ResourceMessage {
+            event_type,
+            resource_type: self.runtime_config.parent_type,
+            resource_id,
+            index_key: None,
+        };
+        // the operator may be busy, and there's no point in making the caller wait, since they can just try again
+        let (status, body) = match sender.clone().try_send(message) {
+            Ok(()) => (202, body),
+            Err(_) => (
+                503,
+                serde_json::json!({ "error": "the operator is unable to accept requests right now" }),
+            ),
+        };
+        let resp = Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))?;
+        Ok(resp)
+    }
+
+    /// Lists the status of every parent that the operator has synced, sorted by namespace and name
+    fn parent_statuses(&self, parent_statuses: &ParentStatuses) -> Result<Response<Body>, Error> {
+        let objects = parent_status::sorted(parent_statuses)
+            .iter()
+            .map(|s| s.to_json())
+            .collect::<Vec<_>>();
+        let body = serde_json::json!({ "objects": objects });
+        let resp = Response::builder()
+            .status(200)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))?;
+        Ok(resp)
+    }
+
     fn handle_request(&self, request: Request<Body>) -> Result<Response<Body>, Error> {
         let req_path = request.uri().path().trim_end_matches('/');
         let req_method = request.method();
 
         log::debug!("Got http request {} {}", req_method, request.uri());
 
+        let serve_health = self.endpoints.health;
+        let serve_metrics = self.endpoints.metrics;
         let result = match (req_method, req_path) {
-            (&Method::GET, "/health") if self.serve_health => self.health(&request),
-            (&Method::GET, "/metrics") if self.serve_metrics => self.metrics(&request),
+            (&Method::GET, "/health") if serve_health => self.health(&request),
+            (&Method::GET, "/healthz") if serve_health => self.liveness(&request),
+            (&Method::GET, "/readyz") if serve_health => self.readiness(&request),
+            (&Method::GET, "/metrics") if serve_metrics => self.metrics(&request),
+            (&Method::POST, path) if path == "/reconcile" || path.starts_with("/reconcile/") => {
+                match (self.endpoints.admin.as_ref(), parse_reconcile_path(path)) {
+                    (Some(admin), Some(parent_id)) => self.reconcile(&admin.sender, parent_id),
+                    _ => self.not_found(&request),
+                }
+            }
+            (&Method::GET, "/status/objects") => match self.endpoints.admin.as_ref() {
+                Some(admin) => self.parent_statuses(&admin.parent_statuses),
+                None => self.not_found(&request),
+            },
             _ => self.not_found(&request),
         };
         match result.as_ref() {
@@ -106,3 +288,317 @@ impl Svc {
         result
     }
 }
+
+/// Groups the endpoints by the port that they're served on, so that a single server is started for each port
+pub(crate) fn endpoints_by_port(
+    metrics_port: Option<u16>,
+    health_port: Option<u16>,
+    admin: Option<(u16, Admin)>,
+) -> Vec<(u16, Endpoints)> {
+    let mut by_port: Vec<(u16, Endpoints)> = Vec::new();
+    let mut endpoints_on = |port: u16, update: &dyn Fn(&mut Endpoints)| match by_port
+        .iter_mut()
+        .find(|(existing, _)| *existing == port)
+    {
+        Some((_, endpoints)) => update(endpoints),
+        None => {
+            let mut endpoints = Endpoints::default();
+            update(&mut endpoints);
+            by_port.push((port, endpoints));
+        }
+    };
+    if let Some(port) = metrics_port {
+        endpoints_on(port, &|endpoints| endpoints.metrics = true);
+    }
+    if let Some(port) = health_port {
+        endpoints_on(port, &|endpoints| endpoints.health = true);
+    }
+    if let Some((port, admin)) = admin {
+        endpoints_on(port, &|endpoints| endpoints.admin = Some(admin.clone()));
+    }
+    by_port
+}
+
+/// Parses the parent id from the path of a reconcile request, which is `/reconcile` for all parents,
+/// `/reconcile/{namespace}/{name}` for a namespaced parent, or `/reconcile/{name}` for a cluster scoped one. Returns
+/// `None` if the path doesn't match any of these.
+fn parse_reconcile_path(path: &str) -> Option<Option<ObjectId>> {
+    let segments = path
+        .trim_start_matches("/reconcile")
+        .split('/')
+        .skip(1)
+        .collect::<Vec<_>>();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return None;
+    }
+    match segments.as_slice() {
+        [] => Some(None),
+        [name] => Some(Some(ObjectId::new(String::new(), (*name).to_owned()))),
+        [namespace, name] => Some(Some(ObjectId::new(
+            (*namespace).to_owned(),
+            (*name).to_owned(),
+        ))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::OperatorConfig;
+    use crate::k8s_types::core::v1::Pod;
+    use crate::runner::informer::WatchStatus;
+    use crate::runner::metrics::Metrics;
+
+    fn health_endpoints() -> Endpoints {
+        Endpoints {
+            health: true,
+            ..Default::default()
+        }
+    }
+
+    fn health_request() -> Request<Body> {
+        Request::get("/health").body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn health_check_fails_once_the_operator_is_shutting_down() {
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = Arc::new(RuntimeConfig::new(&config, Metrics::new(), None));
+        let running = Arc::new(AtomicBool::new(true));
+        let svc = Svc::new(
+            runtime_config,
+            None,
+            running.clone(),
+            WatchStatuses::default(),
+            Duration::from_secs(600),
+            health_endpoints(),
+        );
+
+        let resp = svc.handle_request(health_request()).unwrap();
+        assert_eq!(200, resp.status().as_u16());
+
+        running.store(false, Ordering::Relaxed);
+        let resp = svc.handle_request(health_request()).unwrap();
+        assert_eq!(503, resp.status().as_u16());
+    }
+
+    fn get(svc: &Svc, path: &str) -> u16 {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        svc.handle_request(request).unwrap().status().as_u16()
+    }
+
+    #[test]
+    fn readiness_waits_for_informers_and_leadership() {
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = Arc::new(RuntimeConfig::new(&config, Metrics::new(), None));
+        let running = Arc::new(AtomicBool::new(true));
+        let is_leader = Arc::new(AtomicBool::new(false));
+        let statuses = vec![Arc::new(WatchStatus::new()), Arc::new(WatchStatus::new())];
+        statuses[0].set_synced();
+        let svc = Svc::new(
+            runtime_config,
+            Some(is_leader.clone()),
+            running.clone(),
+            Arc::new(std::sync::Mutex::new(statuses.clone())),
+            Duration::from_secs(600),
+            health_endpoints(),
+        );
+
+        assert_eq!(
+            Some("waiting for the initial list of resources"),
+            svc.not_ready_reason()
+        );
+        assert_eq!(503, get(&svc, "/readyz"));
+        assert_eq!(200, get(&svc, "/healthz"));
+
+        statuses[1].set_synced();
+        assert_eq!(
+            Some("waiting to acquire the leader election lease"),
+            svc.not_ready_reason()
+        );
+
+        is_leader.store(true, Ordering::Relaxed);
+        assert_eq!(200, get(&svc, "/readyz"));
+
+        running.store(false, Ordering::Relaxed);
+        assert_eq!(Some("shutting down"), svc.not_ready_reason());
+        assert_eq!(503, get(&svc, "/readyz"));
+        assert_eq!(200, get(&svc, "/healthz"));
+    }
+
+    #[test]
+    fn readiness_fails_while_any_watch_is_stale() {
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = Arc::new(RuntimeConfig::new(&config, Metrics::new(), None));
+        let statuses = vec![Arc::new(WatchStatus::new()), Arc::new(WatchStatus::new())];
+        for status in statuses.iter() {
+            status.set_synced();
+        }
+        let svc = Svc::new(
+            runtime_config,
+            None,
+            Arc::new(AtomicBool::new(true)),
+            Arc::new(std::sync::Mutex::new(statuses.clone())),
+            Duration::from_millis(50),
+            health_endpoints(),
+        );
+        assert_eq!(200, get(&svc, "/readyz"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        statuses[0].record_activity();
+        assert_eq!(
+            Some("a watch has not heard from the api server within the staleness threshold"),
+            svc.not_ready_reason()
+        );
+        assert_eq!(503, get(&svc, "/readyz"));
+        assert_eq!(200, get(&svc, "/healthz"));
+
+        statuses[1].record_activity();
+        assert_eq!(200, get(&svc, "/readyz"));
+    }
+
+    fn post(svc: &Svc, path: &str) -> u16 {
+        let request = Request::post(path).body(Body::empty()).unwrap();
+        svc.handle_request(request).unwrap().status().as_u16()
+    }
+
+    #[test]
+    fn reconcile_requests_are_only_served_when_admin_endpoints_are_enabled() {
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = Arc::new(RuntimeConfig::new(&config, Metrics::new(), None));
+        let running = Arc::new(AtomicBool::new(true));
+        let svc = Svc::new(
+            runtime_config.clone(),
+            None,
+            running.clone(),
+            WatchStatuses::default(),
+            Duration::from_secs(600),
+            health_endpoints(),
+        );
+        assert_eq!(404, post(&svc, "/reconcile"));
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(2);
+        let endpoints = Endpoints {
+            admin: Some(Admin {
+                sender: tx,
+                parent_statuses: ParentStatuses::default(),
+            }),
+            ..health_endpoints()
+        };
+        let svc = Svc::new(
+            runtime_config,
+            None,
+            running,
+            WatchStatuses::default(),
+            Duration::from_secs(600),
+            endpoints,
+        );
+        assert_eq!(404, get(&svc, "/reconcile"));
+        assert_eq!(404, post(&svc, "/reconcile/a/b/c"));
+        assert_eq!(404, post(&svc, "/reconciled"));
+
+        assert_eq!(202, post(&svc, "/reconcile/"));
+        assert_eq!(202, post(&svc, "/reconcile/default/my-pod"));
+        // the channel is full until the operator receives the messages
+        assert_eq!(503, post(&svc, "/reconcile/other"));
+
+        let all = rx.try_recv().unwrap();
+        assert!(matches!(all.event_type, EventType::ReconcileAllRequested));
+        let one = rx.try_recv().unwrap();
+        assert!(matches!(one.event_type, EventType::ReconcileRequested));
+        assert_eq!(
+            ObjectId::new("default".to_owned(), "my-pod".to_owned()),
+            one.resource_id
+        );
+    }
+
+    #[test]
+    fn parent_statuses_are_only_served_when_admin_endpoints_are_enabled() {
+        use crate::runner::parent_status::{ParentStatus, ReconcileOutcome};
+
+        let config = OperatorConfig::new("test-operator", Pod);
+        let runtime_config = Arc::new(RuntimeConfig::new(&config, Metrics::new(), None));
+        let running = Arc::new(AtomicBool::new(true));
+        let (tx, _rx) = tokio::sync::mpsc::channel(2);
+        let parent_statuses = ParentStatuses::default();
+        let status = |namespace: &str, outcome| ParentStatus {
+            uid: format!("{}-uid", namespace),
+            id: ObjectId::new(namespace.to_owned(), "my-pod".to_owned()),
+            in_progress: false,
+            sync_count: 1,
+            last_sync_time: Some(std::time::UNIX_EPOCH),
+            last_outcome: Some(outcome),
+            retry_after: Some(Duration::from_secs(2)),
+        };
+        {
+            let mut statuses = parent_statuses.lock().unwrap();
+            statuses.insert(
+                "b-uid".to_owned(),
+                status("b", ReconcileOutcome::FatalError),
+            );
+            statuses.insert("a-uid".to_owned(), status("a", ReconcileOutcome::Success));
+        }
+        let endpoints = Endpoints {
+            admin: Some(Admin {
+                sender: tx,
+                parent_statuses,
+            }),
+            ..health_endpoints()
+        };
+        let svc = Svc::new(
+            runtime_config.clone(),
+            None,
+            running.clone(),
+            WatchStatuses::default(),
+            Duration::from_secs(600),
+            endpoints,
+        );
+        let request = Request::get("/status/objects").body(Body::empty()).unwrap();
+        let response = svc.handle_request(request).unwrap();
+        assert_eq!(200, response.status().as_u16());
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .build()
+            .unwrap();
+        let body = runtime
+            .block_on(hyper::body::to_bytes(response.into_body()))
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            serde_json::json!([
+                {
+                    "uid": "a-uid",
+                    "namespace": "a",
+                    "name": "my-pod",
+                    "inProgress": false,
+                    "syncCount": 1,
+                    "lastSyncTime": "1970-01-01T00:00:00.000Z",
+                    "lastOutcome": "success",
+                    "retryAfterMillis": 2000,
+                },
+                {
+                    "uid": "b-uid",
+                    "namespace": "b",
+                    "name": "my-pod",
+                    "inProgress": false,
+                    "syncCount": 1,
+                    "lastSyncTime": "1970-01-01T00:00:00.000Z",
+                    "lastOutcome": "fatal_error",
+                    "retryAfterMillis": 2000,
+                },
+            ]),
+            body["objects"]
+        );
+
+        let svc = Svc::new(
+            runtime_config,
+            None,
+            running,
+            WatchStatuses::default(),
+            Duration::from_secs(600),
+            health_endpoints(),
+        );
+        assert_eq!(404, get(&svc, "/status/objects"));
+    }
+}
@@ -0,0 +1,49 @@
+//! Handles the signals that are sent to ask the operator to stop, such as the `SIGTERM` that kubernetes sends to a
+//! pod that's being deleted or replaced during a rollout.
+use tokio::sync::Notify;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Resolves once the process receives either `SIGTERM` or `SIGINT` (ctrl-c). Returns the name of the signal.
+#[cfg(unix)]
+pub(crate) async fn wait_for_signal() -> &'static str {
+    use futures_util::future::{select, Either};
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(err) => {
+            log::error!("Failed to listen for SIGTERM: {}", err);
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    let received = select(
+        Box::pin(terminate.recv()),
+        Box::pin(tokio::signal::ctrl_c()),
+    )
+    .await;
+    match received {
+        Either::Left(_) => "SIGTERM",
+        Either::Right(_) => "SIGINT",
+    }
+}
+
+/// Resolves once the process receives ctrl-c, which is the only signal that's supported on this platform
+#[cfg(not(unix))]
+pub(crate) async fn wait_for_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+/// Waits for a signal, and then tells the operator to stop starting new syncs
+pub(crate) async fn shutdown_on_signal(running: Arc<AtomicBool>, shutdown: Arc<Notify>) {
+    let signal = wait_for_signal().await;
+    log::info!(
+        "Received {}, finishing in-progress syncs before shutting down",
+        signal
+    );
+    running.store(false, Ordering::Relaxed);
+    shutdown.notify();
+}
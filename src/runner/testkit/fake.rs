@@ -0,0 +1,930 @@
+//! An in-memory fake of the resources that an operator manages, for unit testing a `Handler` without a cluster.
+//! Applying a `SyncResponse` to the fake uses the same code as the real operator to decide which children need to be
+//! created, updated, or deleted, and to add the tracking label or owner reference to each child.
+use super::{TestKitError, ToJson};
+use crate::config::OperatorConfig;
+use crate::handler::cache::{CachedType, ResourceCache};
+use crate::handler::{Handler, SyncRequest, SyncResponse};
+use crate::k8s_types::K8sType;
+use crate::resource::{InvalidResourceError, K8sResource, ObjectId, ObjectIdRef, ResourceJson};
+use crate::runner::informer::ChildTracking;
+use crate::runner::metrics::Metrics;
+use crate::runner::reconcile::status_to_update;
+use crate::runner::reconcile::sync::{
+    add_parent_references, plan_child_scale, plan_child_update, undesired_children,
+    ChildScaleUpdate, ChildUpdate, UpdateType,
+};
+use crate::runner::resource_map::{IdSet, ResourceMap};
+use crate::runner::RuntimeConfig;
+
+use anyhow::Error;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A change that was made to the resources in a `FakeClient` as a result of applying a `SyncResponse`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    Created {
+        k8s_type: &'static K8sType,
+        resource: K8sResource,
+    },
+    Updated {
+        k8s_type: &'static K8sType,
+        resource: K8sResource,
+    },
+    Deleted {
+        k8s_type: &'static K8sType,
+        id: ObjectId,
+    },
+    StatusUpdated {
+        id: ObjectId,
+        status: Value,
+    },
+}
+
+/// A `FakeClient` holds parent and child resources in memory, and records every change that's made to them. It can be
+/// seeded with existing parents and children, and then used to create the `SyncRequest` for a parent, invoke a
+/// `Handler`, and apply the response, just like the operator would. Children are associated with their parents using
+/// either the tracking label or owner references, depending on the `OperatorConfig`.
+///
+/// Unlike the real operator, the fake never adds a finalizer to parents, and deleted children are removed immediately.
+/// The `cache()` of each `SyncRequest` is a snapshot of the fake's resources at the time the request was created.
+///
+/// ```rust
+/// use roperator::prelude::*;
+/// use roperator::runner::testkit::{FakeClient, Mutation};
+/// use roperator::serde_json::json;
+///
+/// static PARENT_TYPE: &K8sType = &K8sType {
+///     api_version: "example.roperator.com/v1alpha1",
+///     kind: "BusyBox",
+///     plural_kind: "busyboxes",
+///     scope: Scope::Namespaced,
+/// };
+///
+/// fn handle_sync(request: &SyncRequest) -> Result<SyncResponse, Error> {
+///     let mut response = SyncResponse::new(json!({"message": "ok"}));
+///     response.add_child(json!({
+///         "apiVersion": "v1",
+///         "kind": "Pod",
+///         "metadata": {"namespace": request.parent.namespace(), "name": request.parent.name()},
+///     }))?;
+///     Ok(response)
+/// }
+///
+/// let config = OperatorConfig::new("busybox-operator", PARENT_TYPE)
+///     .with_child(k8s_types::core::v1::Pod, ChildConfig::replace());
+/// let mut fake = FakeClient::new(config).unwrap();
+/// fake.add_resource(json!({
+///     "apiVersion": "example.roperator.com/v1alpha1",
+///     "kind": "BusyBox",
+///     "metadata": {"namespace": "default", "name": "foo"},
+/// }))
+/// .unwrap();
+///
+/// let response = fake.sync(&handle_sync, ("default", "foo")).unwrap();
+/// assert_eq!(1, response.children.len());
+/// assert!(fake.mutations().iter().any(|m| match m {
+///     Mutation::Created { resource, .. } => resource.name() == "foo",
+///     _ => false,
+/// }));
+/// ```
+#[derive(Debug)]
+pub struct FakeClient {
+    runtime_config: RuntimeConfig,
+    tracking: ChildTracking,
+    watched_types: Vec<&'static K8sType>,
+    store: FakeStore,
+}
+
+impl FakeClient {
+    /// Creates an empty `FakeClient` for the parent and child types in the given config
+    pub fn new(config: OperatorConfig) -> Result<FakeClient, Error> {
+        config.validate_child_tracking()?;
+        Ok(FakeClient {
+            tracking: ChildTracking::for_config(&config),
+            watched_types: config.watched_types.clone(),
+            runtime_config: RuntimeConfig::new(&config, Metrics::new(), None),
+            store: FakeStore::default(),
+        })
+    }
+
+    /// Adds an existing resource, which must be the parent type, one of the child types, or a watched type. A `uid` and
+    /// `resourceVersion` are generated if the resource doesn't already have them. Returns an error if the same
+    /// resource has already been added.
+    pub fn add_resource(&mut self, resource: impl ToJson) -> Result<K8sResource, Error> {
+        let resource = resource.into_json();
+        let k8s_type = self.type_of(&resource)?;
+        let id = resource
+            .get_id_ref()
+            .ok_or_else(|| InvalidResourceError::new("missing name", resource.clone()))?;
+        if self.store.get(k8s_type, &id).is_some() {
+            return Err(Error::new(TestKitError(format!(
+                "{} {} already exists",
+                k8s_type, id
+            ))));
+        }
+        self.store.insert(k8s_type, resource)
+    }
+
+    /// Adds an existing child of the given parent, with the tracking label or owner reference that the operator
+    /// would have added when it created the child
+    pub fn add_child<'a>(
+        &mut self,
+        parent_id: impl Into<ObjectIdRef<'a>>,
+        child: impl ToJson,
+    ) -> Result<K8sResource, Error> {
+        let parent = self.require_parent(&parent_id.into())?;
+        let mut child = child.into_json();
+        add_parent_references(&self.runtime_config, &parent, &mut child)?;
+        self.add_resource(child)
+    }
+
+    /// Returns the resource with the given type and id, if it exists
+    pub fn get<'a>(
+        &self,
+        k8s_type: &K8sType,
+        id: impl Into<ObjectIdRef<'a>>,
+    ) -> Option<&K8sResource> {
+        self.store.get(k8s_type, &id.into())
+    }
+
+    /// Returns the `SyncRequest` that the operator would pass to the handler for the given parent
+    pub fn sync_request<'a>(
+        &self,
+        parent_id: impl Into<ObjectIdRef<'a>>,
+    ) -> Result<SyncRequest, Error> {
+        let parent = self.require_parent(&parent_id.into())?;
+        let children = self
+            .store
+            .resources
+            .iter()
+            .filter(|(k8s_type, _)| self.runtime_config.child_types.contains_key(k8s_type))
+            .filter(|(_, child)| self.tracking.parent_uid(child) == Some(parent.uid()))
+            .map(|(_, child)| child.clone())
+            .collect();
+        Ok(SyncRequest {
+            parent,
+            children,
+            cache: self.cache_snapshot(),
+            cancellation: Default::default(),
+        })
+    }
+
+    /// Applies the response for the given parent. The status of the parent is updated if it's different, and the
+    /// children are created, updated, or deleted according to the `UpdateStrategy` for their type.
+    pub fn apply_sync_response<'a>(
+        &mut self,
+        parent_id: impl Into<ObjectIdRef<'a>>,
+        response: SyncResponse,
+    ) -> Result<(), Error> {
+        let request = self.sync_request(parent_id)?;
+        let SyncResponse {
+            status,
+            children,
+            child_scales,
+            ..
+        } = response;
+        if let Some(status) = status_to_update(&request.parent, &self.runtime_config, status) {
+            self.store
+                .update_status(self.runtime_config.parent_type, &request.parent, status);
+        }
+
+        let mut desired_ids = IdSet::new();
+        for child in children {
+            let ChildUpdate {
+                child_id,
+                child_config,
+                update_type,
+                child,
+                exists,
+            } = plan_child_update(&self.runtime_config, &request, child)
+                .map_err(|err| Error::new(TestKitError(err.to_string())))?;
+            let child_type = child_config.child_type;
+            match update_type {
+                None => {}
+                Some(UpdateType::Delete) => self.store.delete(child_type, &child_id.as_id_ref()),
+                Some(_) if exists => self
+                    .store
+                    .update(child_type, &child_id.as_id_ref(), child)?,
+                // an existing resource that isn't tracked as a child can only be adopted, the same as it would be
+                // after the api server rejected the create
+                Some(_) if self.store.get(child_type, &child_id.as_id_ref()).is_some() => {
+                    if !child_config.adopt_existing {
+                        return Err(Error::new(TestKitError(format!(
+                            "Cannot create child {} {} because it already exists",
+                            child_type.kind, child_id
+                        ))));
+                    }
+                    self.store
+                        .update(child_type, &child_id.as_id_ref(), child)?
+                }
+                Some(_) => self.store.create(child_type, child)?,
+            }
+            desired_ids.insert(child_id);
+        }
+
+        for child_scale in child_scales {
+            let planned = plan_child_scale(&self.runtime_config, &request, child_scale)
+                .map_err(|err| Error::new(TestKitError(err.to_string())))?;
+            if let Some(ChildScaleUpdate {
+                child_type,
+                child_id,
+                scale,
+            }) = planned
+            {
+                // the scale subresource only changes the replicas, and leaves the rest of the child as it is
+                let id = child_id.as_id_ref();
+                let mut child = match self.store.get(child_type, &id) {
+                    Some(child) => child.clone().into_value(),
+                    None => continue,
+                };
+                child["spec"]["replicas"] = scale.spec.replicas.into();
+                self.store.update(child_type, &id, child)?;
+            }
+        }
+
+        let undesired = undesired_children(&self.runtime_config, &desired_ids, &request)
+            .map(|(child_type, child)| (child_type, child.get_object_id().to_owned()))
+            .collect::<Vec<_>>();
+        for (child_type, child_id) in undesired {
+            self.store.delete(child_type, &child_id.as_id_ref());
+        }
+        Ok(())
+    }
+
+    /// Invokes the handler with the `SyncRequest` for the given parent, applies the response, and then returns it. The
+    /// handler's `before_sync` and `after_sync` are invoked around `sync`, just like the operator would.
+    pub fn sync<'a>(
+        &mut self,
+        handler: &dyn Handler,
+        parent_id: impl Into<ObjectIdRef<'a>>,
+    ) -> Result<SyncResponse, Error> {
+        let parent_id = parent_id.into().to_owned();
+        let request = self.sync_request(&parent_id)?;
+        handler.before_sync(&request);
+        let result = handler.sync(&request);
+        handler.after_sync(&request, &result);
+        let response = result?;
+        self.apply_sync_response(&parent_id, response.clone())?;
+        Ok(response)
+    }
+
+    /// Returns all of the changes that have been made, in order
+    pub fn mutations(&self) -> &[Mutation] {
+        self.store.mutations.as_slice()
+    }
+
+    /// Removes and returns all of the changes that have been made so far
+    pub fn take_mutations(&mut self) -> Vec<Mutation> {
+        std::mem::take(&mut self.store.mutations)
+    }
+
+    fn type_of(&self, resource: &Value) -> Result<&'static K8sType, Error> {
+        let type_ref = resource.get_type_ref().ok_or_else(|| {
+            InvalidResourceError::new("missing either apiVersion or kind", resource.clone())
+        })?;
+        if type_ref == *self.runtime_config.parent_type {
+            return Ok(self.runtime_config.parent_type);
+        }
+        self.runtime_config
+            .type_for(&type_ref)
+            .or_else(|| {
+                self.watched_types
+                    .iter()
+                    .copied()
+                    .find(|watched| type_ref == **watched)
+            })
+            .ok_or_else(|| {
+                Error::new(TestKitError(format!(
+                    "{} is not the parent type, one of the child types, or a watched type",
+                    type_ref
+                )))
+            })
+    }
+
+    fn cache_snapshot(&self) -> ResourceCache {
+        let mut snapshots = HashMap::new();
+        let cached_types = std::iter::once(self.runtime_config.parent_type)
+            .chain(self.runtime_config.child_types.keys().copied())
+            .chain(self.watched_types.iter().copied());
+        for k8s_type in cached_types {
+            snapshots.insert(k8s_type, ResourceMap::new());
+        }
+        for (k8s_type, resource) in self.store.resources.iter() {
+            if let Some(snapshot) = snapshots.get_mut(k8s_type) {
+                snapshot.insert(resource.clone());
+            }
+        }
+        let types = snapshots
+            .into_iter()
+            .map(|(k8s_type, snapshot)| {
+                let cached: Arc<dyn CachedType> = Arc::new(CacheSnapshot(snapshot));
+                (k8s_type, cached)
+            })
+            .collect();
+        ResourceCache::new(types)
+    }
+
+    fn require_parent(&self, parent_id: &ObjectIdRef<'_>) -> Result<K8sResource, Error> {
+        self.store
+            .get(self.runtime_config.parent_type, parent_id)
+            .cloned()
+            .ok_or_else(|| Error::new(TestKitError(format!("No parent found: {}", parent_id))))
+    }
+}
+
+/// Stands in for the operator's cache of a single type
+struct CacheSnapshot(ResourceMap);
+
+impl CachedType for CacheSnapshot {
+    fn get(&self, id: &ObjectIdRef<'_>) -> Option<K8sResource> {
+        self.0.get_copy(id.clone())
+    }
+
+    fn list(&self, namespace: Option<&str>) -> Vec<K8sResource> {
+        self.0.iter_namespace(namespace).cloned().collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct FakeStore {
+    resources: Vec<(&'static K8sType, K8sResource)>,
+    mutations: Vec<Mutation>,
+    last_resource_version: u64,
+}
+
+impl FakeStore {
+    fn position(&self, k8s_type: &K8sType, id: &ObjectIdRef<'_>) -> Option<usize> {
+        self.resources
+            .iter()
+            .position(|(t, res)| *t == k8s_type && res.is_id(id))
+    }
+
+    fn get(&self, k8s_type: &K8sType, id: &ObjectIdRef<'_>) -> Option<&K8sResource> {
+        self.position(k8s_type, id).map(|i| &self.resources[i].1)
+    }
+
+    /// Sets the metadata that the api server would set, and returns the resource
+    fn with_server_metadata(
+        &mut self,
+        mut resource: Value,
+        existing: Option<&K8sResource>,
+    ) -> Result<K8sResource, Error> {
+        self.last_resource_version += 1;
+        let meta = resource
+            .pointer_mut("/metadata")
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| InvalidResourceError::new("missing metadata", Value::Null))?;
+        let uid = existing
+            .map(|e| e.uid().to_owned())
+            .or_else(|| meta.get("uid").and_then(Value::as_str).map(String::from))
+            .unwrap_or_else(|| format!("fake-uid-{}", self.last_resource_version));
+        let generation = existing.map(|e| e.generation() + 1).unwrap_or(1);
+        meta.insert("uid".to_owned(), Value::String(uid));
+        meta.insert(
+            "resourceVersion".to_owned(),
+            Value::String(self.last_resource_version.to_string()),
+        );
+        meta.insert("generation".to_owned(), generation.into());
+        let resource = K8sResource::from_value(resource)?;
+        Ok(resource)
+    }
+
+    fn insert(
+        &mut self,
+        k8s_type: &'static K8sType,
+        resource: Value,
+    ) -> Result<K8sResource, Error> {
+        let resource = self.with_server_metadata(resource, None)?;
+        self.resources.push((k8s_type, resource.clone()));
+        Ok(resource)
+    }
+
+    fn create(&mut self, k8s_type: &'static K8sType, resource: Value) -> Result<(), Error> {
+        let resource = self.insert(k8s_type, resource)?;
+        self.mutations
+            .push(Mutation::Created { k8s_type, resource });
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        k8s_type: &'static K8sType,
+        id: &ObjectIdRef<'_>,
+        resource: Value,
+    ) -> Result<(), Error> {
+        let index = self
+            .position(k8s_type, id)
+            .expect("updated resource must exist");
+        let existing = self.resources[index].1.clone();
+        let resource = self.with_server_metadata(resource, Some(&existing))?;
+        self.resources[index].1 = resource.clone();
+        self.mutations
+            .push(Mutation::Updated { k8s_type, resource });
+        Ok(())
+    }
+
+    fn update_status(&mut self, k8s_type: &'static K8sType, parent: &K8sResource, status: Value) {
+        let index = self
+            .position(k8s_type, &parent.get_object_id())
+            .expect("parent must exist");
+        let mut value = parent.clone().into_value();
+        if let Some(obj) = value.as_object_mut() {
+            obj.insert("status".to_owned(), status.clone());
+        }
+        self.last_resource_version += 1;
+        if let Some(meta) = value
+            .pointer_mut("/metadata")
+            .and_then(Value::as_object_mut)
+        {
+            meta.insert(
+                "resourceVersion".to_owned(),
+                Value::String(self.last_resource_version.to_string()),
+            );
+        }
+        self.resources[index].1 = K8sResource::from_value(value).expect("parent must be valid");
+        self.mutations.push(Mutation::StatusUpdated {
+            id: parent.get_object_id().to_owned(),
+            status,
+        });
+    }
+
+    fn delete(&mut self, k8s_type: &'static K8sType, id: &ObjectIdRef<'_>) {
+        if let Some(index) = self.position(k8s_type, id) {
+            self.resources.remove(index);
+            self.mutations.push(Mutation::Deleted {
+                k8s_type,
+                id: id.to_owned(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ChildConfig;
+    use crate::k8s_types::apps::v1::Deployment;
+    use crate::k8s_types::core::v1::{ConfigMap, Pod, Secret, Service};
+    use crate::k8s_types::rbac_authorization_k8s_io::v1::ClusterRole;
+    use crate::k8s_types::Scope;
+    use serde_json::json;
+
+    static PARENT_TYPE: &K8sType = &K8sType {
+        api_version: "example.com/v1",
+        kind: "Foo",
+        plural_kind: "foos",
+        scope: Scope::Namespaced,
+    };
+
+    fn parent() -> Value {
+        json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "metadata": {"namespace": "ns", "name": "foo"},
+            "spec": {"data": "bar"},
+        })
+    }
+
+    fn config_map(name: &str, data: &str) -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": {"namespace": "ns", "name": name},
+            "data": {"value": data},
+        })
+    }
+
+    fn handler(request: &SyncRequest) -> Result<SyncResponse, Error> {
+        let data = request.parent.str_value("/spec/data").unwrap_or_default();
+        let mut response = SyncResponse::new(json!({ "data": data }));
+        response.add_child(config_map("desired", data))?;
+        Ok(response)
+    }
+
+    fn fake(config: OperatorConfig) -> FakeClient {
+        let mut fake = FakeClient::new(config.with_child(ConfigMap, ChildConfig::replace()))
+            .expect("failed to create fake");
+        fake.add_resource(parent()).unwrap();
+        fake
+    }
+
+    #[test]
+    fn sync_creates_children_and_updates_status() {
+        let mut fake = fake(OperatorConfig::new("test-operator", PARENT_TYPE));
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+
+        let mutations = fake.take_mutations();
+        assert_eq!(2, mutations.len());
+        match &mutations[0] {
+            Mutation::StatusUpdated { id, status } => {
+                assert_eq!(ObjectId::new("ns".to_owned(), "foo".to_owned()), *id);
+                assert_eq!(Some("bar"), status.pointer("/data").and_then(Value::as_str));
+            }
+            other => panic!("expected status update, got: {:?}", other),
+        }
+        match &mutations[1] {
+            Mutation::Created { k8s_type, resource } => {
+                assert_eq!(ConfigMap, *k8s_type);
+                assert_eq!("desired", resource.name());
+            }
+            other => panic!("expected create, got: {:?}", other),
+        }
+
+        // the child is tracked using the label, so it's included in the next request
+        let request = fake.sync_request(("ns", "foo")).unwrap();
+        assert_eq!(1, request.children.len());
+        let parent_uid = request.parent.uid();
+        assert_eq!(
+            Some(parent_uid),
+            request.children[0].get_label_value(crate::config::DEFAULT_TRACKING_LABEL_NAME)
+        );
+
+        // nothing has changed, so syncing again is a no-op
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+        assert!(fake.mutations().is_empty());
+    }
+
+    #[test]
+    fn children_that_are_not_in_the_response_are_deleted() {
+        let mut fake = fake(OperatorConfig::new("test-operator", PARENT_TYPE));
+        fake.add_child(("ns", "foo"), config_map("desired", "old"))
+            .unwrap();
+        fake.add_child(("ns", "foo"), config_map("undesired", "old"))
+            .unwrap();
+        // not a child of this parent, so it must be left alone
+        fake.add_resource(config_map("unrelated", "old")).unwrap();
+
+        let request = fake.sync_request(("ns", "foo")).unwrap();
+        assert_eq!(2, request.children.len());
+
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+        let mutations = fake.take_mutations();
+        assert_eq!(3, mutations.len(), "mutations: {:?}", mutations);
+        match &mutations[1] {
+            Mutation::Updated { resource, .. } => {
+                assert_eq!("desired", resource.name());
+                assert_eq!(Some("bar"), resource.str_value("/data/value"));
+            }
+            other => panic!("expected update, got: {:?}", other),
+        }
+        assert_eq!(
+            Mutation::Deleted {
+                k8s_type: ConfigMap,
+                id: ObjectId::new("ns".to_owned(), "undesired".to_owned()),
+            },
+            mutations[2]
+        );
+        assert!(fake.get(ConfigMap, ("ns", "unrelated")).is_some());
+    }
+
+    #[test]
+    fn children_are_tracked_by_owner_reference() {
+        let config =
+            OperatorConfig::new("test-operator", PARENT_TYPE).track_via_owner_references(true);
+        let mut fake = fake(config);
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+
+        let child = fake.get(ConfigMap, ("ns", "desired")).unwrap();
+        assert!(child.pointer("/metadata/labels").is_none());
+        assert_eq!(
+            Some(true),
+            child
+                .pointer("/metadata/ownerReferences/0/blockOwnerDeletion")
+                .and_then(Value::as_bool)
+        );
+        assert_eq!(1, fake.sync_request(("ns", "foo")).unwrap().children.len());
+    }
+
+    #[test]
+    fn children_of_another_parent_are_not_overwritten() {
+        let mut fake = fake(OperatorConfig::new("test-operator", PARENT_TYPE));
+        let mut other_parent = parent();
+        other_parent["metadata"]["name"] = json!("other");
+        other_parent["spec"]["data"] = json!("baz");
+        fake.add_resource(other_parent).unwrap();
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+        fake.take_mutations();
+
+        let err = fake
+            .sync(&handler, ("ns", "other"))
+            .expect_err("expected a collision");
+        assert!(
+            err.to_string()
+                .contains("already exists and belongs to the parent"),
+            "unexpected error: {}",
+            err
+        );
+        assert!(fake
+            .mutations()
+            .iter()
+            .all(|mutation| matches!(mutation, Mutation::StatusUpdated { .. })));
+        let child = fake.get(ConfigMap, ("ns", "desired")).unwrap();
+        assert_eq!(Some("bar"), child.str_value("/data/value"));
+    }
+
+    #[test]
+    fn existing_resources_are_only_adopted_when_enabled() {
+        let mut fake = fake(OperatorConfig::new("test-operator", PARENT_TYPE));
+        fake.add_resource(config_map("desired", "old")).unwrap();
+        let err = fake
+            .sync(&handler, ("ns", "foo"))
+            .expect_err("expected the create to fail");
+        assert!(err.to_string().contains("already exists"), "{}", err);
+
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .with_child(ConfigMap, ChildConfig::on_delete().adopt_existing(true));
+        let mut fake = FakeClient::new(config).unwrap();
+        fake.add_resource(parent()).unwrap();
+        fake.add_resource(config_map("desired", "old")).unwrap();
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+
+        let child = fake.get(ConfigMap, ("ns", "desired")).unwrap();
+        assert_eq!(Some("bar"), child.str_value("/data/value"));
+        assert_eq!(1, fake.sync_request(("ns", "foo")).unwrap().children.len());
+        assert_eq!(
+            1,
+            fake.mutations()
+                .iter()
+                .filter(|mutation| matches!(mutation, Mutation::Updated { .. }))
+                .count()
+        );
+    }
+
+    #[test]
+    fn unknown_types_are_rejected() {
+        let mut fake = fake(OperatorConfig::new("test-operator", PARENT_TYPE));
+        let pod = json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"namespace": "ns", "name": "pod"},
+        });
+        assert!(fake.add_resource(pod.clone()).is_err());
+
+        let response = SyncResponse {
+            status: Value::Null,
+            children: vec![pod],
+            resync: None,
+            child_scales: Vec::new(),
+        };
+        assert!(fake.apply_sync_response(("ns", "foo"), response).is_err());
+        assert!(fake.get(Pod, ("ns", "pod")).is_none());
+    }
+
+    #[test]
+    fn watched_types_are_cached_but_not_children() {
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE).with_watched_type(Secret);
+        let mut fake = fake(config);
+        fake.add_resource(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": {"namespace": "ns", "name": "creds"},
+            "data": {"password": "aHVudGVyMg=="},
+        }))
+        .unwrap();
+
+        let request = fake.sync_request(("ns", "foo")).unwrap();
+        assert!(request.children.is_empty());
+        assert!(request.cache().is_watched(ConfigMap));
+        let secret = request.cache().get(Secret, ("ns", "creds")).unwrap();
+        assert_eq!(Some("aHVudGVyMg=="), secret.str_value("/data/password"));
+        assert!(request.cache().get(Pod, ("ns", "creds")).is_none());
+
+        // the secret must not be deleted, since it isn't one of the desired children
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+        assert!(fake.get(Secret, ("ns", "creds")).is_some());
+    }
+
+    #[test]
+    fn cluster_scoped_children_are_reconciled_for_namespaced_parents() {
+        fn cluster_role_handler(request: &SyncRequest) -> Result<SyncResponse, Error> {
+            let mut response = SyncResponse::new(Value::Null);
+            response.add_child(json!({
+                "apiVersion": "rbac.authorization.k8s.io/v1",
+                "kind": "ClusterRole",
+                "metadata": {"name": format!("{}-reader", request.parent.name())},
+                "rules": [],
+            }))?;
+            Ok(response)
+        }
+
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .with_child(ClusterRole, ChildConfig::replace());
+        let mut fake = fake(config);
+        fake.sync(&cluster_role_handler, ("ns", "foo")).unwrap();
+
+        let role = fake.get(ClusterRole, ("", "foo-reader")).unwrap();
+        assert!(role.get_object_id().namespace().is_none());
+        let request = fake.sync_request(("ns", "foo")).unwrap();
+        assert_eq!(1, request.children.len());
+        fake.take_mutations();
+        fake.sync(&cluster_role_handler, ("ns", "foo")).unwrap();
+        assert!(fake.mutations().is_empty());
+
+        // cluster scoped children can't be put in the namespace of the parent
+        let response = SyncResponse {
+            status: Value::Null,
+            children: vec![json!({
+                "apiVersion": "rbac.authorization.k8s.io/v1",
+                "kind": "ClusterRole",
+                "metadata": {"namespace": "ns", "name": "namespaced-reader"},
+            })],
+            resync: None,
+            child_scales: Vec::new(),
+        };
+        assert!(fake.apply_sync_response(("ns", "foo"), response).is_err());
+        assert!(fake.get(ClusterRole, ("ns", "namespaced-reader")).is_none());
+    }
+
+    #[test]
+    fn namespaced_children_are_reconciled_for_cluster_scoped_parents() {
+        static CLUSTER_PARENT_TYPE: &K8sType = &K8sType {
+            api_version: "example.com/v1",
+            kind: "Tenant",
+            plural_kind: "tenants",
+            scope: Scope::Cluster,
+        };
+        fn tenant_handler(request: &SyncRequest) -> Result<SyncResponse, Error> {
+            let mut response = SyncResponse::new(Value::Null);
+            let namespace = request
+                .parent
+                .str_value("/spec/namespace")
+                .unwrap_or_default();
+            response.add_child(json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": {"namespace": namespace, "name": request.parent.name()},
+                "data": {"tenant": request.parent.name()},
+            }))?;
+            Ok(response)
+        }
+
+        let config = OperatorConfig::new("test-operator", CLUSTER_PARENT_TYPE)
+            .with_child(ConfigMap, ChildConfig::replace())
+            .within_namespace("team-a");
+        let mut fake = FakeClient::new(config).unwrap();
+        fake.add_resource(json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Tenant",
+            "metadata": {"name": "acme"},
+            "spec": {"namespace": "team-a"},
+        }))
+        .unwrap();
+        fake.sync(&tenant_handler, ("", "acme")).unwrap();
+
+        let child = fake.get(ConfigMap, ("team-a", "acme")).unwrap();
+        assert_eq!(Some("acme"), child.str_value("/data/tenant"));
+        let request = fake.sync_request(("", "acme")).unwrap();
+        assert!(request.parent.get_object_id().namespace().is_none());
+        assert_eq!(1, request.children.len());
+        fake.take_mutations();
+        fake.sync(&tenant_handler, ("", "acme")).unwrap();
+        assert!(fake.mutations().is_empty());
+
+        // there's no parent namespace to default to, so namespaced children must say which namespace they go in
+        let response = SyncResponse {
+            status: Value::Null,
+            children: vec![json!({
+                "apiVersion": "v1",
+                "kind": "ConfigMap",
+                "metadata": {"name": "no-namespace"},
+            })],
+            resync: None,
+            child_scales: Vec::new(),
+        };
+        let err = fake
+            .apply_sync_response(("", "acme"), response)
+            .unwrap_err();
+        assert!(err.to_string().contains("must have a namespace"));
+    }
+
+    #[test]
+    fn before_and_after_sync_are_invoked_around_sync() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct AuditingHandler {
+            calls: Mutex<Vec<String>>,
+        }
+
+        impl Handler for AuditingHandler {
+            fn sync(&self, request: &SyncRequest) -> Result<SyncResponse, Error> {
+                self.calls.lock().unwrap().push("sync".to_owned());
+                handler(request)
+            }
+
+            fn before_sync(&self, request: &SyncRequest) {
+                let call = format!("before {}", request.parent.name());
+                self.calls.lock().unwrap().push(call);
+            }
+
+            fn after_sync(&self, _request: &SyncRequest, result: &Result<SyncResponse, Error>) {
+                let call = format!("after ok: {}", result.is_ok());
+                self.calls.lock().unwrap().push(call);
+            }
+        }
+
+        let mut fake = fake(OperatorConfig::new("test-operator", PARENT_TYPE));
+        let handler = AuditingHandler::default();
+        fake.sync(&handler, ("ns", "foo")).unwrap();
+        assert_eq!(
+            vec!["before foo", "sync", "after ok: true"],
+            *handler.calls.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn steady_state_children_are_not_updated() {
+        fn service_handler(request: &SyncRequest) -> Result<SyncResponse, Error> {
+            let mut response = SyncResponse::new(Value::Null);
+            response.add_child(json!({
+                "apiVersion": "v1",
+                "kind": "Service",
+                "metadata": {
+                    "namespace": "ns",
+                    "name": request.parent.name(),
+                    "creationTimestamp": null,
+                },
+                "spec": {
+                    "clusterIP": "",
+                    "ports": [{"name": "http", "port": 80}],
+                },
+            }))?;
+            Ok(response)
+        }
+
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE).with_child(
+            Service,
+            ChildConfig::replace().ignore_path("/spec/clusterIP"),
+        );
+        let mut fake = fake(config);
+        fake.sync(&service_handler, ("ns", "foo")).unwrap();
+
+        // simulate the fields that the api server sets when the service is created
+        let (_, service) = fake
+            .store
+            .resources
+            .iter_mut()
+            .find(|(k8s_type, _)| *k8s_type == Service)
+            .unwrap();
+        let mut value = service.clone().into_value();
+        value["metadata"]["creationTimestamp"] = json!("2020-01-01T00:00:00Z");
+        value["spec"]["clusterIP"] = json!("10.0.0.7");
+        value["spec"]["sessionAffinity"] = json!("None");
+        *service = K8sResource::from_value(value).unwrap();
+
+        fake.take_mutations();
+        for _ in 0..10 {
+            fake.sync(&service_handler, ("ns", "foo")).unwrap();
+        }
+        assert!(fake.mutations().is_empty());
+    }
+
+    #[test]
+    fn child_scale_only_changes_the_replicas() {
+        fn deployment_handler(request: &SyncRequest) -> Result<SyncResponse, Error> {
+            let mut response = SyncResponse::new(Value::Null);
+            response.add_child(json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"namespace": "ns", "name": request.parent.name()},
+                "spec": {
+                    "selector": {"matchLabels": {"app": "web"}},
+                    "template": {"metadata": {"labels": {"app": "web"}}},
+                },
+            }))?;
+            response.set_child_scale(Deployment, request.parent.name(), 3);
+            Ok(response)
+        }
+
+        let config = OperatorConfig::new("test-operator", PARENT_TYPE)
+            .with_child(Deployment, ChildConfig::patch());
+        let mut fake = fake(config);
+        // the deployment doesn't exist yet, so it can only be scaled on the next sync
+        fake.sync(&deployment_handler, ("ns", "foo")).unwrap();
+        let created = fake.get(Deployment, ("ns", "foo")).unwrap().clone();
+        assert!(created.pointer("/spec/replicas").is_none());
+
+        fake.take_mutations();
+        fake.sync(&deployment_handler, ("ns", "foo")).unwrap();
+        let scaled = fake.get(Deployment, ("ns", "foo")).unwrap().clone();
+        assert_eq!(1, fake.mutations().len());
+        assert_eq!(
+            Some(3),
+            scaled.pointer("/spec/replicas").and_then(Value::as_i64)
+        );
+
+        let mut expected = created.into_value();
+        expected["spec"]["replicas"] = json!(3);
+        expected["metadata"]["generation"] = json!(2);
+        expected["metadata"]["resourceVersion"] = scaled["metadata"]["resourceVersion"].clone();
+        assert_eq!(expected, scaled.into_value());
+
+        fake.take_mutations();
+        fake.sync(&deployment_handler, ("ns", "foo")).unwrap();
+        assert!(fake.mutations().is_empty());
+    }
+}
@@ -17,14 +17,17 @@
 //!
 //! Here, name corresponds to the filename that's used under the `tests/` directory, which is the usual place
 //! for integration tests.
+//!
+//! The `TestKit` runs your operator against a real cluster. For unit testing a `Handler` without a cluster, the
+//! `FakeClient` holds parents and children in memory, and records the changes that the operator would make.
 use crate::{
     config::{ClientConfig, OperatorConfig},
     handler::{FinalizeResponse, Handler, SyncRequest, SyncResponse},
     k8s_types::K8sType,
     resource::{K8sResource, ObjectId, ObjectIdRef},
     runner::{
-        client::Client, create_operator_state, metrics::Metrics, reconcile::compare, HandlerRef,
-        OperatorState,
+        check_field_selector, client::Client, create_child_clients, create_operator_state,
+        metrics::Metrics, reconcile::compare, work_queue::WorkQueue, HandlerRef, OperatorState,
     },
 };
 
@@ -45,6 +48,10 @@ macro_rules! test_error {
     }};
 }
 
+mod fake;
+
+pub use self::fake::{FakeClient, Mutation};
+
 /// A `TestKit` is a "batteries-included" fixture for integration testing an operator against a real kubernetes cluster.
 /// The `TestKit` will create the actual operator instance and run it using a tokio `Runtime` that executes only on the current
 /// thread. Part of the convenience of a testkit is that it provides blocking apis for testing the operator, so tests
@@ -57,9 +64,9 @@ pub struct TestKit {
     instrumented_handler: InstrumentedHandler,
     runtime: Runtime,
     client: Client,
-    parents_needing_sync: HashSet<String>,
+    parents_needing_sync: WorkQueue,
     delete_namespace_on_drop: bool,
-    namespace: Option<String>,
+    namespaces: Vec<String>,
     parents: HashSet<ObjectId>,
     cleanup_timeout: Duration,
 }
@@ -100,17 +107,17 @@ impl Drop for TestKit {
             }
 
             let TestKit {
-                ref namespace,
+                ref namespaces,
                 ref client,
                 ref mut runtime,
                 ..
             } = self;
-            if let Some(ns) = namespace.as_ref() {
+            for ns in namespaces.iter() {
                 log::info!("Deleting test namespace: '{}'", ns);
                 let id = ObjectIdRef::new("", ns);
                 let result = runtime.block_on(async {
                     client
-                        .delete_resource(crate::k8s_types::core::v1::Namespace, &id)
+                        .delete_resource(crate::k8s_types::core::v1::Namespace, &id, None)
                         .await
                 });
                 if let Err(err) = result {
@@ -162,9 +169,10 @@ impl TestKit {
 
     /// instructs the testkit to delete it's namespace when it is dropped. This is useful for running tests
     /// each in an isolated namespace. Panics if there was no namespace configured in the `OperatorConfig` that
-    /// was passed when creating the `TestKit`.
+    /// was passed when creating the `TestKit`. If multiple namespaces were configured, then all of them will
+    /// be deleted.
     pub fn delete_namespace_on_drop(mut self) -> Self {
-        if self.namespace.is_none() {
+        if self.namespaces.is_empty() {
             panic!("cannot delete namespace on drop because no namespace has been configured for testkit");
         }
         self.delete_namespace_on_drop = true;
@@ -177,15 +185,18 @@ impl TestKit {
         client_config: ClientConfig,
         handler: impl Handler,
     ) -> Result<TestKit, Error> {
+        operator_config.validate()?;
         let metrics = Metrics::new();
         let client = Client::new(client_config, metrics.client_metrics())?;
-        let namespace = operator_config.namespace.clone();
+        let child_clients = create_child_clients(&operator_config, &metrics)?;
+        let namespaces = operator_config.namespaces.clone().unwrap_or_default();
 
         let mut runtime = tokio::runtime::Builder::new()
             .enable_all()
             .basic_scheduler()
             .build()?;
 
+        runtime.block_on(check_field_selector(&client, &operator_config))?;
         let executor = runtime.handle().clone();
         let operator_client = client.clone();
         let state = runtime.block_on(async move {
@@ -193,8 +204,10 @@ impl TestKit {
                 executor,
                 metrics,
                 Arc::new(AtomicBool::new(true)),
+                Arc::new(tokio::sync::Notify::new()),
                 operator_config,
                 operator_client,
+                child_clients,
             )
             .await
         });
@@ -207,8 +220,8 @@ impl TestKit {
             instrumented_handler,
             runtime,
             client,
-            namespace,
-            parents_needing_sync: HashSet::new(),
+            namespaces,
+            parents_needing_sync: WorkQueue::new(),
             delete_namespace_on_drop: false,
             parents: HashSet::new(),
             cleanup_timeout: Duration::from_secs(10),
@@ -537,7 +550,7 @@ impl TestKit {
             ref mut runtime,
             ..
         } = *self;
-        runtime.block_on(async { client.delete_resource(k8s_type, id).await })?;
+        runtime.block_on(async { client.delete_resource(k8s_type, id, None).await })?;
         Ok(())
     }
 
@@ -718,6 +731,14 @@ impl Handler for InstrumentedHandler {
         result
     }
 
+    fn before_sync(&self, req: &SyncRequest) {
+        self.wrapped.before_sync(req)
+    }
+
+    fn after_sync(&self, req: &SyncRequest, result: &Result<SyncResponse, Error>) {
+        self.wrapped.after_sync(req, result)
+    }
+
     fn finalize(&self, req: &SyncRequest) -> Result<FinalizeResponse, Error> {
         let InstrumentedHandler {
             ref wrapped,
@@ -740,7 +761,7 @@ impl Handler for InstrumentedHandler {
 // TODO: add some sort of "required_quiet_period" parameter so that we can detect hot-loop scenarios
 async fn do_reconciliation_run(
     state: &mut OperatorState,
-    parents_needing_sync: &mut HashSet<String>,
+    parents_needing_sync: &mut WorkQueue,
     handler: &HandlerRef,
     instrumented_handler: &InstrumentedHandler,
     max_timeout: Duration,
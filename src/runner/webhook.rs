@@ -0,0 +1,204 @@
+use crate::webhook::WebhookConfig;
+
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response};
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use tokio::net::TcpListener;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Serves the admission webhooks over HTTPS until the listener fails. Each connection is handled in its own task,
+/// so that a slow TLS handshake doesn't hold up other requests.
+pub(crate) async fn start(config: WebhookConfig) {
+    let address: SocketAddr = ([0u8; 4], config.port).into();
+    log::info!(
+        "Starting webhook server on address: {}, validate path: '{}', mutate path: '{}'",
+        address,
+        config.validate_path,
+        config.mutate_path
+    );
+    if let Err(err) = serve(address, Arc::new(config)).await {
+        log::error!("Webhook server failed with error: {:?}", err);
+    }
+}
+
+fn create_acceptor(config: &WebhookConfig) -> Result<SslAcceptor, Error> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder.set_certificate_chain_file(&config.cert_path)?;
+    builder.set_private_key_file(&config.key_path, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+    Ok(builder.build())
+}
+
+async fn serve(address: SocketAddr, config: Arc<WebhookConfig>) -> Result<(), Error> {
+    let acceptor = Arc::new(create_acceptor(&config)?);
+    let mut listener = TcpListener::bind(&address).await?;
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let stream = match tokio_openssl::accept(&acceptor, stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("TLS handshake with {} failed: {}", peer, err);
+                    return;
+                }
+            };
+            let service = service_fn(move |request| handle_request(config.clone(), request));
+            if let Err(err) = Http::new().serve_connection(stream, service).await {
+                log::debug!("Error serving webhook connection from {}: {}", peer, err);
+            }
+        });
+    }
+}
+
+async fn handle_request(
+    config: Arc<WebhookConfig>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Error> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    log::debug!("Got webhook request {} {}", method, path);
+    if method != Method::POST {
+        return Ok(Response::builder().status(404).body(Body::empty())?);
+    }
+
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    // validators and mutators are user code that may block, so they're called on the blocking thread pool
+    let result = tokio::task::spawn_blocking(move || config.handle(path.as_str(), &body)).await?;
+    let resp = match result {
+        Some(Ok(review)) => Response::builder()
+            .status(200)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(&review)?))?,
+        Some(Err(err)) => {
+            log::warn!("Invalid AdmissionReview: {}", err);
+            Response::builder()
+                .status(400)
+                .body(Body::from(format!("Invalid AdmissionReview: {}", err)))?
+        }
+        None => Response::builder().status(404).body(Body::empty())?,
+    };
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::k8s_types::{K8sType, Scope};
+    use crate::webhook::{AdmissionRequest, AdmissionResponse};
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::ssl::{SslConnector, SslVerifyMode};
+    use openssl::x509::{X509NameBuilder, X509};
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use std::path::PathBuf;
+
+    static FOO_TYPE: &K8sType = &K8sType {
+        api_version: "example.com/v1",
+        kind: "Foo",
+        plural_kind: "foos",
+        scope: Scope::Namespaced,
+    };
+
+    /// Writes a self-signed certificate and key to a temporary directory, and returns their paths
+    fn write_self_signed_cert() -> (PathBuf, PathBuf) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let dir =
+            std::env::temp_dir().join(format!("roperator-webhook-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("tls.crt");
+        let key_path = dir.join("tls.key");
+        std::fs::write(&cert_path, cert.to_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[test]
+    fn admission_reviews_are_served_over_tls() {
+        let (cert_path, key_path) = write_self_signed_cert();
+        let config = WebhookConfig::new(cert_path, key_path)
+            .with_validator(FOO_TYPE, |_: &AdmissionRequest| {
+                AdmissionResponse::deny("no foos allowed")
+            });
+        let review = json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "request-uid",
+                "kind": {"group": "example.com", "version": "v1", "kind": "Foo"},
+                "resource": {"group": "example.com", "version": "v1", "resource": "foos"},
+                "name": "my-foo",
+                "operation": "CREATE",
+                "object": {"apiVersion": "example.com/v1", "kind": "Foo", "metadata": {"name": "my-foo"}},
+            }
+        })
+        .to_string();
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let address = std::net::SocketAddr::from(([127, 0, 0, 1], 0));
+            let acceptor = Arc::new(create_acceptor(&config).unwrap());
+            let mut listener = TcpListener::bind(&address).await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let config = Arc::new(config);
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let stream = tokio_openssl::accept(&acceptor, stream).await.unwrap();
+                let service = service_fn(move |request| handle_request(config.clone(), request));
+                let _ = Http::new().serve_connection(stream, service).await;
+            });
+
+            let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+            connector.set_verify(SslVerifyMode::NONE);
+            let connector = connector.build().configure().unwrap();
+            let stream = TcpStream::connect(&address).await.unwrap();
+            let mut stream = tokio_openssl::connect(connector, "localhost", stream)
+                .await
+                .unwrap();
+            let request = format!(
+                "POST /validate HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                review.len(),
+                review
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+
+            assert!(response.starts_with("HTTP/1.1 200"), "response: {}", response);
+            let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+            let body: Value = serde_json::from_str(body).unwrap();
+            assert_eq!("request-uid", body.pointer("/response/uid").unwrap());
+            assert_eq!(Some(false), body.pointer("/response/allowed").and_then(Value::as_bool));
+            assert_eq!(
+                "no foos allowed",
+                body.pointer("/response/status/message").unwrap()
+            );
+        });
+    }
+}
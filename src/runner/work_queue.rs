@@ -0,0 +1,172 @@
+//! The queue of parents that are waiting to be synced. Parents are identified by their uid, and each one is only
+//! ever in the queue once, so that any number of events for the same parent are coalesced into a single sync.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+#[derive(Debug, Default)]
+pub(crate) struct WorkQueue {
+    order: VecDeque<String>,
+    queued: HashSet<String>,
+    /// parents that were inserted with a delay, which aren't ready until the given time
+    not_before: HashMap<String, Instant>,
+}
+
+impl WorkQueue {
+    pub fn new() -> WorkQueue {
+        WorkQueue::default()
+    }
+
+    /// Adds the parent uid to the back of the queue. Returns false if it was already queued, in which case it keeps
+    /// its existing place in line, and any delay from `insert_delayed` is removed.
+    pub fn insert(&mut self, parent_uid: String) -> bool {
+        self.not_before.remove(&parent_uid);
+        if self.queued.contains(&parent_uid) {
+            return false;
+        }
+        self.queued.insert(parent_uid.clone());
+        self.order.push_back(parent_uid);
+        true
+    }
+
+    /// Adds the parent uid to the back of the queue, but it won't be ready until `ready_at`. Returns false if it was
+    /// already queued, in which case it keeps its existing place in line and delay, if any.
+    pub fn insert_delayed(&mut self, parent_uid: String, ready_at: Instant) -> bool {
+        if self.queued.contains(&parent_uid) {
+            return false;
+        }
+        self.queued.insert(parent_uid.clone());
+        self.order.push_back(parent_uid.clone());
+        self.not_before.insert(parent_uid, ready_at);
+        true
+    }
+
+    /// Adds the parent uid to the back of the queue, so that it's ready no later than `ready_at`. If it was already
+    /// queued, then it keeps its existing place in line, and its delay is shortened if it's later than `ready_at`.
+    /// Returns false if it was already queued.
+    pub fn insert_ready_by(&mut self, parent_uid: String, ready_at: Instant) -> bool {
+        if self.queued.contains(&parent_uid) {
+            if let Some(existing) = self.not_before.get_mut(&parent_uid) {
+                *existing = (*existing).min(ready_at);
+            }
+            return false;
+        }
+        self.insert_delayed(parent_uid, ready_at)
+    }
+
+    /// Returns the earliest time in the future at which a delayed parent becomes ready, if there is one
+    pub fn next_ready_at(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.not_before
+            .values()
+            .filter(|ready_at| **ready_at > now)
+            .min()
+            .copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Removes and returns up to `max` parent uids that `is_ready`, in the order that they were queued. Parents
+    /// that aren't ready, such as those that are already being synced or whose delay hasn't elapsed, keep their
+    /// place in the queue.
+    pub fn take_ready(&mut self, max: usize, is_ready: impl Fn(&str) -> bool) -> Vec<String> {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        let mut remaining = VecDeque::with_capacity(self.order.len());
+        for parent_uid in self.order.drain(..) {
+            let delayed = self
+                .not_before
+                .get(&parent_uid)
+                .map(|ready_at| *ready_at > now)
+                .unwrap_or(false);
+            if ready.len() < max && !delayed && is_ready(parent_uid.as_str()) {
+                self.queued.remove(&parent_uid);
+                self.not_before.remove(&parent_uid);
+                ready.push(parent_uid);
+            } else {
+                remaining.push_back(parent_uid);
+            }
+        }
+        self.order = remaining;
+        ready
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parents_are_only_queued_once() {
+        let mut queue = WorkQueue::new();
+        assert!(queue.insert("a".to_owned()));
+        assert!(queue.insert("b".to_owned()));
+        assert!(!queue.insert("a".to_owned()));
+        assert_eq!(2, queue.len());
+
+        assert_eq!(vec!["a".to_owned()], queue.take_ready(1, |_| true));
+        assert!(queue.insert("a".to_owned()));
+        assert_eq!(
+            vec!["b".to_owned(), "a".to_owned()],
+            queue.take_ready(10, |_| true)
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn parents_that_are_not_ready_keep_their_place() {
+        let mut queue = WorkQueue::new();
+        for uid in &["a", "b", "c", "d"] {
+            queue.insert((*uid).to_owned());
+        }
+        let ready = queue.take_ready(1, |uid| uid != "a");
+        assert_eq!(vec!["b".to_owned()], ready);
+        assert_eq!(
+            vec!["a".to_owned(), "c".to_owned(), "d".to_owned()],
+            queue.take_ready(10, |_| true)
+        );
+    }
+
+    #[test]
+    fn delayed_parents_are_not_ready_until_their_delay_elapses() {
+        let mut queue = WorkQueue::new();
+        let later = Instant::now() + std::time::Duration::from_secs(60);
+        assert!(queue.insert_delayed("a".to_owned(), later));
+        assert!(queue.insert_delayed("b".to_owned(), Instant::now()));
+        assert!(!queue.insert_delayed("b".to_owned(), later));
+        assert_eq!(vec!["b".to_owned()], queue.take_ready(10, |_| true));
+        assert_eq!(1, queue.len());
+
+        // inserting it without a delay makes it ready immediately
+        assert!(!queue.insert("a".to_owned()));
+        assert_eq!(vec!["a".to_owned()], queue.take_ready(10, |_| true));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn insert_ready_by_only_shortens_delays() {
+        let mut queue = WorkQueue::new();
+        let now = Instant::now();
+        let soon = now + std::time::Duration::from_millis(50);
+        let later = now + std::time::Duration::from_secs(60);
+        assert!(queue.insert_delayed("a".to_owned(), later));
+        assert!(queue.insert("b".to_owned()));
+        assert!(queue.insert_ready_by("c".to_owned(), later));
+        assert_eq!(Some(later), queue.next_ready_at());
+
+        assert!(!queue.insert_ready_by("a".to_owned(), soon));
+        assert!(!queue.insert_ready_by("b".to_owned(), later));
+        assert!(!queue.insert_ready_by("c".to_owned(), now + std::time::Duration::from_secs(120)));
+        assert_eq!(Some(soon), queue.next_ready_at());
+        assert_eq!(vec!["b".to_owned()], queue.take_ready(10, |_| true));
+
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        assert_eq!(Some(later), queue.next_ready_at());
+        assert_eq!(vec!["a".to_owned()], queue.take_ready(10, |_| true));
+    }
+}
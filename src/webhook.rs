@@ -0,0 +1,615 @@
+//! The `webhook` module lets an operator also serve validating and mutating admission webhooks for its resources.
+//! Admission webhooks are called by the api server whenever a resource is created, updated, or deleted, and they
+//! can either reject the change, or modify the resource before it's persisted.
+//!
+//! To serve webhooks, create a `WebhookConfig` with the paths to the TLS certificate and private key, register a
+//! `Validator` or `Mutator` for each type of resource, and pass it to `OperatorConfig::with_webhook`. The webhook
+//! server is run alongside the operator, on all replicas regardless of leader election, since the api server may
+//! call any of them. You'll still need to create a `ValidatingWebhookConfiguration` or
+//! `MutatingWebhookConfiguration` and a `Service` that points to the webhook port of your operator. The api server
+//! requires webhooks to use TLS, so the certificate must be valid for the name of that service.
+//!
+//! ```rust
+//! use roperator::prelude::*;
+//! use roperator::webhook::{AdmissionRequest, AdmissionResponse, WebhookConfig};
+//!
+//! static PARENT_TYPE: &K8sType = &K8sType {
+//!     api_version: "example.roperator.com/v1alpha1",
+//!     kind: "BusyBox",
+//!     plural_kind: "busyboxes",
+//!     scope: Scope::Namespaced,
+//! };
+//!
+//! fn validate(request: &AdmissionRequest) -> AdmissionResponse {
+//!     let replicas = request
+//!         .object
+//!         .as_ref()
+//!         .and_then(|obj| obj.pointer("/spec/replicas"))
+//!         .and_then(|r| r.as_u64());
+//!     match replicas {
+//!         Some(r) if r > 10 => AdmissionResponse::deny("spec.replicas must not be greater than 10"),
+//!         _ => AdmissionResponse::allow(),
+//!     }
+//! }
+//!
+//! let webhook = WebhookConfig::new("/tls/tls.crt", "/tls/tls.key").with_validator(PARENT_TYPE, validate);
+//! let operator_config = OperatorConfig::new("busybox-operator", PARENT_TYPE).with_webhook(webhook);
+//! ```
+use crate::k8s_types::K8sType;
+use crate::resource::K8sResource;
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The `apiVersion` of the `AdmissionReview`s that are accepted and returned by the webhook server
+pub const ADMISSION_REVIEW_API_VERSION: &str = "admission.k8s.io/v1";
+
+const ADMISSION_REVIEW_KIND: &str = "AdmissionReview";
+
+/// The default port that the webhook server listens on
+pub const DEFAULT_WEBHOOK_PORT: u16 = 8443;
+
+/// A `Validator` decides whether a change to a resource should be allowed. Functions and closures that accept an
+/// `&AdmissionRequest` and return an `AdmissionResponse` can be used as validators.
+pub trait Validator: Send + Sync + 'static {
+    /// Returns whether the change described by the `request` is allowed. Any patch in the response is ignored,
+    /// since validating webhooks may not modify resources.
+    fn validate(&self, request: &AdmissionRequest) -> AdmissionResponse;
+}
+
+impl<F> Validator for F
+where
+    F: Fn(&AdmissionRequest) -> AdmissionResponse + Send + Sync + 'static,
+{
+    fn validate(&self, request: &AdmissionRequest) -> AdmissionResponse {
+        self(request)
+    }
+}
+
+/// A `Mutator` may modify a resource before it's persisted, by returning a response with a JSON patch. Mutators
+/// may also reject the change, just like a `Validator`.
+pub trait Mutator: Send + Sync + 'static {
+    /// Returns the response for the change described by the `request`, which should use
+    /// `AdmissionResponse::patch` in order to modify the resource.
+    fn mutate(&self, request: &AdmissionRequest) -> AdmissionResponse;
+}
+
+/// Wraps a function so that it can be used as a `Mutator`. This is needed because a closure could otherwise be
+/// either a `Validator` or a `Mutator`.
+pub struct MutatorFn<F>(pub F);
+
+impl<F> Mutator for MutatorFn<F>
+where
+    F: Fn(&AdmissionRequest) -> AdmissionResponse + Send + Sync + 'static,
+{
+    fn mutate(&self, request: &AdmissionRequest) -> AdmissionResponse {
+        (self.0)(request)
+    }
+}
+
+/// An `admission.k8s.io/v1` `AdmissionReview`. The api server sends a review with a `request`, and the webhook
+/// responds with a review that has the `response`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionReview {
+    pub api_version: String,
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<AdmissionRequest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<AdmissionResponse>,
+}
+
+impl AdmissionReview {
+    fn with_response(response: AdmissionResponse) -> AdmissionReview {
+        AdmissionReview {
+            api_version: ADMISSION_REVIEW_API_VERSION.to_owned(),
+            kind: ADMISSION_REVIEW_KIND.to_owned(),
+            request: None,
+            response: Some(response),
+        }
+    }
+}
+
+/// The group, version, and kind of the resource that's being admitted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupVersionKind {
+    #[serde(default)]
+    pub group: String,
+    pub version: String,
+    pub kind: String,
+}
+
+impl GroupVersionKind {
+    /// Returns whether this is the same type as the given `K8sType`
+    pub fn is_type(&self, k8s_type: &K8sType) -> bool {
+        let (group, version) = k8s_type.as_group_and_version();
+        self.group == group && self.version == version && self.kind == k8s_type.kind
+    }
+}
+
+/// The group, version, and plural resource name of the resource that's being admitted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupVersionResource {
+    #[serde(default)]
+    pub group: String,
+    pub version: String,
+    pub resource: String,
+}
+
+/// The request from the api server, describing the change that's being made to a resource
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionRequest {
+    /// Identifies this request, and must be returned in the response. This is done automatically by roperator
+    pub uid: String,
+    /// The type of the object that's being admitted
+    pub kind: GroupVersionKind,
+    pub resource: GroupVersionResource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_resource: Option<String>,
+    /// The name of the object, which may be empty for creates where the name is generated by the api server
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// One of `CREATE`, `UPDATE`, `DELETE`, or `CONNECT`
+    pub operation: String,
+    #[serde(default)]
+    pub user_info: Value,
+    /// The new object, which is `None` for deletes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object: Option<K8sResource>,
+    /// The existing object, which is only present for updates and deletes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_object: Option<K8sResource>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The status that's returned when a request is denied. The `message` is shown to the user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdmissionStatus {
+    pub code: u16,
+    pub message: String,
+}
+
+/// The response to an `AdmissionRequest`. Use `allow`, `deny`, or `patch` to create one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionResponse {
+    /// This is set to the uid of the request automatically
+    #[serde(default)]
+    pub uid: String,
+    pub allowed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<AdmissionStatus>,
+    /// The base64 encoded JSON patch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch_type: Option<String>,
+    /// Warnings that are returned to the user, even if the request is allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl AdmissionResponse {
+    /// Allows the request without modifying the resource
+    pub fn allow() -> AdmissionResponse {
+        AdmissionResponse {
+            uid: String::new(),
+            allowed: true,
+            status: None,
+            patch: None,
+            patch_type: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Rejects the request, with a message that's shown to the user
+    pub fn deny(message: impl Into<String>) -> AdmissionResponse {
+        AdmissionResponse {
+            allowed: false,
+            status: Some(AdmissionStatus {
+                code: 403,
+                message: message.into(),
+            }),
+            ..AdmissionResponse::allow()
+        }
+    }
+
+    /// Allows the request, and modifies the resource using the given JSON patch, which must be an array of
+    /// operations, e.g. `[{"op": "add", "path": "/spec/replicas", "value": 1}]`
+    pub fn patch(json_patch: Value) -> AdmissionResponse {
+        AdmissionResponse {
+            patch: Some(base64::encode(json_patch.to_string())),
+            patch_type: Some("JSONPatch".to_owned()),
+            ..AdmissionResponse::allow()
+        }
+    }
+
+    /// Allows the request, and modifies the resource using the given patch operations. This is the same as `patch`,
+    /// but the operations are built from `PatchOperation`s instead of raw json.
+    ///
+    /// ```rust
+    /// use roperator::webhook::{AdmissionResponse, PatchOperation};
+    /// use roperator::serde_json::json;
+    ///
+    /// let response = AdmissionResponse::with_patch(vec![
+    ///     PatchOperation::add("/spec/replicas", json!(1)),
+    ///     PatchOperation::remove("/spec/paused"),
+    /// ]);
+    /// assert_eq!(Some("JSONPatch"), response.patch_type.as_deref());
+    /// ```
+    pub fn with_patch(operations: Vec<PatchOperation>) -> AdmissionResponse {
+        let json_patch =
+            serde_json::to_value(operations).expect("patch operations are always serializable");
+        AdmissionResponse::patch(json_patch)
+    }
+
+    /// Adds a warning that's returned to the user
+    pub fn with_warning(mut self, warning: impl Into<String>) -> Self {
+        self.warnings.push(warning.into());
+        self
+    }
+}
+
+/// A single operation of a JSON patch, as defined by [RFC 6902](https://tools.ietf.org/html/rfc6902). Each `path`
+/// and `from` is a JSON pointer, so any `/` or `~` within a key must be escaped as `~1` or `~0`, e.g. the label
+/// `app.kubernetes.io/name` is at `/metadata/labels/app.kubernetes.io~1name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    /// Adds the value at the path, replacing any existing value. A path ending in `/-` appends to an array
+    Add { path: String, value: Value },
+    /// Removes the value at the path, which must exist
+    Remove { path: String },
+    /// Replaces the value at the path, which must exist
+    Replace { path: String, value: Value },
+    /// Removes the value at `from` and adds it at the path
+    Move { from: String, path: String },
+    /// Copies the value at `from` to the path
+    Copy { from: String, path: String },
+    /// Fails the whole patch unless the value at the path is equal to the given value
+    Test { path: String, value: Value },
+}
+
+impl PatchOperation {
+    pub fn add(path: impl Into<String>, value: Value) -> PatchOperation {
+        PatchOperation::Add {
+            path: path.into(),
+            value,
+        }
+    }
+
+    pub fn remove(path: impl Into<String>) -> PatchOperation {
+        PatchOperation::Remove { path: path.into() }
+    }
+
+    pub fn replace(path: impl Into<String>, value: Value) -> PatchOperation {
+        PatchOperation::Replace {
+            path: path.into(),
+            value,
+        }
+    }
+}
+
+/// Configuration for serving admission webhooks. Requests are dispatched to the `Validator` or `Mutator` that's
+/// registered for the type of the resource in the request. Requests for types that have no handler registered
+/// are allowed.
+#[derive(Clone)]
+pub struct WebhookConfig {
+    /// The port to listen on for HTTPS. Defaults to `8443`
+    pub port: u16,
+    /// The path to a PEM file with the TLS certificate chain
+    pub cert_path: PathBuf,
+    /// The path to a PEM file with the TLS private key
+    pub key_path: PathBuf,
+    /// The path that validating webhook requests are served on. Defaults to `/validate`
+    pub validate_path: String,
+    /// The path that mutating webhook requests are served on. Defaults to `/mutate`
+    pub mutate_path: String,
+    validators: HashMap<&'static K8sType, Arc<dyn Validator>>,
+    mutators: HashMap<&'static K8sType, Arc<dyn Mutator>>,
+}
+
+impl WebhookConfig {
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> WebhookConfig {
+        WebhookConfig {
+            port: DEFAULT_WEBHOOK_PORT,
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            validate_path: "/validate".to_owned(),
+            mutate_path: "/mutate".to_owned(),
+            validators: HashMap::new(),
+            mutators: HashMap::new(),
+        }
+    }
+
+    /// Sets the port to listen on for HTTPS
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets the path that validating webhook requests are served on
+    pub fn validate_path(mut self, path: impl Into<String>) -> Self {
+        self.validate_path = path.into();
+        self
+    }
+
+    /// Sets the path that mutating webhook requests are served on
+    pub fn mutate_path(mut self, path: impl Into<String>) -> Self {
+        self.mutate_path = path.into();
+        self
+    }
+
+    /// Registers the `Validator` for the given type, replacing any that was previously registered
+    pub fn with_validator(mut self, k8s_type: &'static K8sType, validator: impl Validator) -> Self {
+        self.validators.insert(k8s_type, Arc::new(validator));
+        self
+    }
+
+    /// Registers the `Mutator` for the given type, replacing any that was previously registered
+    pub fn with_mutator(mut self, k8s_type: &'static K8sType, mutator: impl Mutator) -> Self {
+        self.mutators.insert(k8s_type, Arc::new(mutator));
+        self
+    }
+
+    /// Handles the body of a request to the given path. Returns `None` if the path isn't one of the webhook paths,
+    /// and an error if the body isn't a valid `AdmissionReview`.
+    pub(crate) fn handle(
+        &self,
+        path: &str,
+        body: &[u8],
+    ) -> Option<Result<AdmissionReview, serde_json::Error>> {
+        let path = path.trim_end_matches('/');
+        let is_validate = path == self.validate_path.trim_end_matches('/');
+        let is_mutate = path == self.mutate_path.trim_end_matches('/');
+        if !is_validate && !is_mutate {
+            return None;
+        }
+        let request = match serde_json::from_slice::<AdmissionReview>(body) {
+            Ok(AdmissionReview {
+                request: Some(request),
+                ..
+            }) => request,
+            Ok(_) => {
+                return Some(Err(serde::de::Error::missing_field("request")));
+            }
+            Err(err) => return Some(Err(err)),
+        };
+
+        let mut response = if is_validate {
+            let mut response = self
+                .validators
+                .iter()
+                .find(|(k8s_type, _)| request.kind.is_type(k8s_type))
+                .map(|(_, validator)| validator.validate(&request))
+                .unwrap_or_else(AdmissionResponse::allow);
+            response.patch = None;
+            response.patch_type = None;
+            response
+        } else {
+            self.mutators
+                .iter()
+                .find(|(k8s_type, _)| request.kind.is_type(k8s_type))
+                .map(|(_, mutator)| mutator.mutate(&request))
+                .unwrap_or_else(AdmissionResponse::allow)
+        };
+        log::debug!(
+            "Admission request {} for {} {}/{} of {:?} allowed: {}",
+            request.uid,
+            request.operation,
+            request.namespace.as_deref().unwrap_or_default(),
+            request.name,
+            request.kind,
+            response.allowed
+        );
+        response.uid = request.uid;
+        Some(Ok(AdmissionReview::with_response(response)))
+    }
+}
+
+impl Debug for WebhookConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebhookConfig")
+            .field("port", &self.port)
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .field("validate_path", &self.validate_path)
+            .field("mutate_path", &self.mutate_path)
+            .field("validators", &self.validators.keys().collect::<Vec<_>>())
+            .field("mutators", &self.mutators.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Two `WebhookConfig`s are equal if they have the same settings and the same handler instances for each type
+impl PartialEq for WebhookConfig {
+    fn eq(&self, other: &WebhookConfig) -> bool {
+        fn same_handlers<T: ?Sized>(
+            a: &HashMap<&'static K8sType, Arc<T>>,
+            b: &HashMap<&'static K8sType, Arc<T>>,
+        ) -> bool {
+            a.len() == b.len()
+                && a.iter().all(|(k8s_type, handler)| {
+                    b.get(k8s_type)
+                        .filter(|other| Arc::ptr_eq(handler, other))
+                        .is_some()
+                })
+        }
+        self.port == other.port
+            && self.cert_path == other.cert_path
+            && self.key_path == other.key_path
+            && self.validate_path == other.validate_path
+            && self.mutate_path == other.mutate_path
+            && same_handlers(&self.validators, &other.validators)
+            && same_handlers(&self.mutators, &other.mutators)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::k8s_types::Scope;
+    use serde_json::json;
+
+    static FOO_TYPE: &K8sType = &K8sType {
+        api_version: "example.com/v1",
+        kind: "Foo",
+        plural_kind: "foos",
+        scope: Scope::Namespaced,
+    };
+
+    fn review(kind: &str, object: Value) -> Vec<u8> {
+        let review = json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "request-uid",
+                "kind": {"group": "example.com", "version": "v1", "kind": kind},
+                "resource": {"group": "example.com", "version": "v1", "resource": "foos"},
+                "name": "my-foo",
+                "namespace": "ns",
+                "operation": "CREATE",
+                "userInfo": {"username": "someone"},
+                "object": object,
+                "dryRun": false,
+            }
+        });
+        serde_json::to_vec(&review).unwrap()
+    }
+
+    fn foo(replicas: u64) -> Value {
+        json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Foo",
+            "metadata": {"namespace": "ns", "name": "my-foo"},
+            "spec": {"replicas": replicas},
+        })
+    }
+
+    fn webhook() -> WebhookConfig {
+        WebhookConfig::new("tls.crt", "tls.key")
+            .with_validator(FOO_TYPE, |req: &AdmissionRequest| {
+                let replicas = req
+                    .object
+                    .as_ref()
+                    .and_then(|o| o.pointer("/spec/replicas"))
+                    .and_then(Value::as_u64)
+                    .unwrap_or_default();
+                if replicas > 3 {
+                    AdmissionResponse::deny("too many replicas")
+                } else {
+                    AdmissionResponse::allow()
+                }
+            })
+            .with_mutator(
+                FOO_TYPE,
+                MutatorFn(|_: &AdmissionRequest| {
+                    AdmissionResponse::patch(json!([
+                        {"op": "add", "path": "/metadata/labels", "value": {"foo": "bar"}}
+                    ]))
+                }),
+            )
+    }
+
+    #[test]
+    fn invalid_objects_are_denied_with_the_validator_message() {
+        let review = webhook()
+            .handle("/validate", &review("Foo", foo(5)))
+            .unwrap()
+            .unwrap();
+        let response = review.response.unwrap();
+        assert_eq!("request-uid", response.uid);
+        assert!(!response.allowed);
+        assert_eq!("too many replicas", response.status.unwrap().message);
+        assert_eq!(ADMISSION_REVIEW_API_VERSION, review.api_version);
+        assert_eq!("AdmissionReview", review.kind);
+    }
+
+    #[test]
+    fn valid_objects_are_allowed() {
+        let review = webhook()
+            .handle("/validate/", &review("Foo", foo(1)))
+            .unwrap()
+            .unwrap();
+        let response = review.response.unwrap();
+        assert_eq!("request-uid", response.uid);
+        assert!(response.allowed);
+        assert!(response.status.is_none());
+    }
+
+    #[test]
+    fn types_without_a_handler_are_allowed() {
+        let review = webhook()
+            .handle("/validate", &review("Bar", foo(5)))
+            .unwrap()
+            .unwrap();
+        assert!(review.response.unwrap().allowed);
+    }
+
+    #[test]
+    fn mutator_patch_is_base64_encoded() {
+        let review = webhook()
+            .handle("/mutate", &review("Foo", foo(1)))
+            .unwrap()
+            .unwrap();
+        let response = review.response.unwrap();
+        assert!(response.allowed);
+        assert_eq!(Some("JSONPatch"), response.patch_type.as_deref());
+        let patch = base64::decode(response.patch.unwrap()).unwrap();
+        let patch: Value = serde_json::from_slice(&patch).unwrap();
+        assert_eq!(
+            json!([{"op": "add", "path": "/metadata/labels", "value": {"foo": "bar"}}]),
+            patch
+        );
+    }
+
+    #[test]
+    fn patch_operations_are_serialized_as_a_json_patch() {
+        let webhook = WebhookConfig::new("tls.crt", "tls.key").with_mutator(
+            FOO_TYPE,
+            MutatorFn(|_: &AdmissionRequest| {
+                AdmissionResponse::with_patch(vec![
+                    PatchOperation::add("/metadata/labels/app.kubernetes.io~1name", json!("foo")),
+                    PatchOperation::replace("/spec/replicas", json!(2)),
+                    PatchOperation::remove("/spec/paused"),
+                ])
+            }),
+        );
+        let response = webhook
+            .handle("/mutate", &review("Foo", foo(1)))
+            .unwrap()
+            .unwrap()
+            .response
+            .unwrap();
+        assert_eq!("request-uid", response.uid);
+        assert_eq!(Some("JSONPatch"), response.patch_type.as_deref());
+        let patch = base64::decode(response.patch.unwrap()).unwrap();
+        let patch: Value = serde_json::from_slice(&patch).unwrap();
+        assert_eq!(
+            json!([
+                {"op": "add", "path": "/metadata/labels/app.kubernetes.io~1name", "value": "foo"},
+                {"op": "replace", "path": "/spec/replicas", "value": 2},
+                {"op": "remove", "path": "/spec/paused"},
+            ]),
+            patch
+        );
+    }
+
+    #[test]
+    fn unknown_paths_and_invalid_bodies_are_rejected() {
+        assert!(webhook().handle("/other", &review("Foo", foo(1))).is_none());
+        assert!(webhook().handle("/validate", b"not json").unwrap().is_err());
+        let no_request = json!({"apiVersion": "admission.k8s.io/v1", "kind": "AdmissionReview"});
+        let body = serde_json::to_vec(&no_request).unwrap();
+        assert!(webhook().handle("/validate", &body).unwrap().is_err());
+    }
+}
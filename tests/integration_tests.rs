@@ -31,12 +31,14 @@ static PARENT_TYPE: &K8sType = &K8sType {
     api_version: "roperator.com/v1alpha1",
     kind: "TestParent",
     plural_kind: "testparents",
+    scope: Scope::Namespaced,
 };
 
 static CHILD_ONE_TYPE: &K8sType = &K8sType {
     api_version: "roperator.com/v1alpha1",
     kind: "TestChildOne",
     plural_kind: "testchildones",
+    scope: Scope::Namespaced,
 };
 
 fn setup(name: &str, handler: impl Handler) -> TestKit {
@@ -379,6 +381,7 @@ fn handler_is_invoked_after_waiting_when_resync_is_some() {
                 }),
                 children: Vec::new(),
                 resync,
+                child_scales: Vec::new(),
             })
         }
     }
@@ -485,5 +488,6 @@ fn create_child_handler(req: &SyncRequest) -> Result<SyncResponse, Error> {
         }),
         children: vec![child],
         resync: None,
+        child_scales: Vec::new(),
     })
 }